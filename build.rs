@@ -3,54 +3,138 @@ use prost::{bytes::Bytes, Message};
 use std::{collections::HashMap, env, fs, io::Result, path::Path, process::Command};
 
 include!("src/bazel_flags_proto.rs");
+include!("src/bazel_flags_help.rs");
 
-fn dump_flags(cache_dir: &Path, version: &str) -> Vec<u8> {
-    let cache_path = cache_dir.join(format!("flags-dumps/{version}.data"));
-    if cache_path.exists() {
-        fs::read(cache_path).unwrap()
+fn run_bazelisk(version: &str, args: &[&str]) -> std::result::Result<Vec<u8>, String> {
+    let mut bazelisk_cmd = if cfg!(windows) {
+        // In Github Actions, bazelisk is available via powershell on Windows
+        let mut cmd = Command::new("powershell.exe");
+        cmd.arg("-File");
+        cmd.arg("C:\\npm\\prefix\\bazelisk.ps1");
+        cmd
     } else {
-        let mut bazelisk_cmd = if cfg!(windows) {
-            // In Github Actions, bazelisk is available via powershell on Windows
-            let mut cmd = Command::new("powershell.exe");
-            cmd.arg("-File");
-            cmd.arg("C:\\npm\\prefix\\bazelisk.ps1");
-            cmd
-        } else {
-            Command::new("bazelisk")
+        Command::new("bazelisk")
+    };
+    let result = bazelisk_cmd
+        .env("USE_BAZEL_VERSION", version)
+        .arg("--ignore_all_rc_files")
+        .args(args)
+        .output()
+        .map_err(|e| format!("Failed to spawn Bazelisk for version {version}, {e}"))?;
+    if !result.status.success() {
+        return Err(format!(
+            "===stdout===\n{stdout}\n===stderr===\n{stderr}",
+            stdout = String::from_utf8_lossy(&result.stdout),
+            stderr = String::from_utf8_lossy(&result.stderr)
+        ));
+    }
+    Ok(result.stdout)
+}
+
+// Bazel versions before 7.0 don't support `flags-as-proto`. For those,
+// we parse the flags from the output of `bazel help <command> --long`.
+fn dump_flags_from_help(version: &str) -> std::result::Result<Vec<u8>, String> {
+    let help = run_bazelisk(version, &["help"])?;
+    let mut flags = Vec::<FlagInfo>::new();
+    for command in parse_commands_from_help(&String::from_utf8_lossy(&help)) {
+        let help = run_bazelisk(version, &["help", &command, "--long"])?;
+        flags.extend(parse_flags_from_help(
+            &command,
+            &String::from_utf8_lossy(&help),
+        ));
+    }
+    let collection = FlagCollection {
+        flag_infos: merge_flags_from_help(flags),
+        all_bazel_versions: Vec::new(),
+    };
+    Ok(collection.encode_to_vec())
+}
+
+// `flags-as-proto` does not report default values, expansions and value types. Those are taken
+// from the output of `bazel help <command> --long` instead, as far as available.
+fn add_metadata_from_help(
+    version: &str,
+    flags_binary: Vec<u8>,
+) -> std::result::Result<Vec<u8>, String> {
+    let help = run_bazelisk(version, &["help"])?;
+    let mut help_flags = HashMap::<String, FlagInfo>::new();
+    for command in parse_commands_from_help(&String::from_utf8_lossy(&help)) {
+        let Ok(help) = run_bazelisk(version, &["help", &command, "--long"]) else {
+            continue;
         };
-        let result = bazelisk_cmd
-            .env("USE_BAZEL_VERSION", version)
-            .arg("--ignore_all_rc_files")
-            .arg("help")
-            .arg("flags-as-proto")
-            .output()
-            .unwrap_or_else(|e| panic!("Failed to spawn Bazelisk for version {version}, {e}"));
-        if !result.status.success() {
-            panic!(
-                "Failed to get flags for Bazel version {version}:\n===stdout===\n{stdout}\n===stderr===\n{stderr}",
-                stdout = String::from_utf8_lossy(&result.stdout),
-                stderr = String::from_utf8_lossy(&result.stderr)
-            );
+        for flag in parse_flags_from_help(&command, &String::from_utf8_lossy(&help)) {
+            help_flags.insert(flag.name.clone(), flag);
         }
-        let flags_binary = BASE64_STANDARD
-            .decode(result.stdout)
-            .expect("Failed to decode Bazelisk output as base64");
-        if let Some(parent) = cache_path.parent() {
-            fs::create_dir_all(parent).unwrap_or_else(|e| {
-                panic!(
-                    "Failed to create directory at {} for flags, {e}",
-                    parent.display()
-                )
-            });
+    }
+    let mut collection =
+        FlagCollection::decode(Bytes::from(flags_binary)).expect("Failed to decode protobuf flags");
+    for flag in &mut collection.flag_infos {
+        if let Some(help_flag) = help_flags.get(&flag.name) {
+            flag.default_value = help_flag.default_value.clone();
+            flag.expansion = help_flag.expansion.clone();
+            flag.value_description = help_flag.value_description.clone();
         }
-        fs::write(cache_path.clone(), &flags_binary).unwrap_or_else(|e| {
+    }
+    Ok(collection.encode_to_vec())
+}
+
+// Bumped whenever the dumped flags gain further metadata. Cached dumps of older formats
+// are upgraded by adding the metadata from `bazel help`.
+const FLAGS_DUMP_FORMAT: u32 = 3;
+
+// Versions whose cached dump can't be upgraded are collected in `without_metadata`
+fn dump_flags(cache_dir: &Path, version: &str, without_metadata: &mut Vec<String>) -> Vec<u8> {
+    let cache_path = cache_dir.join(format!("flags-dumps/{version}.v{FLAGS_DUMP_FORMAT}.data"));
+    let legacy_cache_path = cache_dir.join(format!("flags-dumps/{version}.data"));
+    if cache_path.exists() {
+        fs::read(cache_path).unwrap()
+    } else if legacy_cache_path.exists() {
+        let legacy_flags = fs::read(legacy_cache_path).unwrap();
+        match add_metadata_from_help(version, legacy_flags.clone()) {
+            Ok(flags_binary) => {
+                write_flags_dump(&cache_path, &flags_binary);
+                flags_binary
+            }
+            // Keep building without Bazelisk, just without the additional metadata
+            Err(_) => {
+                without_metadata.push(version.to_string());
+                legacy_flags
+            }
+        }
+    } else {
+        let flags_binary = match run_bazelisk(version, &["help", "flags-as-proto"]) {
+            Ok(stdout) => {
+                let flags_binary = BASE64_STANDARD
+                    .decode(stdout)
+                    .expect("Failed to decode Bazelisk output as base64");
+                add_metadata_from_help(version, flags_binary).unwrap_or_else(|err| {
+                    panic!("Failed to get the flag metadata for Bazel version {version}:\n{err}")
+                })
+            }
+            Err(proto_err) => dump_flags_from_help(version).unwrap_or_else(|help_err| {
+                panic!("Failed to get flags for Bazel version {version}:\n{proto_err}\n{help_err}")
+            }),
+        };
+        write_flags_dump(&cache_path, &flags_binary);
+        flags_binary
+    }
+}
+
+fn write_flags_dump(cache_path: &Path, flags_binary: &[u8]) {
+    if let Some(parent) = cache_path.parent() {
+        fs::create_dir_all(parent).unwrap_or_else(|e| {
             panic!(
-                "Failed to write flags to disk at {}, {e}",
-                cache_path.display()
+                "Failed to create directory at {} for flags, {e}",
+                parent.display()
             )
         });
-        flags_binary
     }
+    fs::write(cache_path, flags_binary).unwrap_or_else(|e| {
+        panic!(
+            "Failed to write flags to disk at {}, {e}",
+            cache_path.display()
+        )
+    });
 }
 
 fn merge_flags_into(
@@ -70,6 +154,9 @@ fn merge_flags_into(
                     && existing_flag.metadata_tags == new_flag.metadata_tags
                     && existing_flag.documentation_category == new_flag.documentation_category
                     && existing_flag.requires_value == new_flag.requires_value
+                    && existing_flag.default_value == new_flag.default_value
+                    && existing_flag.expansion == new_flag.expansion
+                    && existing_flag.value_description == new_flag.value_description
             });
             if let Some(existing_flag) = existing_flag_opt {
                 existing_flag.bazel_versions.push(bazel_version.to_string());
@@ -86,6 +173,26 @@ fn merge_flags_into(
 
 fn main() -> Result<()> {
     let versions = [
+        "5.0.0",
+        "5.1.0",
+        "5.1.1",
+        "5.2.0",
+        "5.3.0",
+        "5.3.1",
+        "5.3.2",
+        "5.4.0",
+        "5.4.1",
+        "6.0.0",
+        "6.1.0",
+        "6.1.1",
+        "6.1.2",
+        "6.2.0",
+        "6.2.1",
+        "6.3.0",
+        "6.3.1",
+        "6.3.2",
+        "6.4.0",
+        "6.5.0",
         "7.0.0",
         "7.0.1",
         "7.0.2",
@@ -117,12 +224,19 @@ fn main() -> Result<()> {
     }
 
     let mut flags_by_name = HashMap::<String, Vec<FlagInfo>>::new();
+    let mut without_metadata = Vec::<String>::new();
     for version in versions {
-        let flags_proto: Vec<u8> = dump_flags(&cache_dir, version);
+        let flags_proto: Vec<u8> = dump_flags(&cache_dir, version, &mut without_metadata);
         let flags = FlagCollection::decode(Bytes::from(flags_proto))
             .expect("Failed to decode protobuf flags");
         merge_flags_into(flags.flag_infos, &mut flags_by_name, version);
     }
+    if !without_metadata.is_empty() {
+        println!(
+            "cargo:warning=Failed to run `bazel help`, the cached flags of Bazel {} lack default values, expansions and value types",
+            without_metadata.join(", ")
+        );
+    }
 
     // Hack to workaround https://github.com/salesforce-misc/bazelrc-lsp/issues/2
     // Bazel used to have two `--watchfs` flags: A startup-flag and a build flag.
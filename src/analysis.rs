@@ -0,0 +1,69 @@
+// Diagnostics, formatting and config expansion, as part of the stable library API.
+// See `flags` for the flag database and `parser` for the parser.
+
+use std::path::Path;
+
+pub use crate::config_expansion::{expand_config, get_effective_flags, ExpandedFlag};
+pub use crate::diagnostic::{
+    apply_severity_overrides, diagnostics_from_string, DiagnosticCheck, SeverityOverride,
+};
+pub use crate::formatting::{pretty_print, FormatLineFlow, FormatStyle};
+pub use crate::import_graph::{load_imported_files, read_file_from_disk, RcFile};
+// The unit of the columns in the diagnostics' ranges
+pub use crate::lsp_utils::PositionEncoding;
+pub use crate::rc_discovery::{discover_rc_files, RcSource};
+pub use crate::suppressions::filter_suppressed_diagnostics;
+// The LSP types of the diagnostics. Re-exported as a whole such that callers don't need a
+// matching `tower-lsp` dependency; upgrading `tower-lsp` is a breaking change of this API.
+pub use tower_lsp::lsp_types;
+
+use crate::flags::BazelFlags;
+use lsp_types::Diagnostic;
+
+// Reads the bazelrc file at `path` and diagnoses it, like `bazelrc-lsp lint`
+pub fn lint_file(
+    path: &Path,
+    bazel_flags: &BazelFlags,
+    encoding: PositionEncoding,
+) -> std::io::Result<Vec<Diagnostic>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(diagnostics_from_string(
+        &contents,
+        bazel_flags,
+        Some(path),
+        encoding,
+    ))
+}
+
+#[test]
+fn test_public_api() {
+    use crate::flags::load_flags_for_version;
+    use crate::parser::parse_from_str;
+
+    let (bazel_flags, _) = load_flags_for_version("8.0.0");
+    let parsed = parse_from_str("build --jobs=4 --unknown_flag\n");
+    assert_eq!(parsed.lines.len(), 1);
+
+    let diagnostics = diagnostics_from_string(
+        "build --unknown_flag",
+        &bazel_flags,
+        None,
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(DiagnosticCheck::of)
+            .collect::<Vec<_>>(),
+        vec![Some(DiagnosticCheck::UnknownFlag)]
+    );
+
+    let style = FormatStyle {
+        line_flow: FormatLineFlow::Keep,
+        sort_flags: false,
+    };
+    assert_eq!(
+        pretty_print("build   --jobs 4", &bazel_flags, style),
+        Ok("build --jobs=4\n".to_string())
+    );
+}
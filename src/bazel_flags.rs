@@ -1,9 +1,20 @@
 use base64::prelude::*;
 use phf::phf_map;
 use prost::Message;
-use std::{collections::HashMap, io::Cursor, process::Command};
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    path::Path,
+    process::Command,
+};
 
 use crate::bazel_flags_proto::{FlagCollection, FlagInfo};
+use crate::bazel_version::{
+    get_documentation_version, get_flag_lifecycle, get_flag_lifecycle_markdown,
+    get_flag_unavailable_message, AVAILABLE_BAZEL_VERSIONS,
+};
+use crate::flag_values::{edit_distance, get_allowed_values};
 
 pub static COMMAND_DOCS: phf::Map<&'static str, &'static str> = phf_map! {
     // The command line docs, taken from the `bazel help`
@@ -38,13 +49,53 @@ pub static COMMAND_DOCS: phf::Map<&'static str, &'static str> = phf_map! {
     "try-import" => "Tries to import the given file. Does not fail if the file is not found.",
 };
 
-#[derive(Debug)]
+// Commands inheriting the options of another command.
+// Taken from the `inherits` attribute of Bazel's command annotations.
+pub static COMMAND_PARENTS: phf::Map<&'static str, &'static str> = phf_map! {
+    "aquery" => "build",
+    "coverage" => "test",
+    "cquery" => "test",
+    "info" => "build",
+    "mobile-install" => "build",
+    "print_action" => "build",
+    "run" => "build",
+    "test" => "build",
+};
+
+// Reduces a list of commands to the commands not already covered via inheritance.
+// E.g., `build` and `test` are reduced to `build`, since `test` inherits from `build`.
+pub fn get_minimal_commands<'a>(commands: &[&'a str]) -> Vec<&'a str> {
+    commands
+        .iter()
+        .filter(|cmd| {
+            let mut ancestor = COMMAND_PARENTS.get(cmd);
+            while let Some(a) = ancestor {
+                if commands.contains(a) {
+                    return false;
+                }
+                ancestor = COMMAND_PARENTS.get(a);
+            }
+            true
+        })
+        .copied()
+        .collect::<Vec<_>>()
+}
+
+#[derive(Clone, Debug)]
 pub struct BazelFlags {
     pub commands: Vec<String>,
     pub flags: Vec<FlagInfo>,
     pub flags_by_commands: HashMap<String, Vec<usize>>,
+    // Flags supported by every Bazel command, i.e. the flags which are safe to use with `always`
+    pub flags_for_all_commands: HashSet<usize>,
     pub flags_by_name: HashMap<String, usize>,
     pub flags_by_abbreviation: HashMap<String, usize>,
+    // The Bazel version the flags were filtered for, if any
+    pub bazel_version: Option<String>,
+    // Flags which only exist in other Bazel versions
+    pub other_version_flags_by_name: HashMap<String, usize>,
+    // Set if the flags could not be loaded at all, see `syntax_only`
+    pub syntax_only: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -60,10 +111,12 @@ impl BazelFlags {
         let mut flags_by_commands = HashMap::<String, Vec<usize>>::new();
         let mut flags_by_name = HashMap::<String, usize>::new();
         let mut flags_by_abbreviation = HashMap::<String, usize>::new();
+        let mut other_version_flags_by_name = HashMap::<String, usize>::new();
         for (i, f) in flags.iter().enumerate() {
             if bazel_version.is_some()
                 && !f.bazel_versions.iter().any(|v| v == bazel_version.unwrap())
             {
+                other_version_flags_by_name.insert(f.name.clone(), i);
                 continue;
             }
             flags_by_name.insert(f.name.clone(), i);
@@ -79,6 +132,16 @@ impl BazelFlags {
             }
         }
 
+        other_version_flags_by_name.retain(|name, _| !flags_by_name.contains_key(name));
+
+        // Flags supported by every command. `startup` flags are not applied to commands.
+        let flags_for_all_commands = flags_by_commands
+            .iter()
+            .filter(|(c, _)| *c != "startup")
+            .map(|(_, ids)| ids.iter().copied().collect::<HashSet<_>>())
+            .reduce(|a, b| a.intersection(&b).copied().collect())
+            .unwrap_or_default();
+
         // The `common` option is the union of all other options
         let mut common_flags = flags_by_commands
             .values()
@@ -89,10 +152,8 @@ impl BazelFlags {
         common_flags.dedup();
         flags_by_commands.insert("common".to_string(), common_flags.clone());
 
-        // For safe usage, the `always` option should be the intersection of all other options.
-        // Using an option not supported by all commands would otherwise make some commands
-        // unusable. But there are no options which are valid for *all* commands.
-        // Hence, I am using the union of all flags.
+        // `always` accepts the same flags as `common`. Flags not supported by all commands
+        // break the other commands, though. This is diagnosed based on `flags_for_all_commands`.
         flags_by_commands.insert("always".to_string(), common_flags);
 
         // Determine the list of supported commands
@@ -103,11 +164,29 @@ impl BazelFlags {
             commands,
             flags,
             flags_by_commands,
+            flags_for_all_commands,
             flags_by_name,
             flags_by_abbreviation,
+            bazel_version: bazel_version.map(|v| v.to_string()),
+            other_version_flags_by_name,
+            syntax_only: false,
+        }
+    }
+
+    // An empty flag database, used if the flags could not be loaded at all. Only the
+    // syntax-related features work with it.
+    pub fn syntax_only(bazel_version: &str) -> BazelFlags {
+        BazelFlags {
+            syntax_only: true,
+            ..BazelFlags::from_flags(Vec::new(), Some(bazel_version))
         }
     }
 
+    // An empty flag set loaded from Bazel or a custom flags file doesn't disable the checks
+    pub fn is_syntax_only(&self) -> bool {
+        self.syntax_only
+    }
+
     pub fn get_by_invocation(&self, s: &str) -> Option<(FlagLookupType, &FlagInfo)> {
         let stripped = s.strip_suffix('=').unwrap_or(s);
         // Long names
@@ -141,29 +220,179 @@ impl BazelFlags {
         }
         None
     }
+
+    // Looks up a flag which is unknown in this Bazel version, but exists in other versions
+    pub fn get_from_other_versions(&self, invocation: &str) -> Option<&FlagInfo> {
+        let stripped = invocation.strip_suffix('=').unwrap_or(invocation);
+        let name = stripped.strip_prefix("--")?;
+        let index = self.other_version_flags_by_name.get(name).or_else(|| {
+            let info_index = self
+                .other_version_flags_by_name
+                .get(name.strip_prefix("no")?)?;
+            self.flags[*info_index]
+                .has_negative_flag()
+                .then_some(info_index)
+        })?;
+        Some(&self.flags[*index])
+    }
+
+    // Explains why a flag from another Bazel version is not available, e.g. because it was removed
+    pub fn get_unavailable_message(&self, invocation: &str) -> Option<String> {
+        let info = self.get_from_other_versions(invocation)?;
+        let lifecycle =
+            get_flag_lifecycle(AVAILABLE_BAZEL_VERSIONS.as_slice(), &info.bazel_versions);
+        Some(get_flag_unavailable_message(
+            &lifecycle,
+            invocation,
+            self.bazel_version.as_ref()?,
+        ))
+    }
+
+    // The flags the user most likely meant by an unknown flag, best match first.
+    // Long names are compared to long names and abbreviations to abbreviations.
+    pub fn suggest_flags(&self, invocation: &str) -> Vec<String> {
+        let stripped = invocation.strip_suffix('=').unwrap_or(invocation);
+        let candidates = if stripped.starts_with("--") {
+            self.flags_by_name
+                .iter()
+                .flat_map(|(name, id)| {
+                    let negated = self.flags[*id]
+                        .has_negative_flag()
+                        .then(|| format!("--no{name}"));
+                    std::iter::once(format!("--{name}")).chain(negated)
+                })
+                .collect::<Vec<_>>()
+        } else if stripped.starts_with('-') {
+            self.flags_by_abbreviation
+                .keys()
+                .map(|abbreviation| format!("-{abbreviation}"))
+                .collect::<Vec<_>>()
+        } else {
+            return Vec::new();
+        };
+        let max_distance = (stripped.trim_start_matches('-').len() / 3).clamp(1, 3);
+        let mut suggestions = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let distance = edit_distance(stripped, &candidate);
+                (distance <= max_distance).then_some((distance, candidate))
+            })
+            .collect::<Vec<_>>();
+        suggestions.sort();
+        suggestions.dedup();
+        suggestions.into_iter().take(3).map(|(_, c)| c).collect()
+    }
+
+    // The commands which don't support the given flag. Empty for flags safe to use with `always`.
+    pub fn get_unsupported_commands(&self, flag: &FlagInfo) -> Vec<&str> {
+        let Some(id) = self.flags_by_name.get(&flag.name) else {
+            return Vec::new();
+        };
+        if self.flags_for_all_commands.contains(id) {
+            return Vec::new();
+        }
+        let mut commands = self
+            .flags_by_commands
+            .keys()
+            .map(|c| c.as_str())
+            .filter(|c| !["startup", "common", "always"].contains(c))
+            .filter(|c| !flag.commands.iter().any(|fc| fc == c))
+            .collect::<Vec<_>>();
+        commands.sort();
+        commands
+    }
+
+    // The flag superseding the given invocation. Renamed flags are superseded by their new
+    // name. For deprecated flags, the deprecation warning might point to a successor.
+    pub fn get_successor(&self, invocation: &str) -> Option<&FlagInfo> {
+        let (lookup_type, info) = self.get_by_invocation(invocation)?;
+        if lookup_type == FlagLookupType::OldName {
+            return Some(info);
+        }
+        if !info.is_deprecated() {
+            return None;
+        }
+        let warning = info.deprecation_warning.as_deref()?;
+        let re = Regex::new(r"--([a-z][a-z0-9_]*)").unwrap();
+        let successor = re.captures_iter(warning).find_map(|c| {
+            let (_, successor) = self.get_by_invocation(&format!("--{}", &c[1]))?;
+            (successor.name != info.name && !successor.is_deprecated()).then_some(successor)
+        });
+        successor
+    }
+
+    // Adds custom flags, replacing known flags of the same name. Custom flags without
+    // commands apply to all commands, except for `startup`.
+    pub fn with_custom_flags(&self, custom_flags: &[FlagInfo]) -> BazelFlags {
+        let mut all_commands = self
+            .flags_by_commands
+            .keys()
+            .filter(|c| !["common", "always", "startup"].contains(&c.as_str()))
+            .cloned()
+            .collect::<Vec<_>>();
+        all_commands.sort();
+        let mut flags = self
+            .flags
+            .iter()
+            .filter(|f| !custom_flags.iter().any(|c| c.name == f.name))
+            .cloned()
+            .collect::<Vec<_>>();
+        for custom in custom_flags {
+            let mut flag = custom.clone();
+            if flag.commands.is_empty() {
+                flag.commands = all_commands.clone();
+            }
+            flag.bazel_versions = self.bazel_version.iter().cloned().collect();
+            flags.push(flag);
+        }
+        BazelFlags {
+            syntax_only: self.syntax_only,
+            ..BazelFlags::from_flags(flags, self.bazel_version.as_deref())
+        }
+    }
 }
 
-pub fn load_packaged_bazel_flag_collection() -> FlagCollection {
+// Decodes the flags packaged into the binary. Fails if the packaged data is corrupted.
+pub fn load_packaged_bazel_flag_collection() -> Result<FlagCollection, String> {
     let bazel_flags_proto: &[u8] =
         include_bytes!(concat!(env!("OUT_DIR"), "/bazel-flags-combined.data.lz4"));
-    let decompressed = lz4_flex::decompress_size_prepended(bazel_flags_proto).unwrap();
-    FlagCollection::decode(&mut Cursor::new(decompressed)).unwrap()
+    decode_flag_collection(bazel_flags_proto)
 }
 
-pub fn load_packaged_bazel_flags(bazel_version: &str) -> BazelFlags {
-    BazelFlags::from_flags(
-        load_packaged_bazel_flag_collection().flag_infos,
+fn decode_flag_collection(compressed: &[u8]) -> Result<FlagCollection, String> {
+    let decompressed = lz4_flex::decompress_size_prepended(compressed)
+        .map_err(|err| format!("Failed to decompress the packaged flags: {err}"))?;
+    FlagCollection::decode(&mut Cursor::new(decompressed))
+        .map_err(|err| format!("Failed to decode the packaged flags: {err}"))
+}
+
+pub fn try_load_packaged_bazel_flags(bazel_version: &str) -> Result<BazelFlags, String> {
+    Ok(BazelFlags::from_flags(
+        load_packaged_bazel_flag_collection()?.flag_infos,
         Some(bazel_version),
-    )
+    ))
 }
 
-pub fn load_bazel_flags_from_command(bazel_command: &str) -> Result<BazelFlags, String> {
-    let result = Command::new(bazel_command)
+// Falls back to an empty, syntax-only flag database if the packaged flags can't be
+// decoded. Use `try_load_packaged_bazel_flags` to report such errors.
+pub fn load_packaged_bazel_flags(bazel_version: &str) -> BazelFlags {
+    try_load_packaged_bazel_flags(bazel_version).unwrap_or_else(|err| {
+        tracing::error!("{err}");
+        BazelFlags::syntax_only(bazel_version)
+    })
+}
+
+#[tracing::instrument(level = "debug", skip(cwd))]
+fn run_bazel(bazel_command: &str, cwd: Option<&Path>, args: &[&str]) -> Result<Vec<u8>, String> {
+    let mut command = Command::new(bazel_command);
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    let result = command
         // Disable bazelrc loading. Otherwise, with an invalid bazelrc, the `bazel help`
         // command might fail.
         .arg("--ignore_all_rc_files")
-        .arg("help")
-        .arg("flags-as-proto")
+        .args(args)
         .output()
         .map_err(|err| err.to_string())?;
     if !result.status.success() {
@@ -175,16 +404,116 @@ pub fn load_bazel_flags_from_command(bazel_command: &str) -> Result<BazelFlags,
         );
         return Err(msg);
     }
-    let flags_binary = BASE64_STANDARD.decode(&result.stdout).map_err(|_err| {
+    Ok(result.stdout)
+}
+
+fn load_bazel_flags_from_proto(
+    bazel_command: &str,
+    cwd: Option<&Path>,
+) -> Result<Vec<FlagInfo>, String> {
+    let stdout = run_bazel(bazel_command, cwd, &["help", "flags-as-proto"])?;
+    let flags_binary = BASE64_STANDARD.decode(&stdout).map_err(|_err| {
         format!(
             "failed to base64-decode output as base64: {}",
-            String::from_utf8_lossy(&result.stdout)
+            String::from_utf8_lossy(&stdout)
         )
     })?;
     let flags = FlagCollection::decode(&mut Cursor::new(flags_binary))
         .map_err(|_err| "failed to decode protobuf flags")?;
 
-    Ok(BazelFlags::from_flags(flags.flag_infos, None))
+    Ok(flags.flag_infos)
+}
+
+// Shared with `build.rs`, which uses it for Bazel versions without `flags-as-proto`
+mod help_parser {
+    use crate::bazel_flags_proto::FlagInfo;
+    include!("bazel_flags_help.rs");
+}
+use help_parser::{merge_flags_from_help, parse_commands_from_help, parse_flags_from_help};
+
+// Fallback for old Bazel versions which don't support `bazel help flags-as-proto`
+fn load_bazel_flags_from_help(
+    bazel_command: &str,
+    cwd: Option<&Path>,
+) -> Result<Vec<FlagInfo>, String> {
+    let help = run_bazel(bazel_command, cwd, &["help"])?;
+    let commands = parse_commands_from_help(&String::from_utf8_lossy(&help));
+    if commands.is_empty() {
+        return Err("failed to determine the available commands from `bazel help`".to_string());
+    }
+    let mut flags = Vec::<FlagInfo>::new();
+    for command in &commands {
+        let help = run_bazel(bazel_command, cwd, &["help", command, "--long"])?;
+        flags.extend(parse_flags_from_help(
+            command,
+            &String::from_utf8_lossy(&help),
+        ));
+    }
+    Ok(merge_flags_from_help(flags))
+}
+
+// Complements flags loaded from a Bazel binary with the packaged flags of the given version.
+// The live flags decide which flags exist and which commands accept them. Texts missing from
+// the live output (e.g., the documentation of some flags) are taken from the packaged flags.
+pub fn merge_packaged_flag_metadata(
+    mut live_flags: Vec<FlagInfo>,
+    packaged_flags: &[FlagInfo],
+    bazel_version: &str,
+) -> Vec<FlagInfo> {
+    let packaged_by_name = packaged_flags
+        .iter()
+        .filter(|f| f.bazel_versions.iter().any(|v| v == bazel_version))
+        .map(|f| (f.name.as_str(), f))
+        .collect::<HashMap<_, _>>();
+    let is_missing = |s: &Option<String>| s.as_ref().is_none_or(|s| s.trim().is_empty());
+    for flag in &mut live_flags {
+        let Some(packaged) = packaged_by_name.get(flag.name.as_str()) else {
+            continue;
+        };
+        if is_missing(&flag.documentation) {
+            flag.documentation = packaged.documentation.clone();
+        }
+        if is_missing(&flag.documentation_category) {
+            flag.documentation_category = packaged.documentation_category.clone();
+        }
+        if is_missing(&flag.old_name) {
+            flag.old_name = packaged.old_name.clone();
+        }
+        if is_missing(&flag.deprecation_warning) {
+            flag.deprecation_warning = packaged.deprecation_warning.clone();
+        }
+        if flag.default_value.is_none() {
+            flag.default_value = packaged.default_value.clone();
+        }
+        if flag.expansion.is_empty() {
+            flag.expansion = packaged.expansion.clone();
+        }
+    }
+    live_flags
+}
+
+// Reads additional flags, e.g. for company-internal wrapper scripts or forks of Bazel, from
+// a JSON file. The file contains an array of flags in the format printed by `bazelrc-lsp flags`.
+// Only `name` is required.
+pub fn load_custom_flags(path: &Path) -> Result<Vec<FlagInfo>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let flags = serde_json::from_str::<Vec<FlagInfo>>(&contents).map_err(|err| err.to_string())?;
+    if let Some(pos) = flags.iter().position(|f| f.name.is_empty()) {
+        return Err(format!("the flag at index {pos} has no name"));
+    }
+    Ok(flags)
+}
+
+// Runs Bazel within `cwd`, such that Bazelisk picks up the `.bazelversion` of that workspace
+pub fn load_bazel_flag_infos_from_command(
+    bazel_command: &str,
+    cwd: Option<&Path>,
+) -> Result<Vec<FlagInfo>, String> {
+    load_bazel_flags_from_proto(bazel_command, cwd).or_else(|proto_err| {
+        load_bazel_flags_from_help(bazel_command, cwd).map_err(|help_err| {
+            format!("`bazel help flags-as-proto` failed: {proto_err}\n`bazel help --long` failed: {help_err}")
+        })
+    })
 }
 
 fn escape_markdown(str: &str) -> String {
@@ -266,6 +595,85 @@ pub fn combine_key_value_flags(lines: &mut [crate::parser::Line], bazel_flags: &
     }
 }
 
+// Pseudo-commands which only exist within bazelrc files
+// Explains how lines starting with the given command are applied
+fn get_rc_command_note(command: &str) -> Option<String> {
+    match command {
+        "common" | "always" => Some("Only valid in bazelrc files, not on the command line.".to_string()),
+        "import" | "try-import" => Some(
+            "Only valid in bazelrc files. The path may be relative to the workspace via `%workspace%`.".to_string(),
+        ),
+        "startup" => Some(
+            "Applied when starting the Bazel server. Changing them restarts the server. Configs (`startup:name`) are not supported.".to_string(),
+        ),
+        _ => {
+            let mut notes = Vec::<String>::new();
+            if let Some(parent) = COMMAND_PARENTS.get(command) {
+                notes.push(format!("Inherits the options of `{parent}`."));
+            }
+            let mut children = Vec::<&str>::new();
+            for (child, _) in COMMAND_PARENTS.entries() {
+                let mut ancestor = COMMAND_PARENTS.get(child);
+                while let Some(a) = ancestor {
+                    if *a == command {
+                        children.push(child);
+                        break;
+                    }
+                    ancestor = COMMAND_PARENTS.get(a);
+                }
+            }
+            if !children.is_empty() {
+                children.sort();
+                let children = children
+                    .iter()
+                    .map(|c| format!("`{c}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                notes.push(format!("Lines for `{command}` also apply to {children}."));
+            }
+            (!notes.is_empty()).then(|| notes.join(" "))
+        }
+    }
+}
+
+fn get_command_documentation_url(command: &str) -> String {
+    match command {
+        "common" | "always" => "https://bazel.build/run/bazelrc#option-defaults".to_string(),
+        "import" | "try-import" => "https://bazel.build/run/bazelrc#imports".to_string(),
+        _ => format!("https://bazel.build/reference/command-line-reference#{command}-options"),
+    }
+}
+
+// The flag's entry in the command-line reference on bazel.build. Links to the reference
+// of the given Bazel version, or to the latest one if that version is not documented.
+pub fn get_flag_documentation_url(flag_name: &str, bazel_version: Option<&str>) -> String {
+    match bazel_version.and_then(get_documentation_version) {
+        Some(version) => format!(
+            "https://bazel.build/versions/{version}/reference/command-line-reference#flag--{flag_name}"
+        ),
+        None => format!("https://bazel.build/reference/command-line-reference#flag--{flag_name}"),
+    }
+}
+
+pub fn get_command_documentation_markdown(
+    bazel_flags: &BazelFlags,
+    command: &str,
+) -> Option<String> {
+    let docs = COMMAND_DOCS.get(command)?;
+    let mut result = format!("`{command}`\n\n{}", escape_markdown(docs));
+    if let Some(note) = get_rc_command_note(command) {
+        result += &format!("\n\n{note}");
+    }
+    if let Some(flags) = bazel_flags.flags_by_commands.get(command) {
+        result += &format!("\n\nSupports {} flags", flags.len());
+    }
+    result += &format!(
+        "\n\n[Documentation]({})",
+        get_command_documentation_url(command)
+    );
+    Some(result)
+}
+
 impl FlagInfo {
     pub fn is_deprecated(&self) -> bool {
         self.metadata_tags.iter().any(|t| t == "DEPRECATED")
@@ -275,11 +683,41 @@ impl FlagInfo {
         self.effect_tags.iter().any(|t| t == "NO_OP")
     }
 
+    // Is the given invocation the negated `--noflag` form of this flag?
+    pub fn is_negated_invocation(&self, invocation: &str) -> bool {
+        let Some(name) = invocation
+            .strip_prefix("--no")
+            .map(|n| n.strip_suffix('=').unwrap_or(n))
+        else {
+            return false;
+        };
+        self.has_negative_flag() && (name == self.name || Some(name) == self.old_name.as_deref())
+    }
+
+    // The flags an expansion flag expands to, as pairs of invocation and value,
+    // e.g. `("--spawn_strategy", Some("dynamic"))`
+    pub fn get_expanded_flags(&self) -> Vec<(&str, Option<&str>)> {
+        self.expansion
+            .iter()
+            .map(|f| match f.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (f.as_str(), None),
+            })
+            .collect()
+    }
+
     pub fn supports_command(&self, command: &str) -> bool {
-        command == "common" || command == "always" || self.commands.iter().any(|c| c == command)
+        if command == "common" || command == "always" {
+            // Applied to all commands supporting the flag. Startup flags are not accepted, though.
+            self.commands.iter().any(|c| c != "startup")
+        } else {
+            self.commands.iter().any(|c| c == command)
+        }
     }
 
-    pub fn get_documentation_markdown(&self) -> String {
+    // The documentation shown on hover and in completions. `bazel_version` is the
+    // version whose command-line reference is linked.
+    pub fn get_documentation_markdown(&self, bazel_version: Option<&str>) -> String {
         let mut result = String::new();
 
         // First line: Flag name and short hand (if any)
@@ -295,6 +733,41 @@ impl FlagInfo {
             result += "\n\n";
             result += &escape_markdown(&doc.as_str().replace("%{product}", "Bazel"));
         }
+        // The accepted values, as a definition list
+        let mut definitions = Vec::<(&str, String)>::new();
+        if let Some(default_value) = &self.default_value {
+            definitions.push(("Default", format!("`{default_value:?}`")));
+        }
+        if let Some(allowed) = get_allowed_values(self) {
+            let allowed = allowed
+                .iter()
+                .map(|v| format!("`{v}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            definitions.push(("Allowed values", allowed));
+        }
+        if self.allows_multiple() {
+            definitions.push((
+                "Repeatable",
+                "values of repeated assignments accumulate instead of overriding each other"
+                    .to_string(),
+            ));
+        }
+        if !self.expansion.is_empty() {
+            let expansion = self
+                .expansion
+                .iter()
+                .map(|f| format!("`{f}`"))
+                .collect::<Vec<_>>()
+                .join(" ");
+            definitions.push(("Expands to", expansion));
+        }
+        if !definitions.is_empty() {
+            result += "\n";
+            for (term, definition) in definitions {
+                result += &format!("\n* **{term}:** {definition}");
+            }
+        }
         // And a list of tags
         result += "\n\n";
         if !self.effect_tags.is_empty() {
@@ -322,11 +795,88 @@ impl FlagInfo {
         if let Some(catgegory) = &self.documentation_category {
             result += format!("Category: {}\n", catgegory.to_lowercase()).as_str();
         }
+        let lifecycle =
+            get_flag_lifecycle(AVAILABLE_BAZEL_VERSIONS.as_slice(), &self.bazel_versions);
+        if let Some(lifecycle) = get_flag_lifecycle_markdown(&lifecycle) {
+            result += &lifecycle;
+            result += "\n";
+        }
+        if !result.ends_with("\n\n") {
+            result += "\n";
+        }
+        result += &format!(
+            "[Documentation]({})\n",
+            get_flag_documentation_url(&self.name, bazel_version)
+        );
 
         result
     }
 }
 
+#[test]
+fn test_decode_corrupted_flags() {
+    assert!(decode_flag_collection(&[1, 2, 3])
+        .unwrap_err()
+        .starts_with("Failed to decompress the packaged flags"));
+    let garbage = lz4_flex::compress_prepend_size(&[0xff; 16]);
+    assert!(decode_flag_collection(&garbage)
+        .unwrap_err()
+        .starts_with("Failed to decode the packaged flags"));
+    assert!(load_packaged_bazel_flag_collection().is_ok());
+
+    let flags = BazelFlags::syntax_only("8.0.0");
+    assert!(flags.is_syntax_only());
+    assert!(flags.get_by_invocation("--jobs").is_none());
+    assert!(!load_packaged_bazel_flags("8.0.0").is_syntax_only());
+    assert!(!BazelFlags::from_flags(Vec::new(), Some("8.0.0")).is_syntax_only());
+    let custom = FlagInfo {
+        name: "my_flag".to_string(),
+        ..Default::default()
+    };
+    assert!(flags.with_custom_flags(&[custom]).is_syntax_only());
+}
+
+#[test]
+fn test_get_minimal_commands() {
+    assert_eq!(get_minimal_commands(&["build", "test"]), vec!["build"]);
+    assert_eq!(get_minimal_commands(&["coverage", "build"]), vec!["build"]);
+    assert_eq!(
+        get_minimal_commands(&["coverage", "query", "test"]),
+        vec!["query", "test"]
+    );
+}
+
+#[test]
+fn test_merge_packaged_flag_metadata() {
+    let packaged = load_packaged_bazel_flag_collection().unwrap().flag_infos;
+    let live = vec![
+        FlagInfo {
+            name: "jobs".to_string(),
+            commands: vec!["build".to_string()],
+            documentation: Some(" ".to_string()),
+            ..Default::default()
+        },
+        FlagInfo {
+            name: "my_new_flag".to_string(),
+            commands: vec!["build".to_string()],
+            ..Default::default()
+        },
+    ];
+    let merged = merge_packaged_flag_metadata(live, &packaged, "8.0.0");
+    let flags = BazelFlags::from_flags(merged, None);
+    // The documentation is taken from the packaged flags ...
+    let jobs = flags.get_by_invocation("--jobs").unwrap().1;
+    assert!(jobs
+        .documentation
+        .as_ref()
+        .unwrap()
+        .contains("concurrent jobs"));
+    // ... but the live flags decide about the commands and which flags exist
+    assert_eq!(jobs.commands, vec!["build"]);
+    assert!(flags.get_by_invocation("--my_new_flag").is_some());
+    assert!(flags.get_by_invocation("--keep_going").is_none());
+}
+
 #[test]
 fn test_flags() {
     let flags = load_packaged_bazel_flags("7.1.0");
@@ -376,17 +926,289 @@ fn test_flags() {
         .unwrap()
         .iter()
         .any(|id| flags.flags[*id].name == "remote_cache"));
+    // But it can't be used with `always`, since it is not supported by all commands
+    let remote_cache = flags.get_by_invocation("--remote_cache").unwrap().1;
+    let unsupported = flags.get_unsupported_commands(remote_cache);
+    assert!(unsupported.contains(&"version"));
+    assert!(!unsupported.contains(&"build"));
+    // Client options like `--color` are supported by all commands
+    let color = flags.get_by_invocation("--color").unwrap().1;
+    assert!(flags.get_unsupported_commands(color).is_empty());
 }
 
 // Test that different flags are available in different Bazel versions
 #[test]
 fn test_flag_versions() {
+    let bazel6_flags = load_packaged_bazel_flags("6.5.0");
     let bazel7_flags = load_packaged_bazel_flags("7.0.0");
     let bazel8_flags = load_packaged_bazel_flags("8.0.0");
     let bazel9_flags = load_packaged_bazel_flags("9.0.0");
 
     // `python3_path` was removed in Bazel 8
+    assert!(bazel6_flags.flags_by_name.contains_key("python3_path"));
     assert!(bazel7_flags.flags_by_name.contains_key("python3_path"));
     assert!(!bazel8_flags.flags_by_name.contains_key("python3_path"));
     assert!(!bazel9_flags.flags_by_name.contains_key("python3_path"));
 }
+
+#[test]
+fn test_parse_flags_from_help() {
+    let commands = parse_commands_from_help(
+        "Usage: bazel <command> <options> ...\n\n\
+        Available commands:\n  \
+          build     Builds the specified targets.\n  \
+          test      Builds and runs the specified test targets.\n\n\
+        Getting more help:\n  bazel help <command>\n",
+    );
+    assert_eq!(commands, vec!["build", "test"]);
+
+    let help = "Usage: bazel build <options> <targets>\n\n\
+        Options that appear before the command and are parsed by the client:\n  \
+          --output_base (a path; default: see description)\n    \
+            If set, specifies the output location.\n      \
+              Tags: affects_outputs\n\n\
+        Options that control build execution:\n  \
+          --[no]keep_going [-k] (a boolean; default: \"false\")\n    \
+            Continue as much as possible\n    \
+            after an error.\n      \
+              Tags: eagerness_to_exit, experimental\n  \
+          --copt (a string; may be used multiple times)\n    \
+            Additional options to pass to gcc.\n  \
+          --experimental_spawn_scheduler\n    \
+            Enable dynamic execution.\n      \
+              Expands to: --internal_spawn_scheduler --spawn_strategy=dynamic\n";
+    let mut flags = parse_flags_from_help("build", help);
+    flags.extend(parse_flags_from_help("test", help));
+    let flags = merge_flags_from_help(flags);
+    assert_eq!(
+        flags.iter().map(|f| f.name.as_str()).collect::<Vec<_>>(),
+        vec![
+            "output_base",
+            "keep_going",
+            "copt",
+            "experimental_spawn_scheduler"
+        ]
+    );
+
+    assert_eq!(flags[0].commands, vec!["startup"]);
+    assert!(flags[0].requires_value());
+    // Defaults described in prose are not recorded
+    assert_eq!(flags[0].default_value, None);
+    assert_eq!(flags[0].value_description.as_deref(), Some("a path"));
+
+    let keep_going = &flags[1];
+    assert_eq!(keep_going.commands, vec!["build", "test"]);
+    assert_eq!(keep_going.abbreviation.as_deref(), Some("k"));
+    assert!(keep_going.has_negative_flag());
+    assert!(!keep_going.requires_value());
+    assert_eq!(
+        keep_going.documentation.as_deref(),
+        Some("Continue as much as possible after an error.")
+    );
+    assert_eq!(keep_going.effect_tags, vec!["EAGERNESS_TO_EXIT"]);
+    assert_eq!(keep_going.metadata_tags, vec!["EXPERIMENTAL"]);
+    assert_eq!(keep_going.default_value.as_deref(), Some("false"));
+    assert_eq!(keep_going.value_description.as_deref(), Some("a boolean"));
+
+    assert!(flags[2].allows_multiple());
+    assert!(flags[2].requires_value());
+    assert_eq!(flags[2].value_description.as_deref(), Some("a string"));
+
+    let spawn_scheduler = &flags[3];
+    assert_eq!(spawn_scheduler.value_description, None);
+    assert_eq!(
+        spawn_scheduler.expansion,
+        vec!["--internal_spawn_scheduler", "--spawn_strategy=dynamic"]
+    );
+    assert_eq!(
+        spawn_scheduler.documentation.as_deref(),
+        Some("Enable dynamic execution.")
+    );
+    assert_eq!(
+        spawn_scheduler.get_expanded_flags(),
+        vec![
+            ("--internal_spawn_scheduler", None),
+            ("--spawn_strategy", Some("dynamic"))
+        ]
+    );
+}
+
+#[test]
+fn test_flag_documentation() {
+    let flags = crate::test_utils::load_flags_with_value_types("7.4.1");
+    let mut info = flags.get_by_invocation("-c").unwrap().1.clone();
+    info.default_value = Some("fastbuild".to_string());
+    let docs = info.get_documentation_markdown(Some("7.4.1"));
+    assert!(docs.contains(
+        "\n\n* **Default:** `\"fastbuild\"`\n* **Allowed values:** `fastbuild`, `dbg`, `opt`\n"
+    ));
+    // Links the flag's entry in the reference of the given Bazel version
+    assert!(docs.ends_with(
+        "\n\n[Documentation](https://bazel.build/versions/7.4.0/reference/command-line-reference#flag--compilation_mode)\n"
+    ));
+    // Pre-releases link to the latest reference
+    assert!(info
+        .get_documentation_markdown(Some("9.0.0-pre.20250317.2"))
+        .ends_with(
+            "(https://bazel.build/reference/command-line-reference#flag--compilation_mode)\n"
+        ));
+
+    let info = FlagInfo {
+        name: "experimental_spawn_scheduler".to_string(),
+        expansion: vec![
+            "--internal_spawn_scheduler".to_string(),
+            "--spawn_strategy=dynamic".to_string(),
+        ],
+        ..Default::default()
+    };
+    assert!(info
+        .get_documentation_markdown(None)
+        .contains("* **Expands to:** `--internal_spawn_scheduler` `--spawn_strategy=dynamic`\n"));
+
+    let info = FlagInfo {
+        name: "copt".to_string(),
+        allows_multiple: Some(true),
+        ..Default::default()
+    };
+    assert!(info.get_documentation_markdown(None).contains(
+        "* **Repeatable:** values of repeated assignments accumulate instead of overriding each other\n"
+    ));
+}
+
+#[test]
+fn test_command_documentation() {
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let build_docs = get_command_documentation_markdown(&flags, "build").unwrap();
+    assert!(build_docs.starts_with("`build`\n\nBuilds the specified targets\\.\n\nLines for `build` also apply to `aquery`, `coverage`, `cquery`, "));
+    assert!(build_docs.ends_with(
+        "[Documentation](https://bazel.build/reference/command-line-reference#build-options)"
+    ));
+    let common_docs = get_command_documentation_markdown(&flags, "common").unwrap();
+    assert!(common_docs.contains("Only valid in bazelrc files"));
+    assert!(common_docs.ends_with("(https://bazel.build/run/bazelrc#option-defaults)"));
+    let test_docs = get_command_documentation_markdown(&flags, "test").unwrap();
+    assert!(test_docs.contains(
+        "Inherits the options of `build`. Lines for `test` also apply to `coverage`, `cquery`."
+    ));
+    let startup_docs = get_command_documentation_markdown(&flags, "startup").unwrap();
+    assert!(startup_docs.contains("Configs (`startup:name`) are not supported."));
+    assert!(get_command_documentation_markdown(&flags, "unknown").is_none());
+}
+
+#[test]
+fn test_get_successor() {
+    let flag = |name: &str, metadata_tags: Vec<&str>, warning: Option<&str>| FlagInfo {
+        name: name.to_string(),
+        commands: vec!["build".to_string()],
+        metadata_tags: metadata_tags.into_iter().map(str::to_string).collect(),
+        deprecation_warning: warning.map(str::to_string),
+        ..Default::default()
+    };
+    let flags = BazelFlags::from_flags(
+        vec![
+            flag(
+                "old_flag",
+                vec!["DEPRECATED"],
+                Some("Use --new_flag instead."),
+            ),
+            flag("unmaintained", vec!["DEPRECATED"], Some("Will be removed.")),
+            flag("new_flag", vec![], None),
+        ],
+        None,
+    );
+    assert_eq!(
+        flags.get_successor("--old_flag").map(|f| f.name.as_str()),
+        Some("new_flag")
+    );
+    assert_eq!(flags.get_successor("--unmaintained"), None);
+    assert_eq!(flags.get_successor("--new_flag"), None);
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    assert_eq!(
+        flags
+            .get_successor("--noexperimental_remote_cache_compression")
+            .map(|f| f.name.as_str()),
+        Some("remote_cache_compression")
+    );
+}
+
+#[test]
+fn test_custom_flags() {
+    use crate::test_utils::TestDir;
+
+    let dir = TestDir::new();
+    let path = dir.write(
+        "custom-flags.json",
+        r#"[
+            {"name": "remote_profile", "documentation": "The remote profile"},
+            {"name": "jobs", "commands": ["build"], "allowsMultiple": true}
+        ]"#,
+    );
+    let custom_flags = load_custom_flags(&path).unwrap();
+    let packaged_flags = load_packaged_bazel_flags("8.0.0");
+    assert!(packaged_flags
+        .get_by_invocation("--remote_profile")
+        .is_none());
+
+    let flags = packaged_flags.with_custom_flags(&custom_flags);
+    let (_, flag) = flags.get_by_invocation("--remote_profile").unwrap();
+    assert_eq!(flag.documentation(), "The remote profile");
+    assert!(flag.supports_command("build"));
+    assert!(flag.supports_command("test"));
+    assert!(!flag.supports_command("startup"));
+    // Custom flags replace the known flags of the same name
+    let (_, flag) = flags.get_by_invocation("--jobs").unwrap();
+    assert_eq!(flag.commands, vec!["build"]);
+    assert!(flag.allows_multiple());
+    assert_eq!(flags.flags.len(), packaged_flags.flags.len() + 1);
+
+    std::fs::write(&path, r#"[{"documentation": "No name"}]"#).unwrap();
+    assert_eq!(
+        load_custom_flags(&path),
+        Err("the flag at index 0 has no name".to_string())
+    );
+}
+
+#[test]
+fn test_flags_from_other_versions() {
+    let flags = load_packaged_bazel_flags("8.0.0");
+    assert!(flags.get_by_invocation("--python3_path").is_none());
+    assert_eq!(
+        flags
+            .get_from_other_versions("--python3_path")
+            .map(|f| f.name.as_str()),
+        Some("python3_path")
+    );
+    assert_eq!(
+        flags.get_unavailable_message("--python3_path").unwrap(),
+        "The flag \"--python3_path\" was removed in Bazel 8.0.0."
+    );
+    // Flags of the current version are not reported
+    assert!(flags.get_from_other_versions("--jobs").is_none());
+
+    let flags = load_packaged_bazel_flags("7.4.1");
+    assert_eq!(
+        flags.get_unavailable_message("--enable_workspace").unwrap(),
+        "The flag \"--enable_workspace\" is not available in Bazel 7.4.1. It was added in Bazel 8.0.0."
+    );
+    assert!(flags
+        .get_by_invocation("--python3_path")
+        .unwrap()
+        .1
+        .get_documentation_markdown(Some("7.4.1"))
+        .contains("Removed in Bazel 8.0.0.\n"));
+}
+
+#[test]
+fn test_suggest_flags() {
+    let flags = load_packaged_bazel_flags("8.0.0");
+    assert_eq!(flags.suggest_flags("--keep_goign"), vec!["--keep_going"]);
+    assert_eq!(
+        flags.suggest_flags("--nokeep_gonig="),
+        vec!["--nokeep_going"]
+    );
+    assert_eq!(flags.suggest_flags("--disk_cach"), vec!["--disk_cache"]);
+    assert_eq!(flags.suggest_flags("-kk"), vec!["-k"]);
+    assert_eq!(flags.suggest_flags("--unknown_flag"), Vec::<String>::new());
+    assert_eq!(flags.suggest_flags("keep_going"), Vec::<String>::new());
+}
@@ -0,0 +1,146 @@
+// Parsing of the `bazel help` text output.
+//
+// Old Bazel versions do not support `bazel help flags-as-proto`. For those, the
+// flags are extracted from the human-readable output of `bazel help <command> --long`.
+//
+// This file is `include!`d by both the library and `build.rs`. Hence, it must
+// not contain any `use` statements and expects `FlagInfo` to be in scope.
+
+// Parses the command names from the output of `bazel help`
+pub fn parse_commands_from_help(text: &str) -> Vec<String> {
+    text.lines()
+        .skip_while(|l| !l.starts_with("Available commands:"))
+        .skip(1)
+        .take_while(|l| !l.trim().is_empty())
+        .filter_map(|l| l.split_whitespace().next())
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+}
+
+// Tags listed by `bazel help` which are metadata tags. All other tags are effect tags.
+const METADATA_TAGS: [&str; 7] = [
+    "experimental",
+    "incompatible_change",
+    "deprecated",
+    "hidden",
+    "internal",
+    "triggered_by_all_incompatible_changes",
+    "explicit_in_output_path",
+];
+
+// Parses the flags from the output of `bazel help <command> --long`.
+//
+// The output looks like
+// ```
+// Options that control build execution:
+//   --[no]keep_going [-k] (a boolean; default: "false")
+//     Continue as much as possible after an error.
+//       Tags: eagerness_to_exit
+//   --experimental_spawn_scheduler
+//     Enable dynamic execution.
+//       Expands to: --internal_spawn_scheduler --spawn_strategy=dynamic
+// ```
+// Options listed under "Options that appear before the command" are startup options.
+// Defaults described only in prose ("default: see description") are not recorded.
+pub fn parse_flags_from_help(command: &str, text: &str) -> Vec<FlagInfo> {
+    let mut flags = Vec::<FlagInfo>::new();
+    let mut section_command = command;
+    for line in text.lines() {
+        if !line.starts_with(' ') && line.ends_with(':') {
+            section_command = if line.starts_with("Options that appear before the command") {
+                "startup"
+            } else {
+                command
+            };
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("  --") {
+            let (name, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+            let (name, has_negative_flag) = match name.strip_prefix("[no]") {
+                Some(n) => (n, true),
+                None => (name, false),
+            };
+            let rest = rest.trim();
+            let (abbreviation, rest) = match rest.strip_prefix("[-") {
+                Some(r) => match r.split_once(']') {
+                    Some((abbr, r)) => (Some(abbr.to_string()), r.trim()),
+                    None => (None, rest),
+                },
+                None => (None, rest),
+            };
+            let value_description = rest
+                .strip_prefix('(')
+                .and_then(|r| r.strip_suffix(')'))
+                .unwrap_or("");
+            let default_value = value_description
+                .split_once("default: \"")
+                .and_then(|(_, d)| d.strip_suffix('"'))
+                .map(|d| d.to_string());
+            // The value type precedes the default value and the "may be used multiple times"
+            let value_type = value_description
+                .split("; ")
+                .next()
+                .filter(|t| !t.is_empty() && !t.starts_with("default: "))
+                .map(|t| t.to_string());
+            flags.push(FlagInfo {
+                name: name.to_string(),
+                has_negative_flag: Some(has_negative_flag),
+                commands: vec![section_command.to_string()],
+                abbreviation,
+                allows_multiple: Some(value_description.contains("may be used multiple times")),
+                requires_value: Some(
+                    !value_description.is_empty()
+                        && !has_negative_flag
+                        && !value_description.starts_with("a boolean"),
+                ),
+                default_value,
+                value_description: value_type,
+                ..Default::default()
+            });
+        } else if let Some(flag) = flags.last_mut() {
+            let line = line.trim();
+            if let Some(tags) = line.strip_prefix("Tags:") {
+                for tag in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+                    if METADATA_TAGS.contains(&tag) {
+                        flag.metadata_tags.push(tag.to_uppercase());
+                    } else {
+                        flag.effect_tags.push(tag.to_uppercase());
+                    }
+                }
+            } else if let Some(expansion) = line.strip_prefix("Expands to:") {
+                flag.expansion = expansion.split_whitespace().map(|f| f.to_string()).collect();
+            } else if !line.is_empty() {
+                let doc = flag.documentation.get_or_insert_with(String::new);
+                if !doc.is_empty() {
+                    doc.push(' ');
+                }
+                doc.push_str(line);
+            }
+        }
+    }
+    flags
+}
+
+// Merges the flags parsed for the individual commands. Flags supported by
+// multiple commands are listed once per command by `bazel help`.
+pub fn merge_flags_from_help(flags: Vec<FlagInfo>) -> Vec<FlagInfo> {
+    let mut result = Vec::<FlagInfo>::new();
+    let mut index_by_name = std::collections::HashMap::<String, usize>::new();
+    for flag in flags {
+        match index_by_name.get(&flag.name) {
+            Some(idx) => {
+                let existing = &mut result[*idx];
+                for command in flag.commands {
+                    if !existing.commands.contains(&command) {
+                        existing.commands.push(command);
+                    }
+                }
+            }
+            None => {
+                index_by_name.insert(flag.name.clone(), result.len());
+                result.push(flag);
+            }
+        }
+    }
+    result
+}
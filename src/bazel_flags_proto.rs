@@ -3,7 +3,8 @@
 // This file contains additional modifications to store
 // supported Bazel version ranges.
 
-#[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(Clone, PartialEq, ::prost::Message, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct FlagInfo {
     /// Name of the flag, without leading dashes.
     #[prost(string, required, tag = "1")]
@@ -49,12 +50,30 @@ pub struct FlagInfo {
     #[prost(string, optional, tag = "99999")]
     pub deprecation_warning: Option<::prost::alloc::string::String>,
 
+    /// EXTENSION: Default value, as printed by `bazel help <command> --long`.
+    /// Not part of Bazel's `flags-as-proto` output.
+    #[prost(string, optional, tag = "998")]
+    pub default_value: ::core::option::Option<::prost::alloc::string::String>,
+
+    /// EXTENSION: The flags an expansion flag expands to, e.g.
+    /// \['--internal_spawn_scheduler', '--spawn_strategy=dynamic'\].
+    /// Not part of Bazel's `flags-as-proto` output.
+    #[prost(string, repeated, tag = "997")]
+    pub expansion: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+
+    /// EXTENSION: The type of the flag's value, as printed by `bazel help <command> --long`,
+    /// e.g. "an integer" or "fastbuild, dbg or opt".
+    /// Not part of Bazel's `flags-as-proto` output.
+    #[prost(string, optional, tag = "996")]
+    pub value_description: ::core::option::Option<::prost::alloc::string::String>,
+
     /// EXTENSION: List of Bazel versions this flag applies to
     #[prost(string, repeated, tag = "999")]
     pub bazel_versions: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 
-#[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(Clone, PartialEq, ::prost::Message, ::serde::Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct FlagCollection {
     #[prost(message, repeated, tag = "1")]
     pub flag_infos: ::prost::alloc::vec::Vec<FlagInfo>,
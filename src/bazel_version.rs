@@ -1,6 +1,9 @@
 use once_cell::sync::Lazy;
 
-use crate::{bazel_flags::load_packaged_bazel_flag_collection, file_utils::get_workspace_path};
+use crate::{
+    bazel_flags::load_packaged_bazel_flag_collection, bazeliskrc::get_bazeliskrc_version,
+    file_utils::get_workspace_path,
+};
 use std::{env, fs, path::Path};
 
 #[derive(Debug, PartialEq)]
@@ -68,19 +71,40 @@ fn parse_bazel_version(full_version_str: &str) -> Option<BazelVersion> {
     })
 }
 
-// Find the closest available Bazel version
+fn sort_versions(version_strs: &[String]) -> Vec<(BazelVersionTuple, &String)> {
+    let mut versions = version_strs
+        .iter()
+        .map(|s| (parse_bazel_version(s).unwrap().as_tuple(), s))
+        .collect::<Vec<_>>();
+    versions.sort();
+    versions
+}
+
+// Find the closest available Bazel version: Preferably a release with the same major
+// and minor version, otherwise the nearest lower release. Also returns a message
+// explaining which version is used, unless there was an exact match.
 pub fn find_closest_version(
     available_version_strs: &[String],
     version_hint_str: &str,
 ) -> (String, Option<String>) {
-    let mut available_versions = available_version_strs
-        .iter()
-        .map(|s| (parse_bazel_version(s).unwrap().as_tuple(), s))
-        .collect::<Vec<_>>();
-    available_versions.sort();
+    let available_versions = sort_versions(available_version_strs);
+    // Without packaged flags, there is nothing to choose from
+    if available_versions.is_empty() {
+        return (version_hint_str.to_string(), None);
+    }
     let bazel_version = if let Some(version_hint) = parse_bazel_version(version_hint_str) {
-        let match_idx = available_versions.partition_point(|e| e.0 <= version_hint.as_tuple());
-        available_versions[match_idx.saturating_sub(1)].1.clone()
+        let hint = version_hint.as_tuple();
+        let match_idx = available_versions.partition_point(|e| e.0 <= hint);
+        let same_minor = |e: &&(BazelVersionTuple, &String)| e.0 .0 == hint.0 && e.0 .1 == hint.1;
+        let nearest_lower = match_idx.checked_sub(1).map(|i| &available_versions[i]);
+        nearest_lower
+            .filter(same_minor)
+            // e.g., 7.4.1 for 7.4.0, if no 7.4.0 or lower 7.4.x release is available
+            .or_else(|| available_versions[match_idx..].iter().find(same_minor))
+            .or(nearest_lower)
+            .unwrap_or(&available_versions[0])
+            .1
+            .clone()
     } else {
         available_versions.last().unwrap().1.clone()
     };
@@ -96,6 +120,84 @@ pub fn find_closest_version(
     }
 }
 
+// The Bazel versions in which a flag was added and removed. `added` is `None` if the flag
+// already exists in the oldest available version, `removed` is `None` if it still exists
+// in the newest available version.
+#[derive(Debug, PartialEq)]
+pub struct FlagLifecycle {
+    pub added: Option<String>,
+    pub removed: Option<String>,
+}
+
+pub fn get_flag_lifecycle(
+    available_version_strs: &[String],
+    flag_versions: &[String],
+) -> FlagLifecycle {
+    let available_versions = sort_versions(available_version_strs);
+    let first = available_versions
+        .iter()
+        .position(|(_, v)| flag_versions.contains(v));
+    let last = available_versions
+        .iter()
+        .rposition(|(_, v)| flag_versions.contains(v));
+    let (Some(first), Some(last)) = (first, last) else {
+        // E.g., flags loaded from a Bazel binary don't list their versions
+        return FlagLifecycle {
+            added: None,
+            removed: None,
+        };
+    };
+    FlagLifecycle {
+        added: (first > 0).then(|| available_versions[first].1.clone()),
+        removed: available_versions.get(last + 1).map(|(_, v)| (*v).clone()),
+    }
+}
+
+pub fn get_flag_lifecycle_markdown(lifecycle: &FlagLifecycle) -> Option<String> {
+    match (&lifecycle.added, &lifecycle.removed) {
+        (Some(added), Some(removed)) => Some(format!(
+            "Added in Bazel {added}, removed in Bazel {removed}."
+        )),
+        (Some(added), None) => Some(format!("Added in Bazel {added}.")),
+        (None, Some(removed)) => Some(format!("Removed in Bazel {removed}.")),
+        (None, None) => None,
+    }
+}
+
+// Explains why a flag known from other Bazel versions is not available in `bazel_version`
+pub fn get_flag_unavailable_message(
+    lifecycle: &FlagLifecycle,
+    invocation: &str,
+    bazel_version: &str,
+) -> String {
+    let current = parse_bazel_version(bazel_version).map(|v| v.as_tuple());
+    let is_before = |other: &String| {
+        current
+            .as_ref()
+            .zip(parse_bazel_version(other))
+            .is_some_and(|(current, other)| *current < other.as_tuple())
+    };
+    match (&lifecycle.added, &lifecycle.removed) {
+        (Some(added), _) if is_before(added) => format!(
+            "The flag {invocation:?} is not available in Bazel {bazel_version}. It was added in Bazel {added}."
+        ),
+        (_, Some(removed)) if !is_before(removed) => {
+            format!("The flag {invocation:?} was removed in Bazel {removed}.")
+        }
+        _ => format!("The flag {invocation:?} is not available in Bazel {bazel_version}."),
+    }
+}
+
+// The version of the documentation on bazel.build covering the given Bazel version.
+// The documentation is published per minor release. Pre-releases and forks have none.
+pub fn get_documentation_version(version: &str) -> Option<String> {
+    let version = parse_bazel_version(version)?;
+    if version.fork_owner.is_some() || version.pre_release.is_some() || version.minor == 99 {
+        return None;
+    }
+    Some(format!("{}.{}.0", version.major, version.minor))
+}
+
 // Use the Bazelisk logic to figure out the Bazel version
 // Ref: https://github.com/bazelbuild/bazelisk/blob/1f9a1aca958cdb50b4adb84b15cdda55a600ed31/README.md?plain=1#L45-L47
 pub fn determine_bazelisk_version(path: &Path) -> Option<String> {
@@ -104,11 +206,8 @@ pub fn determine_bazelisk_version(path: &Path) -> Option<String> {
     }
     let workspace_root = get_workspace_path(path)?;
     if let Ok(bazeliskrc) = fs::read_to_string(workspace_root.join(".bazeliskrc")) {
-        for line in bazeliskrc.split('\n') {
-            if line.starts_with("USE_BAZEL_VERSION=") {
-                let version_str = &line.split_once('=').unwrap().1;
-                return Some(version_str.trim().to_string());
-            }
+        if let Some(version) = get_bazeliskrc_version(&bazeliskrc) {
+            return Some(version);
         }
     }
     if let Ok(bazelversion) = fs::read_to_string(workspace_root.join(".bazelversion")) {
@@ -118,8 +217,11 @@ pub fn determine_bazelisk_version(path: &Path) -> Option<String> {
     None
 }
 
-pub static AVAILABLE_BAZEL_VERSIONS: Lazy<Vec<String>> =
-    Lazy::new(|| load_packaged_bazel_flag_collection().all_bazel_versions);
+pub static AVAILABLE_BAZEL_VERSIONS: Lazy<Vec<String>> = Lazy::new(|| {
+    load_packaged_bazel_flag_collection()
+        .map(|c| c.all_bazel_versions)
+        .unwrap_or_default()
+});
 
 #[test]
 fn test_parse_bazel_version() {
@@ -270,4 +372,88 @@ fn test_find_closest_version() {
         find_closest_version(&version_strings, "latest-1").0,
         "9.0.0-pre.20250121.1"
     );
+
+    // A release of the same major and minor version is preferred over an older minor version
+    let version_strings = ["7.3.1", "7.4.1", "8.0.0"].map(|s| s.to_string());
+    assert_eq!(find_closest_version(&version_strings, "7.4.0").0, "7.4.1");
+    assert_eq!(find_closest_version(&version_strings, "7.4.2").0, "7.4.1");
+    assert_eq!(find_closest_version(&version_strings, "7.5.0").0, "7.4.1");
+    assert_eq!(find_closest_version(&version_strings, "7.3.0").0, "7.3.1");
+    assert_eq!(find_closest_version(&version_strings, "7.0.0").0, "7.3.1");
+    assert_eq!(
+        find_closest_version(&version_strings, "7.4.2").1.unwrap(),
+        "Using flags from Bazel 7.4.1 because flags for version 7.4.2 are not available"
+    );
+    assert_eq!(find_closest_version(&version_strings, "8.0.0").1, None);
+
+    // The packaged flags cover the LTS releases since Bazel 5
+    assert_eq!(
+        find_closest_version(&AVAILABLE_BAZEL_VERSIONS, "5.4.1").0,
+        "5.4.1"
+    );
+    assert_eq!(
+        find_closest_version(&AVAILABLE_BAZEL_VERSIONS, "6.6.0").0,
+        "6.5.0"
+    );
+    assert_eq!(
+        find_closest_version(&AVAILABLE_BAZEL_VERSIONS, "6").0,
+        "6.0.0"
+    );
+    // Without packaged flags, the hint is kept
+    assert_eq!(
+        find_closest_version(&[], "latest"),
+        ("latest".to_string(), None)
+    );
+}
+
+#[test]
+fn test_flag_lifecycle() {
+    let available = ["6.5.0", "7.0.0", "7.4.1", "8.0.0"].map(|s| s.to_string());
+    let lifecycle = |versions: &[&str]| {
+        let versions = versions.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        get_flag_lifecycle(&available, &versions)
+    };
+    let always = lifecycle(&["6.5.0", "7.0.0", "7.4.1", "8.0.0"]);
+    assert_eq!(
+        always,
+        FlagLifecycle {
+            added: None,
+            removed: None
+        }
+    );
+    assert_eq!(get_flag_lifecycle_markdown(&always), None);
+    assert_eq!(lifecycle(&[]), always);
+
+    let removed = lifecycle(&["7.4.1", "6.5.0", "7.0.0"]);
+    assert_eq!(
+        get_flag_lifecycle_markdown(&removed).unwrap(),
+        "Removed in Bazel 8.0.0."
+    );
+    assert_eq!(
+        get_flag_unavailable_message(&removed, "--python3_path", "8.0.0"),
+        "The flag \"--python3_path\" was removed in Bazel 8.0.0."
+    );
+
+    let added = lifecycle(&["7.0.0", "7.4.1"]);
+    assert_eq!(
+        get_flag_lifecycle_markdown(&added).unwrap(),
+        "Added in Bazel 7.0.0, removed in Bazel 8.0.0."
+    );
+    assert_eq!(
+        get_flag_unavailable_message(&added, "--foo", "6.5.0"),
+        "The flag \"--foo\" is not available in Bazel 6.5.0. It was added in Bazel 7.0.0."
+    );
+    assert_eq!(
+        get_flag_unavailable_message(&added, "--foo", "8.0.0"),
+        "The flag \"--foo\" was removed in Bazel 8.0.0."
+    );
+}
+
+#[test]
+fn test_documentation_version() {
+    assert_eq!(get_documentation_version("7.4.1").as_deref(), Some("7.4.0"));
+    assert_eq!(get_documentation_version("8.0.0").as_deref(), Some("8.0.0"));
+    assert_eq!(get_documentation_version("8.*"), None);
+    assert_eq!(get_documentation_version("9.0.0-pre.20250317.2"), None);
+    assert_eq!(get_documentation_version("GitHubUser/7.4.1"), None);
 }
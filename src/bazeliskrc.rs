@@ -0,0 +1,472 @@
+use std::path::Path;
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity,
+    Documentation, MarkupContent, MarkupKind, TextEdit,
+};
+
+use crate::{
+    bazel_version::{find_closest_version, AVAILABLE_BAZEL_VERSIONS},
+    diagnostic::DiagnosticCheck,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    tokenizer::{Span, Spanned},
+};
+
+pub struct BazeliskVariable {
+    pub name: &'static str,
+    pub documentation: &'static str,
+}
+
+// The settings understood by Bazelisk.
+// See https://github.com/bazelbuild/bazelisk#how-does-bazelisk-know-which-bazel-version-to-run
+pub const BAZELISK_VARIABLES: &[BazeliskVariable] = &[
+    BazeliskVariable {
+        name: "USE_BAZEL_VERSION",
+        documentation: "The Bazel version to run, e.g. `7.4.1`, `7.x`, `latest`, `latest-1`, `last_green`, `last_rc`, `rolling` or `<fork>/<version>`. Takes precedence over the `.bazelversion` file.",
+    },
+    BazeliskVariable {
+        name: "USE_BAZEL_FALLBACK_VERSION",
+        documentation: "The Bazel version to run if no version is configured otherwise. Prefix it with `error:`, `warn:` or `silent:` to control what happens in that case.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_BASE_URL",
+        documentation: "Downloads Bazel from the given URL instead of GitHub, e.g. from a mirror.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_FORMAT_URL",
+        documentation: "The URL to download Bazel from, with placeholders for the version (`%v`), operating system (`%o`), architecture (`%m`), file extension (`%e`) and `-nojdk` suffix (`%h`).",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_GITHUB_TOKEN",
+        documentation: "The token used to authenticate requests to the GitHub API, e.g. to avoid rate limits.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_HOME",
+        documentation: "The directory in which Bazelisk caches the downloaded Bazel binaries.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_HOME_DARWIN",
+        documentation: "Overrides `BAZELISK_HOME` on macOS.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_HOME_LINUX",
+        documentation: "Overrides `BAZELISK_HOME` on Linux.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_HOME_WINDOWS",
+        documentation: "Overrides `BAZELISK_HOME` on Windows.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_NOJDK",
+        documentation: "Downloads the Bazel binary without a bundled JDK.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_SKIP_WRAPPER",
+        documentation: "Runs Bazel directly instead of the `tools/bazel` wrapper script of the workspace.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_SHOW_PROGRESS",
+        documentation: "Shows a progress bar while downloading Bazel.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_USER_AGENT",
+        documentation: "The user agent sent when downloading Bazel.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_VERIFY_SHA256",
+        documentation: "The expected SHA256 hash of the downloaded Bazel binary.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_INCOMPATIBLE_FLAGS",
+        documentation: "The comma-separated list of incompatible flags tested by `bazelisk --migrate`.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_CLEAN",
+        documentation: "Runs `bazel clean --expunge` between the builds of `bazelisk --migrate`.",
+    },
+    BazeliskVariable {
+        name: "BAZELISK_SHUTDOWN",
+        documentation: "Runs `bazel shutdown` between the builds of `bazelisk --migrate`.",
+    },
+];
+
+// The version aliases understood by Bazelisk, in addition to concrete versions
+const VERSION_ALIASES: [(&str, &str); 4] = [
+    ("latest", "The latest Bazel release"),
+    ("last_rc", "The latest release candidate"),
+    ("last_green", "The latest commit which passed Bazel's CI"),
+    ("rolling", "The latest rolling release"),
+];
+
+pub fn is_bazeliskrc_file(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name == ".bazeliskrc")
+}
+
+// A `KEY=value` line
+#[derive(Debug, PartialEq)]
+pub struct BazeliskrcEntry {
+    pub key: Spanned<String>,
+    // Without surrounding quotes
+    pub value: Spanned<String>,
+    pub span: Span,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedBazeliskrc {
+    pub entries: Vec<BazeliskrcEntry>,
+    // Lines which are neither empty, nor a comment, nor a `KEY=value` pair
+    pub invalid_lines: Vec<Span>,
+}
+
+impl ParsedBazeliskrc {
+    // The value Bazelisk uses for the given key. Later lines override earlier lines.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .rev()
+            .find(|e| e.key.0 == key)
+            .map(|e| e.value.0.as_str())
+    }
+
+    fn find_entry_at(&self, pos: usize) -> Option<&BazeliskrcEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.span.start <= pos && pos <= e.span.end)
+    }
+}
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+// Parses the `KEY=value` lines of a `.bazeliskrc`, like Bazelisk does using `godotenv`.
+// Spans are character offsets.
+pub fn parse_bazeliskrc(contents: &str) -> ParsedBazeliskrc {
+    let mut result = ParsedBazeliskrc::default();
+    let mut line_start = 0;
+    for raw_line in contents.split('\n') {
+        let line_len = char_len(raw_line);
+        let line = raw_line.trim_end();
+        let content = line.trim_start();
+        let content_start = line_start + char_len(line) - char_len(content);
+        let content_span = content_start..content_start + char_len(content);
+        line_start += line_len + 1;
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        let assignment = content.strip_prefix("export ").unwrap_or(content);
+        let assignment_start = content_span.end - char_len(assignment);
+        let Some((key, value)) = assignment.split_once('=') else {
+            result.invalid_lines.push(content_span);
+            continue;
+        };
+        let key_trimmed = key.trim_end();
+        if key_trimmed.is_empty() {
+            result.invalid_lines.push(content_span);
+            continue;
+        }
+        let value_trimmed = value.trim_start();
+        let value_start = content_span.end - char_len(value_trimmed);
+        let unquoted = ['"', '\'']
+            .into_iter()
+            .find_map(|q| {
+                value_trimmed
+                    .strip_prefix(q)
+                    .and_then(|v| v.strip_suffix(q))
+            })
+            .unwrap_or(value_trimmed);
+        result.entries.push(BazeliskrcEntry {
+            key: (
+                key_trimmed.to_string(),
+                assignment_start..assignment_start + char_len(key_trimmed),
+            ),
+            value: (
+                unquoted.to_string(),
+                value_start..value_start + char_len(value_trimmed),
+            ),
+            span: content_span,
+        });
+    }
+    result
+}
+
+// The `USE_BAZEL_VERSION` configured in a `.bazeliskrc`
+pub fn get_bazeliskrc_version(contents: &str) -> Option<String> {
+    parse_bazeliskrc(contents)
+        .get("USE_BAZEL_VERSION")
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn get_variable(name: &str) -> Option<&'static BazeliskVariable> {
+    BAZELISK_VARIABLES.iter().find(|v| v.name == name)
+}
+
+pub fn diagnostics_from_bazeliskrc(
+    rope: &Rope,
+    parsed: &ParsedBazeliskrc,
+    workspace: Option<&Path>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    let mut push = |span: &Span, severity: DiagnosticSeverity, message: String| {
+        diagnostics.push(Diagnostic {
+            range: range_to_lsp(rope, span, encoding).unwrap(),
+            severity: Some(severity),
+            message,
+            code: DiagnosticCheck::Bazeliskrc.code(),
+            ..Default::default()
+        })
+    };
+
+    for span in &parsed.invalid_lines {
+        push(
+            span,
+            DiagnosticSeverity::ERROR,
+            "Expected a `KEY=value` pair".to_string(),
+        );
+    }
+
+    for (i, entry) in parsed.entries.iter().enumerate() {
+        let (key, key_span) = &entry.key;
+        if get_variable(key).is_none() {
+            push(
+                key_span,
+                DiagnosticSeverity::WARNING,
+                format!("Unknown Bazelisk setting `{key}`"),
+            );
+        }
+        if let Some(later) = parsed.entries[i + 1..].iter().find(|e| e.key.0 == *key) {
+            let line = rope.char_to_line(later.span.start) + 1;
+            push(
+                key_span,
+                DiagnosticSeverity::WARNING,
+                format!("`{key}` is overridden by line {line}"),
+            );
+        }
+    }
+
+    // The Bazel version also determines which flags are used for the bazelrc files
+    if let Some(entry) = parsed
+        .entries
+        .iter()
+        .rev()
+        .find(|e| e.key.0 == "USE_BAZEL_VERSION")
+    {
+        let (version, span) = &entry.value;
+        let version = version.trim();
+        if let Ok(env_version) = std::env::var("USE_BAZEL_VERSION") {
+            push(
+                span,
+                DiagnosticSeverity::INFORMATION,
+                format!(
+                    "Overridden by the environment variable `USE_BAZEL_VERSION={}`",
+                    env_version.trim()
+                ),
+            );
+        } else if !version.is_empty() {
+            let (_, message) = find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), version);
+            if let Some(message) = message {
+                push(span, DiagnosticSeverity::INFORMATION, message);
+            }
+            let bazelversion = workspace
+                .and_then(|w| std::fs::read_to_string(w.join(".bazelversion")).ok())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty() && v != version);
+            if let Some(bazelversion) = bazelversion {
+                push(
+                    span,
+                    DiagnosticSeverity::INFORMATION,
+                    format!("Takes precedence over the `.bazelversion` ({bazelversion})"),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+pub fn get_bazeliskrc_hover_markdown(
+    parsed: &ParsedBazeliskrc,
+    pos: usize,
+) -> Option<(String, Span)> {
+    let entry = parsed.find_entry_at(pos)?;
+    if entry.value.1.contains(&pos) || entry.value.1.end == pos {
+        if entry.key.0 != "USE_BAZEL_VERSION" {
+            return None;
+        }
+        let (version, _) =
+            find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), entry.value.0.trim());
+        return Some((
+            format!("The bazelrc files of this workspace are analyzed using the flags of Bazel {version}."),
+            entry.value.1.clone(),
+        ));
+    }
+    let variable = get_variable(&entry.key.0)?;
+    Some((
+        format!("**{}**\n\n{}", variable.name, variable.documentation),
+        entry.key.1.clone(),
+    ))
+}
+
+fn markdown(value: &str) -> Documentation {
+    Documentation::MarkupContent(MarkupContent {
+        kind: MarkupKind::Markdown,
+        value: value.to_string(),
+    })
+}
+
+pub fn get_bazeliskrc_completion_items(
+    rope: &Rope,
+    parsed: &ParsedBazeliskrc,
+    pos: usize,
+    encoding: PositionEncoding,
+) -> Vec<CompletionItem> {
+    let line_start = rope.line_to_char(rope.char_to_line(pos.min(rope.len_chars())));
+    let before_cursor = rope.slice(line_start..pos).to_string();
+
+    // Complete the value
+    if let Some((key, _)) = before_cursor.split_once('=') {
+        let key = key.trim().strip_prefix("export ").unwrap_or(key.trim());
+        if key != "USE_BAZEL_VERSION" {
+            return Vec::new();
+        }
+        let range = match parsed.find_entry_at(pos) {
+            Some(entry) => entry.value.1.clone(),
+            None => pos..pos,
+        };
+        let range = range_to_lsp(rope, &range, encoding).unwrap();
+        let aliases = VERSION_ALIASES
+            .iter()
+            .map(|(alias, doc)| (*alias, Some(*doc)));
+        let versions = AVAILABLE_BAZEL_VERSIONS
+            .iter()
+            .rev()
+            .map(|v| (v.as_str(), None));
+        return aliases
+            .chain(versions)
+            .enumerate()
+            .map(|(i, (version, doc))| CompletionItem {
+                label: version.to_string(),
+                kind: Some(CompletionItemKind::ENUM_MEMBER),
+                detail: doc.map(str::to_string),
+                // Newest versions first
+                sort_text: Some(format!("{:04}", i)),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: version.to_string(),
+                })),
+                ..Default::default()
+            })
+            .collect();
+    }
+
+    // Complete the key, replacing the word at the cursor
+    let word_len = char_len(
+        before_cursor
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or(""),
+    );
+    let range = range_to_lsp(rope, &(pos - word_len..pos), encoding).unwrap();
+    BAZELISK_VARIABLES
+        .iter()
+        .map(|variable| CompletionItem {
+            label: variable.name.to_string(),
+            kind: Some(CompletionItemKind::PROPERTY),
+            documentation: Some(markdown(variable.documentation)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: format!("{}=", variable.name),
+            })),
+            ..Default::default()
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_bazeliskrc() {
+    let parsed = parse_bazeliskrc(
+        "# comment\nUSE_BAZEL_VERSION=7.0.0\n  export BAZELISK_BASE_URL = \"https://mirror\" \ninvalid\n",
+    );
+    assert_eq!(
+        parsed.entries,
+        vec![
+            BazeliskrcEntry {
+                key: ("USE_BAZEL_VERSION".to_string(), 10..27),
+                value: ("7.0.0".to_string(), 28..33),
+                span: 10..33,
+            },
+            BazeliskrcEntry {
+                key: ("BAZELISK_BASE_URL".to_string(), 43..60),
+                value: ("https://mirror".to_string(), 63..79),
+                span: 36..79,
+            },
+        ]
+    );
+    assert_eq!(parsed.invalid_lines, vec![81..88]);
+
+    // Later lines take precedence
+    assert_eq!(
+        get_bazeliskrc_version("USE_BAZEL_VERSION=6.5.0\nUSE_BAZEL_VERSION=7.0.0"),
+        Some("7.0.0".to_string())
+    );
+    assert_eq!(get_bazeliskrc_version("USE_BAZEL_VERSION="), None);
+}
+
+#[test]
+fn test_bazeliskrc_diagnostics() {
+    // `USE_BAZEL_VERSION` would take precedence over the `.bazeliskrc`
+    if std::env::var("USE_BAZEL_VERSION").is_ok() {
+        return;
+    }
+    let contents = "USE_BAZEL_VERSION=7.0.0\nUSE_BAZEL_VERSION=8.0.99\nBAZELISK_BASEURL=x\nfoo\n";
+    let rope = Rope::from_str(contents);
+    let diagnostics = diagnostics_from_bazeliskrc(
+        &rope,
+        &parse_bazeliskrc(contents),
+        None,
+        PositionEncoding::Utf16,
+    )
+    .into_iter()
+    .map(|d| (d.range.start.line, d.message))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        diagnostics,
+        vec![
+            (3, "Expected a `KEY=value` pair".to_string()),
+            (0, "`USE_BAZEL_VERSION` is overridden by line 2".to_string()),
+            (2, "Unknown Bazelisk setting `BAZELISK_BASEURL`".to_string()),
+            (
+                1,
+                format!(
+                    "Using flags from Bazel {} because flags for version 8.0.99 are not available",
+                    find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), "8.0.99").0
+                )
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_bazeliskrc_completion_and_hover() {
+    let contents = "USE_BAZEL_VERSION=7.0.0\nBAZ";
+    let rope = Rope::from_str(contents);
+    let parsed = parse_bazeliskrc(contents);
+
+    let items =
+        get_bazeliskrc_completion_items(&rope, &parsed, contents.len(), PositionEncoding::Utf16);
+    assert!(items.iter().any(|i| i.label == "BAZELISK_BASE_URL"));
+    let items = get_bazeliskrc_completion_items(&rope, &parsed, 20, PositionEncoding::Utf16);
+    assert_eq!(items[0].label, "latest");
+    assert!(items.iter().any(|i| i.label == "7.0.0"));
+
+    let (hover, span) = get_bazeliskrc_hover_markdown(&parsed, 3).unwrap();
+    assert!(hover.starts_with("**USE_BAZEL_VERSION**"));
+    assert_eq!(span, 0..17);
+    let (hover, _) = get_bazeliskrc_hover_markdown(&parsed, 20).unwrap();
+    assert_eq!(
+        hover,
+        "The bazelrc files of this workspace are analyzed using the flags of Bazel 7.0.0."
+    );
+}
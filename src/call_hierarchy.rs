@@ -0,0 +1,314 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    CallHierarchyIncomingCall, CallHierarchyItem, CallHierarchyOutgoingCall, Range, SymbolKind, Url,
+};
+
+use crate::{
+    bazel_flags::BazelFlags,
+    import_graph::RcFile,
+    line_index::{IndexEntryKind, IndexedLines},
+    lsp_utils::{range_to_lsp, trim_span_end, PositionEncoding},
+    parser::Line,
+    references::{get_reference_target, is_config_flag, skip_separator, ReferenceTarget},
+    tokenizer::Span,
+};
+
+// A node of the config expansion tree. Stored as the `data` of the call hierarchy
+// items, such that incoming and outgoing calls can be resolved without re-parsing
+// the item's name.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum CallHierarchyNode {
+    // All lines of a config, e.g. `build:ci` and `test:ci`
+    Config(String),
+    // The lines of a command without config, which apply to every invocation of it
+    Command(String),
+}
+
+impl CallHierarchyNode {
+    fn contains(&self, line: &Line) -> bool {
+        match self {
+            CallHierarchyNode::Config(name) => line.config.as_ref().is_some_and(|c| &c.0 == name),
+            CallHierarchyNode::Command(name) => {
+                line.config.is_none() && line.command.as_ref().is_some_and(|c| &c.0 == name)
+            }
+        }
+    }
+
+    // The node a line belongs to
+    fn of_line(line: &Line) -> Option<CallHierarchyNode> {
+        match (&line.command, &line.config) {
+            (_, Some(config)) => Some(CallHierarchyNode::Config(config.0.clone())),
+            (Some(command), None) if command.0 != "import" && command.0 != "try-import" => {
+                Some(CallHierarchyNode::Command(command.0.clone()))
+            }
+            _ => None,
+        }
+    }
+}
+
+// The configs referenced by `--config` flags on the given line, with the spans of their names
+fn get_config_references<'a>(
+    bazel_flags: &'a BazelFlags,
+    file: &'a RcFile,
+    line: &'a Line,
+) -> impl Iterator<Item = (&'a str, Span)> + 'a {
+    line.flags
+        .iter()
+        .filter(|flag| is_config_flag(bazel_flags, flag))
+        .filter_map(|flag| {
+            let (value, span) = flag.value.as_ref()?;
+            Some((value.as_str(), skip_separator(&file.rope, span)))
+        })
+}
+
+// The item for the given node, pointing to its first line within the given files.
+// `None` if the node has no lines, e.g. for references to undefined configs.
+fn make_item(
+    files: &[RcFile],
+    node: &CallHierarchyNode,
+    encoding: PositionEncoding,
+) -> Option<CallHierarchyItem> {
+    let (file, line) = files
+        .iter()
+        .find_map(|f| Some((f, f.lines.iter().find(|l| node.contains(l))?)))?;
+    let (name, kind, selection_span) = match node {
+        CallHierarchyNode::Config(name) => (
+            name.clone(),
+            SymbolKind::NAMESPACE,
+            skip_separator(&file.rope, &line.config.as_ref()?.1),
+        ),
+        CallHierarchyNode::Command(name) => (
+            name.clone(),
+            SymbolKind::MODULE,
+            line.command.as_ref()?.1.clone(),
+        ),
+    };
+    Some(CallHierarchyItem {
+        name,
+        kind,
+        tags: None,
+        detail: file
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned()),
+        uri: Url::from_file_path(&file.path).ok()?,
+        range: range_to_lsp(&file.rope, &trim_span_end(&file.rope, &line.span), encoding)?,
+        selection_range: range_to_lsp(&file.rope, &selection_span, encoding)?,
+        data: serde_json::to_value(node).ok(),
+    })
+}
+
+// Appends the span to the group of the given node, keeping the groups in order of
+// their first occurrence
+fn add_to_group<'a>(
+    groups: &mut Vec<(CallHierarchyNode, Vec<(&'a RcFile, Span)>)>,
+    node: CallHierarchyNode,
+    file: &'a RcFile,
+    span: Span,
+) {
+    match groups.iter_mut().find(|(n, _)| *n == node) {
+        Some((_, spans)) => spans.push((file, span)),
+        None => groups.push((node, vec![(file, span)])),
+    }
+}
+
+// The ranges within the item's file. The LSP only allows ranges relative to the caller.
+fn get_ranges_in_item(
+    item: &CallHierarchyItem,
+    spans: &[(&RcFile, Span)],
+    encoding: PositionEncoding,
+) -> Vec<Range> {
+    spans
+        .iter()
+        .filter(|(file, _)| Url::from_file_path(&file.path).is_ok_and(|uri| uri == item.uri))
+        .filter_map(|(file, span)| range_to_lsp(&file.rope, span, encoding))
+        .collect::<Vec<_>>()
+}
+
+// The node at the cursor: a config definition, a `--config` value or the command of
+// a line without config. `files` is used to locate the node's first line.
+pub fn prepare_call_hierarchy(
+    bazel_flags: &BazelFlags,
+    index: &IndexedLines,
+    pos: usize,
+    files: &[RcFile],
+    encoding: PositionEncoding,
+) -> Option<CallHierarchyItem> {
+    let entry = index.find_symbol_at_position(pos)?;
+    let line = &index.lines[entry.line_nr];
+    let node = match entry.kind {
+        IndexEntryKind::Command if line.config.is_none() => CallHierarchyNode::of_line(line)?,
+        _ => match get_reference_target(bazel_flags, line, &entry.kind)? {
+            ReferenceTarget::Config(config) => CallHierarchyNode::Config(config),
+            ReferenceTarget::Flag(_) => return None,
+        },
+    };
+    make_item(files, &node, encoding)
+}
+
+// The lines referencing the config via `--config`, grouped by the config or command
+// they belong to
+pub fn get_incoming_calls(
+    bazel_flags: &BazelFlags,
+    files: &[RcFile],
+    node: &CallHierarchyNode,
+    encoding: PositionEncoding,
+) -> Vec<CallHierarchyIncomingCall> {
+    let CallHierarchyNode::Config(config) = node else {
+        return Vec::new();
+    };
+    let mut groups = Vec::new();
+    for file in files {
+        for line in &file.lines {
+            let Some(caller) = CallHierarchyNode::of_line(line) else {
+                continue;
+            };
+            for (_, span) in
+                get_config_references(bazel_flags, file, line).filter(|r| r.0 == config)
+            {
+                add_to_group(&mut groups, caller.clone(), file, span);
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .filter_map(|(caller, spans)| {
+            let from = make_item(files, &caller, encoding)?;
+            let from_ranges = get_ranges_in_item(&from, &spans, encoding);
+            Some(CallHierarchyIncomingCall { from, from_ranges })
+        })
+        .collect::<Vec<_>>()
+}
+
+// The configs referenced via `--config` by the lines of the given node. References
+// to undefined configs are skipped, since there is nothing to navigate to.
+pub fn get_outgoing_calls(
+    bazel_flags: &BazelFlags,
+    files: &[RcFile],
+    node: &CallHierarchyNode,
+    encoding: PositionEncoding,
+) -> Vec<CallHierarchyOutgoingCall> {
+    let Some(item) = make_item(files, node, encoding) else {
+        return Vec::new();
+    };
+    let mut groups = Vec::new();
+    for file in files {
+        for line in file.lines.iter().filter(|l| node.contains(l)) {
+            for (config, span) in get_config_references(bazel_flags, file, line) {
+                add_to_group(
+                    &mut groups,
+                    CallHierarchyNode::Config(config.to_string()),
+                    file,
+                    span,
+                );
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .filter_map(|(callee, spans)| {
+            Some(CallHierarchyOutgoingCall {
+                to: make_item(files, &callee, encoding)?,
+                from_ranges: get_ranges_in_item(&item, &spans, encoding),
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_call_hierarchy() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, parser::parse_from_str};
+    use std::path::PathBuf;
+    use tower_lsp::lsp_types::Position;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let text =
+        "build:ci --config=remote -k\ntest:ci --config=remote --config=missing\ntest --config=ci\n";
+    let files = [
+        RcFile::from_str(PathBuf::from("/ws/.bazelrc"), text, &flags),
+        RcFile::from_str(
+            PathBuf::from("/ws/remote.bazelrc"),
+            "build:remote --jobs=100\nbuild:release --config=ci\n",
+            &flags,
+        ),
+    ];
+    let index = IndexedLines::from_lines(parse_from_str(text).lines);
+
+    // Prepared on the `--config` value, pointing to the definition in the other file
+    let item = prepare_call_hierarchy(&flags, &index, 19, &files, PositionEncoding::Utf16).unwrap();
+    assert_eq!(item.name, "remote");
+    assert_eq!(item.uri.path(), "/ws/remote.bazelrc");
+    assert_eq!(
+        item.selection_range,
+        Range::new(Position::new(0, 6), Position::new(0, 12))
+    );
+    let remote = CallHierarchyNode::Config("remote".to_string());
+    assert_eq!(item.data, serde_json::to_value(&remote).ok());
+    // Prepared on a config definition or a command without config
+    let item = prepare_call_hierarchy(&flags, &index, 6, &files, PositionEncoding::Utf16).unwrap();
+    assert_eq!(
+        (item.name.as_str(), item.kind),
+        ("ci", SymbolKind::NAMESPACE)
+    );
+    let item = prepare_call_hierarchy(&flags, &index, 69, &files, PositionEncoding::Utf16).unwrap();
+    assert_eq!(
+        (item.name.as_str(), item.kind),
+        ("test", SymbolKind::MODULE)
+    );
+    assert_eq!(
+        prepare_call_hierarchy(&flags, &index, 26, &files, PositionEncoding::Utf16),
+        None
+    );
+
+    // Incoming calls are grouped by the referencing config or command
+    let ci = CallHierarchyNode::Config("ci".to_string());
+    let incoming = get_incoming_calls(&flags, &files, &ci, PositionEncoding::Utf16)
+        .into_iter()
+        .map(|c| (c.from.name, c.from.uri.path().to_string(), c.from_ranges))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        incoming,
+        vec![
+            (
+                "test".to_string(),
+                "/ws/.bazelrc".to_string(),
+                vec![Range::new(Position::new(2, 14), Position::new(2, 16))]
+            ),
+            (
+                "release".to_string(),
+                "/ws/remote.bazelrc".to_string(),
+                vec![Range::new(Position::new(1, 23), Position::new(1, 25))]
+            ),
+        ]
+    );
+    let incoming = get_incoming_calls(&flags, &files, &remote, PositionEncoding::Utf16);
+    assert_eq!(incoming.len(), 1);
+    assert_eq!(incoming[0].from.name, "ci");
+    assert_eq!(incoming[0].from_ranges.len(), 2);
+
+    // Outgoing calls skip undefined configs and lead to the next level of the tree
+    let outgoing = get_outgoing_calls(&flags, &files, &ci, PositionEncoding::Utf16);
+    assert_eq!(outgoing.len(), 1);
+    assert_eq!(outgoing[0].to.name, "remote");
+    assert_eq!(
+        outgoing[0].from_ranges,
+        vec![
+            Range::new(Position::new(0, 18), Position::new(0, 24)),
+            Range::new(Position::new(1, 17), Position::new(1, 23)),
+        ]
+    );
+    assert_eq!(
+        get_outgoing_calls(&flags, &files, &remote, PositionEncoding::Utf16),
+        vec![]
+    );
+    assert_eq!(
+        get_outgoing_calls(
+            &flags,
+            &files,
+            &CallHierarchyNode::Config("x".to_string()),
+            PositionEncoding::Utf16
+        ),
+        vec![]
+    );
+}
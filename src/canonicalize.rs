@@ -0,0 +1,266 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Range, TextEdit, Url};
+
+use crate::{
+    bazel_flags::{BazelFlags, FlagLookupType},
+    code_action::get_flag_span,
+    config_expansion::canonicalize_invocation,
+    formatting::format_token,
+    index_cache::hash_value,
+    live_flags::get_cache_dir,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::{Flag, Line},
+};
+
+pub const CANONICALIZE_FLAGS_COMMAND: &str = "bazelrc.canonicalizeFlags";
+
+// Arguments of the `bazelrc.canonicalizeFlags` command
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalizeFlagsParams {
+    pub uri: Url,
+    // The lines overlapping this range are canonicalized
+    pub range: Range,
+}
+
+// Can the line's flags be passed to `bazel canonicalize-flags --for_command=...`?
+// Startup options and the pseudo-commands `common` and `always` are not accepted.
+pub fn supports_bazel_canonicalization(line: &Line) -> bool {
+    line.command
+        .as_ref()
+        .is_some_and(|c| !["startup", "common", "always"].contains(&c.0.as_str()))
+}
+
+// The flag as written, in the formatter's style. Used for flags unknown to the flag database.
+fn format_flag(flag: &Flag) -> String {
+    match (&flag.name, &flag.value) {
+        (Some(name), Some(value)) => {
+            format!("{}={}", format_token(&name.0), format_token(&value.0))
+        }
+        (Some(name), None) => format_token(&name.0),
+        (None, Some(value)) => format_token(&value.0),
+        (None, None) => String::new(),
+    }
+}
+
+// The command line arguments passing the flag to Bazel. Abbreviations (e.g. `-c opt`)
+// take their value as a separate argument.
+pub fn get_flag_args(flag: &Flag) -> Vec<String> {
+    match (&flag.name, &flag.value) {
+        (Some(name), Some(value)) if name.0.starts_with("--") => {
+            vec![format!("{}={}", name.0, value.0)]
+        }
+        (name, value) => name
+            .iter()
+            .chain(value.iter())
+            .map(|t| t.0.clone())
+            .collect::<Vec<_>>(),
+    }
+}
+
+// Canonicalizes the flags of a line using the flag database, mirroring
+// `bazel canonicalize-flags`: abbreviations and old names are replaced by the full
+// name, values are attached via `=` and booleans are written as `--name` / `--noname`.
+// Like Bazel, only the last assignment of flags which can't be repeated is kept, e.g.
+// `--nokeep_going --keep_going` becomes `--keep_going`. Unknown flags are kept as they are.
+pub fn canonicalize_line_flags(bazel_flags: &BazelFlags, line: &Line) -> Vec<String> {
+    // The canonical flags, together with the name of flags which can't be repeated
+    let mut result = Vec::<(Option<&str>, String)>::new();
+    let mut flags = line.flags.iter().peekable();
+    while let Some(flag) = flags.next() {
+        let Some((name, _)) = &flag.name else {
+            result.push((None, format_flag(flag)));
+            continue;
+        };
+        let mut value = flag.value.as_ref().map(|v| v.0.as_str());
+        // `combine_key_value_flags` keeps the value of abbreviations (`-c opt`) separate
+        if let Some((FlagLookupType::Abbreviation, info)) = bazel_flags.get_by_invocation(name) {
+            if value.is_none() && info.requires_value() {
+                if let Some(next) = flags.next_if(|f| f.name.is_none()) {
+                    value = next.value.as_ref().map(|v| v.0.as_str());
+                }
+            }
+        }
+        let single_name = bazel_flags
+            .get_by_invocation(name)
+            .filter(|(_, info)| !info.allows_multiple())
+            .map(|(_, info)| info.name.as_str());
+        if single_name.is_some() {
+            result.retain(|(n, _)| *n != single_name);
+        }
+        result.push((
+            single_name,
+            canonicalize_invocation(bazel_flags, name, value).unwrap_or_else(|| format_flag(flag)),
+        ));
+    }
+    result.into_iter().map(|(_, flag)| flag).collect::<Vec<_>>()
+}
+
+// Extracts the flags from the output of `bazel canonicalize-flags`, one flag per line.
+// Values are quoted the same way as by `canonicalize_line_flags`.
+fn parse_canonicalize_output(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| match l.split_once('=') {
+            Some((name, value)) => format!("{}={}", format_token(name), format_token(value)),
+            None => format_token(l),
+        })
+        .collect::<Vec<_>>()
+}
+
+// The output base of the Bazel server canonicalizing the flags of the workspace.
+// `--ignore_all_rc_files` is a startup option, hence a server of its own is needed.
+// Sharing the user's output base would restart their server and drop its analysis cache.
+fn get_output_base(workspace: &Path) -> Option<PathBuf> {
+    let cache_dir = get_cache_dir()?;
+    Some(cache_dir.join(format!("output_base-{:016x}", hash_value(workspace))))
+}
+
+// Runs `bazel canonicalize-flags` within the workspace. All rc files are ignored,
+// such that only the given flags are canonicalized.
+#[tracing::instrument(level = "debug", skip(workspace))]
+fn canonicalize_with_bazel(
+    bazel_command: &str,
+    workspace: &Path,
+    output_base: &Path,
+    command: &str,
+    flags: &[String],
+) -> Result<Vec<String>, String> {
+    let result = Command::new(bazel_command)
+        .current_dir(workspace)
+        .arg(format!("--output_base={}", output_base.display()))
+        .args(["--ignore_all_rc_files", "canonicalize-flags"])
+        .arg(format!("--for_command={command}"))
+        .arg("--")
+        .args(flags)
+        .output()
+        .map_err(|err| err.to_string())?;
+    if !result.status.success() {
+        return Err(format!(
+            "`{bazel_command} canonicalize-flags` failed:\n{}",
+            String::from_utf8_lossy(&result.stderr)
+        ));
+    }
+    Ok(parse_canonicalize_output(&String::from_utf8_lossy(
+        &result.stdout,
+    )))
+}
+
+// Canonicalizes the flags of multiple lines, given as pairs of command and flag arguments,
+// via `bazel canonicalize-flags`. Each line needs an invocation of its own, since Bazel
+// would merge the flags of all lines passed at once. Lines repeating the command and flags
+// of an earlier line are not passed to Bazel again. Stops at the first failure.
+pub fn canonicalize_lines_with_bazel(
+    bazel_command: &str,
+    workspace: &Path,
+    lines: &[(String, Vec<String>)],
+) -> Result<Vec<Vec<String>>, String> {
+    let output_base =
+        get_output_base(workspace).ok_or("No cache directory for Bazel's output base")?;
+    let mut results = HashMap::<&(String, Vec<String>), Vec<String>>::new();
+    for line in lines {
+        if !results.contains_key(line) {
+            let (command, flags) = line;
+            let canonical =
+                canonicalize_with_bazel(bazel_command, workspace, &output_base, command, flags)?;
+            results.insert(line, canonical);
+        }
+    }
+    Ok(lines
+        .iter()
+        .map(|line| results[line].clone())
+        .collect::<Vec<_>>())
+}
+
+// The edit replacing the flags of the line by their canonical form. The command,
+// config and trailing comment are kept. `None` if nothing changes.
+pub fn get_canonicalize_edit(
+    rope: &Rope,
+    line: &Line,
+    canonical: &[String],
+    encoding: PositionEncoding,
+) -> Option<TextEdit> {
+    let start = get_flag_span(line.flags.first()?)?.start;
+    let end = get_flag_span(line.flags.last()?)?.end;
+    let new_text = canonical.join(" ");
+    if rope.get_slice(start..end)? == new_text.as_str() {
+        return None;
+    }
+    Some(TextEdit {
+        range: range_to_lsp(rope, &(start..end), encoding)?,
+        new_text,
+    })
+}
+
+#[test]
+fn test_canonicalize_line_flags() {
+    use crate::{
+        bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags},
+        code_action::apply_text_edits,
+        parser::parse_from_str,
+    };
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let canonicalize = |text: &str| {
+        let rope = Rope::from_str(text);
+        let mut lines = parse_from_str(text).lines;
+        combine_key_value_flags(&mut lines, &bazel_flags);
+        let edits = lines
+            .iter()
+            .filter_map(|line| {
+                let canonical = canonicalize_line_flags(&bazel_flags, line);
+                get_canonicalize_edit(&rope, line, &canonical, PositionEncoding::Utf16)
+            })
+            .collect::<Vec<_>>();
+        apply_text_edits(text, &edits, PositionEncoding::Utf16)
+    };
+
+    assert_eq!(
+        canonicalize("build:ci -c opt --keep_going=false -k --jobs 4 # fast\n"),
+        "build:ci --compilation_mode=opt --keep_going --jobs=4 # fast\n"
+    );
+    // Unknown flags and Starlark flags are kept, values are quoted where necessary
+    assert_eq!(
+        canonicalize("build --unknown_flag=1 --//my:setting \"--copt=-DA B\" --color yes\n"),
+        "build --unknown_flag=1 --//my:setting --copt=\"-DA B\" --color=yes\n"
+    );
+    let canonical = "build --compilation_mode=opt\nimport %workspace%/ci.bazelrc\n";
+    assert_eq!(canonicalize(canonical), canonical);
+}
+
+#[test]
+fn test_get_flag_args() {
+    use crate::{
+        bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags},
+        parser::parse_from_str,
+    };
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let mut lines = parse_from_str("build -c opt --jobs 4 \"--copt=-DA B\" -k").lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    assert_eq!(
+        lines[0]
+            .flags
+            .iter()
+            .flat_map(get_flag_args)
+            .collect::<Vec<_>>(),
+        vec!["-c", "opt", "--jobs=4", "--copt=-DA B", "-k"]
+    );
+}
+
+#[test]
+fn test_parse_canonicalize_output() {
+    assert_eq!(
+        parse_canonicalize_output("--jobs=4\n--copt=-DA B\n\n--nokeep_going\n"),
+        vec!["--jobs=4", "--copt=\"-DA B\"", "--nokeep_going"]
+    );
+}
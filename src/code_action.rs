@@ -0,0 +1,1310 @@
+use std::collections::HashMap;
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Range, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    bazel_flags::{get_minimal_commands, BazelFlags, FlagLookupType},
+    diagnostic::{find_overridden_flags, get_assigned_value},
+    flag_value_style::flag_value_style_actions,
+    flag_values::{get_allowed_values, get_flag_value, is_allowed_value, suggest_values},
+    formatting::{format_line, format_token},
+    line_continuations::line_continuation_action,
+    line_index::IndexedLines,
+    lsp_utils::{lsp_pos_to_offset, range_to_lsp, PositionEncoding},
+    organize::organize_lines_action,
+    parser::{Flag, Line},
+    tokenizer::Span,
+};
+
+// Commands for which it makes sense to consolidate flags into a `common` line
+fn is_regular_command(command: &str) -> bool {
+    !["common", "always", "startup", "import", "try-import"].contains(&command)
+}
+
+// The span covering both the flag name and its value
+pub fn get_flag_span(flag: &Flag) -> Option<Span> {
+    let start = flag.name.as_ref().or(flag.value.as_ref())?.1.start;
+    let end = flag.value.as_ref().or(flag.name.as_ref())?.1.end;
+    Some(start..end)
+}
+
+// Formats a flag, as it would be written by the formatter
+pub fn format_flag(flag: &Flag) -> String {
+    let mut out = String::new();
+    if let Some(name) = &flag.name {
+        out.push_str(&format_token(&name.0));
+        if let Some(value) = &flag.value {
+            out.push('=');
+            if !value.0.is_empty() {
+                out.push_str(&format_token(&value.0));
+            }
+        }
+    } else if let Some(value) = &flag.value {
+        out.push_str(&format_token(&value.0));
+    }
+    out
+}
+
+// The span which has to be removed to delete the given flag from its line.
+// Also removes the whitespace separating the flag from its predecessor.
+pub fn get_flag_removal_span(line: &Line, flag_nr: usize) -> Option<Span> {
+    let flag_span = get_flag_span(line.flags.get(flag_nr)?)?;
+    let prev_end = if flag_nr > 0 {
+        get_flag_span(&line.flags[flag_nr - 1])?.end
+    } else {
+        line.config
+            .as_ref()
+            .or(line.command.as_ref())
+            .map(|c| c.1.end)
+            .unwrap_or(flag_span.start)
+    };
+    Some(prev_end..flag_span.end)
+}
+
+// Edit to remove the given flags from a line. Removes the complete line
+// if no flags and no comment would remain.
+fn remove_flags_from_line(
+    rope: &Rope,
+    line: &Line,
+    flag_nrs: &[usize],
+    encoding: PositionEncoding,
+) -> Vec<TextEdit> {
+    if flag_nrs.len() == line.flags.len() && line.comment.is_none() {
+        return remove_line(rope, line, encoding);
+    }
+    flag_nrs
+        .iter()
+        .filter_map(|flag_nr| {
+            Some(TextEdit {
+                range: range_to_lsp(rope, &get_flag_removal_span(line, *flag_nr)?, encoding)?,
+                new_text: String::new(),
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Edit to remove the complete line, including its comment and the newline
+fn remove_line(rope: &Rope, line: &Line, encoding: PositionEncoding) -> Vec<TextEdit> {
+    range_to_lsp(rope, &line.span, encoding)
+        .map(|range| {
+            vec![TextEdit {
+                range,
+                new_text: String::new(),
+            }]
+        })
+        .unwrap_or_default()
+}
+
+// Edits to remove the given flags from a line and to add `new_lines` in front of it
+fn replace_flags_with_lines(
+    rope: &Rope,
+    line: &Line,
+    flag_nrs: &[usize],
+    new_lines: String,
+    encoding: PositionEncoding,
+) -> Option<Vec<TextEdit>> {
+    let mut edits = remove_flags_from_line(rope, line, flag_nrs, encoding);
+    let replaces_line =
+        edits.len() == 1 && range_to_lsp(rope, &line.span, encoding) == Some(edits[0].range);
+    if replaces_line {
+        edits[0].new_text = new_lines;
+    } else {
+        let start = line.span.start;
+        edits.insert(
+            0,
+            TextEdit {
+                range: range_to_lsp(rope, &(start..start), encoding)?,
+                new_text: new_lines,
+            },
+        );
+    }
+    Some(edits)
+}
+
+fn same_flag(a: &Flag, b: &Flag) -> bool {
+    a.name.as_ref().map(|n| &n.0) == b.name.as_ref().map(|n| &n.0)
+        && a.value.as_ref().map(|v| &v.0) == b.value.as_ref().map(|v| &v.0)
+}
+
+pub fn make_workspace_edit(uri: &Url, edits: Vec<TextEdit>) -> WorkspaceEdit {
+    WorkspaceEdit {
+        changes: Some(HashMap::from([(uri.clone(), edits)])),
+        ..Default::default()
+    }
+}
+
+// Offers to consolidate a flag used for multiple commands into a single `common` line.
+// Since `common` lines are applied before all other lines, this is only offered if no other
+// line of the config assigns a different value, which could then take precedence.
+#[allow(clippy::too_many_arguments)]
+fn move_to_common_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    index: &IndexedLines,
+    uri: &Url,
+    line_nr: usize,
+    flag_nr: usize,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let line = &index.lines[line_nr];
+    let flag = &line.flags[flag_nr];
+    let command = &line.command.as_ref()?.0;
+    if !is_regular_command(command) {
+        return None;
+    }
+    let (_, flag_info) = bazel_flags.get_by_invocation(&flag.name.as_ref()?.0)?;
+    let (_, value, _) = get_assigned_value(line, flag_nr, bazel_flags)?;
+    let config = line.config.as_ref().map(|c| &c.0);
+
+    // Find all occurrences of the same flag with the same value for other commands
+    let mut occurrences = Vec::<(usize, usize)>::new();
+    let mut commands = Vec::<&str>::new();
+    for (other_line_nr, other_line) in index.lines.iter().enumerate() {
+        let Some(other_command) = &other_line.command else {
+            continue;
+        };
+        if other_line.config.as_ref().map(|c| &c.0) != config {
+            continue;
+        }
+        for (other_flag_nr, other_flag) in other_line.flags.iter().enumerate() {
+            // Bazel would apply the `common` line first, such that a different value
+            // assigned for an affected command, or for a command newly reached by the
+            // `common` line, might take precedence
+            let conflicts = get_assigned_value(other_line, other_flag_nr, bazel_flags).is_some_and(
+                |(info, other_value, _)| info.name == flag_info.name && other_value != value,
+            );
+            if conflicts {
+                return None;
+            }
+            if is_regular_command(&other_command.0) && same_flag(flag, other_flag) {
+                occurrences.push((other_line_nr, other_flag_nr));
+                if !commands.contains(&other_command.0.as_str()) {
+                    commands.push(&other_command.0);
+                }
+            }
+        }
+    }
+    // Occurrences for commands inheriting from another one are redundant already
+    if get_minimal_commands(&commands).len() < 2
+        || !commands.iter().all(|c| flag_info.supports_command(c))
+    {
+        return None;
+    }
+
+    // Remove all occurrences and add a `common` line in place of the first one
+    let compute_edits = || {
+        let common_line = format_line(
+            &Line {
+                command: Some(("common".to_string(), 0..0)),
+                config: line.config.clone(),
+                flags: vec![flag.clone()],
+                ..Default::default()
+            },
+            false,
+        );
+        let mut edits = Vec::<TextEdit>::new();
+        let first_line_nr = occurrences[0].0;
+        let mut i = 0;
+        while i < occurrences.len() {
+            let occ_line_nr = occurrences[i].0;
+            let mut flag_nrs = Vec::<usize>::new();
+            while i < occurrences.len() && occurrences[i].0 == occ_line_nr {
+                flag_nrs.push(occurrences[i].1);
+                i += 1;
+            }
+            let occ_line = &index.lines[occ_line_nr];
+            if occ_line_nr == first_line_nr {
+                edits.extend(replace_flags_with_lines(
+                    rope,
+                    occ_line,
+                    &flag_nrs,
+                    common_line.clone(),
+                    encoding,
+                )?);
+            } else {
+                edits.extend(remove_flags_from_line(rope, occ_line, &flag_nrs, encoding));
+            }
+        }
+        Some(edits)
+    };
+    let edit = if resolve_edits {
+        Some(make_workspace_edit(uri, compute_edits()?))
+    } else {
+        None
+    };
+
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!(
+            "Move `{}` to a `common` line (used by {})",
+            format_flag(flag),
+            commands.join(", ")
+        ),
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// Offers to move a flag from a `common` / `always` line to the commands which actually support it
+#[allow(clippy::too_many_arguments)]
+fn demote_from_common_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    index: &IndexedLines,
+    uri: &Url,
+    line_nr: usize,
+    flag_nr: usize,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let line = &index.lines[line_nr];
+    let flag = &line.flags[flag_nr];
+    let command = &line.command.as_ref()?.0;
+    if command != "common" && command != "always" {
+        return None;
+    }
+    let (_, flag_info) = bazel_flags.get_by_invocation(&flag.name.as_ref()?.0)?;
+
+    // Only applicable if the flag is supported by some, but not all commands
+    let mut supported = flag_info
+        .commands
+        .iter()
+        .map(String::as_str)
+        .filter(|c| is_regular_command(c))
+        .collect::<Vec<_>>();
+    supported.sort();
+    let all_commands_count = bazel_flags
+        .flags_by_commands
+        .keys()
+        .filter(|c| is_regular_command(c))
+        .count();
+    if supported.is_empty() || supported.len() >= all_commands_count {
+        return None;
+    }
+
+    let targets = get_minimal_commands(&supported);
+    let edit = if resolve_edits {
+        let new_lines = targets
+            .iter()
+            .map(|target| {
+                format_line(
+                    &Line {
+                        command: Some((target.to_string(), 0..0)),
+                        config: line.config.clone(),
+                        flags: vec![flag.clone()],
+                        ..Default::default()
+                    },
+                    false,
+                )
+            })
+            .collect::<String>();
+        let edits = replace_flags_with_lines(rope, line, &[flag_nr], new_lines, encoding)?;
+        Some(make_workspace_edit(uri, edits))
+    } else {
+        None
+    };
+
+    let title = if command == "always" {
+        format!(
+            "Apply `{}` only to {} (with `always`, all other commands fail)",
+            format_flag(flag),
+            targets.join(", ")
+        )
+    } else {
+        format!(
+            "Apply `{}` only to {}",
+            format_flag(flag),
+            targets.join(", ")
+        )
+    };
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title,
+        kind: Some(CodeActionKind::REFACTOR_REWRITE),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// Offers to remove a flag assignment which is overridden or repeated by a later assignment.
+// The separate value of abbreviations (`-c opt`) is removed as well.
+fn remove_overridden_flag_actions(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    index: &IndexedLines,
+    uri: &Url,
+    range: &Span,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Vec<CodeActionOrCommand> {
+    find_overridden_flags(&index.lines, bazel_flags)
+        .iter()
+        .filter_map(|o| {
+            let line = &index.lines[o.line_nr];
+            let flag = &line.flags[o.flag_nr];
+            let flag_span = get_flag_span(flag)?;
+            if flag_span.end < range.start || flag_span.start > range.end {
+                return None;
+            }
+            let mut flag_nrs = vec![o.flag_nr];
+            if let Some((_, value_nr, _)) = get_flag_value(bazel_flags, line, o.flag_nr) {
+                if value_nr != o.flag_nr {
+                    flag_nrs.push(value_nr);
+                }
+            }
+            let edit = resolve_edits.then(|| {
+                make_workspace_edit(uri, remove_flags_from_line(rope, line, &flag_nrs, encoding))
+            });
+            let reason = if o.same_value {
+                "repeated"
+            } else {
+                "overridden"
+            };
+            Some(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Remove {} `{}`", reason, format_flag(flag)),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit,
+                ..Default::default()
+            }))
+        })
+        .collect::<Vec<_>>()
+}
+
+// Offers to remove a no-op or deprecated flag, including its value
+fn remove_obsolete_flag_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    line: &Line,
+    flag_nr: usize,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let (name, _) = line.flags[flag_nr].name.as_ref()?;
+    let (_, info) = bazel_flags.get_by_invocation(name)?;
+    let reason = if info.is_noop() {
+        "no-op"
+    } else if info.is_deprecated() {
+        "deprecated"
+    } else {
+        return None;
+    };
+    let edit = resolve_edits.then(|| {
+        make_workspace_edit(
+            uri,
+            remove_flags_from_line(rope, line, &[flag_nr], encoding),
+        )
+    });
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Remove {} `{}`", reason, format_flag(&line.flags[flag_nr])),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// Offers to remove a line which only consists of no-op flags
+fn remove_noop_line_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    line: &Line,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    // With a single flag, removing the flag already removes the line
+    if line.flags.len() < 2 {
+        return None;
+    }
+    let all_noops = line.flags.iter().all(|flag| {
+        flag.name
+            .as_ref()
+            .and_then(|name| bazel_flags.get_by_invocation(&name.0))
+            .is_some_and(|(_, info)| info.is_noop())
+    });
+    if !all_noops {
+        return None;
+    }
+    let edit = resolve_edits.then(|| make_workspace_edit(uri, remove_line(rope, line, encoding)));
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: "Remove line (all flags are no-ops)".to_string(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// Offers to replace a renamed or deprecated flag by its successor, keeping the value
+fn replace_with_successor_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    flag: &Flag,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let (name, name_span) = flag.name.as_ref()?;
+    let (_, info) = bazel_flags.get_by_invocation(name)?;
+    let successor = bazel_flags.get_successor(name)?;
+    let new_name = if info.is_negated_invocation(name) {
+        format!("--no{}", successor.name)
+    } else {
+        format!("--{}", successor.name)
+    };
+    let edit = resolve_edits.then(|| {
+        let edits = range_to_lsp(rope, name_span, encoding).map(|range| TextEdit {
+            range,
+            new_text: new_name.clone(),
+        });
+        make_workspace_edit(uri, edits.into_iter().collect::<Vec<_>>())
+    });
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Replace `{}` by `{}`", name, new_name),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// Offers to replace an unknown flag by the flags the user most likely meant
+fn replace_unknown_flag_actions(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    flag: &Flag,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Vec<CodeActionOrCommand> {
+    let Some((name, name_span)) = flag.name.as_ref() else {
+        return Vec::new();
+    };
+    if bazel_flags.get_by_invocation(name).is_some()
+        || bazel_flags.get_from_other_versions(name).is_some()
+    {
+        return Vec::new();
+    }
+    let stripped = name.strip_suffix('=').unwrap_or(name);
+    bazel_flags
+        .suggest_flags(name)
+        .into_iter()
+        .map(|suggestion| {
+            let edit = resolve_edits.then(|| {
+                // Keep a trailing `=`, which separates the name from its value
+                let new_text = name.replacen(stripped, &suggestion, 1);
+                let edits = range_to_lsp(rope, name_span, encoding)
+                    .map(|range| TextEdit { range, new_text });
+                make_workspace_edit(uri, edits.into_iter().collect::<Vec<_>>())
+            });
+            CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Replace `{}` by `{}`", stripped, suggestion),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit,
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Offers to replace an abbreviated flag by its full name, e.g. `-c opt` by `--compilation_mode=opt`
+fn expand_abbreviation_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    line: &Line,
+    flag_nr: usize,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let flag = &line.flags[flag_nr];
+    let (name, name_span) = flag.name.as_ref()?;
+    let (lookup_type, info) = bazel_flags.get_by_invocation(name)?;
+    if lookup_type != FlagLookupType::Abbreviation {
+        return None;
+    }
+    // Merge the value from the following token, as also done by `combine_key_value_flags`
+    let (span, value) = match get_flag_value(bazel_flags, line, flag_nr) {
+        Some((_, _, (value, value_span))) => (name_span.start..value_span.end, Some(value)),
+        None => (name_span.clone(), None),
+    };
+    let replacement = Flag {
+        name: Some((format!("--{}", info.name), 0..0)),
+        value: value.map(|v| (v.clone(), 0..0)),
+    };
+    let new_text = format_flag(&replacement);
+    let edit = resolve_edits.then(|| {
+        let edits = range_to_lsp(rope, &span, encoding).map(|range| TextEdit {
+            range,
+            new_text: new_text.clone(),
+        });
+        make_workspace_edit(uri, edits.into_iter().collect::<Vec<_>>())
+    });
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Replace `{}` by `--{}`", name, info.name),
+        kind: Some(CodeActionKind::QUICKFIX),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// The kind of the rewrites between the different forms of boolean flags
+pub const REWRITE_BOOLEAN_KIND: &str = "refactor.rewrite.boolean";
+
+// Offers to switch between `--noflag` and `--flag=false`, as well as between `--flag`
+// and `--flag=true`, such that a consistent style can be used for boolean flags
+fn toggle_boolean_form_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    flag: &Flag,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let (name, _) = flag.name.as_ref()?;
+    let (lookup_type, info) = bazel_flags.get_by_invocation(name)?;
+    if lookup_type == FlagLookupType::Abbreviation
+        || !info.has_negative_flag()
+        || info.requires_value()
+        || get_allowed_values(info).is_some()
+    {
+        return None;
+    }
+    let name = name.strip_suffix('=').unwrap_or(name);
+    let value = flag.value.as_ref().map(|v| v.0.to_lowercase());
+    let new_text = if info.is_negated_invocation(name) {
+        if value.is_some() {
+            return None;
+        }
+        format!("--{}=false", name.strip_prefix("--no")?)
+    } else {
+        let long_name = name.strip_prefix("--")?;
+        match value.as_deref() {
+            None => format!("--{}=true", long_name),
+            Some("true" | "yes" | "1") => format!("--{}", long_name),
+            Some("false" | "no" | "0") => format!("--no{}", long_name),
+            Some(_) => return None,
+        }
+    };
+    let span = get_flag_span(flag)?;
+    let edit = resolve_edits.then(|| {
+        let edits = range_to_lsp(rope, &span, encoding).map(|range| TextEdit {
+            range,
+            new_text: new_text.clone(),
+        });
+        make_workspace_edit(uri, edits.into_iter().collect::<Vec<_>>())
+    });
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: format!("Replace `{}` by `{}`", format_flag(flag), new_text),
+        kind: Some(CodeActionKind::new(REWRITE_BOOLEAN_KIND)),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// Offers to replace invalid values of enum-typed flags by the closest allowed values
+fn replace_invalid_value_actions(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    line: &Line,
+    uri: &Url,
+    range: &Span,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Vec<CodeActionOrCommand> {
+    let mut actions = Vec::<CodeActionOrCommand>::new();
+    for flag_nr in 0..line.flags.len() {
+        let Some((info, value_nr, (value, value_span))) =
+            get_flag_value(bazel_flags, line, flag_nr)
+        else {
+            continue;
+        };
+        let Some(allowed) = get_allowed_values(info) else {
+            continue;
+        };
+        let Some(flag_span) = get_flag_span(&line.flags[flag_nr]) else {
+            continue;
+        };
+        if is_allowed_value(&allowed, value)
+            || value_span.end < range.start
+            || flag_span.start > range.end
+        {
+            continue;
+        }
+        let value_flag = &line.flags[value_nr];
+        for suggestion in suggest_values(&allowed, value) {
+            let edit = resolve_edits.then(|| {
+                let mut replacement = value_flag.clone();
+                replacement.value = Some((suggestion.to_string(), 0..0));
+                let edits = get_flag_span(value_flag)
+                    .and_then(|span| range_to_lsp(rope, &span, encoding))
+                    .map(|range| TextEdit {
+                        range,
+                        new_text: format_flag(&replacement),
+                    });
+                make_workspace_edit(uri, edits.into_iter().collect::<Vec<_>>())
+            });
+            actions.push(CodeActionOrCommand::CodeAction(CodeAction {
+                title: format!("Replace `{}` by `{}`", value, suggestion),
+                kind: Some(CodeActionKind::QUICKFIX),
+                edit,
+                ..Default::default()
+            }));
+        }
+    }
+    actions
+}
+
+// Stored as `data` of code actions whose edits are only computed once the
+// client resolves the action. The action is identified by its title.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeActionData {
+    pub uri: Url,
+    pub range: Range,
+}
+
+pub fn get_code_action_data(action: &CodeAction) -> Option<CodeActionData> {
+    serde_json::from_value(action.data.clone()?).ok()
+}
+
+// Returns the code actions available for the given range. If `resolve_edits` is
+// false, the edits are omitted and have to be computed via `resolve_code_action`.
+pub fn get_code_actions(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    index: &IndexedLines,
+    uri: &Url,
+    range: &Span,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Vec<CodeActionOrCommand> {
+    let mut actions = remove_overridden_flag_actions(
+        bazel_flags,
+        rope,
+        index,
+        uri,
+        range,
+        resolve_edits,
+        encoding,
+    );
+    for (line_nr, line) in index.lines.iter().enumerate() {
+        if line.span.end < range.start || line.span.start > range.end {
+            continue;
+        }
+        actions.extend(replace_invalid_value_actions(
+            bazel_flags,
+            rope,
+            line,
+            uri,
+            range,
+            resolve_edits,
+            encoding,
+        ));
+        actions.extend(remove_noop_line_action(
+            bazel_flags,
+            rope,
+            uri,
+            line,
+            resolve_edits,
+            encoding,
+        ));
+        actions.extend(line_continuation_action(
+            rope,
+            uri,
+            line,
+            resolve_edits,
+            encoding,
+        ));
+        for (flag_nr, flag) in line.flags.iter().enumerate() {
+            let Some(flag_span) = get_flag_span(flag) else {
+                continue;
+            };
+            if flag_span.end < range.start || flag_span.start > range.end {
+                continue;
+            }
+            actions.extend(expand_abbreviation_action(
+                bazel_flags,
+                rope,
+                uri,
+                line,
+                flag_nr,
+                resolve_edits,
+                encoding,
+            ));
+            actions.extend(toggle_boolean_form_action(
+                bazel_flags,
+                rope,
+                uri,
+                flag,
+                resolve_edits,
+                encoding,
+            ));
+            actions.extend(
+                flag_value_style_actions(bazel_flags, rope, uri, flag, resolve_edits, encoding)
+                    .into_iter()
+                    .map(CodeActionOrCommand::CodeAction),
+            );
+            actions.extend(replace_unknown_flag_actions(
+                bazel_flags,
+                rope,
+                uri,
+                flag,
+                resolve_edits,
+                encoding,
+            ));
+            actions.extend(replace_with_successor_action(
+                bazel_flags,
+                rope,
+                uri,
+                flag,
+                resolve_edits,
+                encoding,
+            ));
+            actions.extend(remove_obsolete_flag_action(
+                bazel_flags,
+                rope,
+                uri,
+                line,
+                flag_nr,
+                resolve_edits,
+                encoding,
+            ));
+            actions.extend(move_to_common_action(
+                bazel_flags,
+                rope,
+                index,
+                uri,
+                line_nr,
+                flag_nr,
+                resolve_edits,
+                encoding,
+            ));
+            actions.extend(demote_from_common_action(
+                bazel_flags,
+                rope,
+                index,
+                uri,
+                line_nr,
+                flag_nr,
+                resolve_edits,
+                encoding,
+            ));
+        }
+    }
+    actions.extend(organize_lines_action(
+        bazel_flags,
+        rope,
+        uri,
+        &index.lines,
+        resolve_edits,
+        encoding,
+    ));
+    if !resolve_edits {
+        let data = range_to_lsp(rope, range, encoding).and_then(|range| {
+            serde_json::to_value(CodeActionData {
+                uri: uri.clone(),
+                range,
+            })
+            .ok()
+        });
+        for action in &mut actions {
+            if let CodeActionOrCommand::CodeAction(action) = action {
+                action.data = data.clone();
+            }
+        }
+    }
+    actions
+}
+
+// Computes the edits of a code action previously returned without edits.
+// Returns the action unchanged if the document changed such that the
+// action is no longer available.
+pub fn resolve_code_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    index: &IndexedLines,
+    action: CodeAction,
+    encoding: PositionEncoding,
+) -> CodeAction {
+    let Some(data) = get_code_action_data(&action) else {
+        return action;
+    };
+    let (Some(start), Some(end)) = (
+        lsp_pos_to_offset(rope, &data.range.start, encoding),
+        lsp_pos_to_offset(rope, &data.range.end, encoding),
+    ) else {
+        return action;
+    };
+    get_code_actions(
+        bazel_flags,
+        rope,
+        index,
+        &data.uri,
+        &(start..end),
+        true,
+        encoding,
+    )
+    .into_iter()
+    .find_map(|candidate| match candidate {
+        CodeActionOrCommand::CodeAction(candidate) if candidate.title == action.title => {
+            Some(candidate)
+        }
+        _ => None,
+    })
+    .unwrap_or(action)
+}
+
+// Applies LSP text edits, e.g. for fixes applied via the command line
+pub fn apply_text_edits(text: &str, edits: &[TextEdit], encoding: PositionEncoding) -> String {
+    let rope = Rope::from_str(text);
+    let mut offset_edits = edits
+        .iter()
+        .map(|e| {
+            (
+                lsp_pos_to_offset(&rope, &e.range.start, encoding).unwrap(),
+                lsp_pos_to_offset(&rope, &e.range.end, encoding).unwrap(),
+                e.new_text.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    offset_edits.sort_by_key(|e| std::cmp::Reverse((e.0, e.1)));
+    let mut result = rope;
+    for (start, end, new_text) in offset_edits {
+        result.remove(start..end);
+        result.insert(start, new_text);
+    }
+    result.to_string()
+}
+
+// Boolean, flag value and line continuation rewrites are available on almost all flags
+// and lines, organizing on most files. They are only included by the tests dedicated to
+// them, to keep the other tests focused.
+#[cfg(test)]
+fn test_code_actions(text: &str, pos: usize) -> Vec<(String, String)> {
+    use crate::{
+        flag_value_style::REWRITE_FLAG_VALUE_KIND,
+        line_continuations::REWRITE_LINE_CONTINUATIONS_KIND, organize::ORGANIZE_LINES_KIND,
+    };
+
+    test_code_actions_of_kind(text, pos, |kind| {
+        ![
+            REWRITE_BOOLEAN_KIND,
+            REWRITE_FLAG_VALUE_KIND,
+            REWRITE_LINE_CONTINUATIONS_KIND,
+            ORGANIZE_LINES_KIND,
+        ]
+        .contains(&kind.as_str())
+    })
+}
+
+#[cfg(test)]
+fn test_code_actions_of_kind(
+    text: &str,
+    pos: usize,
+    filter: impl Fn(&CodeActionKind) -> bool,
+) -> Vec<(String, String)> {
+    use crate::{parser::parse_from_str, test_utils::load_flags_with_value_types};
+
+    let bazel_flags = load_flags_with_value_types("8.0.0");
+    let rope = Rope::from_str(text);
+    let mut lines = parse_from_str(text).lines;
+    crate::bazel_flags::combine_key_value_flags(&mut lines, &bazel_flags);
+    let index = IndexedLines::from_lines(lines);
+    let uri = Url::parse("file:///ws/.bazelrc").unwrap();
+    get_code_actions(
+        &bazel_flags,
+        &rope,
+        &index,
+        &uri,
+        &(pos..pos),
+        true,
+        PositionEncoding::Utf16,
+    )
+    .into_iter()
+    .filter(|a| match a {
+        CodeActionOrCommand::CodeAction(action) => action.kind.as_ref().is_none_or(&filter),
+        CodeActionOrCommand::Command(_) => true,
+    })
+    .map(|a| match a {
+        CodeActionOrCommand::CodeAction(action) => {
+            let edits = action
+                .edit
+                .and_then(|e| e.changes)
+                .and_then(|mut c| c.remove(&uri))
+                .unwrap_or_default();
+            (
+                action.title,
+                apply_text_edits(text, &edits, PositionEncoding::Utf16),
+            )
+        }
+        CodeActionOrCommand::Command(cmd) => (cmd.title, text.to_string()),
+    })
+    .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_move_to_common() {
+    // A flag used for `build` and `query` is moved to `common`
+    assert_eq!(
+        test_code_actions("build --keep_going\nquery --keep_going\n", 8),
+        vec![(
+            "Move `--keep_going` to a `common` line (used by build, query)".to_string(),
+            "common --keep_going\n".to_string()
+        )]
+    );
+    // Other flags on the same lines are kept
+    assert_eq!(
+        test_code_actions(
+            "build:ci --jobs=4 --keep_going\nquery:ci --keep_going # comment\n",
+            20
+        ),
+        vec![(
+            "Move `--keep_going` to a `common` line (used by build, query)".to_string(),
+            "common:ci --keep_going\nbuild:ci --jobs=4\nquery:ci # comment\n".to_string()
+        )]
+    );
+    // Different values or different configs are not consolidated
+    assert!(test_code_actions("build --keep_going\nquery --nokeep_going\n", 8).is_empty());
+    assert!(test_code_actions("build:a --keep_going\nquery:b --keep_going\n", 10).is_empty());
+    // Flags used for a single command only are not moved
+    assert_eq!(
+        test_code_actions("build --keep_going\nbuild --keep_going\n", 8),
+        vec![(
+            "Remove repeated `--keep_going`".to_string(),
+            "build --keep_going\n".to_string()
+        )]
+    );
+    // Flags not supported by all commands are not moved
+    assert!(test_code_actions("build --test_output=all\ntest --test_output=all\n", 8).is_empty());
+}
+
+#[test]
+fn test_move_to_common_precedence() {
+    let offers_move = |text: &str, pos: usize| {
+        test_code_actions(text, pos)
+            .iter()
+            .any(|(title, _)| title.starts_with("Move "))
+    };
+    // `test` inherits the flag from `build` already
+    assert!(!offers_move("build --keep_going\ntest --keep_going\n", 8));
+    // `build` would resolve to `--nokeep_going`, since `common` lines are applied first
+    assert!(!offers_move(
+        "build --nokeep_going\nbuild --keep_going\nquery --keep_going\n",
+        28
+    ));
+    // The `common` line would also reach `test`, which assigns a different value
+    assert!(!offers_move(
+        "build --keep_going\nquery --keep_going\ntest --nokeep_going\n",
+        8
+    ));
+    // Different spellings of the same value don't conflict
+    assert!(offers_move(
+        "build --keep_going\nquery --keep_going\ncquery --keep_going=true\n",
+        8
+    ));
+}
+
+#[test]
+fn test_demote_from_common() {
+    // `--test_output` is only supported by `test` and `coverage`. `coverage` inherits from `test`.
+    assert_eq!(
+        test_code_actions("common:ci --test_output=all\n", 12),
+        vec![(
+            "Apply `--test_output=all` only to test".to_string(),
+            "test:ci --test_output=all\n".to_string()
+        )]
+    );
+    // Other flags are kept on the `common` line
+    assert_eq!(
+        test_code_actions("always --announce_rc --test_output=all", 25),
+        vec![(
+            "Apply `--test_output=all` only to test (with `always`, all other commands fail)"
+                .to_string(),
+            "test --test_output=all\nalways --announce_rc".to_string()
+        )]
+    );
+    // Flags supported by all commands are left alone
+    assert!(test_code_actions("common --announce_rc\n", 10).is_empty());
+    // No action for flags on regular commands
+    assert!(test_code_actions("test --test_output=all\n", 10).is_empty());
+}
+
+#[test]
+fn test_remove_overridden_flag() {
+    assert_eq!(
+        test_code_actions("build --jobs=4 --keep_going\nbuild --jobs=8\n", 8),
+        vec![(
+            "Remove overridden `--jobs=4`".to_string(),
+            "build --keep_going\nbuild --jobs=8\n".to_string()
+        )]
+    );
+    assert_eq!(
+        test_code_actions("build --jobs=4\nbuild --jobs=8\n", 8),
+        vec![(
+            "Remove overridden `--jobs=4`".to_string(),
+            "build --jobs=8\n".to_string()
+        )]
+    );
+    assert_eq!(
+        test_code_actions("build --jobs=4\nbuild --jobs=4\n", 8),
+        vec![(
+            "Remove repeated `--jobs=4`".to_string(),
+            "build --jobs=4\n".to_string()
+        )]
+    );
+    // The separate value of abbreviations is removed together with the flag
+    assert_eq!(
+        test_code_actions("build -c opt\nbuild --compilation_mode=dbg\n", 6)[0],
+        (
+            "Remove overridden `-c`".to_string(),
+            "build --compilation_mode=dbg\n".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_replace_with_successor() {
+    assert_eq!(
+        test_code_actions("build --experimental_remote_cache_compression=true\n", 10),
+        vec![(
+            "Replace `--experimental_remote_cache_compression` by `--remote_cache_compression`"
+                .to_string(),
+            "build --remote_cache_compression=true\n".to_string()
+        )]
+    );
+    assert_eq!(
+        test_code_actions("build --noexperimental_remote_cache_compression\n", 10),
+        vec![(
+            "Replace `--noexperimental_remote_cache_compression` by `--noremote_cache_compression`"
+                .to_string(),
+            "build --noremote_cache_compression\n".to_string()
+        )]
+    );
+}
+
+#[test]
+fn test_replace_unknown_flag() {
+    assert_eq!(
+        test_code_actions("build --keep_goign --disk_cach=/tmp/cache\n", 10),
+        vec![(
+            "Replace `--keep_goign` by `--keep_going`".to_string(),
+            "build --keep_going --disk_cach=/tmp/cache\n".to_string()
+        )]
+    );
+    assert_eq!(
+        test_code_actions("build --keep_goign --disk_cach=/tmp/cache\n", 22),
+        vec![(
+            "Replace `--disk_cach` by `--disk_cache`".to_string(),
+            "build --keep_goign --disk_cache=/tmp/cache\n".to_string()
+        )]
+    );
+    // No suggestions for flags which only exist in other Bazel versions
+    assert_eq!(test_code_actions("build --python3_path=/x\n", 10), vec![]);
+}
+
+#[test]
+fn test_remove_obsolete_flags() {
+    assert_eq!(
+        test_code_actions("build -k --legacy_whole_archive=false\n", 12),
+        vec![(
+            "Remove deprecated `--legacy_whole_archive=false`".to_string(),
+            "build -k\n".to_string()
+        )]
+    );
+    // Removing the only flag removes the complete line
+    assert_eq!(
+        test_code_actions(
+            "build -k\nbuild --incompatible_override_toolchain_transition\n",
+            15
+        ),
+        vec![(
+            "Remove no-op `--incompatible_override_toolchain_transition`".to_string(),
+            "build -k\n".to_string()
+        )]
+    );
+    // Lines consisting only of no-ops can be removed at once, including their comment
+    let text = "build --incompatible_override_toolchain_transition \\\n    --noincompatible_override_toolchain_transition # obsolete\n";
+    assert_eq!(
+        test_code_actions(text, 10),
+        vec![
+            (
+                "Remove overridden `--incompatible_override_toolchain_transition`".to_string(),
+                "build \\\n    --noincompatible_override_toolchain_transition # obsolete\n"
+                    .to_string()
+            ),
+            (
+                "Remove line (all flags are no-ops)".to_string(),
+                "".to_string()
+            ),
+            (
+                "Remove no-op `--incompatible_override_toolchain_transition`".to_string(),
+                "build \\\n    --noincompatible_override_toolchain_transition # obsolete\n"
+                    .to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_expand_abbreviation() {
+    assert_eq!(
+        test_code_actions("build -k -c opt\n", 7),
+        vec![(
+            "Replace `-k` by `--keep_going`".to_string(),
+            "build --keep_going -c opt\n".to_string()
+        )]
+    );
+    assert_eq!(
+        test_code_actions("build -k -c opt\n", 10),
+        vec![(
+            "Replace `-c` by `--compilation_mode`".to_string(),
+            "build -k --compilation_mode=opt\n".to_string()
+        )]
+    );
+}
+
+#[test]
+fn test_boolean_rewrites() {
+    let toggle = |text: &str| {
+        test_code_actions_of_kind(text, 8, |kind| kind.as_str() == REWRITE_BOOLEAN_KIND)
+            .into_iter()
+            .map(|(_, result)| result)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        toggle("build --nokeep_going"),
+        vec!["build --keep_going=false"]
+    );
+    assert_eq!(
+        toggle("build --keep_going=no"),
+        vec!["build --nokeep_going"]
+    );
+    assert_eq!(
+        toggle("build --keep_going"),
+        vec!["build --keep_going=true"]
+    );
+    assert_eq!(toggle("build --keep_going=1"), vec!["build --keep_going"]);
+    // Only applies to boolean flags
+    assert!(toggle("build --jobs=1").is_empty());
+}
+
+#[test]
+fn test_flag_value_rewrites() {
+    use crate::flag_value_style::REWRITE_FLAG_VALUE_KIND;
+
+    let rewrite = |text: &str| {
+        test_code_actions_of_kind(text, 8, |kind| kind.as_str() == REWRITE_FLAG_VALUE_KIND)
+    };
+    assert_eq!(
+        rewrite("build --jobs 4"),
+        vec![
+            (
+                "Write `--jobs` as `--flag=value`".to_string(),
+                "build --jobs=4".to_string()
+            ),
+            (
+                "Write all flags in the workspace as `--flag=value`".to_string(),
+                "build --jobs 4".to_string()
+            ),
+        ]
+    );
+    assert_eq!(rewrite("build --jobs=4")[0].1, "build --jobs 4");
+    // Boolean flags cannot be written as `--flag value`
+    assert!(rewrite("build --keep_going=true").is_empty());
+}
+
+#[test]
+fn test_replace_invalid_value() {
+    assert_eq!(
+        test_code_actions("build --compilation_mode=optt\n", 10),
+        vec![(
+            "Replace `optt` by `opt`".to_string(),
+            "build --compilation_mode=opt\n".to_string()
+        )]
+    );
+    // Abbreviated flags, with the value in a separate token
+    assert_eq!(
+        test_code_actions("build -c fast\n", 10),
+        vec![(
+            "Replace `fast` by `fastbuild`".to_string(),
+            "build -c fastbuild\n".to_string()
+        )]
+    );
+}
+
+#[test]
+fn test_resolve_code_action() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, parser::parse_from_str};
+
+    let text = "build --keep_going\nquery --keep_going\n";
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let rope = Rope::from_str(text);
+    let mut lines = parse_from_str(text).lines;
+    crate::bazel_flags::combine_key_value_flags(&mut lines, &bazel_flags);
+    let index = IndexedLines::from_lines(lines);
+    let uri = Url::parse("file:///ws/.bazelrc").unwrap();
+
+    // Without resolving, only the title and the data for resolving are returned
+    let actions = get_code_actions(
+        &bazel_flags,
+        &rope,
+        &index,
+        &uri,
+        &(8..8),
+        false,
+        PositionEncoding::Utf16,
+    );
+    let actions = actions
+        .into_iter()
+        .filter(|a| match a {
+            CodeActionOrCommand::CodeAction(a) => a.kind == Some(CodeActionKind::REFACTOR_REWRITE),
+            CodeActionOrCommand::Command(_) => false,
+        })
+        .collect::<Vec<_>>();
+    let [CodeActionOrCommand::CodeAction(action)] = &actions[..] else {
+        panic!("Expected a single code action, got {actions:?}");
+    };
+    assert!(action.edit.is_none());
+    assert_eq!(
+        get_code_action_data(action).map(|d| d.uri),
+        Some(uri.clone())
+    );
+
+    // Resolving computes the edits
+    let resolved = resolve_code_action(
+        &bazel_flags,
+        &rope,
+        &index,
+        action.clone(),
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(resolved.title, action.title);
+    let edits = resolved
+        .edit
+        .and_then(|e| e.changes)
+        .and_then(|mut c| c.remove(&uri))
+        .unwrap_or_default();
+    assert_eq!(
+        apply_text_edits(text, &edits, PositionEncoding::Utf16),
+        "common --keep_going\n"
+    );
+
+    // Actions which are no longer applicable are returned unchanged
+    let stale = CodeAction {
+        title: "Outdated action".to_string(),
+        ..action.clone()
+    };
+    assert_eq!(
+        resolve_code_action(
+            &bazel_flags,
+            &rope,
+            &index,
+            stale.clone(),
+            PositionEncoding::Utf16
+        ),
+        stale
+    );
+}
@@ -0,0 +1,198 @@
+use std::collections::{HashMap, HashSet};
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{notification::Notification, CodeLens, Command, Url};
+
+use crate::{
+    bazel_flags::BazelFlags,
+    diagnostic::get_config_references,
+    import_graph::RcFile,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::Line,
+};
+
+pub const RUN_WITH_CONFIG_COMMAND: &str = "bazelrc.runWithConfig";
+
+// Arguments of the `bazelrc.runWithConfig` command
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RunWithConfigParams {
+    // The document defining the config. Used to determine the workspace root.
+    pub uri: Url,
+    // The Bazel command to run, e.g. `build`
+    pub command: String,
+    pub config: String,
+}
+
+// Custom `bazelrc/runInTerminal` notification: Asks the editor to run a
+// shell command in a terminal. Sent in response to `bazelrc.runWithConfig`.
+pub enum RunInTerminal {}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RunInTerminalParams {
+    pub cwd: Option<String>,
+    pub command_line: String,
+}
+
+impl Notification for RunInTerminal {
+    type Params = RunInTerminalParams;
+    const METHOD: &'static str = "bazelrc/runInTerminal";
+}
+
+// Quotes a word for POSIX shells, unless none of its characters has a special meaning
+fn shell_quote(word: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_=./:@%+,".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+// The shell command run in the terminal for a `bazelrc.runWithConfig` command. Config
+// names may contain arbitrary characters, which must not be interpreted by the shell.
+pub fn get_run_command_line(params: &RunWithConfigParams) -> String {
+    format!(
+        "bazel {} {}",
+        shell_quote(&params.command),
+        shell_quote(&format!("--config={}", params.config))
+    )
+}
+
+// Counts how often each config is referenced via `--config` within the given files
+pub fn count_config_references(
+    files: &[RcFile],
+    bazel_flags: &BazelFlags,
+) -> HashMap<String, usize> {
+    let mut counts = HashMap::<String, usize>::new();
+    for file in files {
+        for (config, _) in get_config_references(&file.lines, bazel_flags) {
+            *counts.entry(config.clone()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+// Puts two lenses on the first line defining each config: the number of
+// references and a shortcut to try out the config
+pub fn get_code_lenses(
+    uri: &Url,
+    rope: &Rope,
+    lines: &[Line],
+    reference_counts: &HashMap<String, usize>,
+    encoding: PositionEncoding,
+) -> Vec<CodeLens> {
+    let mut seen = HashSet::<&str>::new();
+    let mut lenses = Vec::<CodeLens>::new();
+    for line in lines {
+        let (Some(command), Some(config)) = (&line.command, &line.config) else {
+            continue;
+        };
+        if command.0 == "startup" || config.0.is_empty() || !seen.insert(&config.0) {
+            continue;
+        }
+        let Some(range) = range_to_lsp(rope, &config.1, encoding) else {
+            continue;
+        };
+        let count = reference_counts.get(&config.0).copied().unwrap_or(0);
+        lenses.push(CodeLens {
+            range,
+            command: Some(Command {
+                title: match count {
+                    1 => "1 reference".to_string(),
+                    _ => format!("{count} references"),
+                },
+                command: String::new(),
+                arguments: None,
+            }),
+            data: None,
+        });
+        // `common` and `always` apply to all commands, hence we try them on a build
+        let bazel_command = match command.0.as_str() {
+            "common" | "always" => "build",
+            c => c,
+        };
+        let params = RunWithConfigParams {
+            uri: uri.clone(),
+            command: bazel_command.to_string(),
+            config: config.0.clone(),
+        };
+        lenses.push(CodeLens {
+            range,
+            command: Some(Command {
+                title: format!("Run `bazel {} --config={}`", bazel_command, config.0),
+                command: RUN_WITH_CONFIG_COMMAND.to_string(),
+                arguments: serde_json::to_value(params).ok().map(|p| vec![p]),
+            }),
+            data: None,
+        });
+    }
+    lenses
+}
+
+#[test]
+fn test_code_lenses() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use std::path::PathBuf;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let file = RcFile::from_str(
+        PathBuf::from("/ws/.bazelrc"),
+        "build:ci -k\nbuild:ci --jobs=4\ncommon:remote --config=ci\ntest --config=ci\nbuild:unused -k\n",
+        &flags,
+    );
+    let other = RcFile::from_str(
+        PathBuf::from("/ws/user.bazelrc"),
+        "build --config=remote",
+        &flags,
+    );
+    let files = [file, other];
+    let counts = count_config_references(&files, &flags);
+    assert_eq!(counts.get("ci"), Some(&2));
+    assert_eq!(counts.get("remote"), Some(&1));
+
+    let uri = Url::from_file_path(&files[0].path).unwrap();
+    let titles = get_code_lenses(
+        &uri,
+        &files[0].rope,
+        &files[0].lines,
+        &counts,
+        PositionEncoding::Utf16,
+    )
+    .into_iter()
+    .map(|l| (l.range.start.line, l.command.unwrap().title))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        titles,
+        vec![
+            (0, "2 references".to_string()),
+            (0, "Run `bazel build --config=ci`".to_string()),
+            (2, "1 reference".to_string()),
+            (2, "Run `bazel build --config=remote`".to_string()),
+            (4, "0 references".to_string()),
+            (4, "Run `bazel build --config=unused`".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_run_command_line() {
+    let command_line = |command: &str, config: &str| {
+        get_run_command_line(&RunWithConfigParams {
+            uri: Url::parse("file:///ws/.bazelrc").unwrap(),
+            command: command.to_string(),
+            config: config.to_string(),
+        })
+    };
+    assert_eq!(command_line("build", "ci"), "bazel build --config=ci");
+    assert_eq!(
+        command_line("build", "ci;rm -rf $(pwd)"),
+        "bazel build '--config=ci;rm -rf $(pwd)'"
+    );
+    assert_eq!(
+        command_line("build", "it's"),
+        r"bazel build '--config=it'\''s'"
+    );
+}
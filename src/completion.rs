@@ -1,34 +1,234 @@
+use std::collections::HashSet;
+
+use phf::phf_map;
 use ropey::Rope;
 use tower_lsp::lsp_types::{
-    CompletionItem, CompletionItemTag, CompletionTextEdit, Documentation, MarkupContent,
-    MarkupKind, Range, TextEdit,
+    CompletionItem, CompletionItemKind, CompletionItemTag, CompletionTextEdit, Documentation,
+    InsertTextFormat, MarkupContent, MarkupKind, Range, TextEdit,
 };
 
 use crate::{
     bazel_flags::{BazelFlags, COMMAND_DOCS},
     bazel_flags_proto::FlagInfo,
+    env_flags::{complete_env_names, is_env_flag},
+    flag_alias::FlagAliases,
+    flag_values::{get_flag_value, get_value_completions, get_value_kind},
     line_index::{IndexEntryKind, IndexedLines},
-    lsp_utils::range_to_lsp,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::Flag,
     tokenizer::Span,
 };
 
-fn complete_bazel_command(bazel_flags: &BazelFlags) -> Vec<CompletionItem> {
-    bazel_flags
-        .commands
+fn complete_config_name(known_configs: &HashSet<String>, range: Range) -> Vec<CompletionItem> {
+    let mut configs = known_configs.iter().collect::<Vec<_>>();
+    configs.sort();
+    configs
+        .into_iter()
+        .map(|config| CompletionItem {
+            label: config.clone(),
+            kind: Some(CompletionItemKind::ENUM_MEMBER),
+            detail: Some("config".to_string()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: config.clone(),
+            })),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>()
+}
+
+fn complete_flag_value(flag_name: &str, values: &[&str], range: Range) -> Vec<CompletionItem> {
+    values
         .iter()
-        .map(|cmd| CompletionItem {
-            label: cmd.clone(),
-            commit_characters: Some(vec![':'.to_string()]),
-            documentation: get_command_documentation(cmd),
+        .enumerate()
+        .map(|(i, value)| CompletionItem {
+            label: value.to_string(),
+            kind: Some(CompletionItemKind::ENUM_MEMBER),
+            detail: Some(format!("--{}", flag_name)),
+            // Keep the order in which the values are listed
+            sort_text: Some(format!("{:03}", i)),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: value.to_string(),
+            })),
             ..Default::default()
         })
         .collect::<Vec<_>>()
 }
 
+// Is the given flag a `--config` flag?
+fn is_config_flag(bazel_flags: &BazelFlags, flag: &Flag) -> bool {
+    flag.name
+        .as_ref()
+        .and_then(|n| bazel_flags.get_by_invocation(&n.0))
+        .is_some_and(|(_, info)| info.name == "config")
+}
+
+fn complete_bazel_command(
+    bazel_flags: &BazelFlags,
+    range: Range,
+    options: &CompletionSettings,
+) -> Vec<CompletionItem> {
+    // Also offer documented commands unknown to the flags, e.g. if they have no flags
+    let mut commands = bazel_flags
+        .commands
+        .iter()
+        .map(|cmd| cmd.as_str())
+        .chain(COMMAND_DOCS.keys().copied())
+        .collect::<Vec<_>>();
+    commands.sort();
+    commands.dedup();
+    commands
+        .into_iter()
+        .map(|cmd| {
+            let is_import = cmd == "import" || cmd == "try-import";
+            if is_import && options.snippets {
+                // Imports are followed by a path, not by a config name
+                return CompletionItem {
+                    label: cmd.to_string(),
+                    kind: Some(CompletionItemKind::KEYWORD),
+                    documentation: get_command_documentation(cmd),
+                    insert_text_format: Some(InsertTextFormat::SNIPPET),
+                    text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                        range,
+                        new_text: format!("{cmd} \"%workspace%/$1\""),
+                    })),
+                    ..Default::default()
+                };
+            }
+            CompletionItem {
+                label: cmd.to_string(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                commit_characters: (!is_import).then(|| vec![':'.to_string()]),
+                documentation: get_command_documentation(cmd),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range,
+                    new_text: cmd.to_string(),
+                })),
+                ..Default::default()
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+// Commonly used flags, ranked higher by auto-completion. Weights range from 1 to 9.
+static FLAG_POPULARITY: phf::Map<&'static str, u8> = phf_map! {
+    "config" => 9,
+    "jobs" => 8,
+    "keep_going" => 8,
+    "remote_cache" => 8,
+    "disk_cache" => 8,
+    "compilation_mode" => 8,
+    "verbose_failures" => 8,
+    "test_output" => 8,
+    "enable_platform_specific_config" => 7,
+    "remote_executor" => 7,
+    "remote_download_outputs" => 7,
+    "repository_cache" => 7,
+    "copt" => 7,
+    "cxxopt" => 7,
+    "define" => 7,
+    "action_env" => 7,
+    "test_env" => 7,
+    "platforms" => 7,
+    "incompatible_strict_action_env" => 6,
+    "bes_backend" => 6,
+    "bes_results_url" => 6,
+    "remote_header" => 6,
+    "remote_timeout" => 6,
+    "build_metadata" => 6,
+    "show_timestamps" => 6,
+    "announce_rc" => 6,
+    "test_summary" => 6,
+    "test_timeout" => 6,
+    "host_platform" => 6,
+    "extra_toolchains" => 6,
+    "java_runtime_version" => 5,
+    "tool_java_runtime_version" => 5,
+    "java_language_version" => 5,
+    "linkopt" => 5,
+    "host_copt" => 5,
+    "host_cxxopt" => 5,
+    "strip" => 5,
+    "sandbox_debug" => 5,
+    "spawn_strategy" => 5,
+    "worker_max_instances" => 5,
+    "local_resources" => 5,
+    "experimental_remote_cache_compression" => 5,
+    "remote_cache_compression" => 5,
+    "lockfile_mode" => 5,
+    "enable_bzlmod" => 5,
+    "color" => 4,
+    "curses" => 4,
+    "show_progress_rate_limit" => 4,
+    "flaky_test_attempts" => 4,
+    "cache_test_results" => 4,
+    "build_tests_only" => 4,
+    "workspace_status_command" => 4,
+    "stamp" => 4,
+};
+
+// Hidden and internal flags are not meant to be used by end users
+fn is_hidden_flag(flag: &FlagInfo) -> bool {
+    flag.documentation_category.as_deref() == Some("UNDOCUMENTED")
+        || flag
+            .metadata_tags
+            .iter()
+            .any(|t| t == "HIDDEN" || t == "INTERNAL")
+}
+
+// Ranks the flags by relevance: Flags supported by the line's command first, then the
+// commonly used flags. Deprecated and no-op flags are listed last.
+fn flag_sort_text(bazel_flags: &BazelFlags, command: &str, flag_idx: usize, label: &str) -> String {
+    let flag = &bazel_flags.flags[flag_idx];
+    // `common` and `always` also accept flags which are only supported by some commands
+    let is_specific = matches!(command, "common" | "always")
+        && !bazel_flags.flags_for_all_commands.contains(&flag_idx);
+    let group = if flag.is_deprecated() || flag.is_noop() {
+        2
+    } else if is_specific {
+        1
+    } else {
+        0
+    };
+    let popularity = FLAG_POPULARITY
+        .get(flag.name.as_str())
+        .copied()
+        .unwrap_or(0);
+    format!("{group}{}{label}", 9 - popularity.min(9))
+}
+
+// User preferences for flag completions. Editors differ in their handling of commit characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompletionSettings {
+    // Should typing `=` accept the currently selected flag?
+    pub commit_with_equals: bool,
+    // Should `=` be appended automatically for flags which require a value?
+    pub insert_equals: bool,
+    // Should hidden and internal flags be offered?
+    pub show_hidden_flags: bool,
+    // Does the editor support snippets? If so and `=` is inserted, a tab stop for the value
+    // follows it.
+    pub snippets: bool,
+}
+
+impl Default for CompletionSettings {
+    fn default() -> Self {
+        CompletionSettings {
+            commit_with_equals: true,
+            insert_equals: false,
+            show_hidden_flags: false,
+            snippets: false,
+        }
+    }
+}
+
 fn complete_bazel_flag(
     bazel_flags: &BazelFlags,
+    flag_aliases: &FlagAliases,
     command: &str,
     range: Range,
+    options: &CompletionSettings,
 ) -> Vec<CompletionItem> {
     let exisiting_flags = bazel_flags.flags_by_commands.get(command);
 
@@ -39,21 +239,27 @@ fn complete_bazel_flag(
     let relevant_flags = exisiting_flags
         .unwrap()
         .iter()
-        .map(|i| &bazel_flags.flags[*i])
-        // Hide undocumented flags
-        .filter(|f| f.documentation_category != Some("UNDOCUMENTED".to_string()));
+        .copied()
+        .filter(|i| options.show_hidden_flags || !is_hidden_flag(&bazel_flags.flags[*i]));
 
     let create_completion_item =
-        |label: String, new_text: String, flag: &FlagInfo, commit_characters: Vec<String>| {
+        |label: String, new_text: String, flag_idx: usize, commit_characters: Vec<String>| {
+            let flag = &bazel_flags.flags[flag_idx];
             let tags = if flag.is_deprecated() || flag.is_noop() {
                 Some(vec![CompletionItemTag::DEPRECATED])
             } else {
                 None
             };
+            let filter_text = format!("--{label}");
             CompletionItem {
+                sort_text: Some(flag_sort_text(bazel_flags, command, flag_idx, &label)),
                 label,
-                documentation: get_flag_documentation(flag),
-                filter_text: Some(new_text.clone()),
+                detail: flag
+                    .default_value
+                    .as_ref()
+                    .map(|d| format!("Default: {d:?}")),
+                documentation: get_flag_documentation(flag, bazel_flags.bazel_version.as_deref()),
+                filter_text: Some(filter_text),
                 text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
                 commit_characters: Some(commit_characters),
                 tags,
@@ -64,47 +270,130 @@ fn complete_bazel_flag(
 
     // The Bazel flags themselves...
     let mut completion_items: Vec<CompletionItem> = Vec::<CompletionItem>::new();
-    completion_items.extend(relevant_flags.clone().map(|flag| {
-        let new_text = format!("--{}", flag.name);
-        create_completion_item(flag.name.clone(), new_text, flag, vec!["=".to_string()])
+    completion_items.extend(relevant_flags.clone().map(|flag_idx| {
+        let flag = &bazel_flags.flags[flag_idx];
+        let insert_equals = options.insert_equals && flag.requires_value();
+        if options.snippets && insert_equals {
+            let snippet = get_value_kind(flag).snippet();
+            let new_text = format!("--{}={}", flag.name, snippet);
+            return CompletionItem {
+                insert_text_format: Some(InsertTextFormat::SNIPPET),
+                ..create_completion_item(flag.name.clone(), new_text, flag_idx, vec![])
+            };
+        }
+        let new_text = if insert_equals {
+            format!("--{}=", flag.name)
+        } else {
+            format!("--{}", flag.name)
+        };
+        let commit_characters = if options.commit_with_equals && !insert_equals {
+            vec!["=".to_string()]
+        } else {
+            vec![]
+        };
+        create_completion_item(flag.name.clone(), new_text, flag_idx, commit_characters)
     }));
 
     // ... and their negations
     completion_items.extend(
         relevant_flags
-            .filter(|flag| flag.has_negative_flag())
-            .map(|flag| {
-                let label = format!("no{}", flag.name.clone());
-                let new_text = format!("--no{}", flag.name);
-                create_completion_item(label, new_text, flag, vec![])
+            .filter(|flag_idx| bazel_flags.flags[*flag_idx].has_negative_flag())
+            .map(|flag_idx| {
+                let name = &bazel_flags.flags[flag_idx].name;
+                let label = format!("no{}", name);
+                let new_text = format!("--no{}", name);
+                create_completion_item(label, new_text, flag_idx, vec![])
             }),
     );
 
+    // ... and the aliases defined via `--flag_alias`
+    let mut aliases = flag_aliases.iter().collect::<Vec<_>>();
+    aliases.sort();
+    completion_items.extend(aliases.into_iter().map(|(name, label)| {
+        let new_text = format!("--{}", name);
+        let commit_characters = if options.commit_with_equals {
+            vec!["=".to_string()]
+        } else {
+            vec![]
+        };
+        CompletionItem {
+            label: name.clone(),
+            detail: Some(format!("alias for {}", label)),
+            filter_text: Some(new_text.clone()),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit { range, new_text })),
+            commit_characters: Some(commit_characters),
+            ..Default::default()
+        }
+    }));
+
     completion_items
 }
 
+// Whether the newline at `pos` is escaped by a `\`, i.e. is a line continuation
+fn is_escaped_newline(rope: &Rope, pos: usize) -> bool {
+    let mut end = pos;
+    if end > 0 && rope.get_char(end - 1) == Some('\r') {
+        end -= 1;
+    }
+    let backslashes = (0..end)
+        .rev()
+        .take_while(|i| rope.get_char(*i) == Some('\\'))
+        .count();
+    backslashes % 2 == 1
+}
+
+// Returns the completion items at the given position. `known_configs` are the
+// configs defined in the import graph, offered as values for `--config`.
+// `flag_aliases` are the flag aliases defined in the import graph.
+#[allow(clippy::too_many_arguments)]
 pub fn get_completion_items(
     bazel_flags: &BazelFlags,
     rope: &Rope,
     index: &IndexedLines,
     pos: usize,
+    options: &CompletionSettings,
+    known_configs: &HashSet<String>,
+    flag_aliases: &FlagAliases,
+    encoding: PositionEncoding,
 ) -> Vec<CompletionItem> {
     // For completion, the indices point between characters and not
     // at characters. We are generally interested in the token so far
     // *before* the cursor. Hence, we lookup `pos - 1` and not `pos`.
-    let lookup_pos = if pos == 0 { 0 } else { pos - 1 };
+    // At the beginning of a line, the previous character would be the newline
+    // of the preceding line, though.
+    // Escaped newlines continue the current line.
+    let at_line_start =
+        pos == 0 || (rope.get_char(pos - 1) == Some('\n') && !is_escaped_newline(rope, pos - 1));
+    let lookup_pos = if at_line_start { pos } else { pos - 1 };
     if let Some(entry) = index.find_symbol_at_position(lookup_pos) {
         let line = index.lines.get(entry.line_nr).unwrap();
         // Complete the item which the user is currently typing
         match entry.kind {
-            IndexEntryKind::Command => complete_bazel_command(bazel_flags),
-            IndexEntryKind::Config => vec![],
+            IndexEntryKind::Command => complete_bazel_command(
+                bazel_flags,
+                range_to_lsp(rope, &entry.span, encoding).unwrap(),
+                options,
+            ),
+            IndexEntryKind::Config => {
+                // The config's span starts at the `:` separating it from the command
+                let mut span = entry.span.clone();
+                if rope.get_char(span.start) == Some(':') {
+                    span.start += 1;
+                }
+                // Don't offer the config which is currently being typed
+                let typed = rope.slice(span.clone()).to_string();
+                let mut configs = known_configs.clone();
+                configs.retain(|c| !c.is_empty() && *c != typed);
+                complete_config_name(&configs, range_to_lsp(rope, &span, encoding).unwrap())
+            }
             IndexEntryKind::FlagName(_) => {
                 if let Some(cmd) = &line.command {
                     complete_bazel_flag(
                         bazel_flags,
+                        flag_aliases,
                         &cmd.0,
-                        range_to_lsp(rope, &entry.span).unwrap(),
+                        range_to_lsp(rope, &entry.span, encoding).unwrap(),
+                        options,
                     )
                 } else {
                     // A flag should never be on a line without a command
@@ -113,13 +402,60 @@ pub fn get_completion_items(
                     vec![]
                 }
             }
-            IndexEntryKind::FlagValue(_) => vec![],
+            IndexEntryKind::FlagValue(flag_nr) => {
+                let flag = &line.flags[flag_nr];
+                // The value's span starts at the `=` separating it from the flag name
+                let mut span = entry.span.clone();
+                if rope.get_char(span.start) == Some('=') {
+                    span.start += 1;
+                }
+                let range = range_to_lsp(rope, &span, encoding).unwrap();
+                // Abbreviated flags take their value from the following token, e.g. `-c opt`
+                let value_of = [Some(flag_nr), flag_nr.checked_sub(1)]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|nr| {
+                        let (info, value_nr, _) = get_flag_value(bazel_flags, line, nr)?;
+                        (value_nr == flag_nr).then_some(info)
+                    });
+                if is_config_flag(bazel_flags, flag) {
+                    complete_config_name(known_configs, range)
+                } else if let Some(info) = value_of.filter(|info| is_env_flag(info)) {
+                    // Complete the variable name, i.e. the part before the first `=`
+                    let value = rope.slice(span.clone()).to_string();
+                    let name_len = value.find('=').unwrap_or(value.len());
+                    let name_span = span.start..span.start + value[..name_len].chars().count();
+                    if pos <= name_span.end && !value.starts_with('=') {
+                        complete_env_names(
+                            std::env::vars_os().filter_map(|(name, _)| name.into_string().ok()),
+                            &info.name,
+                            range_to_lsp(rope, &name_span, encoding).unwrap(),
+                        )
+                    } else {
+                        vec![]
+                    }
+                } else if let Some(info) = value_of {
+                    get_value_completions(info)
+                        .map(|values| complete_flag_value(&info.name, &values, range))
+                        .unwrap_or_default()
+                } else {
+                    vec![]
+                }
+            }
         }
     } else if let Some(line) = index.find_line_at_position(lookup_pos) {
         // Not within any item, but on an existing line.
-        if let Some(cmd) = &line.command {
+        if line
+            .comment
+            .as_ref()
+            .is_some_and(|c| c.1.start <= lookup_pos)
+        {
+            // Nothing to complete within comments
+            vec![]
+        } else if let Some(cmd) = &line.command {
             complete_bazel_flag(
                 bazel_flags,
+                flag_aliases,
                 &cmd.0,
                 range_to_lsp(
                     rope,
@@ -127,8 +463,17 @@ pub fn get_completion_items(
                         start: pos,
                         end: pos,
                     },
+                    encoding,
                 )
                 .unwrap(),
+                options,
+            )
+        } else if line.config.is_none() && line.flags.is_empty() {
+            // An empty line. Complete the command.
+            complete_bazel_command(
+                bazel_flags,
+                range_to_lsp(rope, &(pos..pos), encoding).unwrap(),
+                options,
             )
         } else {
             vec![]
@@ -137,14 +482,21 @@ pub fn get_completion_items(
         // Outside any existing line, i.e. on a completely empty line
         // Complete the bazel command since that has to be at the beginning
         // of every line
-        complete_bazel_command(bazel_flags)
+        complete_bazel_command(
+            bazel_flags,
+            range_to_lsp(rope, &(pos..pos), encoding).unwrap(),
+            options,
+        )
     }
 }
 
-fn get_flag_documentation(flag: &crate::bazel_flags_proto::FlagInfo) -> Option<Documentation> {
+fn get_flag_documentation(
+    flag: &crate::bazel_flags_proto::FlagInfo,
+    bazel_version: Option<&str>,
+) -> Option<Documentation> {
     let mc = MarkupContent {
         kind: MarkupKind::Markdown,
-        value: flag.get_documentation_markdown(),
+        value: flag.get_documentation_markdown(bazel_version),
     };
     Some(Documentation::MarkupContent(mc))
 }
@@ -157,3 +509,377 @@ fn get_command_documentation(command: &str) -> Option<Documentation> {
         })
     })
 }
+
+#[cfg(test)]
+fn test_completion_edits(text: &str, pos: usize) -> Vec<(String, Range)> {
+    test_completion_items(text, pos, &CompletionSettings::default())
+        .into_iter()
+        .filter_map(|item| match item.text_edit? {
+            CompletionTextEdit::Edit(edit) => Some((edit.new_text, edit.range)),
+            CompletionTextEdit::InsertAndReplace(edit) => Some((edit.new_text, edit.replace)),
+        })
+        .collect::<Vec<_>>()
+}
+
+#[cfg(test)]
+fn test_completion_items(
+    text: &str,
+    pos: usize,
+    options: &CompletionSettings,
+) -> Vec<CompletionItem> {
+    use crate::{
+        bazel_flags::combine_key_value_flags, flag_alias::get_flag_aliases,
+        import_graph::get_defined_configs, parser::parse_from_str,
+        test_utils::load_flags_with_value_types,
+    };
+
+    let bazel_flags = load_flags_with_value_types("8.0.0");
+    let rope = Rope::from_str(text);
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let index = IndexedLines::from_lines(lines);
+    let known_configs = get_defined_configs(&index.lines);
+    let flag_aliases = get_flag_aliases(&index.lines, &bazel_flags);
+    get_completion_items(
+        &bazel_flags,
+        &rope,
+        &index,
+        pos,
+        options,
+        &known_configs,
+        &flag_aliases,
+        PositionEncoding::Utf16,
+    )
+}
+
+#[test]
+fn test_completion_replaces_token() {
+    use tower_lsp::lsp_types::Position;
+
+    // Completing in the middle of a flag name replaces the complete flag name
+    let edits = test_completion_edits("build --remo_exec --jobs=1", 12);
+    let expected_range = Range::new(Position::new(0, 6), Position::new(0, 17));
+    assert!(edits.contains(&("--remote_executor".to_string(), expected_range)));
+    assert!(edits.iter().all(|e| e.1 == expected_range));
+
+    // Completing in the middle of a command replaces the complete command
+    let edits = test_completion_edits("bu:ci --jobs=1", 1);
+    let expected_range = Range::new(Position::new(0, 0), Position::new(0, 2));
+    assert!(edits.contains(&("build".to_string(), expected_range)));
+    assert!(edits.iter().all(|e| e.1 == expected_range));
+
+    // On an empty line, the command is inserted at the cursor
+    let edits = test_completion_edits("build --jobs=1\n\n", 15);
+    let expected_range = Range::new(Position::new(1, 0), Position::new(1, 0));
+    assert!(edits.contains(&("build".to_string(), expected_range)));
+}
+
+#[test]
+fn test_completion_options() {
+    let find_item = |options: &CompletionSettings, label: &str| {
+        test_completion_items("build --", 8, options)
+            .into_iter()
+            .find(|i| i.label == label)
+            .unwrap()
+    };
+    let new_text = |item: &CompletionItem| match item.text_edit.as_ref().unwrap() {
+        CompletionTextEdit::Edit(edit) => edit.new_text.clone(),
+        CompletionTextEdit::InsertAndReplace(edit) => edit.new_text.clone(),
+    };
+
+    // By default, `=` is a commit character and is not inserted automatically
+    let options = CompletionSettings::default();
+    let item = find_item(&options, "jobs");
+    assert_eq!(new_text(&item), "--jobs");
+    assert_eq!(item.commit_characters, Some(vec!["=".to_string()]));
+
+    // `=` can be inserted automatically for flags which require a value
+    let options = CompletionSettings {
+        commit_with_equals: true,
+        insert_equals: true,
+        ..Default::default()
+    };
+    let item = find_item(&options, "jobs");
+    assert_eq!(new_text(&item), "--jobs=");
+    assert_eq!(item.filter_text.as_deref(), Some("--jobs"));
+    assert_eq!(item.commit_characters, Some(vec![]));
+    let item = find_item(&options, "keep_going");
+    assert_eq!(new_text(&item), "--keep_going");
+
+    // `=` might also not be a commit character
+    let options = CompletionSettings {
+        commit_with_equals: false,
+        insert_equals: false,
+        ..Default::default()
+    };
+    let item = find_item(&options, "keep_going");
+    assert_eq!(item.commit_characters, Some(vec![]));
+
+    // Snippets only apply if `=` is inserted
+    let options = CompletionSettings {
+        snippets: true,
+        ..Default::default()
+    };
+    let item = find_item(&options, "jobs");
+    assert_eq!(new_text(&item), "--jobs");
+    assert_eq!(item.insert_text_format, None);
+    assert_eq!(item.commit_characters, Some(vec!["=".to_string()]));
+
+    // With snippets, a tab stop for the value follows the `=`
+    let options = CompletionSettings {
+        snippets: true,
+        insert_equals: true,
+        ..Default::default()
+    };
+    let item = find_item(&options, "jobs");
+    assert_eq!(new_text(&item), "--jobs=$1");
+    assert_eq!(item.insert_text_format, Some(InsertTextFormat::SNIPPET));
+    assert_eq!(item.filter_text.as_deref(), Some("--jobs"));
+    assert_eq!(item.commit_characters, Some(vec![]));
+    let item = find_item(&options, "remote_cache");
+    assert_eq!(new_text(&item), "--remote_cache=$1");
+    let item = find_item(&options, "compilation_mode");
+    assert_eq!(
+        new_text(&item),
+        "--compilation_mode=${1|fastbuild,dbg,opt|}"
+    );
+    // Boolean flags are offered in both forms, without snippets
+    let item = find_item(&options, "keep_going");
+    assert_eq!(new_text(&item), "--keep_going");
+    assert_eq!(item.insert_text_format, None);
+    let item = find_item(&options, "nokeep_going");
+    assert_eq!(new_text(&item), "--nokeep_going");
+}
+
+#[test]
+fn test_complete_config_names() {
+    use tower_lsp::lsp_types::Position;
+
+    let text = "build:remote -k\nbuild:ci -k\ntest --config=\ntest --config=re";
+    // Right after `--config=`
+    assert_eq!(
+        test_completion_edits(text, 42),
+        vec![
+            (
+                "ci".to_string(),
+                Range::new(Position::new(2, 14), Position::new(2, 14))
+            ),
+            (
+                "remote".to_string(),
+                Range::new(Position::new(2, 14), Position::new(2, 14))
+            )
+        ]
+    );
+    // Replaces a partially typed config name
+    let edits = test_completion_edits(text, 58);
+    assert!(edits.contains(&(
+        "remote".to_string(),
+        Range::new(Position::new(3, 14), Position::new(3, 16))
+    )));
+}
+
+#[test]
+fn test_complete_flag_aliases() {
+    let text = "common --flag_alias=myflag=//foo:bar\nbuild --";
+    let items = test_completion_items(text, 45, &CompletionSettings::default());
+    let item = items.iter().find(|item| item.label == "myflag").unwrap();
+    assert_eq!(item.detail.as_deref(), Some("alias for //foo:bar"));
+    let Some(CompletionTextEdit::Edit(edit)) = &item.text_edit else {
+        panic!("unexpected text edit")
+    };
+    assert_eq!(edit.new_text, "--myflag");
+}
+
+#[test]
+fn test_complete_flag_values() {
+    let labels = |text: &str, pos: usize| {
+        test_completion_items(text, pos, &CompletionSettings::default())
+            .into_iter()
+            .map(|item| item.label)
+            .collect::<Vec<_>>()
+    };
+    // Enum values
+    assert_eq!(
+        labels("build --compilation_mode=", 25),
+        vec!["fastbuild", "dbg", "opt"]
+    );
+    assert_eq!(labels("build -c d", 10), vec!["fastbuild", "dbg", "opt"]);
+    // Boolean values
+    assert_eq!(
+        labels("build --keep_going=", 19),
+        vec!["true", "false", "yes", "no", "1", "0"]
+    );
+    // No completions for free-form values
+    assert!(labels("build --jobs=", 13).is_empty());
+}
+
+#[test]
+fn test_complete_env_names() {
+    // `PATH` is set in every environment the tests run in
+    let edits = |text: &str, pos: usize| {
+        test_completion_edits(text, pos)
+            .into_iter()
+            .filter(|(name, _)| name == "PATH")
+            .map(|(_, range)| (range.start.character, range.end.character))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(edits("build --action_env=", 19), vec![(19, 19)]);
+    // Only the name is replaced, keeping the value
+    assert_eq!(edits("build --test_env=PA=value", 19), vec![(17, 19)]);
+    assert_eq!(edits("build --test_env=PA=value", 22), vec![]);
+    assert_eq!(edits("build --copt=", 13), vec![]);
+}
+
+#[test]
+fn test_completion_ranking() {
+    let ranked = |text: &str, options: &CompletionSettings| {
+        let mut items = test_completion_items(text, text.len(), options);
+        items.sort_by(|a, b| a.sort_text.cmp(&b.sort_text));
+        items.into_iter().map(|item| item.label).collect::<Vec<_>>()
+    };
+    let position = |labels: &[String], label: &str| labels.iter().position(|l| l == label);
+
+    // Commonly used flags are listed first
+    let labels = ranked("build --", &CompletionSettings::default());
+    assert_eq!(labels[0], "config");
+    assert!(position(&labels, "keep_going") < position(&labels, "announce_rc"));
+    // Deprecated and no-op flags are listed last, and are tagged as such
+    let obsolete = [
+        "enable_workspace",
+        "incompatible_override_toolchain_transition",
+        "legacy_whole_archive",
+    ];
+    let first_obsolete = labels
+        .iter()
+        .position(|l| obsolete.contains(&l.as_str()))
+        .unwrap();
+    assert!(labels[first_obsolete..]
+        .iter()
+        .all(|l| obsolete.iter().any(|o| l.ends_with(o))));
+    let items = test_completion_items("build --", 8, &CompletionSettings::default());
+    let item = items
+        .iter()
+        .find(|i| i.label == "legacy_whole_archive")
+        .unwrap();
+    assert_eq!(item.tags, Some(vec![CompletionItemTag::DEPRECATED]));
+
+    // For `common`, flags supported by all commands are listed before command-specific flags
+    let labels = ranked("common --", &CompletionSettings::default());
+    assert!(position(&labels, "announce_rc") < position(&labels, "copt"));
+}
+
+#[test]
+fn test_completion_hidden_flags() {
+    let has_flag = |options: &CompletionSettings, label: &str| {
+        test_completion_items("build --", 8, options)
+            .iter()
+            .any(|i| i.label == label)
+    };
+    let options = CompletionSettings::default();
+    assert!(!has_flag(&options, "internal_spawn_scheduler"));
+    let options = CompletionSettings {
+        show_hidden_flags: true,
+        ..Default::default()
+    };
+    assert!(has_flag(&options, "internal_spawn_scheduler"));
+}
+
+#[test]
+fn test_complete_commands() {
+    use tower_lsp::lsp_types::Position;
+
+    let items = test_completion_items("", 0, &CompletionSettings::default());
+    let labels = items.iter().map(|i| i.label.as_str()).collect::<Vec<_>>();
+    for command in ["build", "common", "startup", "import", "try-import"] {
+        assert!(labels.contains(&command), "missing {command}");
+    }
+    assert!(labels.windows(2).all(|w| w[0] < w[1]));
+    let build = items.iter().find(|i| i.label == "build").unwrap();
+    assert_eq!(build.commit_characters, Some(vec![":".to_string()]));
+
+    // With snippets, imports are completed including a path placeholder
+    let options = CompletionSettings {
+        snippets: true,
+        ..Default::default()
+    };
+    let items = test_completion_items("try", 3, &options);
+    let item = items.iter().find(|i| i.label == "try-import").unwrap();
+    assert_eq!(item.insert_text_format, Some(InsertTextFormat::SNIPPET));
+    assert_eq!(
+        item.text_edit,
+        Some(CompletionTextEdit::Edit(TextEdit {
+            range: Range::new(Position::new(0, 0), Position::new(0, 3)),
+            new_text: "try-import \"%workspace%/$1\"".to_string(),
+        }))
+    );
+}
+
+#[test]
+fn test_complete_config_after_command() {
+    use tower_lsp::lsp_types::Position;
+
+    // Right after `test:`
+    let text = "build:remote -k\nbuild:ci -k\ntest:";
+    assert_eq!(
+        test_completion_edits(text, 33),
+        vec![
+            (
+                "ci".to_string(),
+                Range::new(Position::new(2, 5), Position::new(2, 5))
+            ),
+            (
+                "remote".to_string(),
+                Range::new(Position::new(2, 5), Position::new(2, 5))
+            )
+        ]
+    );
+    // Replaces a partially typed config name
+    let text = "build:remote -k\nbuild:ci -k\ntest:re";
+    assert_eq!(
+        test_completion_edits(text, 35),
+        vec![
+            (
+                "ci".to_string(),
+                Range::new(Position::new(2, 5), Position::new(2, 7))
+            ),
+            (
+                "remote".to_string(),
+                Range::new(Position::new(2, 5), Position::new(2, 7))
+            )
+        ]
+    );
+}
+
+#[test]
+fn test_completion_within_line_continuations() {
+    use tower_lsp::lsp_types::Position;
+
+    // Flags are completed on continuation lines, including right after the `\`
+    for (text, pos) in [
+        ("build --keep_going \\\n  --jo", 26),
+        ("build --keep_going \\\n", 21),
+        ("build --keep_going \\\n\n", 21),
+    ] {
+        let edits = test_completion_edits(text, pos);
+        assert!(edits.iter().any(|e| e.0 == "--jobs"), "{text:?}");
+        assert!(edits.iter().all(|e| e.1.start.line == 1), "{text:?}");
+    }
+    let edits = test_completion_edits("build --keep_going \\\n  --jo", 26);
+    let expected_range = Range::new(Position::new(1, 2), Position::new(1, 6));
+    assert!(edits.contains(&("--jobs".to_string(), expected_range)));
+
+    // An escaped backslash does not continue the line
+    let edits = test_completion_edits("build --copt=\\\\\n", 16);
+    assert!(edits.iter().any(|e| e.0 == "build"));
+}
+
+#[test]
+fn test_no_completion_in_comments() {
+    // Neither in trailing comments nor in comments on their own line
+    assert!(test_completion_edits("build --jobs=4 # keep in sy", 27).is_empty());
+    assert!(test_completion_edits("build --jobs=4 #", 16).is_empty());
+    assert!(test_completion_edits("# buil", 6).is_empty());
+    // Directly in front of the comment, flags are still completed
+    let edits = test_completion_edits("build  # keep in sync", 6);
+    assert!(edits.iter().any(|e| e.0 == "--jobs"));
+}
@@ -0,0 +1,756 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::Url;
+
+use crate::{
+    bazel_flags::{BazelFlags, COMMAND_PARENTS},
+    code_action::{format_flag, get_flag_span},
+    file_utils::get_workspace_path,
+    flag_values::{get_allowed_values, get_flag_value},
+    formatting::format_token,
+    import_graph::RcFile,
+    parser::Line,
+    rc_discovery::{get_line_order, get_line_read_order},
+    tokenizer::Span,
+};
+
+pub const SHOW_EFFECTIVE_CONFIG_COMMAND: &str = "bazelrc.showEffectiveConfig";
+
+// Arguments of the `bazelrc.showEffectiveConfig` command
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct EffectiveConfigParams {
+    // The bazelrc file to start from. Its imports and the workspace `.bazelrc` are considered.
+    pub uri: Url,
+    // The Bazel command, e.g. `test`
+    pub command: String,
+    #[serde(default)]
+    pub configs: Vec<String>,
+}
+
+// A flag which a `--config` expands to
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExpandedFlag {
+    // The flag as written in the bazelrc file, e.g. `--jobs=4`
+    pub flag: String,
+    // The canonical flag name, used to detect conflicts
+    pub name: String,
+    // The flag name as written, e.g. `-c` or `--nokeep_going`
+    pub invocation: String,
+    pub value: Option<String>,
+    pub path: PathBuf,
+    pub line_nr: usize,
+    pub span: Span,
+    // The command of the line setting the flag, e.g. `build` for `build:ci`
+    pub command: String,
+    // The nested configs through which this flag was reached
+    pub via: Vec<String>,
+    // The expansion flag which set this flag, e.g. `--experimental_spawn_scheduler`
+    pub expanded_from: Option<String>,
+}
+
+// The commands whose lines apply when running `command`, grouped in the order in
+// which Bazel applies them: `common` and `always` first, followed by the inherited
+// commands and finally the command itself. `None` if all lines apply.
+fn get_applicable_commands(command: &str) -> Option<Vec<Vec<&str>>> {
+    if command == "common" || command == "always" {
+        // Applies to all commands, hence all definitions are relevant
+        return None;
+    }
+    let mut commands = vec![vec![command]];
+    while let Some(parent) = COMMAND_PARENTS.get(commands.last().unwrap()[0]) {
+        commands.push(vec![parent]);
+    }
+    commands.push(vec!["common", "always"]);
+    commands.reverse();
+    Some(commands)
+}
+
+// The position at which Bazel reads each line, see `get_line_order`
+type LineOrder = HashMap<(PathBuf, usize), usize>;
+
+// The lines for the given config (or without any config), in the order Bazel applies them.
+// Within each group of commands, lines are applied in the order they are read.
+fn get_applicable_lines<'a>(
+    files: &'a [RcFile],
+    line_order: &LineOrder,
+    commands: &Option<Vec<Vec<&str>>>,
+    config: Option<&str>,
+) -> Vec<(&'a RcFile, usize, &'a Line)> {
+    let mut all_lines = files
+        .iter()
+        .flat_map(|file| {
+            file.lines
+                .iter()
+                .enumerate()
+                .filter_map(move |(line_nr, line)| {
+                    let command = line.command.as_ref()?;
+                    (line.config.as_ref().map(|c| c.0.as_str()) == config).then_some((
+                        file,
+                        line_nr,
+                        line,
+                        command.0.as_str(),
+                    ))
+                })
+        })
+        .collect::<Vec<_>>();
+    all_lines.sort_by_cached_key(|(file, line_nr, _, _)| {
+        line_order.get(&(file.path.clone(), *line_nr)).copied()
+    });
+    match commands {
+        None => all_lines
+            .into_iter()
+            .filter(|(_, _, _, command)| !["startup", "import", "try-import"].contains(command))
+            .map(|(file, line_nr, line, _)| (file, line_nr, line))
+            .collect::<Vec<_>>(),
+        Some(groups) => groups
+            .iter()
+            .flat_map(|group| {
+                all_lines
+                    .iter()
+                    .filter(|(_, _, _, command)| group.contains(command))
+                    .map(|(file, line_nr, line, _)| (*file, *line_nr, *line))
+            })
+            .collect::<Vec<_>>(),
+    }
+}
+
+// Expands the flags of the given lines, following nested `--config` references.
+// `chain` contains the configs being expanded, starting with the outermost config.
+fn expand_lines(
+    files: &[RcFile],
+    line_order: &LineOrder,
+    bazel_flags: &BazelFlags,
+    commands: &Option<Vec<Vec<&str>>>,
+    lines: &[(&RcFile, usize, &Line)],
+    chain: &mut Vec<String>,
+    result: &mut Vec<ExpandedFlag>,
+) {
+    for (file, line_nr, line) in lines {
+        let mut consumed_value = false;
+        for (flag_nr, flag) in line.flags.iter().enumerate() {
+            if std::mem::take(&mut consumed_value) {
+                // The value of an abbreviated flag, e.g. `opt` in `-c opt`
+                continue;
+            }
+            let raw_name = flag.name.as_ref().map(|n| n.0.as_str()).unwrap_or("");
+            let info = bazel_flags.get_by_invocation(raw_name).map(|(_, i)| i);
+            if info.is_some_and(|i| i.name == "config") {
+                // Cyclic `--config` references are rejected by Bazel; don't recurse endlessly
+                if let Some(value) = flag.value.as_ref().filter(|v| !chain.contains(&v.0)) {
+                    chain.push(value.0.clone());
+                    let nested = get_applicable_lines(files, line_order, commands, Some(&value.0));
+                    expand_lines(
+                        files,
+                        line_order,
+                        bazel_flags,
+                        commands,
+                        &nested,
+                        chain,
+                        result,
+                    );
+                    chain.pop();
+                }
+                continue;
+            }
+            let name = match info {
+                Some(info) => info.name.clone(),
+                None => raw_name
+                    .strip_prefix("--")
+                    .unwrap_or(raw_name)
+                    .trim_end_matches('=')
+                    .to_string(),
+            };
+            let mut text = format_flag(flag);
+            let mut value = flag.value.as_ref().map(|v| v.0.clone());
+            let mut span = get_flag_span(flag).unwrap_or_default();
+            if let Some((_, value_nr, abbreviated_value)) =
+                get_flag_value(bazel_flags, line, flag_nr).filter(|(_, nr, _)| *nr != flag_nr)
+            {
+                consumed_value = true;
+                text += " ";
+                text += &format_flag(&line.flags[value_nr]);
+                span.end = abbreviated_value.1.end;
+                value = Some(abbreviated_value.0.clone());
+            }
+            let expanded = ExpandedFlag {
+                flag: text,
+                name,
+                invocation: raw_name.to_string(),
+                value,
+                path: file.path.clone(),
+                line_nr: *line_nr,
+                span,
+                command: line
+                    .command
+                    .as_ref()
+                    .map(|c| c.0.clone())
+                    .unwrap_or_default(),
+                via: chain.iter().skip(1).cloned().collect::<Vec<_>>(),
+                expanded_from: None,
+            };
+            // Expansion flags (e.g. `--experimental_spawn_scheduler`) are replaced by the
+            // flags they expand to, as done by Bazel
+            match info.filter(|i| !i.expansion.is_empty() && !i.is_negated_invocation(raw_name)) {
+                Some(info) => result.extend(info.get_expanded_flags().into_iter().map(
+                    |(invocation, value)| {
+                        ExpandedFlag {
+                            flag: match value {
+                                Some(value) => format!("{invocation}={}", format_token(value)),
+                                None => invocation.to_string(),
+                            },
+                            name: bazel_flags
+                                .get_by_invocation(invocation)
+                                .map(|(_, i)| i.name.clone())
+                                .unwrap_or_else(|| invocation.trim_start_matches('-').to_string()),
+                            invocation: invocation.to_string(),
+                            value: value.map(|v| v.to_string()),
+                            expanded_from: Some(raw_name.to_string()),
+                            ..expanded.clone()
+                        }
+                    },
+                )),
+                None => result.push(expanded),
+            }
+        }
+    }
+}
+
+// Lists all flags `--config=<config>` expands to when used for `command`,
+// following nested `--config` references
+pub fn expand_config(
+    files: &[RcFile],
+    bazel_flags: &BazelFlags,
+    command: &str,
+    config: &str,
+) -> Vec<ExpandedFlag> {
+    expand_config_in_order(files, &get_line_order(files), bazel_flags, command, config)
+}
+
+fn expand_config_in_order(
+    files: &[RcFile],
+    line_order: &LineOrder,
+    bazel_flags: &BazelFlags,
+    command: &str,
+    config: &str,
+) -> Vec<ExpandedFlag> {
+    let commands = get_applicable_commands(command);
+    let lines = get_applicable_lines(files, line_order, &commands, Some(config));
+    let mut result = Vec::<ExpandedFlag>::new();
+    let mut chain = vec![config.to_string()];
+    expand_lines(
+        files,
+        line_order,
+        bazel_flags,
+        &commands,
+        &lines,
+        &mut chain,
+        &mut result,
+    );
+    result
+}
+
+// The flags in effect for `bazel <command> --config=<a> --config=<b>`: the flags of all
+// lines without config applying to the command, followed by the expanded configs
+pub fn get_effective_flags(
+    files: &[RcFile],
+    bazel_flags: &BazelFlags,
+    command: &str,
+    configs: &[String],
+) -> Vec<ExpandedFlag> {
+    let line_order = get_line_order(files);
+    let commands = get_applicable_commands(command);
+    let lines = get_applicable_lines(files, &line_order, &commands, None);
+    let mut result = Vec::<ExpandedFlag>::new();
+    let mut chain = vec![String::new()];
+    expand_lines(
+        files,
+        &line_order,
+        bazel_flags,
+        &commands,
+        &lines,
+        &mut chain,
+        &mut result,
+    );
+    for config in configs {
+        result.extend(expand_config_in_order(
+            files,
+            &line_order,
+            bazel_flags,
+            command,
+            config,
+        ));
+    }
+    result
+}
+
+// The canonical form of a flag, as printed by `bazel canonicalize-flags`: the
+// full flag name, and booleans as `--name` / `--noname`. `None` for unknown flags.
+pub fn canonicalize_invocation(
+    bazel_flags: &BazelFlags,
+    invocation: &str,
+    value: Option<&str>,
+) -> Option<String> {
+    let (_, info) = bazel_flags.get_by_invocation(invocation)?;
+    if info.is_negated_invocation(invocation) {
+        return Some(format!("--no{}", info.name));
+    }
+    let is_boolean =
+        info.has_negative_flag() && !info.requires_value() && get_allowed_values(info).is_none();
+    Some(match value {
+        None => format!("--{}", info.name),
+        Some(v) if is_boolean && ["true", "yes", "1"].contains(&v.to_lowercase().as_str()) => {
+            format!("--{}", info.name)
+        }
+        Some(v) if is_boolean && ["false", "no", "0"].contains(&v.to_lowercase().as_str()) => {
+            format!("--no{}", info.name)
+        }
+        Some(v) => format!("--{}={}", info.name, format_token(v)),
+    })
+}
+
+pub fn canonicalize_flag(bazel_flags: &BazelFlags, flag: &ExpandedFlag) -> String {
+    canonicalize_invocation(bazel_flags, &flag.invocation, flag.value.as_deref())
+        .unwrap_or_else(|| flag.flag.clone())
+}
+
+// Renders the effective flags as a bazelrc-like document. Flags which are
+// overridden later on are omitted, unless they can be passed multiple times.
+pub fn format_effective_config(
+    files: &[RcFile],
+    bazel_flags: &BazelFlags,
+    file_path: &Path,
+    command: &str,
+    configs: &[String],
+) -> String {
+    let workspace = get_workspace_path(file_path);
+    let flags = get_effective_flags(files, bazel_flags, command, configs);
+    let mut invocation = format!("bazel {command}");
+    for config in configs {
+        invocation += &format!(" --config={config}");
+    }
+    let mut result = format!("# Effective flags for `{invocation}`, in canonical form\n");
+    for (i, flag) in flags.iter().enumerate() {
+        let allows_multiple = bazel_flags
+            .get_by_invocation(&flag.invocation)
+            .is_some_and(|(_, info)| info.allows_multiple());
+        if !allows_multiple && flags[i + 1..].iter().any(|f| f.name == flag.name) {
+            continue;
+        }
+        result += &format!(
+            "{}  # {}",
+            canonicalize_flag(bazel_flags, flag),
+            format_location(workspace.as_deref(), flag)
+        );
+        if let Some(expanded_from) = &flag.expanded_from {
+            result += &format!(", expanded from {expanded_from}");
+        }
+        result += "\n";
+    }
+    result
+}
+
+// Flags which are set multiple times with different values. Flags which can be
+// passed multiple times (e.g. `--copt`) are not considered conflicting.
+pub fn find_conflicts<'a>(
+    flags: &'a [ExpandedFlag],
+    bazel_flags: &BazelFlags,
+) -> Vec<Vec<&'a ExpandedFlag>> {
+    let mut conflicts = Vec::<Vec<&ExpandedFlag>>::new();
+    for (i, flag) in flags.iter().enumerate() {
+        if flags[..i].iter().any(|f| f.name == flag.name) || flag.name.is_empty() {
+            continue;
+        }
+        let allows_multiple = bazel_flags
+            .get_by_invocation(&format!("--{}", flag.name))
+            .is_some_and(|(_, info)| info.allows_multiple());
+        if allows_multiple {
+            continue;
+        }
+        let same_name = flags
+            .iter()
+            .filter(|f| f.name == flag.name)
+            .collect::<Vec<_>>();
+        let canonical = canonicalize_flag(bazel_flags, flag);
+        if same_name
+            .iter()
+            .any(|f| canonicalize_flag(bazel_flags, f) != canonical)
+        {
+            conflicts.push(same_name);
+        }
+    }
+    conflicts
+}
+
+// An assignment without effect, since a later assignment of the same flag for the same
+// command and config takes precedence
+#[derive(Debug)]
+pub struct DeadAssignment {
+    pub flag: ExpandedFlag,
+    // The assignment which is applied last, hence wins
+    pub winner: ExpandedFlag,
+}
+
+// Finds the assignments within `file_path` which are overridden later on, either by a
+// later line of the same file or by a file which Bazel reads later. `files` contains the
+// file at `file_path` together with its related files, in the order Bazel reads them.
+// Flags which can be passed multiple times (e.g. `--copt`) are never overridden.
+pub fn find_dead_assignments(
+    files: &[RcFile],
+    bazel_flags: &BazelFlags,
+    file_path: &Path,
+) -> Vec<DeadAssignment> {
+    let Some(current) = files.iter().find(|f| f.path == file_path) else {
+        return Vec::new();
+    };
+    let mut scopes = Vec::<(&str, Option<&str>)>::new();
+    for line in &current.lines {
+        let Some(command) = &line.command else {
+            continue;
+        };
+        if ["startup", "import", "try-import"].contains(&command.0.as_str()) {
+            continue;
+        }
+        let scope = (
+            command.0.as_str(),
+            line.config.as_ref().map(|c| c.0.as_str()),
+        );
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+
+    // Imports are read in place of the `import` line. Files not read by Bazel come first.
+    let read_order = get_line_read_order(file_path, files);
+    let line_order = get_line_order(files);
+    let mut result = Vec::<DeadAssignment>::new();
+    for (command, config) in scopes {
+        // Only the lines of exactly this scope, without following nested configs
+        let commands = Some(vec![vec![command]]);
+        let lines = get_applicable_lines(files, &line_order, &commands, config);
+        let mut flags = Vec::<ExpandedFlag>::new();
+        let mut chain = vec![config.unwrap_or_default().to_string()];
+        expand_lines(
+            files,
+            &line_order,
+            bazel_flags,
+            &commands,
+            &lines,
+            &mut chain,
+            &mut flags,
+        );
+        flags.retain(|f| f.via.is_empty() && !f.name.is_empty());
+        flags.sort_by_key(|f| {
+            read_order
+                .get(&(f.path.clone(), f.line_nr))
+                .map_or(0, |position| position + 1)
+        });
+        for (i, flag) in flags.iter().enumerate() {
+            // Expansion flags set further flags and are hence never entirely dead
+            if flag.path != file_path || flag.expanded_from.is_some() {
+                continue;
+            }
+            let allows_multiple = bazel_flags
+                .get_by_invocation(&format!("--{}", flag.name))
+                .is_some_and(|(_, info)| info.allows_multiple());
+            if allows_multiple {
+                continue;
+            }
+            if let Some(winner) = flags[i + 1..].iter().rfind(|f| f.name == flag.name) {
+                result.push(DeadAssignment {
+                    flag: flag.clone(),
+                    winner: winner.clone(),
+                });
+            }
+        }
+    }
+    result
+}
+
+// Markdown explaining that the assignment at `span` is overridden, and by which assignment
+pub fn get_override_hover_markdown(
+    files: &[RcFile],
+    bazel_flags: &BazelFlags,
+    file_path: &Path,
+    span: &Span,
+) -> Option<String> {
+    let workspace = get_workspace_path(file_path);
+    let overridden = find_dead_assignments(files, bazel_flags, file_path)
+        .into_iter()
+        .find(|o| o.flag.span.start <= span.start && span.end <= o.flag.span.end)?;
+    let reason = if overridden.winner.path == file_path {
+        "a later line"
+    } else {
+        "a file Bazel reads later"
+    };
+    Some(format!(
+        "**This value is overridden.** `{}` ({}) wins, since it is set by {}.",
+        overridden.winner.flag,
+        format_location(workspace.as_deref(), &overridden.winner),
+        reason
+    ))
+}
+
+fn format_location(workspace: Option<&Path>, flag: &ExpandedFlag) -> String {
+    let relative = workspace.and_then(|ws| flag.path.strip_prefix(ws).ok());
+    format!(
+        "{}:{}",
+        relative.unwrap_or(&flag.path).display(),
+        flag.line_nr + 1
+    )
+}
+
+// Markdown describing what `--config=<config>` expands to
+pub fn get_config_hover_markdown(
+    files: &[RcFile],
+    bazel_flags: &BazelFlags,
+    file_path: &Path,
+    command: &str,
+    config: &str,
+) -> String {
+    let workspace = get_workspace_path(file_path);
+    let flags = expand_config(files, bazel_flags, command, config);
+    if flags.is_empty() {
+        return format!("Config `{config}` does not set any flags for `{command}`");
+    }
+    let mut result = format!("Config `{config}` expands to the following flags for `{command}`:\n");
+    for flag in &flags {
+        result += &format!(
+            "\n* `{}` ({})",
+            flag.flag,
+            format_location(workspace.as_deref(), flag)
+        );
+        if let Some(expanded_from) = &flag.expanded_from {
+            result += &format!(", expanded from `{expanded_from}`");
+        }
+        if !flag.via.is_empty() {
+            let via = flag
+                .via
+                .iter()
+                .map(|c| format!("`--config={c}`"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            result += &format!(", via {via}");
+        }
+    }
+    let conflicts = find_conflicts(&flags, bazel_flags);
+    if !conflicts.is_empty() {
+        result += "\n\nConflicts:\n";
+        for conflict in conflicts {
+            let values = conflict
+                .iter()
+                .map(|f| {
+                    format!(
+                        "`{}` ({})",
+                        f.flag,
+                        format_location(workspace.as_deref(), f)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            result += &format!(
+                "\n* `--{}` is set multiple times: {}",
+                conflict[0].name, values
+            );
+        }
+    }
+    result
+}
+
+#[test]
+fn test_config_hover() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let files = vec![
+        RcFile::from_str(
+            PathBuf::from("/ws/.bazelrc"),
+            "build:ci --jobs=4 --config=remote\ntest:ci --test_output=all\nrun:ci -k\nbuild:remote --copt=-O2\nbuild:remote --config=ci\n",
+            &flags,
+        ),
+        RcFile::from_str(
+            PathBuf::from("/ws/ci.bazelrc"),
+            "common:ci --jobs=8 --copt=-g\n",
+            &flags,
+        ),
+    ];
+    let summary = |command: &str| {
+        expand_config(&files, &flags, command, "ci")
+            .into_iter()
+            .map(|f| (f.flag, f.line_nr, f.via))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        summary("test"),
+        vec![
+            ("--jobs=8".to_string(), 0, vec![]),
+            ("--copt=-g".to_string(), 0, vec![]),
+            ("--jobs=4".to_string(), 0, vec![]),
+            ("--copt=-O2".to_string(), 3, vec!["remote".to_string()]),
+            ("--test_output=all".to_string(), 1, vec![]),
+        ]
+    );
+    assert_eq!(summary("build").len(), 4);
+
+    let markdown =
+        get_config_hover_markdown(&files, &flags, Path::new("/ws/.bazelrc"), "build", "ci");
+    assert_eq!(
+        markdown,
+        "Config `ci` expands to the following flags for `build`:\n\n\
+        * `--jobs=8` (/ws/ci.bazelrc:1)\n\
+        * `--copt=-g` (/ws/ci.bazelrc:1)\n\
+        * `--jobs=4` (/ws/.bazelrc:1)\n\
+        * `--copt=-O2` (/ws/.bazelrc:4), via `--config=remote`\n\n\
+        Conflicts:\n\n\
+        * `--jobs` is set multiple times: `--jobs=8` (/ws/ci.bazelrc:1), `--jobs=4` (/ws/.bazelrc:1)"
+    );
+    assert_eq!(
+        get_config_hover_markdown(&files, &flags, Path::new("/ws/.bazelrc"), "build", "x"),
+        "Config `x` does not set any flags for `build`"
+    );
+}
+
+#[test]
+fn test_effective_config() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let files = vec![RcFile::from_str(
+        PathBuf::from("/ws/.bazelrc"),
+        "build --jobs=4 -k\ntest --test_output=errors\ntest:ci --nokeep_going --jobs=8\nbuild:ci -c opt --copt=-O2 --config=debug\nbuild:debug --copt=-g --color=yes\nrun:ci --jobs=1\nstartup --batch\n",
+        &flags,
+    )];
+    assert_eq!(
+        format_effective_config(
+            &files,
+            &flags,
+            Path::new("/ws/.bazelrc"),
+            "test",
+            &["ci".to_string()],
+        ),
+        "# Effective flags for `bazel test --config=ci`, in canonical form\n\
+        --test_output=errors  # /ws/.bazelrc:2\n\
+        --compilation_mode=opt  # /ws/.bazelrc:4\n\
+        --copt=-O2  # /ws/.bazelrc:4\n\
+        --copt=-g  # /ws/.bazelrc:5\n\
+        --color=yes  # /ws/.bazelrc:5\n\
+        --nokeep_going  # /ws/.bazelrc:3\n\
+        --jobs=8  # /ws/.bazelrc:3\n"
+    );
+}
+
+#[test]
+fn test_expansion_flags() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, bazel_flags_proto::FlagInfo};
+
+    let flags = load_packaged_bazel_flags("8.0.0").with_custom_flags(&[FlagInfo {
+        name: "experimental_spawn_scheduler".to_string(),
+        has_negative_flag: Some(true),
+        expansion: vec![
+            "--internal_spawn_scheduler".to_string(),
+            "--spawn_strategy=dynamic".to_string(),
+        ],
+        ..Default::default()
+    }]);
+    let files = vec![RcFile::from_str(
+        PathBuf::from("/ws/.bazelrc"),
+        "build --experimental_spawn_scheduler\nbuild:ci --spawn_strategy=local\nbuild:off --noexperimental_spawn_scheduler\nbuild:dyn --experimental_spawn_scheduler\n",
+        &flags,
+    )];
+    // Expansion flags are replaced by the flags they expand to
+    assert_eq!(
+        format_effective_config(
+            &files,
+            &flags,
+            Path::new("/ws/.bazelrc"),
+            "build",
+            &["ci".to_string()],
+        ),
+        "# Effective flags for `bazel build --config=ci`, in canonical form\n\
+        --internal_spawn_scheduler  # /ws/.bazelrc:1, expanded from --experimental_spawn_scheduler\n\
+        --spawn_strategy=local  # /ws/.bazelrc:2\n"
+    );
+    // The conflict between the expanded value and the config is detected
+    let effective = get_effective_flags(&files, &flags, "build", &["ci".to_string()]);
+    let conflicts = find_conflicts(&effective, &flags);
+    assert_eq!(
+        conflicts
+            .iter()
+            .map(|c| c.iter().map(|f| f.flag.as_str()).collect::<Vec<_>>())
+            .collect::<Vec<_>>(),
+        vec![vec!["--spawn_strategy=dynamic", "--spawn_strategy=local"]]
+    );
+    assert_eq!(
+        effective[1].expanded_from.as_deref(),
+        Some("--experimental_spawn_scheduler")
+    );
+    // Negated expansion flags don't expand to anything
+    assert_eq!(
+        expand_config(&files, &flags, "build", "off")
+            .into_iter()
+            .map(|f| f.flag)
+            .collect::<Vec<_>>(),
+        vec!["--noexperimental_spawn_scheduler"]
+    );
+    assert_eq!(
+        get_config_hover_markdown(&files, &flags, Path::new("/ws/.bazelrc"), "build", "dyn"),
+        "Config `dyn` expands to the following flags for `build`:\n\n\
+        * `--internal_spawn_scheduler` (/ws/.bazelrc:4), expanded from `--experimental_spawn_scheduler`\n\
+        * `--spawn_strategy=dynamic` (/ws/.bazelrc:4), expanded from `--experimental_spawn_scheduler`"
+    );
+}
+
+#[test]
+fn test_dead_assignments() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let files = vec![
+        RcFile::from_str(
+            PathBuf::from("/ws/.bazelrc"),
+            "build --jobs=4 --copt=-g -c opt
+build:ci --jobs=2
+test --jobs=3
+build -c dbg
+",
+            &flags,
+        ),
+        RcFile::from_str(
+            PathBuf::from("/ws/user.bazelrc"),
+            "build --jobs=8 --copt=-O2
+",
+            &flags,
+        ),
+    ];
+    let overridden = find_dead_assignments(&files, &flags, Path::new("/ws/.bazelrc"))
+        .into_iter()
+        .map(|o| (o.flag.flag, o.winner.flag, o.winner.line_nr))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        overridden,
+        vec![
+            ("--jobs=4".to_string(), "--jobs=8".to_string(), 0),
+            ("-c opt".to_string(), "-c dbg".to_string(), 3),
+        ]
+    );
+    // Flags in later files are not overridden by earlier files
+    assert!(find_dead_assignments(&files, &flags, Path::new("/ws/user.bazelrc")).is_empty());
+
+    let jobs_span = files[0].lines[0].flags[0].name.as_ref().unwrap().1.clone();
+    assert_eq!(
+        get_override_hover_markdown(&files, &flags, Path::new("/ws/.bazelrc"), &jobs_span),
+        Some(
+            "**This value is overridden.** `--jobs=8` (/ws/user.bazelrc:1) wins, since it is set by a file Bazel reads later."
+                .to_string()
+        )
+    );
+    let copt_span = files[0].lines[0].flags[1].name.as_ref().unwrap().1.clone();
+    assert_eq!(
+        get_override_hover_markdown(&files, &flags, Path::new("/ws/.bazelrc"), &copt_span),
+        None
+    );
+}
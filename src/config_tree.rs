@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{Range, TextDocumentIdentifier, Url};
+
+use crate::{
+    code_action::get_flag_span,
+    import_graph::RcFile,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+};
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigTreeParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+// The flags of a bazelrc file, grouped by command and config.
+// Consumed by editor extensions to render a tree view.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigTreeFile {
+    pub uri: Url,
+    pub read_only: bool,
+    pub commands: Vec<ConfigTreeCommand>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigTreeCommand {
+    pub name: String,
+    pub configs: Vec<ConfigTreeConfig>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigTreeConfig {
+    // `None` for flags applying without any `--config`
+    pub name: Option<String>,
+    // The first line defining this command / config combination
+    pub range: Range,
+    pub flags: Vec<ConfigTreeFlag>,
+}
+
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigTreeFlag {
+    pub name: Option<String>,
+    pub value: Option<String>,
+    pub range: Range,
+}
+
+pub fn build_config_tree_for_file(
+    file: &RcFile,
+    encoding: PositionEncoding,
+) -> Option<ConfigTreeFile> {
+    let mut commands = Vec::<ConfigTreeCommand>::new();
+    for line in &file.lines {
+        let Some(command) = &line.command else {
+            continue;
+        };
+        let config_name = line.config.as_ref().map(|c| c.0.clone());
+        let command_idx = match commands.iter().position(|c| c.name == command.0) {
+            Some(idx) => idx,
+            None => {
+                commands.push(ConfigTreeCommand {
+                    name: command.0.clone(),
+                    configs: Vec::new(),
+                });
+                commands.len() - 1
+            }
+        };
+        let configs = &mut commands[command_idx].configs;
+        let config_idx = match configs.iter().position(|c| c.name == config_name) {
+            Some(idx) => idx,
+            None => {
+                let definition_span = line.config.as_ref().unwrap_or(command).1.clone();
+                configs.push(ConfigTreeConfig {
+                    name: config_name,
+                    range: range_to_lsp(&file.rope, &definition_span, encoding)?,
+                    flags: Vec::new(),
+                });
+                configs.len() - 1
+            }
+        };
+        for flag in &line.flags {
+            configs[config_idx].flags.push(ConfigTreeFlag {
+                name: flag.name.as_ref().map(|n| n.0.clone()),
+                value: flag.value.as_ref().map(|v| v.0.clone()),
+                range: range_to_lsp(&file.rope, &get_flag_span(flag)?, encoding)?,
+            });
+        }
+    }
+    Some(ConfigTreeFile {
+        uri: Url::from_file_path(&file.path).ok()?,
+        read_only: file.read_only,
+        commands,
+    })
+}
+
+pub fn build_config_tree(files: &[RcFile], encoding: PositionEncoding) -> Vec<ConfigTreeFile> {
+    files
+        .iter()
+        .filter_map(|file| build_config_tree_for_file(file, encoding))
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_config_tree() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use std::path::PathBuf;
+    use tower_lsp::lsp_types::Position;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let file = RcFile::from_str(
+        PathBuf::from("/ws/.bazelrc"),
+        "build --jobs=4\nbuild:ci --keep_going\ntest --test_output=all\nbuild:ci -c opt",
+        &flags,
+    );
+    let tree = build_config_tree(&[file], PositionEncoding::Utf16);
+    assert_eq!(tree.len(), 1);
+    assert_eq!(tree[0].uri.as_str(), "file:///ws/.bazelrc");
+    let summary = tree[0]
+        .commands
+        .iter()
+        .map(|c| {
+            let configs = c
+                .configs
+                .iter()
+                .map(|cfg| (cfg.name.clone(), cfg.flags.len()))
+                .collect::<Vec<_>>();
+            (c.name.as_str(), configs)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        summary,
+        vec![
+            ("build", vec![(None, 1), (Some("ci".to_string()), 3)]),
+            ("test", vec![(None, 1)]),
+        ]
+    );
+    assert_eq!(
+        tree[0].commands[0].configs[1].range,
+        Range::new(Position::new(1, 5), Position::new(1, 8))
+    );
+    assert_eq!(
+        tree[0].commands[0].configs[1].flags[0],
+        ConfigTreeFlag {
+            name: Some("--keep_going".to_string()),
+            value: None,
+            range: Range::new(Position::new(1, 9), Position::new(1, 21))
+        }
+    );
+}
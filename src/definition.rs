@@ -1,8 +1,15 @@
 use std::path::Path;
 
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
 use tower_lsp::lsp_types::*;
 
-use crate::{file_utils::resolve_bazelrc_path, line_index::IndexEntryKind, parser::Line};
+use crate::{
+    file_utils::resolve_bazelrc_path,
+    line_index::IndexEntryKind,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::Line,
+};
 
 pub fn get_definitions(
     file_path: &Path,
@@ -40,3 +47,145 @@ pub fn get_definitions(
         _ => None,
     }
 }
+
+// Stored as `data` of document links, such that the target can be resolved lazily
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentLinkData {
+    pub uri: Url,
+    pub path: String,
+}
+
+// Links the paths of all `import` and `try-import` lines. The targets are
+// only computed by `resolve_document_link`.
+pub fn get_document_links(
+    uri: &Url,
+    rope: &Rope,
+    lines: &[Line],
+    encoding: PositionEncoding,
+) -> Vec<DocumentLink> {
+    lines
+        .iter()
+        .filter_map(|line| {
+            let command = line.command.as_ref()?;
+            if command.0 != "import" && command.0 != "try-import" {
+                return None;
+            }
+            if line.flags.len() != 1 {
+                return None;
+            }
+            let flag = &line.flags[0];
+            if flag.name.is_some() {
+                return None;
+            }
+            let value = flag.value.as_ref()?;
+            let data = DocumentLinkData {
+                uri: uri.clone(),
+                path: value.0.clone(),
+            };
+            Some(DocumentLink {
+                range: range_to_lsp(rope, &value.1, encoding)?,
+                target: None,
+                tooltip: None,
+                data: serde_json::to_value(data).ok(),
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Resolves `%workspace%` and `~` to compute the link target
+pub fn resolve_document_link(mut link: DocumentLink) -> DocumentLink {
+    let Some(data) = link
+        .data
+        .clone()
+        .and_then(|d| serde_json::from_value::<DocumentLinkData>(d).ok())
+    else {
+        return link;
+    };
+    let Some(path) = data
+        .uri
+        .to_file_path()
+        .ok()
+        .and_then(|file_path| resolve_bazelrc_path(&file_path, &data.path))
+    else {
+        return link;
+    };
+    link.tooltip = Some(path.display().to_string());
+    link.target = Url::from_file_path(path).ok();
+    link
+}
+
+// Describes the file imported by an `import` / `try-import` line
+pub fn get_import_hover_markdown(
+    file_path: &Path,
+    command: &str,
+    raw_path: &str,
+) -> Option<String> {
+    let path = resolve_bazelrc_path(file_path, raw_path)?;
+    let status = if path.is_file() {
+        "The file exists."
+    } else if command == "try-import" {
+        "The file does not exist and is skipped."
+    } else {
+        "The file does not exist. Bazel will fail."
+    };
+    Some(format!("`{}`\n\n{}", path.display(), status))
+}
+
+#[test]
+fn test_document_links() {
+    use crate::parser::parse_from_str;
+    use crate::test_utils::TestDir;
+
+    let ws = TestDir::workspace();
+    let uri = Url::from_file_path(ws.join(".bazelrc")).unwrap();
+
+    let text = "build -k\nimport %workspace%/ci.bazelrc\ntry-import \"user.bazelrc\"";
+    let links = get_document_links(
+        &uri,
+        &Rope::from_str(text),
+        &parse_from_str(text).lines,
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(links.len(), 2);
+    assert_eq!(
+        links[0].range,
+        Range::new(Position::new(1, 7), Position::new(1, 29))
+    );
+    // The targets are only computed when resolving the link
+    assert!(links.iter().all(|l| l.target.is_none()));
+    let targets = links
+        .into_iter()
+        .map(|l| resolve_document_link(l).target)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        targets,
+        vec![
+            Url::from_file_path(ws.join("ci.bazelrc")).ok(),
+            Url::from_file_path(ws.join("user.bazelrc")).ok()
+        ]
+    );
+}
+
+#[test]
+fn test_import_hover() {
+    use crate::test_utils::TestDir;
+
+    let ws = TestDir::workspace();
+    ws.write("ci.bazelrc", "");
+    let file_path = ws.join(".bazelrc");
+    assert_eq!(
+        get_import_hover_markdown(&file_path, "import", "%workspace%/ci.bazelrc"),
+        Some(format!(
+            "`{}`\n\nThe file exists.",
+            ws.join("ci.bazelrc").display()
+        ))
+    );
+    assert_eq!(
+        get_import_hover_markdown(&file_path, "try-import", "%workspace%/user.bazelrc"),
+        Some(format!(
+            "`{}`\n\nThe file does not exist and is skipped.",
+            ws.join("user.bazelrc").display()
+        ))
+    );
+}
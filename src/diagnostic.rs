@@ -1,20 +1,174 @@
-use std::path::Path;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
 use chumsky::error::Simple;
 use regex::Regex;
 use ropey::Rope;
-use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, DiagnosticTag};
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DiagnosticTag, Location,
+    NumberOrString, Range, Url,
+};
 
 use crate::{
-    bazel_flags::{combine_key_value_flags, BazelFlags, FlagLookupType},
-    file_utils::resolve_bazelrc_path,
-    lsp_utils::range_to_lsp,
+    bazel_flags::{combine_key_value_flags, BazelFlags, FlagLookupType, COMMAND_PARENTS},
+    bazel_flags_proto::FlagInfo,
+    code_action::get_flag_span,
+    config_expansion::{
+        expand_config, find_conflicts, find_dead_assignments, get_effective_flags, ExpandedFlag,
+    },
+    env_flags::{diagnose_env_value, find_duplicate_env_vars, is_env_flag},
+    file_utils::{get_workspace_path, resolve_bazelrc_path},
+    flag_alias::{get_flag_aliases, lookup_flag_alias, FlagAliases},
+    flag_paths::{expects_directory, get_flag_paths, PathStatus},
+    flag_values::{
+        get_allowed_values, get_flag_value, is_allowed_value, is_boolean_value, suggest_values,
+        BOOLEAN_VALUES,
+    },
+    import_graph::{
+        find_import_issues, get_defined_configs, load_related_files, load_workspace_files,
+        read_file_from_disk, ImportIssue, RcFile,
+    },
+    line_continuations::diagnostics_for_line_continuations,
+    lsp_utils::{range_to_lsp, PositionEncoding},
     parser::{parse_from_str, Line, ParserResult},
+    placeholders::{
+        find_placeholders, is_shell_command_flag, supports_workspace_placeholder, Placeholder,
+        WORKSPACE_PLACEHOLDER_FLAGS,
+    },
+    platform_config::{get_flag_platform, get_platform_name, is_platform_specific_config_enabled},
+    rc_discovery::{get_line_order, sort_by_read_order},
+    references::skip_separator,
+    starlark_flags::{is_starlark_flag, parse_starlark_flag},
+    suppressions::{diagnostics_for_suppressions, filter_suppressed_diagnostics},
+    tokenizer::Span,
 };
 
+// Declares the checks together with their names, which are used as diagnostic codes, as
+// keys of the severity overrides and within suppression comments
+macro_rules! diagnostic_checks {
+    ($($check:ident => $name:literal,)*) => {
+        // The kinds of diagnostics which can be configured by the user, either via their
+        // severity or via suppression comments
+        #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum DiagnosticCheck {
+            $(#[serde(rename = $name)] $check,)*
+        }
+
+        impl DiagnosticCheck {
+            pub const ALL: &[DiagnosticCheck] = &[$(DiagnosticCheck::$check,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(DiagnosticCheck::$check => $name,)*
+                }
+            }
+        }
+    };
+}
+
+diagnostic_checks! {
+    UnknownFlag => "unknown-flag",
+    DeprecatedFlag => "deprecated-flag",
+    WrongCommand => "wrong-command",
+    DuplicateFlag => "duplicate-flag",
+    RepeatedFlag => "repeated-flag",
+    DeadAssignment => "dead-assignment",
+    PlatformConfig => "platform-config",
+    ImportIssue => "import-issue",
+    UnknownConfig => "unknown-config",
+    UnusedConfig => "unused-config",
+    ConflictingFlag => "conflicting-flag",
+    ExpansionOverride => "expansion-override",
+    FlagPath => "flag-path",
+    StarlarkFlag => "starlark-flag",
+    DefaultValue => "default-value",
+    UnstableFlag => "unstable-flag",
+    ConfigNesting => "config-nesting",
+    SyntaxError => "syntax-error",
+    UnknownCommand => "unknown-command",
+    InvalidValue => "invalid-value",
+    EnvValue => "env-value",
+    Abbreviation => "abbreviation",
+    ConfigName => "config-name",
+    Placeholder => "placeholder",
+    LineContinuation => "line-continuation",
+    Bazeliskrc => "bazeliskrc",
+    Suppression => "suppression",
+}
+
+impl DiagnosticCheck {
+    pub fn from_name(name: &str) -> Option<DiagnosticCheck> {
+        DiagnosticCheck::ALL
+            .iter()
+            .copied()
+            .find(|c| c.name() == name)
+    }
+
+    pub fn code(self) -> Option<NumberOrString> {
+        Some(NumberOrString::String(self.name().to_string()))
+    }
+
+    // A diagnostic reported by this check, without a severity. Clients show those as errors.
+    pub fn new_simple(self, range: Range, message: String) -> Diagnostic {
+        Diagnostic {
+            code: self.code(),
+            ..Diagnostic::new_simple(range, message)
+        }
+    }
+
+    // The check which reported the diagnostic, if any
+    pub fn of(diagnostic: &Diagnostic) -> Option<DiagnosticCheck> {
+        match &diagnostic.code {
+            Some(NumberOrString::String(code)) => DiagnosticCheck::from_name(code),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SeverityOverride {
+    Off,
+    Hint,
+    Information,
+    Warning,
+    Error,
+}
+
+// Reports the diagnostics with the severity configured for their check. `off` drops them.
+pub fn apply_severity_overrides(
+    diagnostics: Vec<Diagnostic>,
+    severities: &HashMap<DiagnosticCheck, SeverityOverride>,
+) -> Vec<Diagnostic> {
+    if severities.is_empty() {
+        return diagnostics;
+    }
+    diagnostics
+        .into_iter()
+        .filter_map(|d| {
+            let severity = match DiagnosticCheck::of(&d).and_then(|c| severities.get(&c)) {
+                None => return Some(d),
+                Some(SeverityOverride::Off) => return None,
+                Some(SeverityOverride::Hint) => DiagnosticSeverity::HINT,
+                Some(SeverityOverride::Information) => DiagnosticSeverity::INFORMATION,
+                Some(SeverityOverride::Warning) => DiagnosticSeverity::WARNING,
+                Some(SeverityOverride::Error) => DiagnosticSeverity::ERROR,
+            };
+            Some(Diagnostic {
+                severity: Some(severity),
+                ..d
+            })
+        })
+        .collect()
+}
+
 pub fn diagnostics_from_parser<'a>(
     rope: &'a Rope,
     errors: &'a [Simple<char>],
+    encoding: PositionEncoding,
 ) -> impl Iterator<Item = Diagnostic> + 'a {
     errors.iter().filter_map(move |item| {
         let (message, span) = match item.reason() {
@@ -47,94 +201,375 @@ pub fn diagnostics_from_parser<'a>(
         };
 
         || -> Option<Diagnostic> {
-            Some(Diagnostic::new_simple(range_to_lsp(rope, &span)?, message))
+            Some(
+                DiagnosticCheck::SyntaxError
+                    .new_simple(range_to_lsp(rope, &span, encoding)?, message),
+            )
         }()
     })
 }
 
-const SKIPPED_PREFIXES: [&str; 4] = ["--//", "--no//", "--@", "--no@"];
+fn diagnose_enum_value(
+    rope: &Rope,
+    line: &Line,
+    flag_nr: usize,
+    bazel_flags: &BazelFlags,
+    encoding: PositionEncoding,
+) -> Option<Diagnostic> {
+    let name = &line.flags[flag_nr].name.as_ref()?.0;
+    let (info, _, (value, span)) = get_flag_value(bazel_flags, line, flag_nr)?;
+    let allowed = get_allowed_values(info)?;
+    if info.is_negated_invocation(name) || is_allowed_value(&allowed, value) {
+        return None;
+    }
+    let mut message = format!(
+        "The flag {:?} expects one of {}, not {:?}.",
+        name,
+        allowed.join(", "),
+        value
+    );
+    if let Some(suggestion) = suggest_values(&allowed, value).first() {
+        message += &format!(" Did you mean {:?}?", suggestion);
+    }
+    Some(Diagnostic {
+        range: range_to_lsp(rope, span, encoding)?,
+        message,
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: DiagnosticCheck::InvalidValue.code(),
+        ..Default::default()
+    })
+}
+
+// Normalizes the spellings of boolean values, see BooleanConverter in Bazel's `Converters.java`
+fn normalize_boolean(value: &str) -> String {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "1" => "true".to_string(),
+        "false" | "no" | "0" => "false".to_string(),
+        other => other.to_string(),
+    }
+}
+
+// The value assigned by the given flag, normalized such that different spellings of the same
+// value compare equal, together with the span of the complete assignment
+pub fn get_assigned_value<'a>(
+    line: &Line,
+    flag_nr: usize,
+    bazel_flags: &'a BazelFlags,
+) -> Option<(&'a FlagInfo, String, Span)> {
+    let (name, name_span) = line.flags[flag_nr].name.as_ref()?;
+    let (_, info) = bazel_flags.get_by_invocation(name)?;
+    // Boolean flags can also be set via `--flag` and `--noflag`
+    let is_boolean = info.has_negative_flag() && !info.requires_value();
+    let (value, span) = if info.is_negated_invocation(name) {
+        ("false", name_span.clone())
+    } else if let Some((_, _, (value, value_span))) = get_flag_value(bazel_flags, line, flag_nr) {
+        (value.as_str(), name_span.start..value_span.end)
+    } else if is_boolean {
+        ("true", name_span.clone())
+    } else {
+        return None;
+    };
+    Some((info, normalize_value(info, value), span))
+}
+
+fn normalize_value(info: &FlagInfo, value: &str) -> String {
+    if info.has_negative_flag() && !info.requires_value() {
+        normalize_boolean(value)
+    } else if get_allowed_values(info).is_some() {
+        value.to_lowercase()
+    } else {
+        value.to_string()
+    }
+}
+
+// Do lines of the `inherited` command apply when running `command`?
+fn inherits_command(command: &str, inherited: &str) -> bool {
+    if inherited == command || inherited == "common" || inherited == "always" {
+        return true;
+    }
+    let mut ancestor = COMMAND_PARENTS.get(command);
+    while let Some(a) = ancestor {
+        if *a == inherited {
+            return true;
+        }
+        ancestor = COMMAND_PARENTS.get(a);
+    }
+    false
+}
+
+// Diagnoses assignments which restate the flag's default value. Those are pointless, unless
+// they reset a different value assigned earlier within the effective config, e.g.
+// `build:ci --nokeep_going` after `build --keep_going`. Assignments of the related files
+// count as well, in the order Bazel reads the lines.
+pub fn diagnostics_for_default_values(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    file_path: Option<&Path>,
+    related_files: &[RcFile],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let current_path = file_path.map(Path::to_path_buf).unwrap_or_default();
+    let mut files = vec![RcFile {
+        path: current_path.clone(),
+        rope: rope.clone(),
+        lines: lines.to_vec(),
+        read_only: false,
+    }];
+    if let Some(file_path) = file_path {
+        files.extend(related_files.iter().cloned());
+        sort_by_read_order(file_path, &mut files);
+    }
+    let mut ordered_lines = get_line_order(&files)
+        .into_iter()
+        .map(|((path, line_nr), position)| (position, path, line_nr))
+        .collect::<Vec<_>>();
+    ordered_lines.sort();
+
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    // The values assigned so far, by command, config and flag name
+    let mut assigned = HashMap::<(&str, Option<&str>, &str), String>::new();
+    for (_, path, line_nr) in ordered_lines {
+        let Some(file) = files.iter().find(|f| f.path == path) else {
+            continue;
+        };
+        let line = &file.lines[line_nr];
+        let Some((command, _)) = &line.command else {
+            continue;
+        };
+        let config = line.config.as_ref().map(|c| c.0.as_str());
+        for flag_nr in 0..line.flags.len() {
+            let Some((info, value, span)) = get_assigned_value(line, flag_nr, bazel_flags) else {
+                continue;
+            };
+            if info.allows_multiple() {
+                continue;
+            }
+            let default_value = info.default_value.as_ref().filter(|_| path == current_path);
+            if let Some(default_value) = default_value {
+                let resets_value = assigned.iter().any(|((c, cfg, name), v)| {
+                    *name == info.name
+                        && *v != value
+                        && (cfg.is_none() || *cfg == config)
+                        && inherits_command(command, c)
+                });
+                if value == normalize_value(info, default_value) && !resets_value {
+                    let name = &line.flags[flag_nr].name.as_ref().unwrap().0;
+                    diagnostics.extend(range_to_lsp(rope, &span, encoding).map(|range| {
+                        Diagnostic {
+                            range,
+                            message: format!(
+                                "The flag {name:?} is set to its default value {default_value:?}"
+                            ),
+                            severity: Some(DiagnosticSeverity::HINT),
+                            tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                            code: DiagnosticCheck::DefaultValue.code(),
+                            ..Default::default()
+                        }
+                    }));
+                }
+            }
+            assigned.insert((command, config, &info.name), value);
+        }
+    }
+    diagnostics
+}
 
-fn diagnostics_for_flags(rope: &Rope, line: &Line, bazel_flags: &BazelFlags) -> Vec<Diagnostic> {
+fn diagnostics_for_flags(
+    rope: &Rope,
+    line: &Line,
+    bazel_flags: &BazelFlags,
+    flag_aliases: &FlagAliases,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
     let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
     let command = &line.command.as_ref().unwrap().0;
-    for flag in &line.flags {
+    for (flag_nr, flag) in line.flags.iter().enumerate() {
         if let Some(name) = &flag.name {
-            if SKIPPED_PREFIXES
-                .iter()
-                .any(|prefix| name.0.starts_with(prefix))
-            {
+            if is_starlark_flag(&name.0) || lookup_flag_alias(flag_aliases, &name.0).is_some() {
                 // Don't diagnose custom settings at all
             } else if let Some((lookup_type, flag_description)) =
                 bazel_flags.get_by_invocation(&name.0)
             {
                 // Diagnose flags used on the wrong command
                 if !flag_description.supports_command(command) {
-                    diagnostics.push(Diagnostic::new_simple(
-                        range_to_lsp(rope, &name.1).unwrap(),
-                        format!("The flag {:?} is not supported for {:?}. It is supported for {:?} commands, though.", name.0, command, flag_description.commands),
+                    diagnostics.push(Diagnostic {
+                        range: range_to_lsp(rope, &name.1, encoding).unwrap(),
+                        message: format!(
+                            "The flag {:?} is not supported for {:?}. It is only supported by: {}",
+                            name.0,
+                            command,
+                            flag_description.commands.join(", ")
+                        ),
+                        severity: Some(DiagnosticSeverity::ERROR),
+                        code: DiagnosticCheck::WrongCommand.code(),
+                        ..Default::default()
+                    })
+                } else if command == "always" {
+                    // Diagnose flags which break some commands when used with `always`
+                    let unsupported = bazel_flags.get_unsupported_commands(flag_description);
+                    if !unsupported.is_empty() {
+                        diagnostics.push(Diagnostic {
+                            range: range_to_lsp(rope, &name.1, encoding).unwrap(),
+                            message: format!(
+                                "The flag {:?} is not supported by all commands. `always` makes the following commands fail: {}. Use `common` instead.",
+                                name.0,
+                                unsupported.join(", ")
+                            ),
+                            severity: Some(DiagnosticSeverity::WARNING),
+                            code: DiagnosticCheck::WrongCommand.code(),
+                            ..Default::default()
+                        })
+                    }
+                }
+                // Diagnose missing values
+                let has_value = if lookup_type == FlagLookupType::Abbreviation {
+                    // Abbreviated flags take their value from the following token, e.g. `-c opt`
+                    line.flags
+                        .get(flag_nr + 1)
+                        .is_some_and(|next| next.name.is_none())
+                } else {
+                    flag.value.is_some()
+                };
+                if flag_description.requires_value()
+                    && !has_value
+                    && !flag_description.is_negated_invocation(&name.0)
+                {
+                    diagnostics.push(DiagnosticCheck::InvalidValue.new_simple(
+                        range_to_lsp(rope, &name.1, encoding).unwrap(),
+                        format!("The flag {:?} requires a value", name.0),
                     ))
                 }
+                // Diagnose values passed to flags which don't accept them
+                if let Some(value) = &flag.value {
+                    if flag_description.is_negated_invocation(&name.0) {
+                        diagnostics.push(DiagnosticCheck::InvalidValue.new_simple(
+                            range_to_lsp(rope, &value.1, encoding).unwrap(),
+                            format!("The negated flag {:?} does not accept a value", name.0),
+                        ))
+                    } else if flag_description.has_negative_flag()
+                        && !flag_description.requires_value()
+                        && get_allowed_values(flag_description).is_none()
+                        && !is_boolean_value(&value.0)
+                    {
+                        diagnostics.push(DiagnosticCheck::InvalidValue.new_simple(
+                            range_to_lsp(rope, &value.1, encoding).unwrap(),
+                            format!(
+                                "The flag {:?} expects a boolean value ({}), not {:?}",
+                                name.0,
+                                BOOLEAN_VALUES.join(", "),
+                                value.0
+                            ),
+                        ))
+                    }
+                }
+                // Diagnose values not accepted by enum-typed flags
+                if let Some(diagnostic) =
+                    diagnose_enum_value(rope, line, flag_nr, bazel_flags, encoding)
+                {
+                    diagnostics.push(diagnostic);
+                }
+                // Diagnose malformed `NAME=VALUE` pairs of env flags
+                if let Some((info, _, (value, span))) = get_flag_value(bazel_flags, line, flag_nr) {
+                    if is_env_flag(info) {
+                        let span = skip_separator(rope, span);
+                        diagnostics
+                            .extend(diagnose_env_value(rope, &name.0, value, &span, encoding));
+                    }
+                }
+                // Diagnose placeholders which are not expanded
+                if let Some(value) = &flag.value {
+                    diagnostics.extend(diagnostics_for_placeholders(
+                        rope,
+                        &value.1,
+                        Some(&flag_description.name),
+                        encoding,
+                    ));
+                }
                 // Diagnose deprecated options
                 if flag_description.is_deprecated() {
                     diagnostics.push(Diagnostic {
-                        range: range_to_lsp(rope, &name.1).unwrap(),
+                        range: range_to_lsp(rope, &name.1, encoding).unwrap(),
                         message: format!("The flag {:?} is deprecated.", name.0),
                         severity: Some(DiagnosticSeverity::WARNING),
+                        code: DiagnosticCheck::DeprecatedFlag.code(),
                         tags: Some(vec![DiagnosticTag::DEPRECATED]),
                         ..Default::default()
                     });
                 } else if flag_description.is_noop() {
                     diagnostics.push(Diagnostic {
-                        range: range_to_lsp(rope, &name.1).unwrap(),
+                        range: range_to_lsp(rope, &name.1, encoding).unwrap(),
                         message: format!("The flag {:?} is a no-op.", name.0),
                         severity: Some(DiagnosticSeverity::WARNING),
+                        code: DiagnosticCheck::DeprecatedFlag.code(),
                         ..Default::default()
                     });
                 } else if lookup_type == FlagLookupType::OldName {
                     diagnostics.push(Diagnostic {
-                        range: range_to_lsp(rope, &name.1).unwrap(),
+                        range: range_to_lsp(rope, &name.1, encoding).unwrap(),
                         message: format!(
                             "The flag {:?} was renamed to \"--{}\".",
                             name.0, flag_description.name
                         ),
                         tags: Some(vec![DiagnosticTag::DEPRECATED]),
                         severity: Some(DiagnosticSeverity::WARNING),
+                        code: DiagnosticCheck::DeprecatedFlag.code(),
                         ..Default::default()
                     });
                 } else if lookup_type == FlagLookupType::Abbreviation {
                     diagnostics.push(Diagnostic {
-                        range: range_to_lsp(rope, &name.1).unwrap(),
+                        range: range_to_lsp(rope, &name.1, encoding).unwrap(),
                         message: format!(
                             "Use the full name {:?} instead of its abbreviation.",
                             flag_description.name
                         ),
                         severity: Some(DiagnosticSeverity::WARNING),
+                        code: DiagnosticCheck::Abbreviation.code(),
                         ..Default::default()
                     });
                 }
+            } else if let Some(message) = bazel_flags.get_unavailable_message(&name.0) {
+                // Diagnose flags which only exist in other Bazel versions
+                diagnostics.push(Diagnostic {
+                    range: range_to_lsp(rope, &name.1, encoding).unwrap(),
+                    message,
+                    code: DiagnosticCheck::UnknownFlag.code(),
+                    ..Default::default()
+                })
             } else {
-                // Diagnose unknown flags
-                diagnostics.push(Diagnostic::new_simple(
-                    range_to_lsp(rope, &name.1).unwrap(),
-                    format!("Unknown flag {:?}", name.0),
-                ))
+                // Diagnose unknown flags, suggesting the most likely intended flag
+                let mut message = format!("Unknown flag {:?}", name.0);
+                if let Some(suggestion) = bazel_flags.suggest_flags(&name.0).first() {
+                    message += &format!(". Did you mean {:?}?", suggestion);
+                }
+                diagnostics.push(Diagnostic {
+                    range: range_to_lsp(rope, &name.1, encoding).unwrap(),
+                    message,
+                    code: DiagnosticCheck::UnknownFlag.code(),
+                    ..Default::default()
+                })
             }
         }
     }
     diagnostics
 }
 
-fn diagnostics_for_imports(rope: &Rope, line: &Line, base_path: Option<&Path>) -> Vec<Diagnostic> {
+fn diagnostics_for_imports(
+    rope: &Rope,
+    line: &Line,
+    base_path: Option<&Path>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
     let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
     let command = line.command.as_ref().unwrap();
     if line.flags.is_empty() {
-        diagnostics.push(Diagnostic::new_simple(
-            range_to_lsp(rope, &command.1).unwrap(),
+        diagnostics.push(DiagnosticCheck::ImportIssue.new_simple(
+            range_to_lsp(rope, &command.1, encoding).unwrap(),
             "Missing file path".to_string(),
         ))
     } else if line.flags.len() > 1 {
-        diagnostics.push(Diagnostic::new_simple(
-            range_to_lsp(rope, &command.1).unwrap(),
+        diagnostics.push(DiagnosticCheck::ImportIssue.new_simple(
+            range_to_lsp(rope, &command.1, encoding).unwrap(),
             format!(
                 "`{}` expects a single file name, but received multiple arguments",
                 command.0
@@ -143,11 +578,14 @@ fn diagnostics_for_imports(rope: &Rope, line: &Line, base_path: Option<&Path>) -
     } else {
         let flag = &line.flags[0];
         if flag.name.is_some() {
-            diagnostics.push(Diagnostic::new_simple(
-                range_to_lsp(rope, &command.1).unwrap(),
+            diagnostics.push(DiagnosticCheck::ImportIssue.new_simple(
+                range_to_lsp(rope, &command.1, encoding).unwrap(),
                 format!("`{}` expects a file name, not a flag name", command.0),
             ))
         }
+        if let Some(value) = flag.value.as_ref() {
+            diagnostics.extend(diagnostics_for_placeholders(rope, &value.1, None, encoding));
+        }
         if let Some(act_base_path) = base_path {
             if let Some(value) = flag.value.as_ref() {
                 let severity = if command.0 == "try-import" {
@@ -159,24 +597,27 @@ fn diagnostics_for_imports(rope: &Rope, line: &Line, base_path: Option<&Path>) -
                 if let Some(path) = opt_path {
                     if !path.exists() {
                         diagnostics.push(Diagnostic {
-                            range: range_to_lsp(rope, &value.1).unwrap(),
+                            range: range_to_lsp(rope, &value.1, encoding).unwrap(),
                             message: "Imported file does not exist".to_string(),
                             severity: Some(severity),
+                            code: DiagnosticCheck::ImportIssue.code(),
                             ..Default::default()
                         })
                     } else if !path.is_file() {
                         diagnostics.push(Diagnostic {
-                            range: range_to_lsp(rope, &value.1).unwrap(),
+                            range: range_to_lsp(rope, &value.1, encoding).unwrap(),
                             message: "Imported path exists, but is not a file".to_string(),
                             severity: Some(severity),
+                            code: DiagnosticCheck::ImportIssue.code(),
                             ..Default::default()
                         })
                     }
                 } else {
                     diagnostics.push(Diagnostic {
-                        range: range_to_lsp(rope, &value.1).unwrap(),
+                        range: range_to_lsp(rope, &value.1, encoding).unwrap(),
                         message: "Unable to resolve file name".to_string(),
                         severity: Some(severity),
+                        code: DiagnosticCheck::ImportIssue.code(),
                         ..Default::default()
                     })
                 }
@@ -186,256 +627,2400 @@ fn diagnostics_for_imports(rope: &Rope, line: &Line, base_path: Option<&Path>) -
     diagnostics
 }
 
-pub fn diagnostics_from_rcconfig(
+// Diagnoses placeholders which Bazel does not expand within the given value. `flag_name`
+// is the name of the flag the value belongs to, or `None` for import paths.
+fn diagnostics_for_placeholders(
     rope: &Rope,
-    lines: &[Line],
-    bazel_flags: &BazelFlags,
-    file_path: Option<&Path>,
+    value_span: &Span,
+    flag_name: Option<&str>,
+    encoding: PositionEncoding,
 ) -> Vec<Diagnostic> {
-    let config_regex = Regex::new(r"^[a-z_][a-z0-9]*(?:[-_][a-z0-9]+)*$").unwrap();
+    let raw_value = rope.slice(value_span.clone()).to_string();
     let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
-
-    for l in lines {
-        // Command-specific diagnostics
-        if let Some((command, span)) = &l.command {
-            if command == "import" || command == "try-import" {
-                diagnostics.extend(diagnostics_for_imports(rope, l, file_path))
-            } else if bazel_flags.flags_by_commands.contains_key(command) {
-                diagnostics.extend(diagnostics_for_flags(rope, l, bazel_flags))
-            } else {
-                diagnostics.push(Diagnostic::new_simple(
-                    range_to_lsp(rope, span).unwrap(),
-                    format!("Unknown command {:?}", command),
-                ));
+    for (placeholder, range) in find_placeholders(&raw_value) {
+        let message = match (&placeholder, flag_name) {
+            (Placeholder::Workspace, Some(name)) if !supports_workspace_placeholder(name) => {
+                format!(
+                    "`%workspace%` is not expanded for \"--{}\". Bazel only expands it in imports and for {}.",
+                    name,
+                    WORKSPACE_PLACEHOLDER_FLAGS
+                        .iter()
+                        .map(|f| format!("`--{f}`"))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
             }
-        } else if !l.flags.is_empty() {
-            diagnostics.push(Diagnostic::new_simple(
-                range_to_lsp(rope, &l.span).unwrap(),
-                "Missing command".to_string(),
-            ));
-        }
+            (Placeholder::Home, Some(_)) => {
+                "`~` is not expanded in flag values. Use an absolute path or `%workspace%` instead."
+                    .to_string()
+            }
+            (Placeholder::EnvVar(name), flag_name)
+                if !flag_name.is_some_and(is_shell_command_flag) =>
+            {
+                format!("Environment variables such as `{name}` are not expanded in bazelrc files.")
+            }
+            (Placeholder::Unknown(name), _) => {
+                format!("Unsupported placeholder `%{name}%`. Bazel only expands `%workspace%`.")
+            }
+            _ => continue,
+        };
+        let span = value_span.start + range.start..value_span.start + range.end;
+        diagnostics.push(Diagnostic {
+            range: range_to_lsp(rope, &span, encoding).unwrap(),
+            message,
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: DiagnosticCheck::Placeholder.code(),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
 
-        // Diagnostics for config names
-        if let Some((config_name, span)) = &l.config {
-            if config_name.is_empty() {
-                // Empty config names make no sense
-                diagnostics.push(Diagnostic::new_simple(
-                    range_to_lsp(rope, span).unwrap(),
-                    "Empty configuration names are pointless".to_string(),
-                ));
-            } else if !config_regex.is_match(config_name) {
-                // Overly complex config names
-                diagnostics.push(Diagnostic::new_simple(
-                    range_to_lsp(rope, span).unwrap(),
-                    "Overly complicated config name. Config names should consist only of lower-case ASCII characters.".to_string(),
-                ));
+// Shortens the paths of an import chain to be relative to the workspace
+fn format_import_chain(file_path: &Path, chain: &[PathBuf]) -> String {
+    let workspace = get_workspace_path(file_path);
+    chain
+        .iter()
+        .map(|path| {
+            let relative = workspace.as_ref().and_then(|ws| path.strip_prefix(ws).ok());
+            relative.unwrap_or(path).display().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+pub fn diagnostics_for_import_issues(
+    rope: &Rope,
+    lines: &[Line],
+    file_path: &Path,
+    issues: &[ImportIssue],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    issues
+        .iter()
+        .filter_map(|issue| {
+            let (line_nr, chain, message, severity) = match issue {
+                ImportIssue::Cycle { line_nr, chain } => {
+                    (line_nr, chain, "Import cycle", DiagnosticSeverity::ERROR)
+                }
+                ImportIssue::Duplicate { line_nr, chain } => (
+                    line_nr,
+                    chain,
+                    "File is already imported",
+                    DiagnosticSeverity::WARNING,
+                ),
+            };
+            let span = &lines[*line_nr].flags.first()?.value.as_ref()?.1;
+            Some(Diagnostic {
+                range: range_to_lsp(rope, span, encoding)?,
+                message: format!("{}: {}", message, format_import_chain(file_path, chain)),
+                severity: Some(severity),
+                code: DiagnosticCheck::ImportIssue.code(),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// A flag assignment which is overridden by a later assignment within the same `command:config` scope
+#[derive(Debug, PartialEq, Eq)]
+pub struct OverriddenFlag {
+    pub line_nr: usize,
+    pub flag_nr: usize,
+    pub overridden_by: (usize, usize),
+    // The later assignment repeats the same value, i.e. this assignment is redundant
+    pub same_value: bool,
+}
+
+// Finds flags which are assigned multiple times within the same `command:config` scope.
+// Only the last assignment is effective. Flags which can be repeated (such as `--copt`)
+// accumulate their values and are hence never overridden.
+pub fn find_overridden_flags(lines: &[Line], bazel_flags: &BazelFlags) -> Vec<OverriddenFlag> {
+    let mut result = Vec::<OverriddenFlag>::new();
+    let mut last_assignments = HashMap::<(&str, Option<&str>, &str), (usize, usize, String)>::new();
+    for (line_nr, line) in lines.iter().enumerate() {
+        let Some(command) = &line.command else {
+            continue;
+        };
+        let config = line.config.as_ref().map(|c| c.0.as_str());
+        for (flag_nr, flag) in line.flags.iter().enumerate() {
+            let Some(name) = &flag.name else {
+                continue;
+            };
+            let Some((_, info)) = bazel_flags.get_by_invocation(&name.0) else {
+                continue;
+            };
+            if info.allows_multiple() {
+                continue;
             }
-            if let Some((command, _)) = &l.command {
-                if ["startup", "import", "try-import"].contains(&command.as_str()) {
-                    diagnostics.push(Diagnostic::new_simple(
-                        range_to_lsp(rope, span).unwrap(),
-                        format!(
-                            "Configuration names not supported on {:?} commands",
-                            command
-                        ),
-                    ));
+            // Normalize `--noflag` to `--flag=false` and `--flag` to `--flag=true`
+            let is_boolean = info.has_negative_flag() && !info.requires_value();
+            let value = if info.is_negated_invocation(&name.0) {
+                "false".to_string()
+            } else if let Some((_, _, (value, _))) = get_flag_value(bazel_flags, line, flag_nr) {
+                if is_boolean {
+                    normalize_boolean(value)
+                } else {
+                    value.clone()
                 }
+            } else {
+                "true".to_string()
+            };
+            let key = (command.0.as_str(), config, info.name.as_str());
+            if let Some((prev_line_nr, prev_flag_nr, prev_value)) = last_assignments.get(&key) {
+                result.push(OverriddenFlag {
+                    line_nr: *prev_line_nr,
+                    flag_nr: *prev_flag_nr,
+                    overridden_by: (line_nr, flag_nr),
+                    same_value: *prev_value == value,
+                });
             }
+            last_assignments.insert(key, (line_nr, flag_nr, value));
         }
     }
-    diagnostics
+    result
 }
 
-pub fn diagnostics_from_string(
-    str: &str,
+// The diagnostic for a flag assignment which is overridden or repeated by a later one
+fn diagnose_overridden_flag(
+    rope: &Rope,
+    lines: &[Line],
+    uri: Option<&Url>,
+    o: &OverriddenFlag,
+    encoding: PositionEncoding,
+) -> Option<Diagnostic> {
+    let flag = &lines[o.line_nr].flags[o.flag_nr];
+    let name = &flag.name.as_ref()?.0;
+    let (winner_line_nr, winner_flag_nr) = o.overridden_by;
+    let winner_span = get_flag_span(&lines[winner_line_nr].flags[winner_flag_nr])?;
+    let (check, message, related_message) = if o.same_value {
+        (
+            DiagnosticCheck::RepeatedFlag,
+            format!(
+                "The flag {:?} is repeated with the same value on line {}",
+                name,
+                winner_line_nr + 1
+            ),
+            "Repeated assignment",
+        )
+    } else {
+        (
+            DiagnosticCheck::DuplicateFlag,
+            format!(
+                "The flag {:?} is overridden by a later assignment on line {}",
+                name,
+                winner_line_nr + 1
+            ),
+            "Overriding assignment",
+        )
+    };
+    let related_information = uri.and_then(|uri| {
+        Some(vec![DiagnosticRelatedInformation {
+            location: Location {
+                uri: uri.clone(),
+                range: range_to_lsp(rope, &winner_span, encoding)?,
+            },
+            message: related_message.to_string(),
+        }])
+    });
+    Some(Diagnostic {
+        range: range_to_lsp(rope, &get_flag_span(flag)?, encoding)?,
+        message,
+        severity: Some(DiagnosticSeverity::INFORMATION),
+        code: check.code(),
+        tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+        related_information,
+        ..Default::default()
+    })
+}
+
+// Diagnoses flag assignments overridden by a different value within the same scope
+fn diagnostics_for_overridden_flags(
+    rope: &Rope,
+    lines: &[Line],
     bazel_flags: &BazelFlags,
     file_path: Option<&Path>,
+    encoding: PositionEncoding,
 ) -> Vec<Diagnostic> {
-    let rope = Rope::from_str(str);
-    let ParserResult {
-        tokens: _,
-        mut lines,
-        errors,
-    } = parse_from_str(str);
-    combine_key_value_flags(&mut lines, bazel_flags);
+    let uri = file_path.and_then(|p| Url::from_file_path(p).ok());
+    find_overridden_flags(lines, bazel_flags)
+        .iter()
+        .filter(|o| !o.same_value)
+        .filter_map(|o| diagnose_overridden_flag(rope, lines, uri.as_ref(), o, encoding))
+        .collect::<Vec<_>>()
+}
 
-    let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
-    diagnostics.extend(diagnostics_from_parser(&rope, &errors));
-    diagnostics.extend(diagnostics_from_rcconfig(
-        &rope,
-        &lines,
-        bazel_flags,
-        file_path,
-    ));
+// Diagnoses flag assignments repeated with the same value within the same scope. Unlike
+// overrides, they don't change the effective value and are reported by a check of their own.
+fn diagnostics_for_repeated_flags(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    file_path: Option<&Path>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let uri = file_path.and_then(|p| Url::from_file_path(p).ok());
+    find_overridden_flags(lines, bazel_flags)
+        .iter()
+        .filter(|o| o.same_value)
+        .filter_map(|o| diagnose_overridden_flag(rope, lines, uri.as_ref(), o, encoding))
+        .collect::<Vec<_>>()
+}
+
+// Diagnoses flags which override a value set by an earlier expansion flag within the same
+// `command:config` scope, e.g. `--spawn_strategy=local` after `--experimental_spawn_scheduler`.
+// Such overrides silently undo part of what the expansion flag was used for.
+fn diagnostics_for_expansion_overrides(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    file_path: Option<&Path>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let uri = file_path.and_then(|p| Url::from_file_path(p).ok());
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    // The values set by expansion flags, together with the expanded flag and the expansion flag
+    let mut expanded_values = HashMap::<(&str, Option<&str>, String), (String, &str, Span)>::new();
+    for line in lines {
+        let Some(command) = &line.command else {
+            continue;
+        };
+        let config = line.config.as_ref().map(|c| c.0.as_str());
+        for flag in &line.flags {
+            let Some(name) = &flag.name else {
+                continue;
+            };
+            let Some((_, info)) = bazel_flags.get_by_invocation(&name.0) else {
+                continue;
+            };
+            let Some(span) = get_flag_span(flag) else {
+                continue;
+            };
+            let is_expansion = !info.expansion.is_empty() && !info.is_negated_invocation(&name.0);
+            let assignments = if is_expansion {
+                info.get_expanded_flags()
+            } else {
+                vec![(name.0.as_str(), flag.value.as_ref().map(|v| v.0.as_str()))]
+            };
+            for (invocation, value) in assignments {
+                let Some((_, assigned)) = bazel_flags.get_by_invocation(invocation) else {
+                    continue;
+                };
+                if assigned.allows_multiple() {
+                    continue;
+                }
+                // Normalize `--noflag` to `--flag=false` and `--flag` to `--flag=true`
+                let value = if assigned.is_negated_invocation(invocation) {
+                    "false".to_string()
+                } else {
+                    normalize_boolean(value.unwrap_or("true"))
+                };
+                let key = (command.0.as_str(), config, assigned.name.clone());
+                if let Some((expanded, expansion, expansion_span)) = expanded_values.get(&key) {
+                    if *expanded != value {
+                        let related_information = uri.as_ref().and_then(|uri| {
+                            Some(vec![DiagnosticRelatedInformation {
+                                location: Location {
+                                    uri: uri.clone(),
+                                    range: range_to_lsp(rope, expansion_span, encoding)?,
+                                },
+                                message: format!("Expanded from `{expansion}`"),
+                            }])
+                        });
+                        diagnostics.push(Diagnostic {
+                            range: range_to_lsp(rope, &span, encoding).unwrap(),
+                            message: format!(
+                                "The flag \"--{}\" overrides the value {:?} set by the earlier expansion flag {:?}",
+                                assigned.name, expanded, expansion
+                            ),
+                            severity: Some(DiagnosticSeverity::WARNING),
+                            code: DiagnosticCheck::ExpansionOverride.code(),
+                            related_information,
+                            ..Default::default()
+                        });
+                    }
+                }
+                if is_expansion {
+                    expanded_values.insert(key, (value, name.0.as_str(), span.clone()));
+                } else {
+                    expanded_values.remove(&key);
+                }
+            }
+        }
+    }
     diagnostics
 }
 
-#[cfg(test)]
-fn test_diagnose_string(str: &str) -> Vec<String> {
-    use crate::bazel_flags::load_packaged_bazel_flags;
+// The `--config` values used within the given lines, together with their spans
+pub fn get_config_references<'a>(
+    lines: &'a [Line],
+    bazel_flags: &'a BazelFlags,
+) -> impl Iterator<Item = &'a (String, Span)> {
+    lines
+        .iter()
+        .flat_map(|line| &line.flags)
+        .filter_map(|flag| {
+            let (_, info) = bazel_flags.get_by_invocation(&flag.name.as_ref()?.0)?;
+            if info.name != "config" {
+                return None;
+            }
+            flag.value.as_ref()
+        })
+}
 
-    let bazel_flags = load_packaged_bazel_flags("8.0.0");
-    return diagnostics_from_string(str, &bazel_flags, None)
-        .iter_mut()
-        .map(|d| std::mem::take(&mut d.message))
+// Points at all `command:config` lines of the given files defining `config`
+fn get_config_definition_sites(
+    files: &[RcFile],
+    config: &str,
+    message: &str,
+    encoding: PositionEncoding,
+) -> Option<Vec<DiagnosticRelatedInformation>> {
+    let sites = files
+        .iter()
+        .flat_map(|file| {
+            let uri = Url::from_file_path(&file.path).ok();
+            file.lines.iter().filter_map(move |line| {
+                let (name, span) = line.config.as_ref()?;
+                if name != config {
+                    return None;
+                }
+                Some(DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: uri.clone()?,
+                        range: range_to_lsp(&file.rope, span, encoding)?,
+                    },
+                    message: message.to_string(),
+                })
+            })
+        })
         .collect::<Vec<_>>();
+    if sites.is_empty() {
+        None
+    } else {
+        Some(sites)
+    }
 }
 
-#[test]
-fn test_diagnose_commands() {
-    // Nothing wrong with this `build` command
-    assert_eq!(
-        test_diagnose_string("build --remote_upload_local_results=false"),
-        Vec::<&str>::new()
-    );
-    // The command should be named `build`, not `built`
-    assert_eq!(
-        test_diagnose_string("built --remote_upload_local_results=false"),
-        vec!["Unknown command \"built\""]
-    );
-    // Completely missing command
-    assert_eq!(
-        test_diagnose_string("--remote_upload_local_results=false"),
-        vec!["Missing command"]
-    );
-    // Completely missing command
-    assert_eq!(
-        test_diagnose_string(":opt --remote_upload_local_results=false"),
-        vec!["Missing command"]
-    );
+// Configs referenced via `--config`, but defined neither in this file nor in any imported file.
+// Definitions in other, not imported files of the workspace are listed as related information.
+pub fn diagnostics_for_unknown_configs(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    known_configs: &HashSet<String>,
+    workspace_files: &[RcFile],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    get_config_references(lines, bazel_flags)
+        .filter_map(|(config, span)| {
+            if config.is_empty() || known_configs.contains(config) {
+                return None;
+            }
+            Some(Diagnostic {
+                range: range_to_lsp(rope, span, encoding)?,
+                message: format!(
+                    "Unknown config {:?}. It is neither defined in this file nor in any imported file",
+                    config
+                ),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: DiagnosticCheck::UnknownConfig.code(),
+                related_information: get_config_definition_sites(
+                    workspace_files,
+                    config,
+                    "Defined here, but not imported", encoding,
+                ),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>()
 }
 
-#[test]
-fn test_diagnose_config_names() {
-    // Diagnose empty config names
+// Configs defined in this file, but never referenced via `--config` from any bazelrc file
+// of the workspace. Other definitions of the same config are listed as related information.
+pub fn diagnostics_for_unused_configs(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    workspace_files: &[RcFile],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let used_configs = get_config_references(lines, bazel_flags)
+        .chain(
+            workspace_files
+                .iter()
+                .flat_map(|f| get_config_references(&f.lines, bazel_flags)),
+        )
+        .map(|(config, _)| config.as_str())
+        .collect::<HashSet<_>>();
+    lines
+        .iter()
+        .filter_map(|line| {
+            let (config, span) = line.config.as_ref()?;
+            if config.is_empty() || used_configs.contains(config.as_str()) {
+                return None;
+            }
+            Some(Diagnostic {
+                range: range_to_lsp(rope, span, encoding)?,
+                message: format!(
+                    "Config {:?} is never referenced via `--config` within the workspace",
+                    config
+                ),
+                severity: Some(DiagnosticSeverity::INFORMATION),
+                code: DiagnosticCheck::UnusedConfig.code(),
+                related_information: get_config_definition_sites(
+                    workspace_files,
+                    config,
+                    "Also defined here",
+                    encoding,
+                ),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Bazel does not limit how deeply configs are nested, but chains of `--config`
+// references this long are hard to follow and usually unintended
+pub const MAX_CONFIG_NESTING: usize = 10;
+
+// The configs referenced via `--config` by the lines of each config, together with the
+// command of the referencing line
+fn get_config_graph<'a>(
+    lines: impl Iterator<Item = &'a Line>,
+    bazel_flags: &'a BazelFlags,
+) -> HashMap<&'a str, Vec<(&'a str, &'a str)>> {
+    let mut graph = HashMap::<&str, Vec<(&str, &str)>>::new();
+    for line in lines {
+        let (Some((command, _)), Some((config, _))) = (&line.command, &line.config) else {
+            continue;
+        };
+        let nested = graph.entry(config).or_default();
+        for (value, _) in get_config_references(std::slice::from_ref(line), bazel_flags) {
+            if !nested.contains(&(command.as_str(), value.as_str())) {
+                nested.push((command, value));
+            }
+        }
+    }
+    graph
+}
+
+// A chain of nested configs leading from `from` to `to`, including both ends. Only follows
+// the references of lines which apply when running `command`.
+fn find_config_chain<'a>(
+    graph: &HashMap<&'a str, Vec<(&'a str, &'a str)>>,
+    command: &str,
+    from: &'a str,
+    to: &str,
+    visited: &mut HashSet<&'a str>,
+) -> Option<Vec<&'a str>> {
+    if from == to {
+        return Some(vec![from]);
+    }
+    if !visited.insert(from) {
+        return None;
+    }
+    graph.get(from)?.iter().find_map(|(line_command, next)| {
+        if !inherits_command(command, line_command) {
+            return None;
+        }
+        let mut chain = find_config_chain(graph, command, next, to, visited)?;
+        chain.insert(0, from);
+        Some(chain)
+    })
+}
+
+// The number of configs in the longest chain of nested configs starting at `config`,
+// together with the next config of that chain. Memoized in `depths`, such that each
+// config is visited once. Cycles are diagnosed separately and are not followed.
+fn get_config_depth<'a>(
+    graph: &HashMap<&'a str, Vec<(&'a str, &'a str)>>,
+    config: &'a str,
+    depths: &mut HashMap<&'a str, (usize, Option<&'a str>)>,
+) -> usize {
+    if let Some((depth, _)) = depths.get(config) {
+        return *depth;
+    }
+    // Marks the config as being visited, to stop at cycles
+    depths.insert(config, (1, None));
+    let mut result = (1, None);
+    for (_, next) in graph.get(config).into_iter().flatten() {
+        let depth = get_config_depth(graph, next, depths) + 1;
+        if depth > result.0 {
+            result = (depth, Some(*next));
+        }
+    }
+    depths.insert(config, result);
+    result.0
+}
+
+// `--config` references which expand back into the config they are used in, either
+// directly or via other configs. Bazel rejects such cycles when expanding the config.
+// Lines only expand for the commands inheriting their command, e.g. `query:a --config=b`
+// and `build:b --config=a` never expand into each other.
+// References starting chains nested deeper than `MAX_CONFIG_NESTING` are reported, too.
+// Configs defined in `related_files` are followed as well.
+pub fn diagnostics_for_config_nesting(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    related_files: &[RcFile],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let graph = get_config_graph(
+        lines
+            .iter()
+            .chain(related_files.iter().flat_map(|f| &f.lines)),
+        bazel_flags,
+    );
+    let mut depths = HashMap::new();
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    for line in lines {
+        for (value, span) in get_config_references(std::slice::from_ref(line), bazel_flags) {
+            if value.is_empty() {
+                continue;
+            }
+            let Some(range) = range_to_lsp(rope, span, encoding) else {
+                continue;
+            };
+            let cycle = line.config.as_ref().and_then(|(config, _)| {
+                // The cycle has to exist for a single command, which then inherits the
+                // commands of all lines within the cycle
+                let line_command = line.command.as_ref().map_or("common", |c| c.0.as_str());
+                let mut commands = graph
+                    .values()
+                    .flatten()
+                    .map(|(command, _)| *command)
+                    .chain([line_command])
+                    .filter(|command| inherits_command(command, line_command))
+                    .collect::<Vec<_>>();
+                commands.sort();
+                commands.dedup();
+                commands.into_iter().find_map(|command| {
+                    let mut chain =
+                        find_config_chain(&graph, command, value, config, &mut HashSet::new())?;
+                    chain.insert(0, config);
+                    Some(chain)
+                })
+            });
+            if let Some(cycle) = cycle {
+                diagnostics.push(Diagnostic {
+                    range,
+                    message: format!("Config expansion has a cycle: {}", cycle.join(" -> ")),
+                    severity: Some(DiagnosticSeverity::ERROR),
+                    code: DiagnosticCheck::ConfigNesting.code(),
+                    ..Default::default()
+                });
+                continue;
+            }
+            if get_config_depth(&graph, value, &mut depths) > MAX_CONFIG_NESTING {
+                let mut longest = vec![value.as_str()];
+                while longest.len() <= MAX_CONFIG_NESTING {
+                    match depths.get(longest[longest.len() - 1]) {
+                        Some((_, Some(next))) => longest.push(next),
+                        _ => break,
+                    }
+                }
+                diagnostics.push(Diagnostic {
+                    range,
+                    message: format!(
+                        "Config {:?} nests more than {} levels of configs: {} -> ...",
+                        value,
+                        MAX_CONFIG_NESTING,
+                        longest.join(" -> ")
+                    ),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: DiagnosticCheck::ConfigNesting.code(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
+// Is the flag experimental (`--experimental_*`) or does it guard an incompatible change
+// (`--incompatible_*`)? Besides the name prefix, the flag's metadata tags are considered.
+// Renamed flags are recognized by the name they are used with, too.
+fn get_unstable_flag_kind(name: &str, info: Option<&FlagInfo>) -> Option<&'static str> {
+    let mut names = vec![name];
+    names.extend(info.map(|i| i.name.as_str()));
+    let tags = info.map(|i| i.metadata_tags.as_slice()).unwrap_or_default();
+    if names.iter().any(|n| n.starts_with("incompatible_"))
+        || tags.iter().any(|t| t == "INCOMPATIBLE_CHANGE")
+    {
+        Some("guards an incompatible change")
+    } else if names.iter().any(|n| n.starts_with("experimental_"))
+        || tags.iter().any(|t| t == "EXPERIMENTAL")
+    {
+        Some("is experimental")
+    } else {
+        None
+    }
+}
+
+// Diagnoses experimental and incompatible flags, which are subject to change and hence
+// unwanted in shared bazelrc files. Flags in `allowed_flags` (with or without the leading
+// `--`) are accepted.
+pub fn diagnostics_for_unstable_flags(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    allowed_flags: &[String],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let is_allowed = |name: &str| {
+        allowed_flags
+            .iter()
+            .any(|a| a.strip_prefix("--").unwrap_or(a) == name)
+    };
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    for flag in lines.iter().flat_map(|l| &l.flags) {
+        let Some((invocation, span)) = &flag.name else {
+            continue;
+        };
+        let info = bazel_flags.get_by_invocation(invocation).map(|(_, i)| i);
+        let name = invocation.strip_prefix("--").unwrap_or(invocation);
+        let name = name.strip_suffix('=').unwrap_or(name);
+        let name = match info {
+            Some(info) if info.is_negated_invocation(invocation) => &name[2..],
+            _ => name,
+        };
+        let Some(kind) = get_unstable_flag_kind(name, info) else {
+            continue;
+        };
+        if is_allowed(name) || info.is_some_and(|i| is_allowed(&i.name)) {
+            continue;
+        }
+        diagnostics.push(Diagnostic {
+            range: range_to_lsp(rope, span, encoding).unwrap(),
+            message: format!(
+                "The flag \"--{name}\" {kind} and might change or be removed in future Bazel versions"
+            ),
+            severity: Some(DiagnosticSeverity::WARNING),
+            code: DiagnosticCheck::UnstableFlag.code(),
+            ..Default::default()
+        });
+    }
+    diagnostics
+}
+
+// Path-valued flags pointing to missing or unsuitable paths. `path_statuses` contains
+// the statuses of the paths checked on disk. Unchecked paths are not diagnosed.
+pub fn diagnostics_for_flag_paths(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    file_path: &Path,
+    path_statuses: &HashMap<PathBuf, PathStatus>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    get_flag_paths(lines, bazel_flags, file_path)
+        .filter_map(|(info, path, span)| {
+            let status = path_statuses.get(&path)?;
+            let (message, severity) = match (status, expects_directory(info)?) {
+                (PathStatus::Missing, true) => (
+                    format!(
+                        "The directory {:?} does not exist. Bazel will create it.",
+                        path
+                    ),
+                    DiagnosticSeverity::HINT,
+                ),
+                (PathStatus::Missing, false) => (
+                    format!("The file {:?} does not exist", path),
+                    DiagnosticSeverity::WARNING,
+                ),
+                (PathStatus::File, true) => (
+                    format!(
+                        "{:?} is a file, but \"--{}\" expects a directory",
+                        path, info.name
+                    ),
+                    DiagnosticSeverity::WARNING,
+                ),
+                (PathStatus::Directory { .. }, false) => (
+                    format!(
+                        "{:?} is a directory, but \"--{}\" expects a file",
+                        path, info.name
+                    ),
+                    DiagnosticSeverity::WARNING,
+                ),
+                (PathStatus::Directory { writable: false }, true) => (
+                    format!("The directory {:?} is not writable", path),
+                    DiagnosticSeverity::WARNING,
+                ),
+                _ => return None,
+            };
+            Some(Diagnostic {
+                range: range_to_lsp(rope, span, encoding)?,
+                message,
+                severity: Some(severity),
+                code: DiagnosticCheck::FlagPath.code(),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Starlark flags whose build setting can't be found in the workspace. `existing_labels`
+// maps the labels checked via `bazel query` to whether they exist. Unchecked labels
+// are not diagnosed.
+pub fn diagnostics_for_missing_starlark_flags(
+    rope: &Rope,
+    lines: &[Line],
+    existing_labels: &HashMap<String, bool>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    lines
+        .iter()
+        .flat_map(|line| &line.flags)
+        .filter_map(|flag| {
+            let name = flag.name.as_ref()?;
+            let label = parse_starlark_flag(&name.0)?.label();
+            if existing_labels.get(&label).copied().unwrap_or(true) {
+                return None;
+            }
+            Some(Diagnostic {
+                range: range_to_lsp(rope, &name.1, encoding)?,
+                message: format!(
+                    "The build setting {:?} does not exist in the workspace",
+                    label
+                ),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: DiagnosticCheck::StarlarkFlag.code(),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Flags specific to one operating system within the platform-specific config of another
+// one, e.g. `build:linux --xcode_version=...`. Only diagnosed if `--enable_platform_specific_config`
+// is set, since the configs are applied automatically in that case.
+pub fn diagnostics_for_platform_configs(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    platform_specific_config_enabled: bool,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    if !platform_specific_config_enabled {
+        return Vec::new();
+    }
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    for line in lines {
+        let Some((config, _)) = &line.config else {
+            continue;
+        };
+        let Some(os) = get_platform_name(config) else {
+            continue;
+        };
+        for flag in &line.flags {
+            let Some(name) = &flag.name else {
+                continue;
+            };
+            let Some((_, info)) = bazel_flags.get_by_invocation(&name.0) else {
+                continue;
+            };
+            let Some(flag_platform) = get_flag_platform(&info.name) else {
+                continue;
+            };
+            if flag_platform == config {
+                continue;
+            }
+            diagnostics.push(Diagnostic {
+                range: range_to_lsp(rope, &name.1, encoding).unwrap(),
+                message: format!(
+                    "The flag {:?} only applies to {}, but the config {:?} is applied automatically on {}",
+                    name.0,
+                    get_platform_name(flag_platform).unwrap(),
+                    config,
+                    os
+                ),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: DiagnosticCheck::PlatformConfig.code(),
+                ..Default::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+// Flags set to different values multiple times within one effective configuration,
+// i.e. by lines with the same command and config, across nested configs and imported
+// files. Bazel silently applies the last value. The same holds for environment variables
+// set multiple times via env flags (e.g. `--action_env`). `files` contains the file at `file_path`
+// together with its related files. Only assignments within that file are reported.
+pub fn diagnostics_for_conflicting_flags(
+    file_path: &Path,
+    bazel_flags: &BazelFlags,
+    files: &[RcFile],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let Some(current) = files.iter().find(|f| f.path == file_path) else {
+        return Vec::new();
+    };
+    // The command / config combinations used within this file
+    let mut invocations = Vec::<(&str, Option<&str>)>::new();
+    for line in &current.lines {
+        let Some(command) = &line.command else {
+            continue;
+        };
+        let command = match command.0.as_str() {
+            "startup" | "import" | "try-import" => continue,
+            // Evaluate `common` lines in the context of the most common command
+            "common" | "always" => "build",
+            c => c,
+        };
+        let invocation = (command, line.config.as_ref().map(|c| c.0.as_str()));
+        if !invocations.contains(&invocation) {
+            invocations.push(invocation);
+        }
+    }
+
+    let mut reported = HashSet::<Span>::new();
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    for (command, config) in invocations {
+        let (flags, label) = match config {
+            Some(config) => (
+                expand_config(files, bazel_flags, command, config),
+                format!("`{command} --config={config}`"),
+            ),
+            None => (
+                get_effective_flags(files, bazel_flags, command, &[]),
+                format!("`{command}`"),
+            ),
+        };
+        // Overriding a flag for a more specific command (e.g. `test` after `build`) is intended
+        let mut line_commands = flags.iter().map(|f| f.command.as_str()).collect::<Vec<_>>();
+        line_commands.sort();
+        line_commands.dedup();
+        for line_command in line_commands {
+            let same_command = flags
+                .iter()
+                .filter(|f| f.command == line_command)
+                .cloned()
+                .collect::<Vec<_>>();
+            let format_values = |conflict: &[&ExpandedFlag]| {
+                conflict
+                    .iter()
+                    .map(|f| format!("`{}`", f.flag))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let mut groups = Vec::<(Vec<&ExpandedFlag>, String)>::new();
+            for conflict in find_conflicts(&same_command, bazel_flags) {
+                // Conflicts within a single `command:config` scope of this file are
+                // already reported by `diagnostics_for_overridden_flags`
+                if conflict
+                    .iter()
+                    .all(|f| f.path == file_path && f.via.is_empty())
+                {
+                    continue;
+                }
+                let message = format!(
+                    "The flag \"--{}\" is set to different values for {}: {}. The last value wins.",
+                    conflict[0].name,
+                    label,
+                    format_values(&conflict)
+                );
+                groups.push((conflict, message));
+            }
+            // Env flags can be passed multiple times, but each variable only takes one value
+            for (variable, duplicates) in find_duplicate_env_vars(&same_command) {
+                let message = format!(
+                    "The environment variable {:?} is set multiple times for {}: {}. The last value wins.",
+                    variable,
+                    label,
+                    format_values(&duplicates)
+                );
+                groups.push((duplicates, message));
+            }
+            for (conflict, message) in groups {
+                for (i, flag) in conflict.iter().enumerate() {
+                    if flag.path != file_path || !reported.insert(flag.span.clone()) {
+                        continue;
+                    }
+                    let Some(range) = range_to_lsp(&current.rope, &flag.span, encoding) else {
+                        continue;
+                    };
+                    let related_information = conflict
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .filter_map(|(_, other)| {
+                            let file = files.iter().find(|f| f.path == other.path)?;
+                            Some(DiagnosticRelatedInformation {
+                                location: Location {
+                                    uri: Url::from_file_path(&other.path).ok()?,
+                                    range: range_to_lsp(&file.rope, &other.span, encoding)?,
+                                },
+                                message: format!("Also set to `{}` here", other.flag),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    diagnostics.push(Diagnostic {
+                        range,
+                        message: message.clone(),
+                        severity: Some(DiagnosticSeverity::WARNING),
+                        code: DiagnosticCheck::ConflictingFlag.code(),
+                        related_information: Some(related_information),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+// Assignments which are overridden by a file Bazel reads later, for the same command and
+// config. Overrides within the file itself are reported by `diagnostics_for_overridden_flags`.
+// `files` contains the file at `file_path` together with its related files, in read order.
+pub fn diagnostics_for_dead_assignments(
+    file_path: &Path,
+    bazel_flags: &BazelFlags,
+    files: &[RcFile],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let Some(current) = files.iter().find(|f| f.path == file_path) else {
+        return Vec::new();
+    };
+    let workspace = get_workspace_path(file_path);
+    find_dead_assignments(files, bazel_flags, file_path)
+        .into_iter()
+        .filter(|d| d.winner.path != file_path)
+        .filter_map(|dead| {
+            let winner_file = files.iter().find(|f| f.path == dead.winner.path)?;
+            let winner_path = workspace
+                .as_deref()
+                .and_then(|ws| dead.winner.path.strip_prefix(ws).ok())
+                .unwrap_or(&dead.winner.path);
+            Some(Diagnostic {
+                range: range_to_lsp(&current.rope, &dead.flag.span, encoding)?,
+                message: format!(
+                    "The flag \"--{}\" is overridden by `{}` in {}:{}, which Bazel reads later",
+                    dead.flag.name,
+                    dead.winner.flag,
+                    winner_path.display(),
+                    dead.winner.line_nr + 1
+                ),
+                severity: Some(DiagnosticSeverity::HINT),
+                code: DiagnosticCheck::DeadAssignment.code(),
+                tags: Some(vec![DiagnosticTag::UNNECESSARY]),
+                related_information: Some(vec![DiagnosticRelatedInformation {
+                    location: Location {
+                        uri: Url::from_file_path(&dead.winner.path).ok()?,
+                        range: range_to_lsp(&winner_file.rope, &dead.winner.span, encoding)?,
+                    },
+                    message: "Overriding assignment".to_string(),
+                }]),
+                ..Default::default()
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Diagnoses junk in front of the actual command, e.g. a pasted shell prompt as in
+// `$ bazel build --keep_going`. Returns `None` if no known command follows.
+fn diagnose_stray_prefix(
+    rope: &Rope,
+    line: &Line,
+    bazel_flags: &BazelFlags,
+    encoding: PositionEncoding,
+) -> Option<Diagnostic> {
+    let command_idx = line.flags.iter().position(|f| {
+        f.name.is_none()
+            && f.value.as_ref().is_some_and(|v| {
+                let command = v.0.split(':').next().unwrap_or_default();
+                bazel_flags.commands.iter().any(|c| c == command)
+            })
+    })?;
+    let stray_start = line.command.as_ref()?.1.start;
+    let stray_end = if command_idx == 0 {
+        line.config.as_ref().or(line.command.as_ref())?.1.end
+    } else {
+        get_flag_span(&line.flags[command_idx - 1])?.end
+    };
+    let command = &line.flags[command_idx].value.as_ref()?.0;
+    Some(DiagnosticCheck::UnknownCommand.new_simple(
+        range_to_lsp(rope, &(stray_start..stray_end), encoding)?,
+        format!(
+            "Expected a Bazel command, found stray text before {:?}",
+            command.split(':').next().unwrap_or_default()
+        ),
+    ))
+}
+
+// `flag_aliases` are the aliases defined anywhere in the import graph
+pub fn diagnostics_from_rcconfig(
+    rope: &Rope,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    flag_aliases: &FlagAliases,
+    file_path: Option<&Path>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let config_regex = Regex::new(r"^[a-z_][a-z0-9]*(?:[-_][a-z0-9]+)*$").unwrap();
+    let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
+
+    for l in lines {
+        // Command-specific diagnostics
+        if let Some((command, span)) = &l.command {
+            if command == "import" || command == "try-import" {
+                diagnostics.extend(diagnostics_for_imports(rope, l, file_path, encoding))
+            } else if bazel_flags.is_syntax_only() {
+                // Without any flags, neither the command nor its flags can be validated
+            } else if bazel_flags.flags_by_commands.contains_key(command) {
+                diagnostics.extend(diagnostics_for_flags(
+                    rope,
+                    l,
+                    bazel_flags,
+                    flag_aliases,
+                    encoding,
+                ))
+            } else if let Some(diagnostic) = diagnose_stray_prefix(rope, l, bazel_flags, encoding) {
+                diagnostics.push(diagnostic);
+            } else {
+                diagnostics.push(DiagnosticCheck::UnknownCommand.new_simple(
+                    range_to_lsp(rope, span, encoding).unwrap(),
+                    format!("Unknown command {:?}", command),
+                ));
+            }
+        } else if !l.flags.is_empty() {
+            // Point at the first token, which should have been the command
+            let (found, found_span) = if let Some(config) = &l.config {
+                (format!(":{}", config.0), config.1.clone())
+            } else {
+                let flag = &l.flags[0];
+                let token = flag.name.as_ref().or(flag.value.as_ref()).unwrap();
+                (token.0.clone(), token.1.clone())
+            };
+            diagnostics.push(DiagnosticCheck::UnknownCommand.new_simple(
+                range_to_lsp(rope, &found_span, encoding).unwrap(),
+                format!("Expected a Bazel command, found {:?}", found),
+            ));
+        }
+
+        diagnostics.extend(diagnostics_for_line_continuations(rope, l, encoding));
+
+        // Diagnostics for config names
+        if let Some((config_name, span)) = &l.config {
+            if config_name.is_empty() {
+                // Empty config names make no sense
+                diagnostics.push(DiagnosticCheck::ConfigName.new_simple(
+                    range_to_lsp(rope, span, encoding).unwrap(),
+                    "Empty configuration names are pointless".to_string(),
+                ));
+            } else if !config_regex.is_match(config_name) {
+                // Overly complex config names
+                diagnostics.push(DiagnosticCheck::ConfigName.new_simple(
+                    range_to_lsp(rope, span, encoding).unwrap(),
+                    "Overly complicated config name. Config names should consist only of lower-case ASCII characters.".to_string(),
+                ));
+            }
+            if let Some((command, _)) = &l.command {
+                if ["startup", "import", "try-import"].contains(&command.as_str()) {
+                    diagnostics.push(DiagnosticCheck::ConfigName.new_simple(
+                        range_to_lsp(rope, span, encoding).unwrap(),
+                        format!(
+                            "Configuration names not supported on {:?} commands",
+                            command
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+    diagnostics.extend(diagnostics_for_overridden_flags(
+        rope,
+        lines,
+        bazel_flags,
+        file_path,
+        encoding,
+    ));
+    diagnostics.extend(diagnostics_for_repeated_flags(
+        rope,
+        lines,
+        bazel_flags,
+        file_path,
+        encoding,
+    ));
+    diagnostics.extend(diagnostics_for_expansion_overrides(
+        rope,
+        lines,
+        bazel_flags,
+        file_path,
+        encoding,
+    ));
+    diagnostics
+}
+
+pub fn diagnostics_from_string(
+    str: &str,
+    bazel_flags: &BazelFlags,
+    file_path: Option<&Path>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let rope = Rope::from_str(str);
+    let ParserResult {
+        tokens: _,
+        mut lines,
+        errors,
+    } = parse_from_str(str);
+    combine_key_value_flags(&mut lines, bazel_flags);
+
+    // Relative paths (e.g., passed on the command line) would not match the paths of the
+    // related files, and the file would then be compared with itself
+    let absolute_path = file_path.and_then(|p| std::path::absolute(p).ok());
+    let file_path = absolute_path.as_deref();
+
+    // Imports, configs and flag aliases can only be resolved if we know where the file is located
+    let related_files = file_path
+        .map(|p| load_related_files(p, &lines, bazel_flags, false, read_file_from_disk))
+        .unwrap_or_default();
+    let flag_aliases = get_flag_aliases(
+        lines
+            .iter()
+            .chain(related_files.iter().flat_map(|f| &f.lines)),
+        bazel_flags,
+    );
+
+    let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
+    diagnostics.extend(diagnostics_from_parser(&rope, &errors, encoding));
+    diagnostics.extend(diagnostics_from_rcconfig(
+        &rope,
+        &lines,
+        bazel_flags,
+        &flag_aliases,
+        file_path,
+        encoding,
+    ));
+    diagnostics.extend(diagnostics_for_default_values(
+        &rope,
+        &lines,
+        bazel_flags,
+        file_path,
+        &related_files,
+        encoding,
+    ));
+    diagnostics.extend(diagnostics_for_platform_configs(
+        &rope,
+        &lines,
+        bazel_flags,
+        is_platform_specific_config_enabled(
+            lines
+                .iter()
+                .chain(related_files.iter().flat_map(|f| &f.lines)),
+            bazel_flags,
+        ),
+        encoding,
+    ));
+    diagnostics.extend(diagnostics_for_config_nesting(
+        &rope,
+        &lines,
+        bazel_flags,
+        &related_files,
+        encoding,
+    ));
+    if let Some(file_path) = file_path {
+        let known_configs = get_defined_configs(
+            lines
+                .iter()
+                .chain(related_files.iter().flat_map(|f| &f.lines)),
+        );
+        let import_issues =
+            find_import_issues(file_path, &lines, bazel_flags, false, read_file_from_disk);
+        diagnostics.extend(diagnostics_for_import_issues(
+            &rope,
+            &lines,
+            file_path,
+            &import_issues,
+            encoding,
+        ));
+        let workspace_files = load_workspace_files(file_path, bazel_flags, read_file_from_disk);
+        diagnostics.extend(diagnostics_for_unknown_configs(
+            &rope,
+            &lines,
+            bazel_flags,
+            &known_configs,
+            &workspace_files,
+            encoding,
+        ));
+        if get_workspace_path(file_path).is_some() {
+            diagnostics.extend(diagnostics_for_unused_configs(
+                &rope,
+                &lines,
+                bazel_flags,
+                &workspace_files,
+                encoding,
+            ));
+        }
+        let mut files = vec![RcFile {
+            path: file_path.to_path_buf(),
+            rope: rope.clone(),
+            lines: lines.clone(),
+            read_only: false,
+        }];
+        files.extend(related_files);
+        sort_by_read_order(file_path, &mut files);
+        diagnostics.extend(diagnostics_for_conflicting_flags(
+            file_path,
+            bazel_flags,
+            &files,
+            encoding,
+        ));
+        diagnostics.extend(diagnostics_for_dead_assignments(
+            file_path,
+            bazel_flags,
+            &files,
+            encoding,
+        ));
+    }
+    let mut diagnostics = filter_suppressed_diagnostics(&rope, &lines, diagnostics, encoding);
+    diagnostics.extend(diagnostics_for_suppressions(&rope, &lines, encoding));
+    diagnostics
+}
+
+#[cfg(test)]
+#[allow(clippy::needless_return)]
+fn test_diagnose_string(str: &str) -> Vec<String> {
+    use crate::test_utils::load_flags_with_value_types;
+
+    let bazel_flags = load_flags_with_value_types("8.0.0");
+    return diagnostics_from_string(str, &bazel_flags, None, PositionEncoding::Utf16)
+        .iter_mut()
+        .map(|d| std::mem::take(&mut d.message))
+        .collect::<Vec<_>>();
+}
+
+#[test]
+fn test_diagnostics_have_checks() {
+    // Every diagnostic can be suppressed or have its severity overridden
+    let text = "build:Invalid -k --jobs -c optt --nokeep_going=1 --action_env==x\n\
+        build --copt=$HOME/x \\ \n\
+        startup:x --batch\n\
+        import\n\
+        built --jobs=4\n\
+        --jobs=4\n\
+        build \"unclosed\n";
+    let bazel_flags = crate::test_utils::load_flags_with_value_types("8.0.0");
+    let diagnostics = diagnostics_from_string(text, &bazel_flags, None, PositionEncoding::Utf16);
+    assert!(diagnostics.len() >= 10);
+    for diagnostic in diagnostics {
+        assert!(
+            DiagnosticCheck::of(&diagnostic).is_some(),
+            "{}",
+            diagnostic.message
+        );
+    }
+}
+
+#[test]
+fn test_diagnose_syntax_only() {
+    // Without any flags, only syntax errors are reported
+    let bazel_flags = BazelFlags::syntax_only("8.0.0");
+    let diagnose = |str: &str| {
+        diagnostics_from_string(str, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        diagnose("built --unknown_flag\nbuild:ci --jobs=4\n"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        diagnose("--jobs=4\nbuild:Invalid --jobs=4\n"),
+        vec![
+            "Expected a Bazel command, found \"--jobs\"",
+            "Overly complicated config name. Config names should consist only of lower-case ASCII characters."
+        ]
+    );
+}
+
+#[test]
+fn test_diagnose_commands() {
+    // Nothing wrong with this `build` command
+    assert_eq!(
+        test_diagnose_string("build --remote_upload_local_results=false"),
+        Vec::<&str>::new()
+    );
+    // The command should be named `build`, not `built`
+    assert_eq!(
+        test_diagnose_string("built --remote_upload_local_results=false"),
+        vec!["Unknown command \"built\""]
+    );
+    // Completely missing command
+    assert_eq!(
+        test_diagnose_string("--remote_upload_local_results=false"),
+        vec!["Expected a Bazel command, found \"--remote_upload_local_results\""]
+    );
+    // Completely missing command
+    assert_eq!(
+        test_diagnose_string(":opt --remote_upload_local_results=false"),
+        vec!["Expected a Bazel command, found \":opt\""]
+    );
+    // Stray tokens in front of the command, e.g. from a pasted shell prompt
+    assert_eq!(
+        test_diagnose_string("$ bazel build:ci --keep_going"),
+        vec!["Expected a Bazel command, found stray text before \"build\""]
+    );
+    let bazel_flags = crate::bazel_flags::load_packaged_bazel_flags("8.0.0");
+    let diagnostics = diagnostics_from_string(
+        "x\n$ bazel test",
+        &bazel_flags,
+        None,
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        diagnostics[1].range,
+        tower_lsp::lsp_types::Range::new(
+            tower_lsp::lsp_types::Position::new(1, 0),
+            tower_lsp::lsp_types::Position::new(1, 7)
+        )
+    );
+}
+
+#[test]
+fn test_diagnose_config_names() {
+    // Diagnose empty config names
+    assert_eq!(
+        test_diagnose_string("build: --disk_cache="),
+        vec!["Empty configuration names are pointless"]
+    );
+
+    // Diagnose config names on commands which don't support configs
+    assert_eq!(
+        test_diagnose_string("startup:opt --digest_function=blake3"),
+        vec!["Configuration names not supported on \"startup\" commands"]
+    );
+    assert_eq!(
+        test_diagnose_string("import:opt \"x.bazelrc\""),
+        vec!["Configuration names not supported on \"import\" commands"]
+    );
+    assert_eq!(
+        test_diagnose_string("try-import:opt \"x.bazelrc\""),
+        vec!["Configuration names not supported on \"try-import\" commands"]
+    );
+
+    // Diagnose overly complicated config names
+    let config_name_diag = "Overly complicated config name. Config names should consist only of lower-case ASCII characters.";
+    assert_eq!(
+        test_diagnose_string("common:Uncached --disk_cache="),
+        vec![config_name_diag]
+    );
+    assert_eq!(
+        test_diagnose_string("common:-opt --disk_cache="),
+        vec![config_name_diag]
+    );
+    assert_eq!(
+        test_diagnose_string("common:opt- --disk_cache="),
+        vec![config_name_diag]
+    );
+    assert_eq!(
+        test_diagnose_string("common:2opt --disk_cache="),
+        vec![config_name_diag]
+    );
+    assert_eq!(
+        test_diagnose_string("common:opt2 --disk_cache="),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        test_diagnose_string("common:opt-2 --disk_cache="),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        test_diagnose_string("common:opt--2 --disk_cache="),
+        vec![config_name_diag]
+    );
+    // The Bazel documentation recommends to prefix all user-specific settings with an `_`.
+    // As such, config names prefixed that way shouldn't be diagnosed as errors.
+    assert_eq!(
+        test_diagnose_string("common:_personal --disk_cache="),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_diagnose_flags() {
+    // Diagnose unknown flags
+    assert_eq!(
+        test_diagnose_string("build --unknown_flag"),
+        vec!["Unknown flag \"--unknown_flag\""]
+    );
+    assert_eq!(
+        test_diagnose_string("build --keep_goign"),
+        vec!["Unknown flag \"--keep_goign\". Did you mean \"--keep_going\"?"]
+    );
+    // Flags removed in the used Bazel version are explained
+    assert_eq!(
+        test_diagnose_string("build --python3_path=/usr/bin/python3"),
+        vec!["The flag \"--python3_path\" was removed in Bazel 8.0.0."]
+    );
+    // Diagnose flags which are applied for the wrong command
+    assert_eq!(
+        test_diagnose_string("startup --disk_cache="),
+        vec!["The flag \"--disk_cache\" is not supported for \"startup\". It is only supported by: analyze-profile, aquery, build, canonicalize-flags, clean, config, coverage, cquery, dump, fetch, help, info, license, mobile-install, mod, print_action, query, run, shutdown, sync, test, vendor, version"]
+    );
+    // Startup flags can't be applied via `common`
+    assert_eq!(
+        test_diagnose_string("common --output_base=/tmp/out\nbuild --output_base=/tmp/out"),
+        vec![
+            "The flag \"--output_base\" is not supported for \"common\". It is only supported by: startup",
+            "The flag \"--output_base\" is not supported for \"build\". It is only supported by: startup"
+        ]
+    );
+    // Flags used with `always` must be supported by all commands
+    assert_eq!(
+        test_diagnose_string("always --color=yes --test_output=all"),
+        vec!["The flag \"--test_output\" is not supported by all commands. `always` makes the following commands fail: analyze-profile, aquery, build, canonicalize-flags, clean, config, cquery, dump, fetch, help, info, license, mobile-install, mod, print_action, query, run, shutdown, sync, vendor, version. Use `common` instead."]
+    );
+    // Diagnose invalid values for enum-typed flags
+    assert_eq!(
+        test_diagnose_string("test --compilation_mode=optt --test_output=ALL\nbuild -c fast"),
+        vec![
+            "The flag \"--compilation_mode\" expects one of fastbuild, dbg, opt, not \"optt\". Did you mean \"opt\"?",
+            "The flag \"-c\" expects one of fastbuild, dbg, opt, not \"fast\". Did you mean \"fastbuild\"?",
+            "Use the full name \"compilation_mode\" instead of its abbreviation."
+        ]
+    );
+    // Diagnose deprecated flags
+    assert_eq!(
+        test_diagnose_string("common --legacy_whole_archive"),
+        vec!["The flag \"--legacy_whole_archive\" is deprecated."]
+    );
+    // Diagnose no_op flags
+    assert_eq!(
+        test_diagnose_string("common --incompatible_override_toolchain_transition"),
+        vec!["The flag \"--incompatible_override_toolchain_transition\" is a no-op."]
+    );
+    // Diagnose abbreviated flag names
+    assert_eq!(
+        test_diagnose_string("build -k"),
+        vec!["Use the full name \"keep_going\" instead of its abbreviation."]
+    );
+
+    // Don't diagnose custom flags
+    assert_eq!(
+        test_diagnose_string(
+            "build --//my/package:setting=foobar
+            build --no//my/package:bool_flag
+            build --@dependency:my/package:bool_flag
+            build --no@dependency:my/package:bool_flag"
+        ),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_diagnose_flag_aliases() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    // Aliases defined in imported files are known as well
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+    std::fs::write(
+        ws.join("aliases.bazelrc"),
+        "common --flag_alias=imported=//foo:imported\n",
+    )
+    .unwrap();
+    let rc = "import %workspace%/aliases.bazelrc\n\
+        common --flag_alias=myflag=//foo:bar\n\
+        build --myflag=1 --nomyflag --imported=x --other=y\n";
+    let diagnostics = diagnostics_from_string(
+        rc,
+        &bazel_flags,
+        Some(&ws.join(".bazelrc")),
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>(),
+        vec!["Unknown flag \"--other\""]
+    );
+}
+
+#[test]
+fn test_diagnose_platform_configs() {
+    use crate::bazel_flags_proto::FlagInfo;
+
+    let flag = |name: &str| FlagInfo {
+        name: name.to_string(),
+        commands: vec!["build".to_string()],
+        ..Default::default()
+    };
+    let bazel_flags = BazelFlags::from_flags(
+        vec![
+            flag("xcode_version"),
+            flag("windows_enable_symlinks"),
+            flag("copt"),
+            flag("enable_platform_specific_config"),
+        ],
+        None,
+    );
+    let text = "build:linux --xcode_version=15 --windows_enable_symlinks --copt=-O2\n\
+        build:macos --xcode_version=15\n\
+        build:ci --xcode_version=15\n";
+    let rope = Rope::from_str(text);
+    let lines = parse_from_str(text).lines;
+    let messages = |enabled: bool| {
+        diagnostics_for_platform_configs(
+            &rope,
+            &lines,
+            &bazel_flags,
+            enabled,
+            PositionEncoding::Utf16,
+        )
+        .into_iter()
+        .map(|d| (d.range.start.line, d.message))
+        .collect::<Vec<_>>()
+    };
+    // Without `--enable_platform_specific_config`, the configs are regular configs
+    assert_eq!(messages(false), Vec::new());
+    assert_eq!(
+        messages(true),
+        vec![
+            (0, "The flag \"--xcode_version\" only applies to macOS, but the config \"linux\" is applied automatically on Linux".to_string()),
+            (0, "The flag \"--windows_enable_symlinks\" only applies to Windows, but the config \"linux\" is applied automatically on Linux".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_diagnose_combined_flags() {
+    // The `--copt` flag expects an argument and hence consumes the
+    // following `--std=c++20`. `--std=c++20` should not raise
+    // an error about an unrecognized Bazel flag.
+    assert_eq!(
+        test_diagnose_string("build --copt --std=c++20"),
+        Vec::<&str>::new()
+    );
+    // On the other hand, `--keep_going` only takes an optional value.
+    // Hence, the `true` is interpreted as a separate flag, which then triggers
+    // an error.
+    assert_eq!(
+        test_diagnose_string("build --keep_going --foobar"),
+        vec!["Unknown flag \"--foobar\""]
+    );
+}
+
+#[test]
+fn test_diagnose_import() {
+    assert_eq!(test_diagnose_string("import"), vec!["Missing file path"]);
+    assert_eq!(
+        test_diagnose_string("try-import"),
+        vec!["Missing file path"]
+    );
+    assert_eq!(
+        test_diagnose_string("import --a"),
+        vec!["`import` expects a file name, not a flag name"]
+    );
     assert_eq!(
-        test_diagnose_string("build: --disk_cache="),
-        vec!["Empty configuration names are pointless"]
+        test_diagnose_string("import a b"),
+        vec!["`import` expects a single file name, but received multiple arguments"]
     );
+}
 
-    // Diagnose config names on commands which don't support configs
+#[test]
+fn test_diagnose_overridden_flags() {
+    use crate::test_utils::load_flags_with_value_types;
+
+    let bazel_flags = load_flags_with_value_types("8.0.0");
+    let test_diagnose_string = |str: &str| {
+        diagnostics_from_string(str, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .filter(|d| DiagnosticCheck::of(d) == Some(DiagnosticCheck::DuplicateFlag))
+            .map(|d| d.message)
+            .collect::<Vec<_>>()
+    };
+
+    // A later assignment overrides the earlier one
     assert_eq!(
-        test_diagnose_string("startup:opt --digest_function=blake3"),
-        vec!["Configuration names not supported on \"startup\" commands"]
+        test_diagnose_string("build --jobs=4\nbuild --jobs=8"),
+        vec!["The flag \"--jobs\" is overridden by a later assignment on line 2"]
     );
+    // Negated flags and abbreviations refer to the same flag
     assert_eq!(
-        test_diagnose_string("import:opt \"x.bazelrc\""),
-        vec!["Configuration names not supported on \"import\" commands"]
+        test_diagnose_string("build --keep_going --nokeep_going"),
+        vec!["The flag \"--keep_going\" is overridden by a later assignment on line 1"]
     );
+    // Exact duplicates are not overrides
     assert_eq!(
-        test_diagnose_string("try-import:opt \"x.bazelrc\""),
-        vec!["Configuration names not supported on \"try-import\" commands"]
+        test_diagnose_string("build --jobs=4\nbuild --jobs=4"),
+        Vec::<String>::new()
+    );
+    // Different scopes are independent from each other
+    assert_eq!(
+        test_diagnose_string("build --jobs=4\nbuild:ci --jobs=8\ntest --jobs=8"),
+        Vec::<String>::new()
+    );
+    // Repeatable flags accumulate their values
+    assert_eq!(
+        test_diagnose_string("build --copt=-O2 --copt=-g"),
+        Vec::<String>::new()
     );
+}
 
-    // Diagnose overly complicated config names
-    let config_name_diag = "Overly complicated config name. Config names should consist only of lower-case ASCII characters.";
+#[test]
+fn test_diagnose_repeated_flags() {
+    use crate::test_utils::load_flags_with_value_types;
+
+    let bazel_flags = load_flags_with_value_types("8.0.0");
+    let diagnose = |str: &str| {
+        diagnostics_from_string(str, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|d| (DiagnosticCheck::of(&d), d.message))
+            .collect::<Vec<_>>()
+    };
+
+    // Repeats are reported separately from overrides
     assert_eq!(
-        test_diagnose_string("common:Uncached --disk_cache="),
-        vec![config_name_diag]
+        diagnose("build --jobs=4\nbuild --jobs=4"),
+        vec![(
+            Some(DiagnosticCheck::RepeatedFlag),
+            "The flag \"--jobs\" is repeated with the same value on line 2".to_string()
+        )]
     );
+    // Different spellings of the same value are repeats as well
     assert_eq!(
-        test_diagnose_string("common:-opt --disk_cache="),
-        vec![config_name_diag]
+        diagnose("build --keep_going --keep_going=yes"),
+        vec![(
+            Some(DiagnosticCheck::RepeatedFlag),
+            "The flag \"--keep_going\" is repeated with the same value on line 1".to_string()
+        )]
     );
+    // Repeatable flags accumulate their values, even if a value is repeated
+    assert!(diagnose("build --copt=-O2 --copt=-g --copt=-O2").is_empty());
+    assert!(diagnose("test --test_env=HOME\ntest --test_env=CI=1").is_empty());
+}
+
+#[test]
+fn test_diagnose_missing_values() {
+    // The flag is the last token on the line and there is no value to combine with
     assert_eq!(
-        test_diagnose_string("common:opt- --disk_cache="),
-        vec![config_name_diag]
+        test_diagnose_string("build --jobs\nbuild --keep_going"),
+        vec!["The flag \"--jobs\" requires a value"]
     );
     assert_eq!(
-        test_diagnose_string("common:2opt --disk_cache="),
-        vec![config_name_diag]
+        test_diagnose_string("build -c"),
+        vec![
+            "The flag \"-c\" requires a value",
+            "Use the full name \"compilation_mode\" instead of its abbreviation."
+        ]
     );
+    // Values might be passed as separate tokens
+    assert_eq!(test_diagnose_string("build --jobs 4"), Vec::<String>::new());
     assert_eq!(
-        test_diagnose_string("common:opt2 --disk_cache="),
-        Vec::<String>::new()
+        test_diagnose_string("build -c opt"),
+        vec!["Use the full name \"compilation_mode\" instead of its abbreviation."]
     );
+    // An empty value is still a value
     assert_eq!(
-        test_diagnose_string("common:opt-2 --disk_cache="),
+        test_diagnose_string("build --disk_cache="),
         Vec::<String>::new()
     );
+}
+
+#[test]
+fn test_diagnose_default_values() {
+    use crate::test_utils::load_flags_with_value_types;
+
+    // The packaged flags only know the defaults of flags dumped via `bazel help`
+    let packaged = load_flags_with_value_types("8.0.0");
+    let mut flags = packaged.flags.clone();
+    for flag in &mut flags {
+        flag.default_value = match flag.name.as_str() {
+            "keep_going" => Some("false".to_string()),
+            "compilation_mode" => Some("fastbuild".to_string()),
+            "jobs" => Some("auto".to_string()),
+            "copt" => Some("".to_string()),
+            _ => None,
+        };
+    }
+    let bazel_flags = BazelFlags::from_flags(flags, Some("8.0.0"));
+    let diagnose = |str: &str| {
+        diagnostics_from_string(str, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .filter(|d| DiagnosticCheck::of(d) == Some(DiagnosticCheck::DefaultValue))
+            .map(|d| d.message)
+            .collect::<Vec<_>>()
+    };
+
+    // All spellings of boolean values are recognized
+    for line in [
+        "build --keep_going=false",
+        "build --keep_going=0",
+        "build --nokeep_going",
+    ] {
+        assert_eq!(
+            diagnose(line),
+            vec![format!(
+                "The flag {:?} is set to its default value \"false\"",
+                line.split(['=', ' ']).nth(1).unwrap()
+            )],
+            "{line}"
+        );
+    }
+    assert!(diagnose("build --keep_going").is_empty());
+    // Values in separate tokens and enum values in different casing
+    assert_eq!(diagnose("build --jobs auto").len(), 1);
+    assert_eq!(diagnose("build -c FASTBUILD").len(), 1);
+    assert!(diagnose("build --jobs=4 -c opt").is_empty());
+    // Flags which accumulate their values are never redundant
+    assert!(diagnose("build --copt=").is_empty());
+    // Points at the complete assignment
+    let diagnostics = diagnostics_from_string(
+        "build --jobs auto",
+        &bazel_flags,
+        None,
+        PositionEncoding::Utf16,
+    );
     assert_eq!(
-        test_diagnose_string("common:opt--2 --disk_cache="),
-        vec![config_name_diag]
+        diagnostics[0].range,
+        tower_lsp::lsp_types::Range::new(
+            tower_lsp::lsp_types::Position::new(0, 6),
+            tower_lsp::lsp_types::Position::new(0, 17)
+        )
     );
-    // The Bazel documentation recommends to prefix all user-specific settings with an `_`.
-    // As such, config names prefixed that way shouldn't be diagnosed as errors.
+    // Without a known default value, nothing is reported
+    assert!(diagnostics_from_string(
+        "build --jobs=auto",
+        &packaged,
+        None,
+        PositionEncoding::Utf16
+    )
+    .is_empty());
+
+    // Resetting a value assigned earlier within the effective config is fine
+    assert!(diagnose("build --keep_going\nbuild:ci --nokeep_going").is_empty());
+    assert!(diagnose("common --keep_going\ntest --nokeep_going").is_empty());
+    assert!(diagnose("build --keep_going\nbuild --nokeep_going").is_empty());
+    // ... but other commands, other configs and later lines don't count
+    assert_eq!(diagnose("test --keep_going\nbuild --nokeep_going").len(), 1);
     assert_eq!(
-        test_diagnose_string("common:_personal --disk_cache="),
+        diagnose("build:a --keep_going\nbuild:b --nokeep_going").len(),
+        1
+    );
+    assert_eq!(
+        diagnose("build:ci --nokeep_going\nbuild --keep_going").len(),
+        1
+    );
+
+    // Values assigned by files Bazel reads earlier count as well
+    let ws = crate::test_utils::TestDir::workspace();
+    ws.write(
+        ".bazelrc",
+        "build --keep_going\ntry-import %workspace%/user.bazelrc\n",
+    );
+    let user_rc = ws.write("user.bazelrc", "build --nokeep_going\n");
+    assert!(diagnostics_from_string(
+        "build --nokeep_going\n",
+        &bazel_flags,
+        Some(&user_rc),
+        PositionEncoding::Utf16
+    )
+    .iter()
+    .all(|d| DiagnosticCheck::of(d) != Some(DiagnosticCheck::DefaultValue)));
+}
+
+#[test]
+fn test_diagnose_unexpected_values() {
+    // Boolean flags accept various spellings of true and false
+    assert_eq!(
+        test_diagnose_string("build --announce_rc=yes --keep_going=0 --verbose_failures=TRUE"),
         Vec::<String>::new()
     );
+    assert_eq!(
+        test_diagnose_string("build --announce_rc=sometimes"),
+        vec!["The flag \"--announce_rc\" expects a boolean value (true, false, yes, no, 1, 0), not \"sometimes\""]
+    );
+    // Negated flags don't accept any value
+    assert_eq!(
+        test_diagnose_string("build --nokeep_going=false"),
+        vec!["The negated flag \"--nokeep_going\" does not accept a value"]
+    );
 }
 
 #[test]
-fn test_diagnose_flags() {
-    // Diagnose unknown flags
+fn test_diagnose_unknown_configs() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+    std::fs::write(
+        ws.join(".bazelrc"),
+        "import %workspace%/ci.bazelrc\nbuild:remote --remote_cache=grpc://cache\ntest --config=ci",
+    )
+    .unwrap();
+    let ci_rc = "build:ci --config=remote --config local\nbuild:ci --config=missing";
+    std::fs::write(ws.join("ci.bazelrc"), ci_rc).unwrap();
+
+    // Configs defined in the same file or in the workspace `.bazelrc` are known
+    let messages = diagnostics_from_string(
+        ci_rc,
+        &bazel_flags,
+        Some(&ws.join("ci.bazelrc")),
+        PositionEncoding::Utf16,
+    )
+    .into_iter()
+    .map(|d| d.message)
+    .collect::<Vec<_>>();
     assert_eq!(
-        test_diagnose_string("build --unknown_flag"),
-        vec!["Unknown flag \"--unknown_flag\""]
+        messages,
+        vec![
+            "Unknown config \"local\". It is neither defined in this file nor in any imported file",
+            "Unknown config \"missing\". It is neither defined in this file nor in any imported file"
+        ]
     );
-    // Diagnose flags which are applied for the wrong command
+}
+
+#[test]
+fn test_diagnose_unused_configs() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+    std::fs::write(
+        ws.join(".bazelrc"),
+        "build --config=remote
+test:ci -k",
+    )
+    .unwrap();
+    ws.write("other.bazelrc", "build:local -k");
+    let rc =
+        "build:remote --keep_going\nbuild:ci --config local\nbuild:debug --compilation_mode=dbg";
+    std::fs::write(ws.join("user.bazelrc"), rc).unwrap();
+
+    let diagnostics = diagnostics_from_string(
+        rc,
+        &bazel_flags,
+        Some(&ws.join("user.bazelrc")),
+        PositionEncoding::Utf16,
+    );
+    let messages = diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect::<Vec<_>>();
     assert_eq!(
-        test_diagnose_string("startup --disk_cache="),
-        vec!["The flag \"--disk_cache\" is not supported for \"startup\". It is supported for [\"analyze-profile\", \"aquery\", \"build\", \"canonicalize-flags\", \"clean\", \"config\", \"coverage\", \"cquery\", \"dump\", \"fetch\", \"help\", \"info\", \"license\", \"mobile-install\", \"mod\", \"print_action\", \"query\", \"run\", \"shutdown\", \"sync\", \"test\", \"vendor\", \"version\"] commands, though."]
+        messages,
+        vec![
+            "Unknown config \"local\". It is neither defined in this file nor in any imported file",
+            "Config \"ci\" is never referenced via `--config` within the workspace",
+            "Config \"debug\" is never referenced via `--config` within the workspace"
+        ]
     );
-    // Diagnose deprecated flags
+    // Definitions in other files are pointed at as related information
+    let related_paths = |d: &Diagnostic| {
+        d.related_information
+            .iter()
+            .flatten()
+            .map(|r| r.location.uri.to_file_path().unwrap())
+            .collect::<Vec<_>>()
+    };
     assert_eq!(
-        test_diagnose_string("common --legacy_whole_archive"),
-        vec!["The flag \"--legacy_whole_archive\" is deprecated."]
+        related_paths(&diagnostics[0]),
+        vec![ws.join("other.bazelrc")]
     );
-    // Diagnose no_op flags
+    assert_eq!(related_paths(&diagnostics[1]), vec![ws.join(".bazelrc")]);
     assert_eq!(
-        test_diagnose_string("common --incompatible_override_toolchain_transition"),
-        vec!["The flag \"--incompatible_override_toolchain_transition\" is a no-op."]
+        related_paths(&diagnostics[2]),
+        Vec::<std::path::PathBuf>::new()
+    );
+}
+
+#[test]
+fn test_diagnose_import_issues() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+    ws.write("ci.bazelrc", "import %workspace%/.bazelrc");
+    let rc = "import %workspace%/ci.bazelrc\ntry-import %workspace%/ci.bazelrc";
+    std::fs::write(ws.join(".bazelrc"), rc).unwrap();
+
+    let diagnostics = diagnostics_from_string(
+        rc,
+        &bazel_flags,
+        Some(&ws.join(".bazelrc")),
+        PositionEncoding::Utf16,
     );
-    // Diagnose abbreviated flag names
     assert_eq!(
-        test_diagnose_string("build -k"),
-        vec!["Use the full name \"keep_going\" instead of its abbreviation."]
+        diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.severity.unwrap(), d.message.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                0,
+                DiagnosticSeverity::ERROR,
+                "Import cycle: .bazelrc -> ci.bazelrc -> .bazelrc"
+            ),
+            (
+                1,
+                DiagnosticSeverity::WARNING,
+                "File is already imported: .bazelrc -> ci.bazelrc"
+            )
+        ]
     );
+}
 
-    // Don't diagnose custom flags
+#[test]
+fn test_diagnose_conflicting_flags() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+    ws.write("ci.bazelrc", "build:ci --jobs=8\n");
+    // The import is read in place, hence `--jobs=4` wins
+    let rc = "import %workspace%/ci.bazelrc\n\
+        build:ci --jobs=4 --copt=-O2 --copt=-g\n\
+        test:ci --jobs=2\n\
+        build:ci --config=opt\n\
+        build:opt --compilation_mode=opt\n\
+        build:ci --compilation_mode=dbg\n\
+        build --keep_going --keep_going=true\n\
+        test --config=ci\n";
+    std::fs::write(ws.join(".bazelrc"), rc).unwrap();
+
+    let diagnostics = diagnostics_from_string(
+        rc,
+        &bazel_flags,
+        Some(&ws.join(".bazelrc")),
+        PositionEncoding::Utf16,
+    );
+    let summary = diagnostics
+        .iter()
+        .map(|d| (d.range.start.line, d.message.as_str()))
+        .collect::<Vec<_>>();
     assert_eq!(
-        test_diagnose_string(
-            "build --//my/package:setting=foobar
-            build --no//my/package:bool_flag
-            build --@dependency:my/package:bool_flag
-            build --no@dependency:my/package:bool_flag"
-        ),
-        Vec::<String>::new()
+        summary,
+        vec![
+            (6, "The flag \"--keep_going\" is repeated with the same value on line 7"),
+            (1, "The flag \"--jobs\" is set to different values for `build --config=ci`: `--jobs=8`, `--jobs=4`. The last value wins."),
+            (4, "The flag \"--compilation_mode\" is set to different values for `build --config=ci`: `--compilation_mode=opt`, `--compilation_mode=dbg`. The last value wins."),
+            (5, "The flag \"--compilation_mode\" is set to different values for `build --config=ci`: `--compilation_mode=opt`, `--compilation_mode=dbg`. The last value wins."),
+        ]
+    );
+    // The other assignments are pointed at as related information
+    let related = diagnostics[1].related_information.as_ref().unwrap();
+    assert_eq!(related.len(), 1);
+    assert_eq!(
+        related[0].location.uri.to_file_path().unwrap(),
+        ws.join("ci.bazelrc")
     );
+    assert_eq!(related[0].message, "Also set to `--jobs=8` here");
 }
 
 #[test]
-fn test_diagnose_combined_flags() {
-    // The `--copt` flag expects an argument and hence consumes the
-    // following `--std=c++20`. `--std=c++20` should not raise
-    // an error about an unrecognized Bazel flag.
+fn test_diagnose_dead_assignments() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+    ws.write("user.bazelrc", "build --jobs=8 --keep_going\n");
+    // `--keep_going` is set before the import, hence overridden. `--jobs` is set afterwards.
+    let rc = "build --keep_going=false\n\
+        try-import %workspace%/user.bazelrc\n\
+        build --jobs=4\n";
+    ws.write(".bazelrc", rc);
+
+    let diagnostics = diagnostics_from_string(
+        rc,
+        &bazel_flags,
+        Some(&ws.join(".bazelrc")),
+        PositionEncoding::Utf16,
+    )
+    .into_iter()
+    .filter(|d| d.severity == Some(DiagnosticSeverity::HINT))
+    .collect::<Vec<_>>();
     assert_eq!(
-        test_diagnose_string("build --copt --std=c++20"),
+        diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect::<Vec<_>>(),
+        vec![(
+            0,
+            "The flag \"--keep_going\" is overridden by `--keep_going` in user.bazelrc:1, which Bazel reads later"
+        )]
+    );
+    assert_eq!(diagnostics[0].tags, Some(vec![DiagnosticTag::UNNECESSARY]));
+}
+
+#[test]
+fn test_diagnose_missing_starlark_flags() {
+    let text = "build --//tools:my_flag=1 --//tools:missing --no//tools:missing\n\
+        build --//tools:unchecked --@rules_x//y:z";
+    let rope = Rope::from_str(text);
+    let lines = parse_from_str(text).lines;
+    let existing_labels = HashMap::from([
+        ("//tools:my_flag".to_string(), true),
+        ("//tools:missing".to_string(), false),
+    ]);
+    let diagnostics = diagnostics_for_missing_starlark_flags(
+        &rope,
+        &lines,
+        &existing_labels,
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|d| (d.range.start.character, d.message.as_str()))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                26,
+                "The build setting \"//tools:missing\" does not exist in the workspace"
+            ),
+            (
+                44,
+                "The build setting \"//tools:missing\" does not exist in the workspace"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_diagnose_placeholders() {
+    // `%workspace%` is only expanded for some flags
+    assert_eq!(
+        test_diagnose_string("try-import %workspace%/user.bazelrc"),
         Vec::<&str>::new()
     );
-    // On the other hand, `--keep_going` only takes an optional value.
-    // Hence, the `true` is interpreted as a separate flag, which then triggers
-    // an error.
     assert_eq!(
-        test_diagnose_string("build --keep_going --foobar"),
-        vec!["Unknown flag \"--foobar\""]
+        test_diagnose_string("build --disk_cache=%workspace%/.cache"),
+        vec!["`%workspace%` is not expanded for \"--disk_cache\". Bazel only expands it in imports and for `--credential_helper`, `--override_module`, `--override_repository`, `--registry`."]
+    );
+    assert_eq!(
+        test_diagnose_string("build --disk_cache=~/.cache"),
+        vec!["`~` is not expanded in flag values. Use an absolute path or `%workspace%` instead."]
+    );
+    assert_eq!(
+        test_diagnose_string("build --disk_cache=${HOME}/.cache"),
+        vec!["Environment variables such as `HOME` are not expanded in bazelrc files."]
+    );
+    assert_eq!(
+        test_diagnose_string("try-import %WORKSPACE%/user.bazelrc"),
+        vec!["Unsupported placeholder `%WORKSPACE%`. Bazel only expands `%workspace%`."]
+    );
+    // Shell commands expand environment variables themselves
+    let rope = Rope::from_str("--workspace_status_command=\"echo $USER\"");
+    let span = 26..rope.len_chars();
+    assert!(diagnostics_for_placeholders(
+        &rope,
+        &span,
+        Some("workspace_status_command"),
+        PositionEncoding::Utf16
+    )
+    .is_empty());
+    assert_eq!(
+        diagnostics_for_placeholders(&rope, &span, Some("jobs"), PositionEncoding::Utf16).len(),
+        1
+    );
+    // The diagnostic points at the placeholder
+    let bazel_flags = crate::bazel_flags::load_packaged_bazel_flags("8.0.0");
+    let diagnostics = diagnostics_from_string(
+        "build --disk_cache=/x/$HOME",
+        &bazel_flags,
+        None,
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        diagnostics[0].range,
+        tower_lsp::lsp_types::Range::new(
+            tower_lsp::lsp_types::Position::new(0, 22),
+            tower_lsp::lsp_types::Position::new(0, 27)
+        )
     );
 }
 
 #[test]
-fn test_diagnose_import() {
-    assert_eq!(test_diagnose_string("import"), vec!["Missing file path"]);
+fn test_diagnose_flag_paths() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let root = TestDir::workspace();
+    std::fs::create_dir_all(root.join("out")).unwrap();
+    let file_path = root.join(".bazelrc");
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let text = "build --disk_cache=.cache\n\
+        startup --output_base=out --output_user_root=MODULE.bazel\n\
+        startup --bazelrc=out --output_base=unchecked";
+    let rope = Rope::from_str(text);
+    let lines = parse_from_str(text).lines;
+    let path_statuses = get_flag_paths(&lines, &bazel_flags, &file_path)
+        .filter(|(_, path, _)| !path.ends_with("unchecked"))
+        .map(|(_, path, _)| {
+            let status = crate::flag_paths::get_path_status(&path);
+            (path, status)
+        })
+        .collect::<HashMap<_, _>>();
+    let diagnostics = diagnostics_for_flag_paths(
+        &rope,
+        &lines,
+        &bazel_flags,
+        &file_path,
+        &path_statuses,
+        PositionEncoding::Utf16,
+    )
+    .into_iter()
+    .map(|d| (d.message, d.severity.unwrap()))
+    .collect::<Vec<_>>();
     assert_eq!(
-        test_diagnose_string("try-import"),
-        vec!["Missing file path"]
+        diagnostics,
+        vec![
+            (
+                format!(
+                    "The directory {:?} does not exist. Bazel will create it.",
+                    root.join(".cache")
+                ),
+                DiagnosticSeverity::HINT
+            ),
+            (
+                format!(
+                    "{:?} is a file, but \"--output_user_root\" expects a directory",
+                    root.join("MODULE.bazel")
+                ),
+                DiagnosticSeverity::WARNING
+            ),
+            (
+                format!(
+                    "{:?} is a directory, but \"--bazelrc\" expects a file",
+                    root.join("out")
+                ),
+                DiagnosticSeverity::WARNING
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_severity_overrides() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let severities = serde_json::from_str::<HashMap<DiagnosticCheck, SeverityOverride>>(
+        r#"{"unused-config": "off", "deprecated-flag": "error"}"#,
+    )
+    .unwrap();
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let diagnostics = diagnostics_from_string(
+        "build --legacy_whole_archive --unknown_flag\nbuild:ci --jobs=1 --jobs=2",
+        &bazel_flags,
+        None,
+        PositionEncoding::Utf16,
     );
+    let severities_by_code = |diagnostics: Vec<Diagnostic>| {
+        diagnostics
+            .iter()
+            .map(|d| (DiagnosticCheck::of(d).map(|c| c.name()), d.severity))
+            .collect::<Vec<_>>()
+    };
     assert_eq!(
-        test_diagnose_string("import --a"),
-        vec!["`import` expects a file name, not a flag name"]
+        severities_by_code(diagnostics.clone()),
+        vec![
+            (Some("deprecated-flag"), Some(DiagnosticSeverity::WARNING)),
+            (Some("unknown-flag"), None),
+            (
+                Some("duplicate-flag"),
+                Some(DiagnosticSeverity::INFORMATION)
+            ),
+        ]
     );
     assert_eq!(
-        test_diagnose_string("import a b"),
-        vec!["`import` expects a single file name, but received multiple arguments"]
+        severities_by_code(apply_severity_overrides(diagnostics, &severities)),
+        vec![
+            (Some("deprecated-flag"), Some(DiagnosticSeverity::ERROR)),
+            (Some("unknown-flag"), None),
+            (
+                Some("duplicate-flag"),
+                Some(DiagnosticSeverity::INFORMATION)
+            ),
+        ]
+    );
+
+    // The diagnostic codes match the names used within the settings
+    for check in DiagnosticCheck::ALL {
+        assert_eq!(serde_json::to_value(check).unwrap(), check.name());
+        assert_eq!(DiagnosticCheck::from_name(check.name()), Some(*check));
+    }
+}
+
+#[test]
+fn test_diagnose_expansion_overrides() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, bazel_flags_proto::FlagInfo};
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0").with_custom_flags(&[FlagInfo {
+        name: "experimental_spawn_scheduler".to_string(),
+        expansion: vec![
+            "--internal_spawn_scheduler".to_string(),
+            "--spawn_strategy=dynamic".to_string(),
+        ],
+        ..Default::default()
+    }]);
+    let diagnose = |str: &str| {
+        diagnostics_from_string(str, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .filter(|d| DiagnosticCheck::of(d) == Some(DiagnosticCheck::ExpansionOverride))
+            .map(|d| (d.message, d.range.start.line))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        diagnose("build --experimental_spawn_scheduler\nbuild --spawn_strategy=local --nointernal_spawn_scheduler\n"),
+        vec![
+            (
+                "The flag \"--spawn_strategy\" overrides the value \"dynamic\" set by the earlier expansion flag \"--experimental_spawn_scheduler\"".to_string(),
+                1
+            ),
+            (
+                "The flag \"--internal_spawn_scheduler\" overrides the value \"true\" set by the earlier expansion flag \"--experimental_spawn_scheduler\"".to_string(),
+                1
+            ),
+        ]
+    );
+    // Restating the expanded value, other scopes and assignments before the expansion are fine
+    assert!(diagnose("build --experimental_spawn_scheduler --internal_spawn_scheduler=1 --spawn_strategy=dynamic\n").is_empty());
+    assert!(diagnose("build --experimental_spawn_scheduler\nbuild:ci --spawn_strategy=local\ntest --spawn_strategy=local\n").is_empty());
+    assert!(
+        diagnose("build --spawn_strategy=local\nbuild --experimental_spawn_scheduler\n").is_empty()
+    );
+}
+
+#[test]
+fn test_diagnose_unstable_flags() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let diagnose = |str: &str, allowed: &[&str]| {
+        let rope = Rope::from_str(str);
+        let mut lines = parse_from_str(str).lines;
+        combine_key_value_flags(&mut lines, &bazel_flags);
+        let allowed = allowed.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        diagnostics_for_unstable_flags(
+            &rope,
+            &lines,
+            &bazel_flags,
+            &allowed,
+            PositionEncoding::Utf16,
+        )
+        .into_iter()
+        .map(|d| d.message)
+        .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        diagnose(
+            "build --experimental_remote_merkle_tree_cache --noincompatible_strict_action_env --jobs=4\n",
+            &[]
+        ),
+        vec![
+            "The flag \"--experimental_remote_merkle_tree_cache\" is experimental and might change or be removed in future Bazel versions",
+            "The flag \"--incompatible_strict_action_env\" guards an incompatible change and might change or be removed in future Bazel versions",
+        ]
+    );
+    // Unknown flags are recognized by their prefix
+    assert_eq!(diagnose("build --experimental_unknown=1\n", &[]).len(), 1);
+    // Allowed flags can be listed with or without the leading `--`
+    assert!(diagnose(
+        "build --experimental_remote_merkle_tree_cache --incompatible_strict_action_env\n",
+        &[
+            "experimental_remote_merkle_tree_cache",
+            "--incompatible_strict_action_env"
+        ]
+    )
+    .is_empty());
+}
+
+#[test]
+fn test_diagnose_config_nesting() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    // Direct and indirect cycles are reported on every reference within the cycle
+    assert_eq!(
+        test_diagnose_string(
+            "build:a --config=b\nbuild:b --config=c\ntest:c --config=a\nbuild:self --config=self\nbuild --config=a\n"
+        ),
+        vec![
+            "Config expansion has a cycle: a -> b -> c -> a",
+            "Config expansion has a cycle: b -> c -> a -> b",
+            "Config expansion has a cycle: c -> a -> b -> c",
+            "Config expansion has a cycle: self -> self",
+        ]
+    );
+    // Lines of unrelated commands never expand into each other, `common` lines always do
+    assert_eq!(
+        test_diagnose_string("query:a --config=b\nbuild:b --config=a\n"),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        test_diagnose_string("common:a --config=b\nbuild:b --config=a\n"),
+        vec![
+            "Config expansion has a cycle: a -> b -> a",
+            "Config expansion has a cycle: b -> a -> b",
+        ]
+    );
+    // Diamonds are no cycles
+    assert_eq!(
+        test_diagnose_string(
+            "build:a --config=b --config=c\nbuild:b --config=d\nbuild:c --config=d\nbuild:d --keep_going\n"
+        ),
+        Vec::<String>::new()
+    );
+
+    // Deeply nested chains, continuing in an imported file
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let nested = (1..=MAX_CONFIG_NESTING)
+        .map(|i| format!("build:c{i} --config=c{}\n", i + 1))
+        .collect::<String>();
+    let related = RcFile::from_str(PathBuf::from("/ws/nested.bazelrc"), &nested, &bazel_flags);
+    let text = "build:ci --config=c1\nbuild:short --config=c2\n";
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let diagnostics = diagnostics_for_config_nesting(
+        &Rope::from_str(text),
+        &lines,
+        &bazel_flags,
+        &[related],
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        diagnostics
+            .iter()
+            .map(|d| (d.range.start.line, d.message.as_str()))
+            .collect::<Vec<_>>(),
+        vec![(
+            0,
+            "Config \"c1\" nests more than 10 levels of configs: c1 -> c2 -> c3 -> c4 -> c5 -> c6 -> c7 -> c8 -> c9 -> c10 -> c11 -> ..."
+        )]
+    );
+    assert_eq!(diagnostics[0].severity, Some(DiagnosticSeverity::WARNING));
+}
+
+#[test]
+fn test_diagnose_after_non_ascii_comment() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    // The ranges count UTF-16 code units, without drifting behind non-ASCII characters
+    let diagnostics = diagnostics_from_string(
+        "# Grüße 🚀\nbuild --unknown_flag # ä\n",
+        &bazel_flags,
+        None,
+        PositionEncoding::Utf16,
+    );
+    assert_eq!(
+        diagnostics.iter().map(|d| d.range).collect::<Vec<_>>(),
+        vec![tower_lsp::lsp_types::Range::new(
+            tower_lsp::lsp_types::Position::new(1, 6),
+            tower_lsp::lsp_types::Position::new(1, 20)
+        )]
     );
 }
@@ -0,0 +1,265 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionTextEdit, Diagnostic, DiagnosticSeverity, Range,
+    TextEdit,
+};
+
+use crate::{
+    bazel_flags_proto::FlagInfo,
+    config_expansion::ExpandedFlag,
+    diagnostic::DiagnosticCheck,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    tokenizer::Span,
+};
+
+// Flags setting environment variables, e.g. `--action_env=PATH=/usr/bin` or `--test_env=HOME`
+pub const ENV_FLAGS: [&str; 4] = ["action_env", "host_action_env", "repo_env", "test_env"];
+
+pub fn is_env_flag(info: &FlagInfo) -> bool {
+    ENV_FLAGS.contains(&info.name.as_str())
+}
+
+// The value of an env flag
+#[derive(Debug, PartialEq)]
+pub struct EnvAssignment<'a> {
+    pub name: &'a str,
+    // `NAME=VALUE` sets the variable, while a bare `NAME` inherits it from the client
+    pub value: Option<&'a str>,
+    // `=NAME` removes the variable again
+    pub unset: bool,
+}
+
+pub fn parse_env_value(value: &str) -> EnvAssignment<'_> {
+    if let Some(name) = value.strip_prefix('=') {
+        return EnvAssignment {
+            name,
+            value: None,
+            unset: true,
+        };
+    }
+    match value.split_once('=') {
+        Some((name, value)) => EnvAssignment {
+            name,
+            value: Some(value),
+            unset: false,
+        },
+        None => EnvAssignment {
+            name: value,
+            value: None,
+            unset: false,
+        },
+    }
+}
+
+// POSIX shells only accept letters, digits and underscores in variable names
+fn is_portable_env_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Validates the `NAME=VALUE` / `NAME` shape of an env flag's value. `span` is the
+// value's span, without the `=` separating it from the flag name.
+pub fn diagnose_env_value(
+    rope: &Rope,
+    flag_name: &str,
+    value: &str,
+    span: &Span,
+    encoding: PositionEncoding,
+) -> Option<Diagnostic> {
+    let assignment = parse_env_value(value);
+    let (message, severity) = if assignment.name.is_empty() {
+        (
+            format!("The flag {flag_name:?} expects `NAME=VALUE` or `NAME`, but the variable name is missing"),
+            DiagnosticSeverity::ERROR,
+        )
+    } else if !is_portable_env_name(assignment.name) {
+        (
+            format!(
+                "{:?} is not a portable environment variable name. Names should only consist of letters, digits and underscores.",
+                assignment.name
+            ),
+            DiagnosticSeverity::WARNING,
+        )
+    } else {
+        return None;
+    };
+    Some(Diagnostic {
+        range: range_to_lsp(rope, span, encoding)?,
+        message,
+        severity: Some(severity),
+        code: DiagnosticCheck::EnvValue.code(),
+        ..Default::default()
+    })
+}
+
+// Offers the variables of the host environment as names for env flags. Only the name
+// part of the value is replaced, keeping an already typed `=VALUE`.
+pub fn complete_env_names(
+    names: impl Iterator<Item = String>,
+    flag_name: &str,
+    range: Range,
+) -> Vec<CompletionItem> {
+    let mut names = names.collect::<Vec<_>>();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| CompletionItem {
+            label: name.clone(),
+            kind: Some(CompletionItemKind::VARIABLE),
+            detail: Some(format!("--{flag_name}, from the environment")),
+            text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: name,
+            })),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>()
+}
+
+// Variables set multiple times via the same env flag (e.g. `--action_env=CC=gcc` and
+// `--action_env=CC=clang`) within the given effective flags, grouped by variable
+pub fn find_duplicate_env_vars(flags: &[ExpandedFlag]) -> Vec<(String, Vec<&ExpandedFlag>)> {
+    let mut groups = Vec::<((&str, &str), Vec<&ExpandedFlag>)>::new();
+    for flag in flags {
+        if !ENV_FLAGS.contains(&flag.name.as_str()) {
+            continue;
+        }
+        let Some(value) = &flag.value else {
+            continue;
+        };
+        let assignment = parse_env_value(value);
+        if assignment.name.is_empty() {
+            continue;
+        }
+        let key = (flag.name.as_str(), assignment.name);
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(flag),
+            None => groups.push((key, vec![flag])),
+        }
+    }
+    groups
+        .into_iter()
+        .filter(|(_, group)| group.len() > 1)
+        .map(|((_, name), group)| (name.to_string(), group))
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_parse_env_value() {
+    assert_eq!(
+        parse_env_value("PATH=/usr/bin:/bin"),
+        EnvAssignment {
+            name: "PATH",
+            value: Some("/usr/bin:/bin"),
+            unset: false
+        }
+    );
+    assert_eq!(
+        parse_env_value("CC=a=b"),
+        EnvAssignment {
+            name: "CC",
+            value: Some("a=b"),
+            unset: false
+        }
+    );
+    assert_eq!(
+        parse_env_value("HOME"),
+        EnvAssignment {
+            name: "HOME",
+            value: None,
+            unset: false
+        }
+    );
+    assert_eq!(
+        parse_env_value("=HOME"),
+        EnvAssignment {
+            name: "HOME",
+            value: None,
+            unset: true
+        }
+    );
+}
+
+#[test]
+fn test_diagnose_env_values() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, diagnostic::diagnostics_from_string};
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let diagnose = |text: &str| {
+        diagnostics_from_string(text, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|d| (d.range.start.character, d.message))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        diagnose("build --action_env=CC=gcc --action_env=HOME --action_env==TMP\n"),
+        vec![]
+    );
+    assert_eq!(
+        diagnose("build --action_env=MY-VAR=1 --action_env==\n"),
+        vec![
+            (19, "\"MY-VAR\" is not a portable environment variable name. Names should only consist of letters, digits and underscores.".to_string()),
+            (41, "The flag \"--action_env\" expects `NAME=VALUE` or `NAME`, but the variable name is missing".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_diagnose_duplicate_env_vars() {
+    use crate::{
+        bazel_flags::load_packaged_bazel_flags, diagnostic::diagnostics_for_conflicting_flags,
+        import_graph::RcFile,
+    };
+    use std::path::PathBuf;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let text = "build:ci --action_env=CC=gcc --test_env=CC=clang\n\
+        build:ci --config=clang\n\
+        build:clang --action_env=CC=clang\n\
+        build --action_env=HOME --action_env=PATH\n";
+    let files = [RcFile::from_str(
+        PathBuf::from("/ws/.bazelrc"),
+        text,
+        &bazel_flags,
+    )];
+    let diagnostics = diagnostics_for_conflicting_flags(
+        &files[0].path,
+        &bazel_flags,
+        &files,
+        PositionEncoding::Utf16,
+    )
+    .into_iter()
+    .map(|d| (d.range.start.line, d.message))
+    .collect::<Vec<_>>();
+    let message = "The environment variable \"CC\" is set multiple times for `build --config=ci`: `--action_env=CC=gcc`, `--action_env=CC=clang`. The last value wins.";
+    assert_eq!(
+        diagnostics,
+        vec![(0, message.to_string()), (2, message.to_string())]
+    );
+}
+
+#[test]
+fn test_complete_env_names() {
+    let range = Range::default();
+    let names = ["PATH", "HOME", "PATH"].map(String::from);
+    let items = complete_env_names(names.into_iter(), "action_env", range);
+    assert_eq!(
+        items
+            .iter()
+            .map(|item| item.label.as_str())
+            .collect::<Vec<_>>(),
+        vec!["HOME", "PATH"]
+    );
+    assert_eq!(
+        items[0].detail.as_deref(),
+        Some("--action_env, from the environment")
+    );
+    assert_eq!(
+        items[0].text_edit,
+        Some(CompletionTextEdit::Edit(TextEdit {
+            range,
+            new_text: "HOME".to_string()
+        }))
+    );
+}
@@ -1,4 +1,9 @@
-use std::path::{Path, PathBuf};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use walkdir::WalkDir;
 
 fn find_file_in_parent_dirs(dir: &Path, file_names: &[&str]) -> Option<PathBuf> {
     let mut path_buf = PathBuf::from(dir);
@@ -21,13 +26,95 @@ pub fn get_workspace_path(path: &Path) -> Option<PathBuf> {
     // See https://github.com/bazelbuild/bazel/blob/20c49b49d6d616aeb97d30454656ebbf9cbacd21/src/main/cpp/workspace_layout.cc#L35
     const ROOT_FILE_NAME: [&str; 4] =
         ["MODULE.bazel", "REPO.bazel", "WORKSPACE.bazel", "WORKSPACE"];
-    find_file_in_parent_dirs(path, &ROOT_FILE_NAME)
+    // Relative paths (e.g., passed on the command line) are resolved against the working directory
+    find_file_in_parent_dirs(&std::path::absolute(path).ok()?, &ROOT_FILE_NAME)
+}
+
+pub fn get_home_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|h| !h.is_empty())
+        .map(PathBuf::from)
 }
 
+// Resolves the path of an `import` / `try-import` inside the file at `file_path`.
+// Like Bazel, relative paths are resolved against the importing file's directory.
 pub fn resolve_bazelrc_path(file_path: &Path, raw_path: &str) -> Option<PathBuf> {
-    let mut path = raw_path.to_string();
-    if path.contains("%workspace%") {
-        path = path.replace("%workspace%", get_workspace_path(file_path)?.to_str()?);
+    let path = if raw_path.contains("%workspace%") {
+        PathBuf::from(raw_path.replace("%workspace%", get_workspace_path(file_path)?.to_str()?))
+    } else if let Some(rest) = raw_path.strip_prefix("~/") {
+        get_home_path()?.join(rest)
+    } else {
+        PathBuf::from(raw_path)
+    };
+    if path.is_absolute() {
+        return Some(path);
+    }
+    Some(file_path.parent()?.join(path))
+}
+
+pub fn is_bazelrc_file(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".bazelrc")
+}
+
+// Finds all bazelrc files inside the given directory, skipping hidden sub-directories
+pub fn find_bazelrc_files(dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_entry(|e| {
+            let s = e.file_name().to_string_lossy();
+            e.depth() == 0 || !s.starts_with('.') || s == ".bazelrc"
+        })
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .filter(|p| is_bazelrc_file(p) && p.is_file())
+        .collect::<Vec<_>>()
+}
+
+// Is the given path located inside the workspace containing `file_path`?
+pub fn is_inside_workspace(file_path: &Path, path: &Path) -> bool {
+    match get_workspace_path(file_path) {
+        Some(workspace) => path.starts_with(workspace),
+        None => false,
     }
-    Some(file_path.join(Path::new(&path)))
+}
+
+#[test]
+fn test_resolve_home_path() {
+    let Some(home) = get_home_path() else {
+        return;
+    };
+    assert_eq!(
+        resolve_bazelrc_path(Path::new("/ws/.bazelrc"), "~/.bazelrc"),
+        Some(home.join(".bazelrc"))
+    );
+}
+
+#[test]
+fn test_resolve_relative_path() {
+    use crate::test_utils::TestDir;
+
+    let ws = TestDir::workspace();
+    let rc_path = ws.write("tools/ci.bazelrc", "");
+
+    // Relative paths are resolved against the importing file's directory
+    assert_eq!(
+        resolve_bazelrc_path(&rc_path, "remote.bazelrc"),
+        Some(ws.join("tools/remote.bazelrc"))
+    );
+    assert_eq!(
+        resolve_bazelrc_path(&rc_path, "../.bazelrc"),
+        Some(ws.join("tools/../.bazelrc"))
+    );
+    // `%workspace%` refers to the workspace root
+    assert_eq!(
+        resolve_bazelrc_path(&rc_path, "%workspace%/tools/remote.bazelrc"),
+        Some(ws.join("tools/remote.bazelrc"))
+    );
+    // Absolute paths are kept as is
+    let absolute = env::temp_dir().join("bazel.bazelrc");
+    assert_eq!(
+        resolve_bazelrc_path(&rc_path, absolute.to_str().unwrap()),
+        Some(absolute)
+    );
 }
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use crate::{bazel_flags::BazelFlags, parser::Line};
+
+// The aliases defined via `--flag_alias=name=label`, mapping each name to its label
+pub type FlagAliases = HashMap<String, String>;
+
+// Collects the flag aliases defined within the given lines, e.g. all lines of the import graph
+pub fn get_flag_aliases<'a, I>(lines: I, bazel_flags: &BazelFlags) -> FlagAliases
+where
+    I: IntoIterator<Item = &'a Line>,
+{
+    lines
+        .into_iter()
+        .flat_map(|line| &line.flags)
+        .filter(|flag| {
+            flag.name
+                .as_ref()
+                .and_then(|name| bazel_flags.get_by_invocation(&name.0))
+                .is_some_and(|(_, info)| info.name == "flag_alias")
+        })
+        .filter_map(|flag| {
+            let (name, label) = flag.value.as_ref()?.0.split_once('=')?;
+            (!name.is_empty() && !label.is_empty()).then(|| (name.to_string(), label.to_string()))
+        })
+        .collect()
+}
+
+// Resolves an invocation like `--myflag` or `--nomyflag` to the alias name and its label
+pub fn lookup_flag_alias<'a>(
+    aliases: &'a FlagAliases,
+    invocation: &str,
+) -> Option<(&'a str, &'a str)> {
+    let stripped = invocation.strip_suffix('=').unwrap_or(invocation);
+    let name = stripped.strip_prefix("--")?;
+    aliases
+        .get_key_value(name)
+        .or_else(|| aliases.get_key_value(name.strip_prefix("no")?))
+        .map(|(name, label)| (name.as_str(), label.as_str()))
+}
+
+pub fn get_flag_alias_hover_markdown(aliases: &FlagAliases, invocation: &str) -> Option<String> {
+    let (name, label) = lookup_flag_alias(aliases, invocation)?;
+    Some(format!(
+        "`--{name}` is an alias for the Starlark flag `{label}`, defined via `--flag_alias={name}={label}`."
+    ))
+}
+
+#[test]
+fn test_flag_aliases() {
+    use crate::bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags};
+    use crate::parser::parse_from_str;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let mut lines = parse_from_str(
+        "build --flag_alias=myflag=//foo:bar\n\
+        common --flag_alias other=@rules_x//y:z --flag_alias=broken\n\
+        build --myflag=1",
+    )
+    .lines;
+    combine_key_value_flags(&mut lines, &flags);
+    let aliases = get_flag_aliases(&lines, &flags);
+    assert_eq!(
+        aliases,
+        FlagAliases::from([
+            ("myflag".to_string(), "//foo:bar".to_string()),
+            ("other".to_string(), "@rules_x//y:z".to_string()),
+        ])
+    );
+
+    assert_eq!(
+        lookup_flag_alias(&aliases, "--myflag"),
+        Some(("myflag", "//foo:bar"))
+    );
+    assert_eq!(
+        lookup_flag_alias(&aliases, "--nomyflag"),
+        Some(("myflag", "//foo:bar"))
+    );
+    assert_eq!(lookup_flag_alias(&aliases, "--myflag2"), None);
+    assert_eq!(lookup_flag_alias(&aliases, "myflag"), None);
+    assert_eq!(
+        get_flag_alias_hover_markdown(&aliases, "--other").unwrap(),
+        "`--other` is an alias for the Starlark flag `@rules_x//y:z`, defined via `--flag_alias=other=@rules_x//y:z`."
+    );
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::bazel_flags::{BazelFlags, COMMAND_DOCS};
+use crate::bazel_flags_proto::FlagInfo;
+use crate::bazel_version::{get_flag_lifecycle, AVAILABLE_BAZEL_VERSIONS};
+use crate::flag_values::get_allowed_values;
+
+fn is_documented(flag: &FlagInfo) -> bool {
+    flag.documentation_category.as_deref() != Some("UNDOCUMENTED")
+}
+
+fn flag_anchor(flag: &FlagInfo) -> String {
+    format!("flag-{}", flag.name)
+}
+
+// Renders a markdown reference for all flags, grouped by command and category.
+// Each flag is documented once at the end and linked from all commands supporting it.
+pub fn render_flags_markdown(bazel_flags: &BazelFlags, title: &str) -> String {
+    let mut result = format!("# {title}\n\n");
+
+    let mut commands = bazel_flags
+        .flags_by_commands
+        .keys()
+        .filter(|c| *c != "common" && *c != "always")
+        .collect::<Vec<_>>();
+    commands.sort();
+
+    // Table of contents
+    result += "## Commands\n\n";
+    for command in &commands {
+        result += &format!("* [`{command}`](#command-{command})\n");
+    }
+    result += "* [All flags](#all-flags)\n\n";
+
+    // The flags of each command, grouped by category
+    for command in &commands {
+        result += &format!("<a id=\"command-{command}\"></a>\n\n### `{command}`\n\n");
+        if let Some(doc) = COMMAND_DOCS.get(command.as_str()) {
+            result += &format!("{doc}\n\n");
+        }
+        let mut by_category = BTreeMap::<String, Vec<&FlagInfo>>::new();
+        for flag_nr in &bazel_flags.flags_by_commands[*command] {
+            let flag = &bazel_flags.flags[*flag_nr];
+            if !is_documented(flag) {
+                continue;
+            }
+            let category = flag
+                .documentation_category
+                .as_deref()
+                .unwrap_or("uncategorized")
+                .to_lowercase()
+                .replace('_', " ");
+            by_category.entry(category).or_default().push(flag);
+        }
+        for (category, mut flags) in by_category {
+            flags.sort_by(|a, b| a.name.cmp(&b.name));
+            result += &format!("#### {category}\n\n");
+            for flag in flags {
+                result += &format!("* [`--{}`](#{})", flag.name, flag_anchor(flag));
+                if let Some(abbr) = &flag.abbreviation {
+                    result += &format!(" [`-{abbr}`]");
+                }
+                result += "\n";
+            }
+            result += "\n";
+        }
+    }
+
+    // The documentation of all flags
+    let mut flags = bazel_flags
+        .flags_by_name
+        .iter()
+        // Skip the entries for old names
+        .filter(|(name, flag_nr)| **name == bazel_flags.flags[**flag_nr].name)
+        .map(|(_, flag_nr)| &bazel_flags.flags[*flag_nr])
+        .filter(|flag| is_documented(flag))
+        .collect::<Vec<_>>();
+    flags.sort_by(|a, b| a.name.cmp(&b.name));
+    result += "<a id=\"all-flags\"></a>\n\n## All flags\n\n";
+    for flag in flags {
+        result += &format!("<a id=\"{}\"></a>\n\n", flag_anchor(flag));
+        result += &flag.get_documentation_markdown(bazel_flags.bazel_version.as_deref());
+        if let Some(old_name) = &flag.old_name {
+            result += &format!("Old name: `--{old_name}`\n");
+        }
+        result += &format!("Commands: {}\n\n", flag.commands.join(", "));
+    }
+    result
+}
+
+// The flags of the Bazel version as JSON objects, sorted by name. Besides the fields of
+// `FlagInfo`, each object lists the allowed values of enum-typed flags and the Bazel
+// versions the flag was added / removed in, instead of all versions supporting it.
+pub fn export_flags(bazel_flags: &BazelFlags) -> Vec<Value> {
+    let mut flags = bazel_flags
+        .flags_by_name
+        .iter()
+        // Skip the entries for old names
+        .filter(|(name, flag_nr)| **name == bazel_flags.flags[**flag_nr].name)
+        .map(|(_, flag_nr)| &bazel_flags.flags[*flag_nr])
+        .collect::<Vec<_>>();
+    flags.sort_by(|a, b| a.name.cmp(&b.name));
+    flags
+        .into_iter()
+        .map(|flag| {
+            let lifecycle = get_flag_lifecycle(&AVAILABLE_BAZEL_VERSIONS, &flag.bazel_versions);
+            let mut value = serde_json::to_value(flag).unwrap();
+            let object = value.as_object_mut().unwrap();
+            object.remove("bazelVersions");
+            object.insert("allowedValues".to_string(), json!(get_allowed_values(flag)));
+            object.insert("addedIn".to_string(), json!(lifecycle.added));
+            object.insert("removedIn".to_string(), json!(lifecycle.removed));
+            value
+        })
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_export_flags() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, test_utils::load_flags_with_value_types};
+
+    let flags = export_flags(&load_flags_with_value_types("8.0.0"));
+    let find = |name: &str| flags.iter().find(|f| f["name"] == name).unwrap();
+    let compilation_mode = find("compilation_mode");
+    assert_eq!(compilation_mode["abbreviation"], "c");
+    assert_eq!(
+        compilation_mode["allowedValues"],
+        json!(["fastbuild", "dbg", "opt"])
+    );
+    assert!(compilation_mode["commands"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("build")));
+    assert!(compilation_mode.get("bazelVersions").is_none());
+    assert_eq!(find("jobs")["allowedValues"], Value::Null);
+    // Flags from other Bazel versions are not exported
+    assert!(find("enable_workspace")["addedIn"].is_string());
+    let old_flags = export_flags(&load_packaged_bazel_flags("7.0.0"));
+    assert!(!old_flags.iter().any(|f| f["name"] == "enable_workspace"));
+    assert!(flags
+        .windows(2)
+        .all(|w| w[0]["name"].as_str() < w[1]["name"].as_str()));
+}
+
+#[test]
+fn test_render_flags_markdown() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let markdown = render_flags_markdown(&flags, "Bazel 8.0.0 flags");
+    assert!(markdown.starts_with("# Bazel 8.0.0 flags\n\n## Commands\n\n"));
+    // Commands are documented with their flags, grouped by category
+    assert!(markdown.contains(
+        "### `build`\n\nBuilds the specified targets.\n\n#### execution strategy\n\n\
+        * [`--keep_going`](#flag-keep_going) [`-k`]\n"
+    ));
+    // Each flag is documented exactly once
+    assert_eq!(
+        markdown
+            .matches("<a id=\"flag-remote_cache_compression\"></a>")
+            .count(),
+        1
+    );
+    assert!(markdown.contains("Old name: `--experimental_remote_cache_compression`\n"));
+    // Undocumented flags are skipped
+    assert!(!markdown.contains("internal_spawn_scheduler"));
+}
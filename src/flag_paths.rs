@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    bazel_flags::BazelFlags,
+    bazel_flags_proto::FlagInfo,
+    file_utils::get_workspace_path,
+    flag_values::{get_flag_value, get_value_kind, ValueKind},
+    parser::Line,
+    placeholders::{find_placeholders, supports_workspace_placeholder, Placeholder},
+    tokenizer::Span,
+};
+
+// What exists on disk at the path passed to a flag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathStatus {
+    Missing,
+    File,
+    Directory { writable: bool },
+}
+
+pub fn get_path_status(path: &Path) -> PathStatus {
+    match std::fs::metadata(path) {
+        Ok(metadata) if metadata.is_dir() => PathStatus::Directory {
+            writable: is_writable(path, &metadata),
+        },
+        Ok(_) => PathStatus::File,
+        Err(_) => PathStatus::Missing,
+    }
+}
+
+// The permission bits alone don't tell whether the current user may write, e.g. for
+// directories owned by someone else or on read-only mounts. Ask the OS instead.
+#[cfg(unix)]
+fn is_writable(path: &Path, _metadata: &std::fs::Metadata) -> bool {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return true;
+    };
+    // SAFETY: `path` is a valid, NUL-terminated string
+    unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_writable(_path: &Path, metadata: &std::fs::Metadata) -> bool {
+    !metadata.permissions().readonly()
+}
+
+// Path-valued flags pointing to directories, which Bazel creates if necessary
+pub const DIRECTORY_FLAGS: &[&str] = &[
+    "disk_cache",
+    "distdir",
+    "install_base",
+    "output_base",
+    "output_user_root",
+    "repository_cache",
+    "sandbox_base",
+    "vendor_dir",
+];
+
+// Path-valued flags pointing to files which Bazel reads. Files written by Bazel, e.g. the
+// profile or the execution log, are not checked.
+pub const FILE_FLAGS: &[&str] = &[
+    "bazelrc",
+    "downloader_config",
+    "experimental_downloader_config",
+    "google_credentials",
+    "tls_certificate",
+    "tls_client_certificate",
+    "tls_client_key",
+];
+
+// Whether a path-valued flag points to a directory or to a file. `None` for paths whose
+// expectations aren't known, which are not checked.
+pub fn expects_directory(info: &FlagInfo) -> Option<bool> {
+    let name = info.name.as_str();
+    if DIRECTORY_FLAGS.contains(&name) {
+        Some(true)
+    } else if FILE_FLAGS.contains(&name) {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// Resolves the value of a path-valued flag. Relative paths are resolved against the
+// workspace root, since Bazel is usually invoked from there. Values containing
+// placeholders which Bazel does not expand are skipped.
+pub fn resolve_flag_path(file_path: &Path, flag_name: &str, value: &str) -> Option<PathBuf> {
+    let workspace = get_workspace_path(file_path);
+    let mut value = value.to_string();
+    for (placeholder, _) in find_placeholders(&value) {
+        if placeholder != Placeholder::Workspace || !supports_workspace_placeholder(flag_name) {
+            return None;
+        }
+    }
+    if value.contains("%workspace%") {
+        value = value.replace("%workspace%", workspace.as_ref()?.to_str()?);
+    }
+    if value.is_empty() {
+        return None;
+    }
+    let path = PathBuf::from(value);
+    if path.is_absolute() {
+        Some(path)
+    } else {
+        Some(workspace?.join(path))
+    }
+}
+
+// The path-valued flags within the lines, with their resolved paths and value spans
+pub fn get_flag_paths<'a>(
+    lines: &'a [Line],
+    bazel_flags: &'a BazelFlags,
+    file_path: &'a Path,
+) -> impl Iterator<Item = (&'a FlagInfo, PathBuf, &'a Span)> + 'a {
+    lines
+        .iter()
+        .filter(|line| {
+            line.command
+                .as_ref()
+                .is_some_and(|c| c.0 != "import" && c.0 != "try-import")
+        })
+        .flat_map(move |line| {
+            (0..line.flags.len()).filter_map(move |flag_nr| {
+                let (info, _, (value, span)) = get_flag_value(bazel_flags, line, flag_nr)?;
+                if get_value_kind(info) != ValueKind::Path || expects_directory(info).is_none() {
+                    return None;
+                }
+                Some((info, resolve_flag_path(file_path, &info.name, value)?, span))
+            })
+        })
+}
+
+#[test]
+fn test_get_flag_paths() {
+    use crate::bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags};
+    use crate::parser::parse_from_str;
+    use crate::test_utils::TestDir;
+
+    let root = TestDir::workspace();
+    let file_path = root.join(".bazelrc");
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let text = "build --disk_cache=.cache --jobs=4\n\
+        startup --output_base=/tmp/out --output_user_root=~/root\n\
+        common --vendor_dir=vendor --disk_cache=%workspace%/cache\n\
+        import %workspace%/user.bazelrc";
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let paths = get_flag_paths(&lines, &bazel_flags, &file_path)
+        .map(|(info, path, _)| (info.name.as_str(), path))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        paths,
+        vec![
+            ("disk_cache", root.join(".cache")),
+            ("output_base", PathBuf::from("/tmp/out")),
+            ("vendor_dir", root.join("vendor")),
+        ]
+    );
+
+    assert_eq!(
+        get_path_status(&root),
+        PathStatus::Directory { writable: true }
+    );
+    assert_eq!(
+        get_path_status(&root.join("MODULE.bazel")),
+        PathStatus::File
+    );
+    assert_eq!(get_path_status(&root.join("missing")), PathStatus::Missing);
+}
@@ -0,0 +1,286 @@
+use std::{fmt, path::PathBuf};
+
+use crate::{
+    bazel_flags::{BazelFlags, FlagLookupType},
+    import_graph::RcFile,
+};
+
+// The status of a flag within a specific Bazel version
+#[derive(Debug, PartialEq, Eq)]
+pub enum FlagStatus {
+    NotAvailable,
+    Available,
+    Deprecated,
+    NoOp,
+    RenamedTo(String),
+}
+
+impl fmt::Display for FlagStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FlagStatus::NotAvailable => write!(f, "not available"),
+            FlagStatus::Available => write!(f, "available"),
+            FlagStatus::Deprecated => write!(f, "deprecated"),
+            FlagStatus::NoOp => write!(f, "no-op"),
+            FlagStatus::RenamedTo(name) => write!(f, "renamed to --{name}"),
+        }
+    }
+}
+
+pub fn get_flag_status(bazel_flags: &BazelFlags, name: &str) -> FlagStatus {
+    let Some((lookup_type, info)) = bazel_flags.get_by_invocation(name) else {
+        return FlagStatus::NotAvailable;
+    };
+    if lookup_type == FlagLookupType::OldName {
+        FlagStatus::RenamedTo(info.name.clone())
+    } else if info.is_noop() {
+        FlagStatus::NoOp
+    } else if info.is_deprecated() {
+        FlagStatus::Deprecated
+    } else {
+        FlagStatus::Available
+    }
+}
+
+// A usage of an experimental or incompatible flag
+#[derive(Debug)]
+pub struct ExposedFlag {
+    pub path: PathBuf,
+    // 1-based, as usual for compiler-style output
+    pub line: usize,
+    pub name: String,
+    pub pinned: FlagStatus,
+    pub latest: FlagStatus,
+}
+
+fn is_experimental_or_incompatible(name: &str) -> bool {
+    let name = name.strip_prefix("--").unwrap_or(name);
+    let name = name.strip_prefix("no").unwrap_or(name);
+    name.starts_with("experimental_") || name.starts_with("incompatible_")
+}
+
+// Finds all `--experimental_*` and `--incompatible_*` flags used in the given files
+pub fn find_exposed_flags(
+    files: &[RcFile],
+    pinned_flags: &BazelFlags,
+    latest_flags: &BazelFlags,
+) -> Vec<ExposedFlag> {
+    let mut result = Vec::<ExposedFlag>::new();
+    for file in files {
+        for line in &file.lines {
+            for flag in &line.flags {
+                let Some((name, span)) = &flag.name else {
+                    continue;
+                };
+                let name = name.strip_suffix('=').unwrap_or(name);
+                if !is_experimental_or_incompatible(name) {
+                    continue;
+                }
+                result.push(ExposedFlag {
+                    path: file.path.clone(),
+                    line: file.rope.char_to_line(span.start) + 1,
+                    name: name.to_string(),
+                    pinned: get_flag_status(pinned_flags, name),
+                    latest: get_flag_status(latest_flags, name),
+                });
+            }
+        }
+    }
+    result
+}
+
+pub fn format_exposure_report(flags: &[ExposedFlag], latest_version: &str) -> String {
+    let mut result = String::new();
+    for flag in flags {
+        result += &format!(
+            "{}:{}: {}: pinned: {}, Bazel {}: {}\n",
+            flag.path.display(),
+            flag.line,
+            flag.name,
+            flag.pinned,
+            latest_version,
+            flag.latest
+        );
+    }
+    let is_incompatible = |f: &&ExposedFlag| f.name.contains("incompatible_");
+    let incompatible_count = flags.iter().filter(is_incompatible).count();
+    let breaking_count = flags
+        .iter()
+        .filter(|f| f.pinned != f.latest && f.latest != FlagStatus::Available)
+        .count();
+    result += &format!(
+        "{} experimental and {} incompatible flag usages, {} of which change status in Bazel {}\n",
+        flags.len() - incompatible_count,
+        incompatible_count,
+        breaking_count,
+        latest_version
+    );
+    result
+}
+
+// A flag usage which needs attention when upgrading Bazel
+#[derive(Debug)]
+pub struct UpgradeIssue {
+    pub path: PathBuf,
+    // 1-based, as usual for compiler-style output
+    pub line: usize,
+    pub name: String,
+    pub from: FlagStatus,
+    pub to: FlagStatus,
+}
+
+// Finds all flags which are removed, renamed or newly deprecated between two Bazel versions
+pub fn find_upgrade_issues(
+    files: &[RcFile],
+    from_flags: &BazelFlags,
+    to_flags: &BazelFlags,
+) -> Vec<UpgradeIssue> {
+    let mut result = Vec::<UpgradeIssue>::new();
+    for file in files {
+        for line in &file.lines {
+            for flag in &line.flags {
+                let Some((name, span)) = &flag.name else {
+                    continue;
+                };
+                let name = name.strip_suffix('=').unwrap_or(name);
+                let from = get_flag_status(from_flags, name);
+                let to = get_flag_status(to_flags, name);
+                // Unknown flags are already reported by `lint`
+                if from == to || from == FlagStatus::NotAvailable || to == FlagStatus::Available {
+                    continue;
+                }
+                result.push(UpgradeIssue {
+                    path: file.path.clone(),
+                    line: file.rope.char_to_line(span.start) + 1,
+                    name: name.to_string(),
+                    from,
+                    to,
+                });
+            }
+        }
+    }
+    result
+}
+
+pub fn format_upgrade_report(
+    issues: &[UpgradeIssue],
+    from_version: &str,
+    to_version: &str,
+) -> String {
+    let mut result = String::new();
+    for issue in issues {
+        let change = match &issue.to {
+            FlagStatus::NotAvailable => "removed".to_string(),
+            FlagStatus::RenamedTo(name) => format!("renamed to --{name}"),
+            FlagStatus::Deprecated => "deprecated".to_string(),
+            FlagStatus::NoOp => "a no-op".to_string(),
+            FlagStatus::Available => "available".to_string(),
+        };
+        result += &format!(
+            "{}:{}: {}: {} in Bazel {} ({} in Bazel {})\n",
+            issue.path.display(),
+            issue.line,
+            issue.name,
+            change,
+            to_version,
+            issue.from,
+            from_version
+        );
+    }
+    result += &format!(
+        "{} flag usages need attention when upgrading from Bazel {} to Bazel {}\n",
+        issues.len(),
+        from_version,
+        to_version
+    );
+    result
+}
+
+#[test]
+fn test_exposure_report() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let pinned = load_packaged_bazel_flags("7.0.0");
+    let latest = load_packaged_bazel_flags("8.0.0");
+    let file = RcFile::from_str(
+        PathBuf::from("/ws/.bazelrc"),
+        "build --keep_going\n\
+        build --experimental_remote_cache_compression\n\
+        build:ci --noincompatible_strict_action_env --experimental_unknown=1\n",
+        &pinned,
+    );
+    let flags = find_exposed_flags(&[file], &pinned, &latest);
+    assert_eq!(
+        flags
+            .iter()
+            .map(|f| (f.line, f.name.as_str(), &f.pinned, &f.latest))
+            .collect::<Vec<_>>(),
+        vec![
+            (
+                2,
+                "--experimental_remote_cache_compression",
+                &FlagStatus::Available,
+                &FlagStatus::RenamedTo("remote_cache_compression".to_string())
+            ),
+            (
+                3,
+                "--noincompatible_strict_action_env",
+                &FlagStatus::Available,
+                &FlagStatus::Available
+            ),
+            (
+                3,
+                "--experimental_unknown",
+                &FlagStatus::NotAvailable,
+                &FlagStatus::NotAvailable
+            ),
+        ]
+    );
+    assert_eq!(
+        format_exposure_report(&flags, "8.0.0"),
+        "/ws/.bazelrc:2: --experimental_remote_cache_compression: pinned: available, \
+            Bazel 8.0.0: renamed to --remote_cache_compression\n\
+        /ws/.bazelrc:3: --noincompatible_strict_action_env: pinned: available, \
+            Bazel 8.0.0: available\n\
+        /ws/.bazelrc:3: --experimental_unknown: pinned: not available, \
+            Bazel 8.0.0: not available\n\
+        2 experimental and 1 incompatible flag usages, 1 of which change status in Bazel 8.0.0\n"
+    );
+}
+
+#[test]
+fn test_upgrade_report() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let from = load_packaged_bazel_flags("7.4.1");
+    let to = load_packaged_bazel_flags("8.0.0");
+    let file = RcFile::from_str(
+        PathBuf::from("/ws/.bazelrc"),
+        "build --keep_going --python3_path=/usr/bin/python3\n\
+        build --experimental_remote_cache_compression\n\
+        build:ci --enable_workspace --unknown_flag\n",
+        &from,
+    );
+    let issues = find_upgrade_issues(&[file], &from, &to);
+    assert_eq!(
+        issues
+            .iter()
+            .map(|f| (f.line, f.name.as_str(), &f.to))
+            .collect::<Vec<_>>(),
+        vec![
+            (1, "--python3_path", &FlagStatus::NotAvailable),
+            (
+                2,
+                "--experimental_remote_cache_compression",
+                &FlagStatus::RenamedTo("remote_cache_compression".to_string())
+            ),
+        ]
+    );
+    assert_eq!(
+        format_upgrade_report(&issues, "7.4.1", "8.0.0"),
+        "/ws/.bazelrc:1: --python3_path: removed in Bazel 8.0.0 (no-op in Bazel 7.4.1)\n\
+        /ws/.bazelrc:2: --experimental_remote_cache_compression: \
+            renamed to --remote_cache_compression in Bazel 8.0.0 (available in Bazel 7.4.1)\n\
+        2 flag usages need attention when upgrading from Bazel 7.4.1 to Bazel 8.0.0\n"
+    );
+}
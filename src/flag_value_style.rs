@@ -0,0 +1,191 @@
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{CodeAction, CodeActionKind, Command, TextEdit, Url};
+
+use crate::{
+    bazel_flags::{BazelFlags, FlagLookupType},
+    code_action::{get_flag_span, make_workspace_edit},
+    formatting::format_token,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::{Flag, Line},
+};
+
+pub const NORMALIZE_FLAG_VALUES_COMMAND: &str = "bazelrc.normalizeFlagValues";
+
+// The kind of the rewrites between `--flag value` and `--flag=value`
+pub const REWRITE_FLAG_VALUE_KIND: &str = "refactor.rewrite.flagValue";
+
+// How values are attached to their flags
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FlagValueStyle {
+    // `--flag=value`
+    Equals,
+    // `--flag value`
+    Space,
+}
+
+// Arguments of the `bazelrc.normalizeFlagValues` command
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NormalizeFlagValuesParams {
+    // Any document inside the workspace. Used to determine the workspace root.
+    pub uri: Url,
+    pub style: FlagValueStyle,
+}
+
+// The style the flag is written in. `None` if the flag can only be written in one style,
+// e.g. boolean flags and abbreviations (`-c=opt` would be invalid).
+fn get_flag_value_style(bazel_flags: &BazelFlags, flag: &Flag) -> Option<FlagValueStyle> {
+    let (name, name_span) = flag.name.as_ref()?;
+    let (value, value_span) = flag.value.as_ref()?;
+    let (lookup_type, info) = bazel_flags.get_by_invocation(name)?;
+    if lookup_type == FlagLookupType::Abbreviation || !info.requires_value() {
+        return None;
+    }
+    // `combine_key_value_flags` attaches the following token as value. Otherwise, the
+    // value directly follows the name, starting with the `=`.
+    if name_span.end != value_span.start {
+        Some(FlagValueStyle::Space)
+    } else if !value.is_empty() {
+        Some(FlagValueStyle::Equals)
+    } else {
+        None
+    }
+}
+
+// The edit rewriting a single flag into the given style. Only the separator between
+// the name and the value is replaced, unless quoting requires rewriting the whole flag.
+fn get_flag_value_style_edit(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    flag: &Flag,
+    style: FlagValueStyle,
+    encoding: PositionEncoding,
+) -> Option<TextEdit> {
+    let current = get_flag_value_style(bazel_flags, flag)?;
+    if current == style {
+        return None;
+    }
+    let (name, name_span) = flag.name.as_ref()?;
+    let (value, value_span) = flag.value.as_ref()?;
+    let (span, new_text) = match style {
+        FlagValueStyle::Equals => (name_span.end..value_span.start, "=".to_string()),
+        // Within a quoted token (`"--flag=a b"`), the name and value have to be quoted separately
+        FlagValueStyle::Space if matches!(rope.get_char(name_span.start), Some('"' | '\'')) => (
+            get_flag_span(flag)?,
+            format!("{} {}", format_token(name), format_token(value)),
+        ),
+        FlagValueStyle::Space => (value_span.start..value_span.start + 1, " ".to_string()),
+    };
+    Some(TextEdit {
+        range: range_to_lsp(rope, &span, encoding)?,
+        new_text,
+    })
+}
+
+// The edits rewriting all flags of a file into the given style
+pub fn get_flag_value_style_edits(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    lines: &[Line],
+    style: FlagValueStyle,
+    encoding: PositionEncoding,
+) -> Vec<TextEdit> {
+    lines
+        .iter()
+        .flat_map(|line| &line.flags)
+        .filter_map(|flag| get_flag_value_style_edit(bazel_flags, rope, flag, style, encoding))
+        .collect::<Vec<_>>()
+}
+
+// Offers to switch a flag between `--flag value` and `--flag=value`, and to apply
+// that style to all flags of the workspace
+pub fn flag_value_style_actions(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    flag: &Flag,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Vec<CodeAction> {
+    let Some(current) = get_flag_value_style(bazel_flags, flag) else {
+        return Vec::new();
+    };
+    let (style, example) = match current {
+        FlagValueStyle::Equals => (FlagValueStyle::Space, "--flag value"),
+        FlagValueStyle::Space => (FlagValueStyle::Equals, "--flag=value"),
+    };
+    let Some(name) = flag.name.as_ref().map(|n| &n.0) else {
+        return Vec::new();
+    };
+    let edit = resolve_edits.then(|| {
+        let edits = get_flag_value_style_edit(bazel_flags, rope, flag, style, encoding);
+        make_workspace_edit(uri, edits.into_iter().collect::<Vec<_>>())
+    });
+    let params = NormalizeFlagValuesParams {
+        uri: uri.clone(),
+        style,
+    };
+    vec![
+        CodeAction {
+            title: format!("Write `{name}` as `{example}`"),
+            kind: Some(CodeActionKind::new(REWRITE_FLAG_VALUE_KIND)),
+            edit,
+            ..Default::default()
+        },
+        CodeAction {
+            title: format!("Write all flags in the workspace as `{example}`"),
+            kind: Some(CodeActionKind::new(REWRITE_FLAG_VALUE_KIND)),
+            command: Some(Command {
+                title: format!("Write all flags as `{example}`"),
+                command: NORMALIZE_FLAG_VALUES_COMMAND.to_string(),
+                arguments: serde_json::to_value(params).ok().map(|p| vec![p]),
+            }),
+            ..Default::default()
+        },
+    ]
+}
+
+#[cfg(test)]
+fn normalize(text: &str, style: FlagValueStyle) -> String {
+    use crate::{
+        bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags},
+        code_action::apply_text_edits,
+        parser::parse_from_str,
+    };
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let rope = Rope::from_str(text);
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let edits =
+        get_flag_value_style_edits(&bazel_flags, &rope, &lines, style, PositionEncoding::Utf16);
+    apply_text_edits(text, &edits, PositionEncoding::Utf16)
+}
+
+#[test]
+fn test_flag_value_style_edits() {
+    // Only the separator is replaced, keeping comments and line continuations intact
+    assert_eq!(
+        normalize(
+            "build --jobs 4 --keep_going # fast\nbuild --disk_cache \\\n  ~/cache\n",
+            FlagValueStyle::Equals
+        ),
+        "build --jobs=4 --keep_going # fast\nbuild --disk_cache=~/cache\n"
+    );
+    assert_eq!(
+        normalize(
+            "build --jobs=4 --keep_going=false\nbuild \"--copt=-DA B\"\n",
+            FlagValueStyle::Space
+        ),
+        "build --jobs 4 --keep_going=false\nbuild --copt \"-DA B\"\n"
+    );
+    // Abbreviations, boolean flags and empty values keep their form
+    let text = "build -c opt --keep_going=true --define=\nbuild --jobs=4\n";
+    assert_eq!(normalize(text, FlagValueStyle::Equals), text);
+    assert_eq!(
+        normalize(text, FlagValueStyle::Space),
+        "build -c opt --keep_going=true --define=\nbuild --jobs 4\n"
+    );
+}
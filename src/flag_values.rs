@@ -0,0 +1,295 @@
+use crate::{
+    bazel_flags::{BazelFlags, FlagLookupType},
+    bazel_flags_proto::FlagInfo,
+    flag_paths::{DIRECTORY_FLAGS, FILE_FLAGS},
+    parser::Line,
+    tokenizer::Span,
+};
+
+// The values accepted by boolean flags, see BooleanConverter in Bazel's `Converters.java`
+pub const BOOLEAN_VALUES: [&str; 6] = ["true", "false", "yes", "no", "1", "0"];
+
+// The values accepted by enum-typed flags, derived from the type described by `bazel help`.
+// Enums are described as e.g. "fastbuild, dbg or opt", tri-states as "a tri-state (auto, yes,
+// no)". Bazel parses enum values case-insensitively.
+pub fn get_allowed_values(info: &FlagInfo) -> Option<Vec<&str>> {
+    parse_allowed_values(info.value_description.as_deref()?)
+}
+
+fn parse_allowed_values(description: &str) -> Option<Vec<&str>> {
+    // See TriStateConverter in Bazel's `Converters.java`
+    if description.starts_with("a tri-state") {
+        return Some([&["auto"], BOOLEAN_VALUES.as_slice()].concat());
+    }
+    let (values, last_value) = description.rsplit_once(" or ")?;
+    let values = values.split(", ").chain([last_value]).collect::<Vec<_>>();
+    let is_keyword = |v: &&str| {
+        !v.is_empty()
+            && v.chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    };
+    values.iter().all(is_keyword).then_some(values)
+}
+
+// Tri-state flags can't be told apart from boolean flags without their value description,
+// hence `auto` is accepted for both
+pub fn is_boolean_value(value: &str) -> bool {
+    value.eq_ignore_ascii_case("auto") || is_allowed_value(&BOOLEAN_VALUES, value)
+}
+
+pub fn is_allowed_value(allowed: &[&str], value: &str) -> bool {
+    allowed.iter().any(|a| a.eq_ignore_ascii_case(value))
+}
+
+// The values to offer for completion, either the enum values or the boolean values
+pub fn get_value_completions(info: &FlagInfo) -> Option<Vec<&str>> {
+    if let Some(allowed) = get_allowed_values(info) {
+        Some(allowed)
+    } else if info.has_negative_flag() && !info.requires_value() {
+        Some(BOOLEAN_VALUES.to_vec())
+    } else {
+        None
+    }
+}
+
+// The kind of value a flag expects, derived from the value type described by `bazel help`
+// or looked up for well-known flags
+#[derive(Debug, PartialEq)]
+pub enum ValueKind {
+    Enum(Vec<String>),
+    Boolean,
+    Integer,
+    Path,
+    Label,
+    KeyValue,
+    Url,
+    String,
+}
+
+pub fn get_value_kind(info: &FlagInfo) -> ValueKind {
+    if let Some(allowed) = get_allowed_values(info) {
+        ValueKind::Enum(allowed.into_iter().map(|v| v.to_string()).collect())
+    } else if info.has_negative_flag() && !info.requires_value() {
+        ValueKind::Boolean
+    } else if let Some(kind) = get_known_value_kind(&info.name) {
+        kind
+    } else {
+        info.value_description
+            .as_deref()
+            .and_then(parse_value_kind)
+            .unwrap_or(ValueKind::String)
+    }
+}
+
+// The value kinds of commonly used flags. Bazel describes URLs as plain strings, and the
+// packaged flags of some versions lack the value descriptions.
+fn get_known_value_kind(name: &str) -> Option<ValueKind> {
+    if DIRECTORY_FLAGS.contains(&name) || FILE_FLAGS.contains(&name) {
+        return Some(ValueKind::Path);
+    }
+    Some(match name {
+        "remote_cache" | "remote_executor" | "remote_downloader" | "bes_backend"
+        | "bes_results_url" => ValueKind::Url,
+        "jobs"
+        | "local_test_jobs"
+        | "loading_phase_threads"
+        | "remote_retries"
+        | "remote_max_connections" => ValueKind::Integer,
+        "platforms"
+        | "host_platform"
+        | "extra_toolchains"
+        | "extra_execution_platforms"
+        | "crosstool_top"
+        | "host_crosstool_top"
+        | "apple_crosstool_top"
+        | "android_crosstool_top" => ValueKind::Label,
+        "action_env" | "host_action_env" | "repo_env" | "test_env" | "define" | "flag_alias" => {
+            ValueKind::KeyValue
+        }
+        _ => return None,
+    })
+}
+
+// The value kind according to the type described by `bazel help`, e.g. "a path"
+fn parse_value_kind(description: &str) -> Option<ValueKind> {
+    if description.starts_with("an integer") {
+        Some(ValueKind::Integer)
+    } else if description == "a path" {
+        Some(ValueKind::Path)
+    } else if description.starts_with("a build target label") && !description.contains("list") {
+        Some(ValueKind::Label)
+    } else if description.starts_with("a 'name=value' assignment") {
+        Some(ValueKind::KeyValue)
+    } else {
+        None
+    }
+}
+
+impl ValueKind {
+    // A short placeholder, e.g. `<path>`
+    pub fn placeholder(&self) -> &'static str {
+        match self {
+            ValueKind::Enum(_) => "<choice>",
+            ValueKind::Boolean => "<boolean>",
+            ValueKind::Integer => "<integer>",
+            ValueKind::Path => "<path>",
+            ValueKind::Label => "<label>",
+            ValueKind::KeyValue => "<name=value>",
+            ValueKind::Url => "<url>",
+            ValueKind::String => "<string>",
+        }
+    }
+
+    // The value part of a completion snippet, with a tab stop for the value. Only the choices
+    // are offered, such that no placeholder text is left behind if the value is skipped.
+    pub fn snippet(&self) -> String {
+        match self {
+            ValueKind::Enum(values) => format!("${{1|{}|}}", values.join(",")),
+            ValueKind::Boolean => format!("${{1|{}|}}", BOOLEAN_VALUES.join(",")),
+            _ => "$1".to_string(),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let format_values = |values: &[&str]| {
+            values
+                .iter()
+                .map(|v| format!("`{v}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            ValueKind::Enum(values) => format!(
+                "Expects one of {}.",
+                format_values(&values.iter().map(String::as_str).collect::<Vec<_>>())
+            ),
+            ValueKind::Boolean => {
+                format!("Expects a boolean: {}.", format_values(&BOOLEAN_VALUES))
+            }
+            ValueKind::Integer => "Expects an integer.".to_string(),
+            ValueKind::Path => "Expects a file system path.".to_string(),
+            ValueKind::Label => "Expects a Bazel label, e.g. `//my/package:target`.".to_string(),
+            ValueKind::KeyValue => "Expects a `name=value` pair.".to_string(),
+            ValueKind::Url => "Expects a URL, e.g. `grpcs://remote.example.com`.".to_string(),
+            ValueKind::String => "Expects a value.".to_string(),
+        }
+    }
+}
+
+// The value assigned to the given flag, together with the flag's description.
+// Abbreviated flags take their value from the following token, e.g. `-c opt`.
+pub fn get_flag_value<'a>(
+    bazel_flags: &'a BazelFlags,
+    line: &'a Line,
+    flag_nr: usize,
+) -> Option<(&'a FlagInfo, usize, &'a (String, Span))> {
+    let flag = line.flags.get(flag_nr)?;
+    let (lookup_type, info) = bazel_flags.get_by_invocation(&flag.name.as_ref()?.0)?;
+    if lookup_type == FlagLookupType::Abbreviation && flag.value.is_none() {
+        if !info.requires_value() {
+            return None;
+        }
+        let next = line.flags.get(flag_nr + 1)?;
+        if next.name.is_some() {
+            return None;
+        }
+        Some((info, flag_nr + 1, next.value.as_ref()?))
+    } else {
+        Some((info, flag_nr, flag.value.as_ref()?))
+    }
+}
+
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            cur.push((prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1));
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+// The allowed values which the user most likely meant, best match first
+pub fn suggest_values<'a>(allowed: &[&'a str], value: &str) -> Vec<&'a str> {
+    let value = value.to_lowercase();
+    let mut candidates = allowed
+        .iter()
+        .filter_map(|a| {
+            let distance = edit_distance(&value, a);
+            let is_prefix = !value.is_empty() && a.starts_with(&value);
+            (distance <= 2 || is_prefix).then_some((distance, *a))
+        })
+        .collect::<Vec<_>>();
+    candidates.sort();
+    candidates.into_iter().map(|(_, a)| a).collect::<Vec<_>>()
+}
+
+#[test]
+fn test_value_kinds() {
+    use crate::test_utils::load_flags_with_value_types;
+
+    let flags = load_flags_with_value_types("8.0.0");
+    let kind = |name: &str| get_value_kind(flags.get_by_invocation(name).unwrap().1);
+    assert_eq!(
+        kind("--compilation_mode"),
+        ValueKind::Enum(vec!["fastbuild".into(), "dbg".into(), "opt".into()])
+    );
+    assert_eq!(kind("--keep_going"), ValueKind::Boolean);
+    assert_eq!(kind("--jobs"), ValueKind::Integer);
+    assert_eq!(kind("--disk_cache"), ValueKind::Path);
+    assert_eq!(kind("--output_user_root"), ValueKind::Path);
+    assert_eq!(kind("--platforms"), ValueKind::Label);
+    assert_eq!(kind("--action_env"), ValueKind::KeyValue);
+    assert_eq!(kind("--remote_cache"), ValueKind::Url);
+    assert_eq!(kind("--vendor_dir"), ValueKind::Path);
+    // Not guessed from the name, `--package_path` takes a list of paths
+    let package_path = FlagInfo {
+        name: "package_path".to_string(),
+        requires_value: Some(true),
+        value_description: Some("colon-separated list of options".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(get_value_kind(&package_path), ValueKind::String);
+    assert_eq!(kind("--vendor_dir"), ValueKind::Path);
+
+    assert_eq!(kind("--jobs").snippet(), "$1");
+    assert_eq!(kind("--remote_cache").snippet(), "$1");
+    assert_eq!(
+        kind("--compilation_mode").snippet(),
+        "${1|fastbuild,dbg,opt|}"
+    );
+}
+
+#[test]
+fn test_parse_value_kind() {
+    assert_eq!(parse_value_kind("a path"), Some(ValueKind::Path));
+    assert_eq!(
+        parse_value_kind("an integer, or a keyword (\"auto\", \"HOST_CPUS\", \"HOST_RAM\")"),
+        Some(ValueKind::Integer)
+    );
+    assert_eq!(
+        parse_value_kind("a build target label"),
+        Some(ValueKind::Label)
+    );
+    assert_eq!(
+        parse_value_kind("a 'name=value' assignment with an optional value part"),
+        Some(ValueKind::KeyValue)
+    );
+    assert_eq!(parse_value_kind("colon-separated list of options"), None);
+    assert_eq!(parse_value_kind("a string"), None);
+}
+
+#[test]
+fn test_suggest_values() {
+    let allowed = ["fastbuild", "dbg", "opt"];
+    assert!(is_allowed_value(&allowed, "OPT"));
+    assert!(!is_allowed_value(&allowed, "optimized"));
+    assert_eq!(suggest_values(&allowed, "opz"), vec!["opt"]);
+    assert_eq!(suggest_values(&allowed, "fast"), vec!["fastbuild"]);
+    assert_eq!(suggest_values(&allowed, "debug"), vec!["dbg"]);
+    assert_eq!(suggest_values(&allowed, "release"), Vec::<&str>::new());
+}
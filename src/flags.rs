@@ -0,0 +1,29 @@
+// The flag database, as part of the stable library API. Other tools can use it to look
+// up flags without spawning the language server. Modules not re-exported via `flags`,
+// `parser` or `analysis` are implementation details and might change at any time.
+
+pub use crate::bazel_flags::{
+    combine_key_value_flags, load_packaged_bazel_flags, BazelFlags, FlagLookupType, COMMAND_DOCS,
+    COMMAND_PARENTS,
+};
+pub use crate::bazel_flags_proto::FlagInfo;
+pub use crate::bazel_version::{
+    determine_bazelisk_version, find_closest_version, AVAILABLE_BAZEL_VERSIONS,
+};
+pub use crate::live_flags::load_live_bazel_flags;
+
+// Loads the packaged flags of the Bazel version closest to `version` (e.g. `7.4.1`,
+// `latest`). Also returns a message if the version could not be matched exactly.
+pub fn load_flags_for_version(version: &str) -> (BazelFlags, Option<String>) {
+    let (version, message) = find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), version);
+    (load_packaged_bazel_flags(&version), message)
+}
+
+#[test]
+fn test_load_flags_for_version() {
+    let (flags, message) = load_flags_for_version("8.0.0");
+    assert_eq!(message, None);
+    let (lookup_type, info) = flags.get_by_invocation("--jobs").unwrap();
+    assert_eq!(lookup_type, FlagLookupType::Normal);
+    assert_eq!(info.name, "jobs");
+}
@@ -0,0 +1,90 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{FoldingRange, FoldingRangeKind};
+
+use crate::{lsp_utils::trim_span_end, parser::Line};
+
+// The first and last line number covered by a parsed line
+fn get_line_nrs(rope: &Rope, line: &Line) -> Option<(u32, u32)> {
+    let span = trim_span_end(rope, &line.span);
+    let start = rope.try_char_to_line(span.start).ok()?;
+    let end = rope.try_char_to_line(span.end).ok()?;
+    Some((start as u32, end as u32))
+}
+
+// Folds blocks of consecutive lines sharing the same command and config
+// (e.g. multiple `build:ci` lines) as well as lines spanning multiple
+// lines via `\` continuations.
+pub fn get_folding_ranges(rope: &Rope, lines: &[Line]) -> Vec<FoldingRange> {
+    let make_range =
+        |start_line: u32, end_line: u32, kind: Option<FoldingRangeKind>| FoldingRange {
+            start_line,
+            start_character: None,
+            end_line,
+            end_character: None,
+            kind,
+            collapsed_text: None,
+        };
+
+    let mut result = Vec::<FoldingRange>::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let Some((start_line, mut end_line)) = get_line_nrs(rope, &lines[i]) else {
+            i += 1;
+            continue;
+        };
+        // Find the end of the block of lines with the same command and config
+        let mut j = i + 1;
+        if lines[i].command.is_some() {
+            let key = |line: &Line| {
+                (
+                    line.command.as_ref().map(|c| c.0.clone()),
+                    line.config.as_ref().map(|c| c.0.clone()),
+                )
+            };
+            while j < lines.len() && key(&lines[j]) == key(&lines[i]) {
+                if let Some((_, end)) = get_line_nrs(rope, &lines[j]) {
+                    end_line = end;
+                }
+                j += 1;
+            }
+        }
+        if j > i + 1 {
+            result.push(make_range(
+                start_line,
+                end_line,
+                Some(FoldingRangeKind::Region),
+            ));
+        }
+        // Continuation lines within the block. A continuation on the block's first
+        // line would start on the same line as the block, hence we skip it.
+        for (k, line) in lines.iter().enumerate().take(j).skip(i) {
+            if let Some((start, end)) = get_line_nrs(rope, line) {
+                if end > start && (k != i || j == i + 1) {
+                    result.push(make_range(start, end, None));
+                }
+            }
+        }
+        i = j;
+    }
+    result
+}
+
+#[test]
+fn test_folding_ranges() {
+    use crate::parser::parse_from_str;
+
+    let text = "# comment\nbuild:ci -k\nbuild:ci \\\n  --jobs=4\nbuild:ci -c opt\n\ntest --test_output=all \\\n  -k\nbuild -k\n";
+    let rope = Rope::from_str(text);
+    let ranges = get_folding_ranges(&rope, &parse_from_str(text).lines)
+        .into_iter()
+        .map(|r| (r.start_line, r.end_line, r.kind))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ranges,
+        vec![
+            (1, 4, Some(FoldingRangeKind::Region)),
+            (2, 3, None),
+            (6, 7, None),
+        ]
+    );
+}
@@ -4,8 +4,8 @@ use tower_lsp::lsp_types::TextEdit;
 
 use crate::{
     bazel_flags::BazelFlags,
-    lsp_utils::range_to_lsp,
-    parser::{parse_from_str, Line, ParserResult},
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::{parse_from_str, Flag, Line, ParserResult},
     tokenizer::Span,
 };
 
@@ -86,6 +86,8 @@ pub fn format_line_into(out: &mut String, line: &Line, mut use_line_continuation
             line.command.is_none() && line.config.is_none() && line.flags.is_empty();
         let stripped_comment = if could_be_ascii_art {
             comment.0.trim_end().to_string()
+        } else if comment.0.trim().is_empty() {
+            String::new()
         } else {
             " ".to_string() + comment.0.trim()
         };
@@ -117,6 +119,53 @@ pub enum FormatLineFlow {
     SingleLine,
 }
 
+// How bazelrc files are formatted
+#[derive(PartialEq, Eq, Default, Debug, Clone, Copy)]
+pub struct FormatStyle {
+    pub line_flow: FormatLineFlow,
+    // Sort the flags within each line alphabetically
+    pub sort_flags: bool,
+}
+
+// Sorts the flags of a line alphabetically by their canonical name. `--config` flags are
+// expanded in place by Bazel, such that flags are never moved across them. Assignments
+// to the same flag keep their relative order, also if spelled differently (e.g.
+// `--nokeep_going --keep_going`), since the last one wins.
+pub fn sort_line_flags(line: &mut Line, bazel_flags: &BazelFlags) {
+    if line
+        .command
+        .as_ref()
+        .is_some_and(|c| c.0 == "import" || c.0 == "try-import")
+    {
+        return;
+    }
+    // Values in separate tokens (e.g. `-c opt`) stay attached to their flag
+    let mut groups = Vec::<Vec<Flag>>::new();
+    for flag in line.flags.drain(..) {
+        match groups.last_mut() {
+            Some(group) if flag.name.is_none() => group.push(flag),
+            _ => groups.push(vec![flag]),
+        }
+    }
+    let sort_key = |group: &Vec<Flag>| {
+        let name = group[0].name.as_ref().map(|n| n.0.as_str()).unwrap_or("");
+        match bazel_flags.get_by_invocation(name) {
+            Some((_, info)) => info.name.clone(),
+            None => name.trim_start_matches('-').to_string(),
+        }
+    };
+    let is_config = |group: &Vec<Flag>| {
+        group[0]
+            .name
+            .as_ref()
+            .is_some_and(|n| n.0.strip_suffix('=').unwrap_or(&n.0) == "--config")
+    };
+    for segment in groups.split_mut(is_config) {
+        segment.sort_by_cached_key(sort_key);
+    }
+    line.flags = groups.into_iter().flatten().collect::<Vec<_>>();
+}
+
 pub fn reflow_lines(lines: &[Line], line_flow: FormatLineFlow) -> Vec<Line> {
     let mut result1 = Vec::<Line>::with_capacity(lines.len());
     match line_flow {
@@ -199,20 +248,33 @@ pub fn reflow_lines(lines: &[Line], line_flow: FormatLineFlow) -> Vec<Line> {
     result2
 }
 
+// Reflows the lines and, if requested, sorts their flags
+fn apply_style(lines: &[Line], bazel_flags: &BazelFlags, style: FormatStyle) -> Vec<Line> {
+    let mut lines = reflow_lines(lines, style.line_flow);
+    if style.sort_flags {
+        for line in &mut lines {
+            sort_line_flags(line, bazel_flags);
+        }
+    }
+    lines
+}
+
 // Gets the LSP edits for reformatting a line range
 pub fn get_text_edits_for_lines(
     lines: &[Line],
     rope: &Rope,
-    line_flow: FormatLineFlow,
+    bazel_flags: &BazelFlags,
+    style: FormatStyle,
+    encoding: PositionEncoding,
 ) -> Vec<TextEdit> {
-    reflow_lines(lines, line_flow)
+    apply_style(lines, bazel_flags, style)
         .iter()
         .filter_map(|line| {
-            let use_line_continuations = line_flow == FormatLineFlow::LineContinuations;
+            let use_line_continuations = style.line_flow == FormatLineFlow::LineContinuations;
             let formatted = format_line(line, use_line_continuations);
             if formatted != rope.slice(line.span.clone()) {
                 Some(TextEdit {
-                    range: range_to_lsp(rope, &line.span)?,
+                    range: range_to_lsp(rope, &line.span, encoding)?,
                     new_text: formatted,
                 })
             } else {
@@ -222,11 +284,26 @@ pub fn get_text_edits_for_lines(
         .collect::<Vec<_>>()
 }
 
+// The lines overlapping the given range. An empty range selects the line it is placed on.
+pub fn get_lines_in_range<'a>(lines: &'a [Line], range: &Span) -> &'a [Line] {
+    let first_idx = lines.partition_point(|l| l.span.end <= range.start);
+    let last_idx = lines
+        .partition_point(|l| l.span.start < range.end.max(range.start + 1))
+        .max(first_idx);
+    &lines[first_idx..last_idx]
+}
+
+// The line completed by typing a newline just before `pos`. Lines continued
+// via a trailing `\` are not complete, yet.
+pub fn get_completed_line(lines: &[Line], pos: usize) -> Option<&Line> {
+    lines.iter().find(|l| l.span.end == pos)
+}
+
 // Parse and pretty-print the given string
 pub fn pretty_print(
     str: &str,
     bazel_flags: &BazelFlags,
-    line_flow: FormatLineFlow,
+    style: FormatStyle,
 ) -> Result<String, Vec<String>> {
     let ParserResult {
         tokens: _,
@@ -240,8 +317,8 @@ pub fn pretty_print(
             .collect::<Vec<_>>());
     }
     crate::bazel_flags::combine_key_value_flags(&mut lines, bazel_flags);
-    lines = reflow_lines(&lines, line_flow);
-    let use_line_continuations = line_flow == FormatLineFlow::LineContinuations;
+    lines = apply_style(&lines, bazel_flags, style);
+    let use_line_continuations = style.line_flow == FormatLineFlow::LineContinuations;
     let mut out = String::with_capacity(str.len());
     for line in lines {
         format_line_into(&mut out, &line, use_line_continuations);
@@ -274,7 +351,7 @@ fn test_format_token() {
 #[test]
 fn test_pretty_print_command() {
     let flags = load_packaged_bazel_flags("7.4.0");
-    let lf = FormatLineFlow::Keep;
+    let lf = FormatStyle::default();
 
     // Command & config names
     assert_eq!(pretty_print("build", &flags, lf).unwrap(), "build\n");
@@ -297,7 +374,7 @@ fn test_pretty_print_command() {
 #[test]
 fn test_pretty_print_flags() {
     let flags = load_packaged_bazel_flags("7.4.0");
-    let lf = FormatLineFlow::Keep;
+    let lf = FormatStyle::default();
 
     // Flags (also works without a command, although that is strictly speaking invalid)
     assert_eq!(pretty_print("--x", &flags, lf).unwrap(), "--x\n");
@@ -334,7 +411,7 @@ fn test_pretty_print_flags() {
 #[test]
 fn test_pretty_print_combined_flags() {
     let flags = load_packaged_bazel_flags("7.4.0");
-    let lf = FormatLineFlow::Keep;
+    let lf = FormatStyle::default();
 
     // The `--copt` flag expects an argument and hence consumes the
     // following `--std=c++20`. `--std=c++20` should not raise
@@ -370,13 +447,67 @@ fn test_pretty_print_combined_flags() {
 
 #[test]
 fn test_pretty_print_comments() {
-    // TODO
+    let flags = load_packaged_bazel_flags("7.4.0");
+    let lf = FormatStyle::default();
+
+    // Trailing comments are kept on the line of the flags they annotate
+    assert_eq!(
+        pretty_print("build --jobs=auto # keep in sync with CI", &flags, lf).unwrap(),
+        "build --jobs=auto # keep in sync with CI\n"
+    );
+    // They are not mistaken for flags, even without a separating whitespace
+    assert_eq!(
+        pretty_print("build --jobs auto#fast", &flags, lf).unwrap(),
+        "build --jobs=auto # fast\n"
+    );
+    // Empty comments don't leave trailing whitespace
+    assert_eq!(
+        pretty_print("build --keep_going #  ", &flags, lf).unwrap(),
+        "build --keep_going #\n"
+    );
+    // Comments continued with `\` stay continued
+    assert_eq!(
+        pretty_print("build --keep_going # a\\\n b", &flags, lf).unwrap(),
+        "build --keep_going # a\\\n b\n"
+    );
+
+    // Lines with trailing comments are neither merged with other lines nor
+    // split using line continuations
+    let input = "build --jobs=1 # slow\nbuild --keep_going\n";
+    for line_flow in [
+        FormatLineFlow::SingleLine,
+        FormatLineFlow::LineContinuations,
+    ] {
+        let style = FormatStyle {
+            line_flow,
+            ..Default::default()
+        };
+        assert_eq!(pretty_print(input, &flags, style).unwrap(), input);
+    }
+    // When splitting flags into separate lines, the comment stays with the first flag
+    let style = FormatStyle {
+        line_flow: FormatLineFlow::SeparateLines,
+        ..Default::default()
+    };
+    assert_eq!(
+        pretty_print("build --jobs=1 --keep_going # slow", &flags, style).unwrap(),
+        "build --jobs=1 # slow\nbuild --keep_going\n"
+    );
+    // Sorting the flags keeps the comment at the end of the line
+    let style = FormatStyle {
+        sort_flags: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        pretty_print("build --jobs=1 --disk_cache= # slow", &flags, style).unwrap(),
+        "build --disk_cache= --jobs=1 # slow\n"
+    );
 }
 
 #[test]
 fn test_pretty_print_whitespace() {
     let flags = load_packaged_bazel_flags("7.4.0");
-    let lf = FormatLineFlow::Keep;
+    let lf = FormatStyle::default();
 
     // Removes unnecessary whitespace
     assert_eq!(pretty_print("  build   ", &flags, lf).unwrap(), "build\n");
@@ -399,7 +530,7 @@ fn test_pretty_print_whitespace() {
 #[test]
 fn test_pretty_print_newlines() {
     let flags = load_packaged_bazel_flags("7.4.0");
-    let lf = FormatLineFlow::Keep;
+    let lf = FormatStyle::default();
 
     // We add a final new line, if it is missing
     assert_eq!(pretty_print("build", &flags, lf).unwrap(), "build\n");
@@ -436,14 +567,30 @@ fn test_pretty_print_line_styles() {
         build:c3 --xyz";
 
     assert_eq!(
-        pretty_print(input, &flags, FormatLineFlow::LineContinuations).unwrap(),
+        pretty_print(
+            input,
+            &flags,
+            FormatStyle {
+                line_flow: FormatLineFlow::LineContinuations,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
         "build:c1 \\\n    --a=b \\\n    --c=d\n\
          build:c2 \\\n    --e=f \\\n    --g=h\n\
          build:c3 --xyz\n"
     );
 
     assert_eq!(
-        pretty_print(input, &flags, FormatLineFlow::SeparateLines).unwrap(),
+        pretty_print(
+            input,
+            &flags,
+            FormatStyle {
+                line_flow: FormatLineFlow::SeparateLines,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
         "build:c1 --a=b\n\
          build:c1 --c=d\n\
          build:c2 --e=f\n\
@@ -452,7 +599,15 @@ fn test_pretty_print_line_styles() {
     );
 
     assert_eq!(
-        pretty_print(input, &flags, FormatLineFlow::SingleLine).unwrap(),
+        pretty_print(
+            input,
+            &flags,
+            FormatStyle {
+                line_flow: FormatLineFlow::SingleLine,
+                ..Default::default()
+            }
+        )
+        .unwrap(),
         "build:c1 --a=b --c=d\n\
          build:c2 --e=f --g=h\n\
          build:c3 --xyz\n"
@@ -462,10 +617,88 @@ fn test_pretty_print_line_styles() {
         pretty_print(
             "import \"a.bazelrc\"\nimport \"b.bazelrc\"",
             &flags,
-            FormatLineFlow::SingleLine
+            FormatStyle {
+                line_flow: FormatLineFlow::SingleLine,
+                ..Default::default()
+            }
         )
         .unwrap(),
         "import a.bazelrc\n\
          import b.bazelrc\n"
     );
 }
+
+#[test]
+fn test_pretty_print_sorted_flags() {
+    let flags = load_packaged_bazel_flags("7.4.0");
+    let style = FormatStyle {
+        sort_flags: true,
+        ..Default::default()
+    };
+
+    // Repeated flags keep their order, values stay attached to abbreviated flags
+    assert_eq!(
+        pretty_print("build --x=2 -c opt --b --x=1 --a", &flags, style).unwrap(),
+        "build --a --b -c opt --x=2 --x=1\n"
+    );
+    // Assignments to the same flag keep their order, independent of their spelling
+    assert_eq!(
+        pretty_print("build --nokeep_going --keep_going", &flags, style).unwrap(),
+        "build --nokeep_going --keep_going\n"
+    );
+    assert_eq!(
+        pretty_print("build -k --jobs=4 --nokeep_going", &flags, style).unwrap(),
+        "build --jobs=4 -k --nokeep_going\n"
+    );
+    // Flags are not moved across `--config`
+    assert_eq!(
+        pretty_print("build --y --config=ci --x --b", &flags, style).unwrap(),
+        "build --y --config=ci --b --x\n"
+    );
+    // Lines are merged before sorting
+    assert_eq!(
+        pretty_print(
+            "build --y\nbuild --x",
+            &flags,
+            FormatStyle {
+                line_flow: FormatLineFlow::SingleLine,
+                sort_flags: true
+            }
+        )
+        .unwrap(),
+        "build --x --y\n"
+    );
+}
+
+#[test]
+fn test_format_ranges() {
+    let flags = load_packaged_bazel_flags("7.4.0");
+    let text = "build  --a\nbuild  --b \\\n  --c\nbuild  --d\n";
+    let mut lines = parse_from_str(text).lines;
+    crate::bazel_flags::combine_key_value_flags(&mut lines, &flags);
+    let format = |lines: &[Line]| {
+        let edits = get_text_edits_for_lines(
+            lines,
+            &Rope::from_str(text),
+            &flags,
+            FormatStyle::default(),
+            PositionEncoding::Utf16,
+        );
+        crate::code_action::apply_text_edits(text, &edits, PositionEncoding::Utf16)
+    };
+
+    // Only the lines overlapping the range are formatted, even if the range starts mid-line
+    assert_eq!(
+        format(get_lines_in_range(&lines, &(8..14))),
+        "build --a\nbuild --b --c\nbuild  --d\n"
+    );
+    // An empty range selects the line of the cursor
+    assert_eq!(
+        format(get_lines_in_range(&lines, &(30..30))),
+        "build  --a\nbuild  --b \\\n  --c\nbuild --d\n"
+    );
+    // Typing a newline completes the line before it, but not a continued line
+    assert_eq!(get_completed_line(&lines, 11).unwrap().span, 0..11);
+    assert!(get_completed_line(&lines, 24).is_none());
+    assert_eq!(get_completed_line(&lines, 30).unwrap().span, 11..30);
+}
@@ -0,0 +1,474 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ropey::Rope;
+
+use crate::{
+    bazel_flags::{combine_key_value_flags, BazelFlags},
+    file_utils::{get_workspace_path, is_inside_workspace, resolve_bazelrc_path},
+    parallel::map_in_parallel,
+    parser::{parse_from_str, Line},
+    rc_discovery::{discover_rc_files, find_workspace_rc_files, sort_by_read_order},
+};
+
+// A bazelrc file which was loaded as part of the import graph
+#[derive(Clone, Debug)]
+pub struct RcFile {
+    pub path: PathBuf,
+    pub rope: Rope,
+    pub lines: Vec<Line>,
+    // Files outside the workspace (e.g. `~/.bazelrc`) are only analyzed, never edited
+    pub read_only: bool,
+}
+
+impl RcFile {
+    pub fn from_str(path: PathBuf, contents: &str, bazel_flags: &BazelFlags) -> RcFile {
+        let mut lines = parse_from_str(contents).lines;
+        combine_key_value_flags(&mut lines, bazel_flags);
+        RcFile {
+            path,
+            rope: Rope::from_str(contents),
+            lines,
+            read_only: false,
+        }
+    }
+}
+
+// Returns the line numbers and paths of all imports, in order of appearance
+pub fn get_imports(file_path: &Path, lines: &[Line]) -> Vec<(usize, PathBuf)> {
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(line_nr, line)| {
+            let command = &line.command.as_ref()?.0;
+            if command != "import" && command != "try-import" {
+                return None;
+            }
+            if line.flags.len() != 1 || line.flags[0].name.is_some() {
+                return None;
+            }
+            let value = &line.flags[0].value.as_ref()?.0;
+            Some((line_nr, resolve_bazelrc_path(file_path, value)?))
+        })
+        .collect::<Vec<_>>()
+}
+
+// Returns the paths imported by the given lines, in order of appearance
+pub fn get_imported_paths(file_path: &Path, lines: &[Line]) -> Vec<PathBuf> {
+    get_imports(file_path, lines)
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect::<Vec<_>>()
+}
+
+// Transitively loads all files imported by the given root file.
+//
+// The root file itself is not part of the result. `read_file` is used to read
+// the file contents, such that the language server can substitute the contents
+// of files currently opened in the editor.
+//
+// Files outside the workspace (e.g., a `try-import ~/.bazelrc`) might contain
+// personal information. They are only read if `include_outside_workspace` is set.
+pub fn load_imported_files<F>(
+    root_path: &Path,
+    root_lines: &[Line],
+    bazel_flags: &BazelFlags,
+    include_outside_workspace: bool,
+    read_file: F,
+) -> Vec<RcFile>
+where
+    F: Fn(&Path) -> Option<String>,
+{
+    load_imported_rc_files(root_path, root_lines, include_outside_workspace, |path| {
+        let contents = read_file(path)?;
+        Some(RcFile::from_str(path.to_path_buf(), &contents, bazel_flags))
+    })
+}
+
+// Like `load_imported_files`, but `load_file` provides the parsed files, e.g. from a cache
+pub fn load_imported_rc_files<F>(
+    root_path: &Path,
+    root_lines: &[Line],
+    include_outside_workspace: bool,
+    load_file: F,
+) -> Vec<RcFile>
+where
+    F: Fn(&Path) -> Option<RcFile>,
+{
+    let mut result = Vec::<RcFile>::new();
+    let mut visited = HashSet::<PathBuf>::from([root_path.to_path_buf()]);
+    let mut pending = get_imported_paths(root_path, root_lines);
+    pending.reverse();
+    while let Some(path) = pending.pop() {
+        if !visited.insert(path.clone()) {
+            continue;
+        }
+        let inside_workspace = is_inside_workspace(root_path, &path);
+        if !inside_workspace && !include_outside_workspace {
+            continue;
+        }
+        let Some(mut file) = load_file(&path) else {
+            continue;
+        };
+        file.read_only = !inside_workspace;
+        let mut imports = get_imported_paths(&file.path, &file.lines);
+        imports.reverse();
+        pending.extend(imports);
+        result.push(file);
+    }
+    result
+}
+
+// Returns the paths transitively imported by the given root file, including the paths
+// of missing files
+pub fn get_transitive_imports<F>(
+    root_path: &Path,
+    root_lines: &[Line],
+    bazel_flags: &BazelFlags,
+    include_outside_workspace: bool,
+    read_file: F,
+) -> HashSet<PathBuf>
+where
+    F: Fn(&Path) -> Option<String>,
+{
+    let imported_files = load_imported_files(
+        root_path,
+        root_lines,
+        bazel_flags,
+        include_outside_workspace,
+        read_file,
+    );
+    let mut result = get_imported_paths(root_path, root_lines)
+        .into_iter()
+        .collect::<HashSet<_>>();
+    for file in imported_files {
+        result.extend(get_imported_paths(&file.path, &file.lines));
+    }
+    result
+}
+
+// Loads the files relevant for analyzing the given file: its own imports and, since
+// files imported by e.g. the workspace's `.bazelrc` can rely on the definitions in there,
+// all bazelrc files Bazel reads together with their imports. The files are returned in
+// the order in which Bazel reads them.
+pub fn load_related_files<F>(
+    file_path: &Path,
+    lines: &[Line],
+    bazel_flags: &BazelFlags,
+    include_outside_workspace: bool,
+    read_file: F,
+) -> Vec<RcFile>
+where
+    F: Fn(&Path) -> Option<String>,
+{
+    load_related_rc_files(file_path, lines, include_outside_workspace, |path| {
+        let contents = read_file(path)?;
+        Some(RcFile::from_str(path.to_path_buf(), &contents, bazel_flags))
+    })
+}
+
+// Like `load_related_files`, but `load_file` provides the parsed files, e.g. from a cache
+pub fn load_related_rc_files<F>(
+    file_path: &Path,
+    lines: &[Line],
+    include_outside_workspace: bool,
+    load_file: F,
+) -> Vec<RcFile>
+where
+    F: Fn(&Path) -> Option<RcFile>,
+{
+    let mut result =
+        load_imported_rc_files(file_path, lines, include_outside_workspace, &load_file);
+    for (_, rc_path) in discover_rc_files(file_path) {
+        if rc_path == file_path || result.iter().any(|f| f.path == rc_path) {
+            continue;
+        }
+        let inside_workspace = is_inside_workspace(file_path, &rc_path);
+        if !inside_workspace && !include_outside_workspace {
+            continue;
+        }
+        let Some(mut rc_file) = load_file(&rc_path) else {
+            continue;
+        };
+        rc_file.read_only = !inside_workspace;
+        let rc_imports = load_imported_rc_files(
+            &rc_file.path,
+            &rc_file.lines,
+            include_outside_workspace,
+            &load_file,
+        );
+        for file in std::iter::once(rc_file).chain(rc_imports) {
+            if file.path != file_path && !result.iter().any(|f| f.path == file.path) {
+                result.push(file);
+            }
+        }
+    }
+    sort_by_read_order(file_path, &mut result);
+    result
+}
+
+// Loads all bazelrc files of the workspace containing the given file, except for the
+// file itself. The files are read and parsed in parallel.
+pub fn load_workspace_files<F>(
+    file_path: &Path,
+    bazel_flags: &BazelFlags,
+    read_file: F,
+) -> Vec<RcFile>
+where
+    F: Fn(&Path) -> Option<String> + Sync,
+{
+    let Some(workspace) = get_workspace_path(file_path) else {
+        return Vec::new();
+    };
+    let paths = find_workspace_rc_files(&workspace, false)
+        .into_iter()
+        .filter(|path| path != file_path)
+        .collect::<Vec<_>>();
+    map_in_parallel(
+        &paths,
+        |path| {
+            let contents = read_file(path)?;
+            Some(RcFile::from_str(path.clone(), &contents, bazel_flags))
+        },
+        &|| {},
+    )
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+}
+
+// A problematic `import` within the root file. Each chain of imports starts at the root file.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ImportIssue {
+    // The import leads into a cycle. The last file of the chain is the one imported again.
+    Cycle { line_nr: usize, chain: Vec<PathBuf> },
+    // The imported file was already imported before, via the given chain
+    Duplicate { line_nr: usize, chain: Vec<PathBuf> },
+}
+
+// Follows the imports of the root file, looking for import cycles and for files
+// which are imported more than once.
+pub fn find_import_issues<F>(
+    root_path: &Path,
+    root_lines: &[Line],
+    bazel_flags: &BazelFlags,
+    include_outside_workspace: bool,
+    read_file: F,
+) -> Vec<ImportIssue>
+where
+    F: Fn(&Path) -> Option<String>,
+{
+    let mut result = Vec::<ImportIssue>::new();
+    // The chain via which each file was imported first
+    let mut seen = HashMap::<PathBuf, Vec<PathBuf>>::new();
+    for (line_nr, path) in get_imports(root_path, root_lines) {
+        let chain = vec![root_path.to_path_buf(), path.clone()];
+        if path == root_path {
+            result.push(ImportIssue::Cycle { line_nr, chain });
+            continue;
+        }
+        if let Some(earlier_chain) = seen.get(&path) {
+            result.push(ImportIssue::Duplicate {
+                line_nr,
+                chain: earlier_chain.clone(),
+            });
+            continue;
+        }
+        let mut cycle = None;
+        let mut pending = vec![chain];
+        while let Some(chain) = pending.pop() {
+            let path = chain.last().unwrap();
+            if seen.contains_key(path) {
+                continue;
+            }
+            seen.insert(path.clone(), chain.clone());
+            if !is_inside_workspace(root_path, path) && !include_outside_workspace {
+                continue;
+            }
+            let Some(contents) = read_file(path) else {
+                continue;
+            };
+            let file = RcFile::from_str(path.clone(), &contents, bazel_flags);
+            for import in get_imported_paths(&file.path, &file.lines)
+                .into_iter()
+                .rev()
+            {
+                let mut next_chain = chain.clone();
+                next_chain.push(import.clone());
+                if chain.contains(&import) {
+                    cycle.get_or_insert(next_chain);
+                } else {
+                    pending.push(next_chain);
+                }
+            }
+        }
+        if let Some(chain) = cycle {
+            result.push(ImportIssue::Cycle { line_nr, chain });
+        }
+    }
+    result
+}
+
+// The names of all configs defined via `command:config` lines
+pub fn get_defined_configs<'a, I>(lines: I) -> HashSet<String>
+where
+    I: IntoIterator<Item = &'a Line>,
+{
+    lines
+        .into_iter()
+        .filter_map(|line| Some(line.config.as_ref()?.0.clone()))
+        .collect::<HashSet<_>>()
+}
+
+pub fn read_file_from_disk(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok()
+}
+
+#[test]
+fn test_load_imported_files() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+
+    let ws_str = ws.to_str().unwrap().to_string();
+    let read_file = |p: &Path| {
+        let rel = p.strip_prefix(&ws).ok().and_then(Path::to_str);
+        match (rel, p.to_str()?) {
+            (Some("a.bazelrc"), _) => Some(format!("import {ws_str}/b.bazelrc\nbuild:a -k")),
+            (Some("b.bazelrc"), _) => Some(format!("import {ws_str}/a.bazelrc\nbuild:b -k")),
+            (_, "/outside/.bazelrc") => Some("build:personal --keep_going".to_string()),
+            _ => None,
+        }
+    };
+    let root = RcFile::from_str(
+        ws.join(".bazelrc"),
+        "import %workspace%/a.bazelrc\n\
+        try-import /outside/.bazelrc\n\
+        try-import %workspace%/missing.bazelrc",
+        &flags,
+    );
+
+    // Imports are followed transitively, but each file is only loaded once
+    let files = load_imported_files(&root.path, &root.lines, &flags, true, read_file);
+    assert_eq!(
+        files.iter().map(|f| f.path.clone()).collect::<Vec<_>>(),
+        vec![
+            ws.join("a.bazelrc"),
+            ws.join("b.bazelrc"),
+            PathBuf::from("/outside/.bazelrc")
+        ]
+    );
+    assert_eq!(
+        files.iter().map(|f| f.read_only).collect::<Vec<_>>(),
+        vec![false, false, true]
+    );
+
+    // Files outside the workspace are skipped unless explicitly requested
+    let files = load_imported_files(&root.path, &root.lines, &flags, false, read_file);
+    assert_eq!(files.len(), 2);
+    assert!(files.iter().all(|f| !f.read_only));
+
+    // The transitive imports also contain missing files
+    assert_eq!(
+        get_transitive_imports(&root.path, &root.lines, &flags, false, read_file),
+        HashSet::from([
+            ws.join("a.bazelrc"),
+            ws.join("b.bazelrc"),
+            ws.join("missing.bazelrc"),
+            PathBuf::from("/outside/.bazelrc")
+        ])
+    );
+}
+
+#[test]
+fn test_load_related_files() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+
+    let read_file = |p: &Path| match p.strip_prefix(&ws).ok()?.to_str()? {
+        ".bazelrc" => Some("import %workspace%/ci.bazelrc\nbuild:opt -c opt".to_string()),
+        "ci.bazelrc" => Some("build:ci --config=opt".to_string()),
+        _ => None,
+    };
+    // A file imported by the workspace `.bazelrc` also sees the workspace `.bazelrc`
+    let ci = RcFile::from_str(ws.join("ci.bazelrc"), "build:ci --config=opt", &flags);
+    let files = load_related_files(&ci.path, &ci.lines, &flags, false, read_file);
+    assert_eq!(
+        files.iter().map(|f| f.path.clone()).collect::<Vec<_>>(),
+        vec![ws.join(".bazelrc")]
+    );
+    assert_eq!(
+        get_defined_configs(files.iter().flat_map(|f| &f.lines)),
+        HashSet::from(["opt".to_string()])
+    );
+}
+
+#[test]
+fn test_find_import_issues() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::test_utils::TestDir;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let ws = TestDir::workspace();
+
+    let read_file = |p: &Path| match p.strip_prefix(&ws).ok()?.to_str()? {
+        "a.bazelrc" => Some("import %workspace%/common.bazelrc".to_string()),
+        "b.bazelrc" => Some("import %workspace%/c.bazelrc".to_string()),
+        "c.bazelrc" => Some("import %workspace%/b.bazelrc".to_string()),
+        "common.bazelrc" => Some("import %workspace%/.bazelrc".to_string()),
+        _ => None,
+    };
+    let root = RcFile::from_str(
+        ws.join(".bazelrc"),
+        "import %workspace%/a.bazelrc\n\
+        import %workspace%/b.bazelrc\n\
+        try-import %workspace%/common.bazelrc\n\
+        import %workspace%/.bazelrc",
+        &flags,
+    );
+    let issues = find_import_issues(&root.path, &root.lines, &flags, false, read_file);
+    assert_eq!(
+        issues,
+        vec![
+            ImportIssue::Cycle {
+                line_nr: 0,
+                chain: vec![
+                    ws.join(".bazelrc"),
+                    ws.join("a.bazelrc"),
+                    ws.join("common.bazelrc"),
+                    ws.join(".bazelrc")
+                ]
+            },
+            // Cycles which do not pass through the root file are also reported
+            ImportIssue::Cycle {
+                line_nr: 1,
+                chain: vec![
+                    ws.join(".bazelrc"),
+                    ws.join("b.bazelrc"),
+                    ws.join("c.bazelrc"),
+                    ws.join("b.bazelrc")
+                ]
+            },
+            ImportIssue::Duplicate {
+                line_nr: 2,
+                chain: vec![
+                    ws.join(".bazelrc"),
+                    ws.join("a.bazelrc"),
+                    ws.join("common.bazelrc")
+                ]
+            },
+            ImportIssue::Cycle {
+                line_nr: 3,
+                chain: vec![ws.join(".bazelrc"), ws.join(".bazelrc")]
+            },
+        ]
+    );
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher13;
+use tower_lsp::lsp_types::Diagnostic;
+
+use crate::{
+    bazel_flags::{combine_key_value_flags, BazelFlags},
+    import_graph::RcFile,
+    parser::{parse_from_str, Line},
+};
+
+// Bumped whenever the format of the cached data changes. Indices are also invalidated
+// by every release, since the parser might have changed.
+const INDEX_FORMAT_VERSION: u32 = 2;
+
+fn get_index_version() -> String {
+    format!("{INDEX_FORMAT_VERSION}-{}", env!("CARGO_PKG_VERSION"))
+}
+
+// A bazelrc file of the workspace, as parsed from disk
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct IndexedFile {
+    // While the modification time and size are unchanged, the file isn't read again
+    modified: Option<SystemTime>,
+    len: u64,
+    // Files which were touched without changing their contents aren't parsed again
+    content_hash: u64,
+    // rc files might contain secrets, e.g. in `--remote_header`, hence the cache file is
+    // only readable by the user
+    contents: String,
+    // The lines as returned by the parser, before flags are combined with their separate
+    // values, which depends on the Bazel version. They contain the file's imports and
+    // config definitions.
+    lines: Vec<Line>,
+}
+
+impl IndexedFile {
+    pub fn to_rc_file(&self, path: PathBuf, bazel_flags: &BazelFlags) -> RcFile {
+        let mut lines = self.lines.clone();
+        combine_key_value_flags(&mut lines, bazel_flags);
+        RcFile {
+            path,
+            rope: self.contents.as_str().into(),
+            lines,
+            read_only: false,
+        }
+    }
+}
+
+// The digest of the diagnostics last reported for a file, together with their result id.
+// Keeps result ids stable across restarts of the language server.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct DiagnosticsDigest {
+    pub result_id: String,
+    pub digest: u64,
+}
+
+// The parsed bazelrc files of a workspace, persisted between runs of the language server,
+// such that reopening a large workspace doesn't parse all of its files again
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct WorkspaceIndex {
+    version: String,
+    files: HashMap<PathBuf, IndexedFile>,
+    pub diagnostics: HashMap<PathBuf, DiagnosticsDigest>,
+}
+
+// Unlike `DefaultHasher`, the hash is stable across Rust releases, such that it can be
+// persisted, e.g. in file names and the cached indices
+pub fn hash_value<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = SipHasher13::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+// `Diagnostic`s can't be hashed directly, as they might contain arbitrary JSON data
+pub fn get_diagnostics_digest(diagnostics: &[Diagnostic]) -> u64 {
+    hash_value(&serde_json::to_string(diagnostics).unwrap_or_default())
+}
+
+impl Default for WorkspaceIndex {
+    fn default() -> WorkspaceIndex {
+        WorkspaceIndex {
+            version: get_index_version(),
+            files: Default::default(),
+            diagnostics: Default::default(),
+        }
+    }
+}
+
+impl WorkspaceIndex {
+    // The indexed state of the file on disk. Only reads and parses the file if it changed
+    // since it was indexed. `None` if the file can't be read.
+    pub fn index_file(&self, path: &Path) -> Option<IndexedFile> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok();
+        let len = metadata.len();
+        let cached = self.files.get(path);
+        if let Some(cached) =
+            cached.filter(|c| c.modified.is_some() && c.modified == modified && c.len == len)
+        {
+            return Some(cached.clone());
+        }
+        let contents = fs::read_to_string(path).ok()?;
+        let content_hash = hash_value(&contents);
+        let lines = match cached.filter(|c| c.content_hash == content_hash) {
+            Some(cached) => cached.lines.clone(),
+            None => parse_from_str(&contents).lines,
+        };
+        Some(IndexedFile {
+            modified,
+            len,
+            content_hash,
+            contents,
+            lines,
+        })
+    }
+
+    pub fn file_count(&self) -> usize {
+        self.files.len()
+    }
+
+    // Replaces the indexed files. Returns whether anything changed.
+    pub fn update_files(&mut self, files: HashMap<PathBuf, IndexedFile>) -> bool {
+        if self.files == files {
+            return false;
+        }
+        self.files = files;
+        true
+    }
+}
+
+// The file caching the index of the given workspace
+pub fn get_index_cache_file(cache_dir: &Path, workspace: &Path) -> PathBuf {
+    cache_dir.join(format!("index-{:016x}.json", hash_value(workspace)))
+}
+
+// Reads a cached index. Indices written by other versions of the language server are ignored.
+pub fn read_workspace_index(cache_file: &Path) -> Option<WorkspaceIndex> {
+    let data = fs::read(cache_file).ok()?;
+    serde_json::from_slice::<WorkspaceIndex>(&data)
+        .ok()
+        .filter(|index| index.version == get_index_version())
+}
+
+pub fn write_workspace_index(cache_file: &Path, index: &WorkspaceIndex) -> io::Result<()> {
+    if let Some(parent) = cache_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let data = serde_json::to_vec(index).map_err(io::Error::other)?;
+    // Concurrent language servers must never observe a partially written index
+    let tmp_file = cache_file.with_extension(format!("tmp{}", std::process::id()));
+    let mut options = fs::File::options();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&tmp_file)?.write_all(&data)?;
+    fs::rename(&tmp_file, cache_file)
+}
+
+#[test]
+fn test_workspace_index() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, test_utils::TestDir};
+
+    let dir = TestDir::new();
+    let path = dir.write("ci.bazelrc", "build:ci -c opt\n");
+
+    let mut index = WorkspaceIndex::default();
+    let file = index.index_file(&path).unwrap();
+    assert!(index.update_files(HashMap::from([(path.clone(), file.clone())])));
+    assert!(!index.update_files(HashMap::from([(path.clone(), file.clone())])));
+    assert_eq!(index.file_count(), 1);
+    assert_eq!(index.index_file(&path), Some(file.clone()));
+    assert_eq!(index.index_file(&dir.join("missing.bazelrc")), None);
+
+    // Flags are combined with their separate values when converting to an `RcFile`
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let rc_file = file.to_rc_file(path.clone(), &flags);
+    assert_eq!(
+        rc_file.lines,
+        RcFile::from_str(path.clone(), "build:ci -c opt\n", &flags).lines
+    );
+
+    // Changed files are parsed again
+    fs::write(&path, "build:ci -c dbg --keep_going\n").unwrap();
+    let changed = index.index_file(&path).unwrap();
+    assert_eq!(changed.contents, "build:ci -c dbg --keep_going\n");
+    assert_eq!(changed.lines[0].flags.len(), 3);
+
+    // The index survives a round trip through the cache file
+    index.diagnostics.insert(
+        path.clone(),
+        DiagnosticsDigest {
+            result_id: "3".to_string(),
+            digest: get_diagnostics_digest(&[Diagnostic::default()]),
+        },
+    );
+    let cache_file = get_index_cache_file(&dir.join("cache"), &dir);
+    assert_eq!(read_workspace_index(&cache_file), None);
+    write_workspace_index(&cache_file, &index).unwrap();
+    assert_eq!(read_workspace_index(&cache_file), Some(index));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&cache_file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    // Indices of other versions are ignored
+    fs::write(
+        &cache_file,
+        r#"{"version":"1","files":{},"diagnostics":{}}"#,
+    )
+    .unwrap();
+    assert_eq!(read_workspace_index(&cache_file), None);
+
+    // The hash is stable, such that cache file names don't change between builds
+    assert_eq!(hash_value(&0u32), 0xcc22_47b7_9ac4_8af0);
+}
@@ -0,0 +1,207 @@
+use std::path::Path;
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{InlayHint, InlayHintKind, InlayHintLabel, Range};
+
+use crate::{
+    bazel_flags::{BazelFlags, FlagLookupType},
+    file_utils::resolve_bazelrc_path,
+    flag_values::get_allowed_values,
+    lsp_utils::{offset_to_lsp_pos, range_to_lsp, PositionEncoding},
+    parser::Line,
+};
+
+// Which kinds of inlay hints should be shown?
+#[derive(Debug, Clone, Copy)]
+pub struct InlayHintSettings {
+    // The long name next to abbreviated flags, e.g. `-k` → `--keep_going`
+    pub abbreviations: bool,
+    // The implied `=true` next to boolean flags without a value
+    pub implied_values: bool,
+    // The resolved path next to `import %workspace%/...` lines
+    pub import_paths: bool,
+}
+
+impl Default for InlayHintSettings {
+    fn default() -> Self {
+        InlayHintSettings {
+            abbreviations: true,
+            implied_values: true,
+            import_paths: true,
+        }
+    }
+}
+
+fn make_hint(
+    rope: &Rope,
+    offset: usize,
+    label: String,
+    kind: Option<InlayHintKind>,
+    encoding: PositionEncoding,
+) -> Option<InlayHint> {
+    Some(InlayHint {
+        position: offset_to_lsp_pos(rope, offset, encoding)?,
+        label: InlayHintLabel::String(label),
+        kind,
+        text_edits: None,
+        tooltip: None,
+        padding_left: Some(true),
+        padding_right: None,
+        data: None,
+    })
+}
+
+fn hints_for_line(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    file_path: Option<&Path>,
+    line: &Line,
+    settings: &InlayHintSettings,
+    encoding: PositionEncoding,
+) -> Vec<InlayHint> {
+    let mut hints = Vec::<InlayHint>::new();
+    let Some(command) = &line.command else {
+        return hints;
+    };
+
+    if command.0 == "import" || command.0 == "try-import" {
+        if !settings.import_paths || line.flags.len() != 1 {
+            return hints;
+        }
+        let Some(value) = &line.flags[0].value else {
+            return hints;
+        };
+        if let Some(path) = value
+            .0
+            .contains("%workspace%")
+            .then(|| resolve_bazelrc_path(file_path?, &value.0))
+            .flatten()
+        {
+            hints.extend(make_hint(
+                rope,
+                value.1.end,
+                path.display().to_string(),
+                None,
+                encoding,
+            ));
+        }
+        return hints;
+    }
+
+    for flag in &line.flags {
+        let Some(name) = &flag.name else {
+            continue;
+        };
+        let Some((lookup_type, info)) = bazel_flags.get_by_invocation(&name.0) else {
+            continue;
+        };
+        if lookup_type == FlagLookupType::Abbreviation {
+            if settings.abbreviations {
+                hints.extend(make_hint(
+                    rope,
+                    name.1.end,
+                    format!("--{}", info.name),
+                    None,
+                    encoding,
+                ));
+            }
+        } else if settings.implied_values
+            && flag.value.is_none()
+            && info.has_negative_flag()
+            && !info.requires_value()
+            && get_allowed_values(info).is_none()
+            && !info.is_negated_invocation(&name.0)
+        {
+            hints.extend(make_hint(
+                rope,
+                name.1.end,
+                "=true".to_string(),
+                Some(InlayHintKind::PARAMETER),
+                encoding,
+            ));
+        }
+    }
+    hints
+}
+
+// The inlay hints for all lines overlapping the given range
+pub fn get_inlay_hints(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    file_path: Option<&Path>,
+    lines: &[Line],
+    range: &Range,
+    settings: &InlayHintSettings,
+    encoding: PositionEncoding,
+) -> Vec<InlayHint> {
+    lines
+        .iter()
+        .filter(|line| {
+            range_to_lsp(rope, &line.span, encoding)
+                .is_some_and(|r| r.start <= range.end && r.end > range.start)
+        })
+        .flat_map(|line| hints_for_line(bazel_flags, rope, file_path, line, settings, encoding))
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_inlay_hints() {
+    use crate::bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags};
+    use crate::parser::parse_from_str;
+    use crate::test_utils::TestDir;
+    use tower_lsp::lsp_types::Position;
+
+    let ws = TestDir::workspace();
+    let file_path = ws.join(".bazelrc");
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let text = "build -k -c opt --nokeep_going --keep_going --color\nimport %workspace%/ci.bazelrc\nimport ci.bazelrc\n";
+    let rope = Rope::from_str(text);
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &flags);
+    let full_range = Range::new(Position::new(0, 0), Position::new(3, 0));
+    let get_hints = |range: &Range, settings: &InlayHintSettings| {
+        get_inlay_hints(
+            &flags,
+            &rope,
+            Some(&file_path),
+            &lines,
+            range,
+            settings,
+            PositionEncoding::Utf16,
+        )
+        .into_iter()
+        .map(|h| {
+            let InlayHintLabel::String(label) = h.label else {
+                panic!("unexpected label")
+            };
+            (h.position.line, h.position.character, label)
+        })
+        .collect::<Vec<_>>()
+    };
+    let ci_path = ws.join("ci.bazelrc").display().to_string();
+    assert_eq!(
+        get_hints(&full_range, &Default::default()),
+        vec![
+            (0, 8, "--keep_going".to_string()),
+            (0, 11, "--compilation_mode".to_string()),
+            (0, 43, "=true".to_string()),
+            (1, 29, ci_path.clone()),
+        ]
+    );
+
+    // Each kind of hint can be disabled
+    let settings = InlayHintSettings {
+        abbreviations: false,
+        implied_values: false,
+        import_paths: true,
+    };
+    assert_eq!(get_hints(&full_range, &settings), vec![(1, 29, ci_path)]);
+
+    // Only the lines within the requested range are considered
+    let second_line = Range::new(Position::new(1, 0), Position::new(1, 5));
+    assert_eq!(get_hints(&second_line, &settings).len(), 1);
+    let first_line = Range::new(Position::new(0, 0), Position::new(0, 5));
+    assert_eq!(get_hints(&first_line, &settings).len(), 0);
+    assert_eq!(get_hints(&first_line, &Default::default()).len(), 3);
+}
@@ -1,98 +1,2005 @@
-use crate::bazel_flags::{combine_key_value_flags, BazelFlags, COMMAND_DOCS};
-use crate::completion::get_completion_items;
-use crate::definition::get_definitions;
-use crate::diagnostic::{diagnostics_from_parser, diagnostics_from_rcconfig};
-use crate::file_utils::resolve_bazelrc_path;
-use crate::formatting::{get_text_edits_for_lines, FormatLineFlow};
+use crate::bazel_flags::{
+    combine_key_value_flags, get_command_documentation_markdown, load_custom_flags, BazelFlags,
+};
+use crate::bazeliskrc::{
+    diagnostics_from_bazeliskrc, get_bazeliskrc_completion_items, get_bazeliskrc_hover_markdown,
+    is_bazeliskrc_file, parse_bazeliskrc,
+};
+use crate::call_hierarchy::{
+    get_incoming_calls, get_outgoing_calls, prepare_call_hierarchy, CallHierarchyNode,
+};
+use crate::canonicalize::{
+    canonicalize_line_flags, canonicalize_lines_with_bazel, get_canonicalize_edit, get_flag_args,
+    supports_bazel_canonicalization, CanonicalizeFlagsParams, CANONICALIZE_FLAGS_COMMAND,
+};
+use crate::code_action::{
+    get_code_action_data, get_code_actions, make_workspace_edit, resolve_code_action,
+    REWRITE_BOOLEAN_KIND,
+};
+use crate::code_lens::{
+    count_config_references, get_code_lenses, get_run_command_line, RunInTerminal,
+    RunInTerminalParams, RunWithConfigParams, RUN_WITH_CONFIG_COMMAND,
+};
+use crate::completion::{get_completion_items, CompletionSettings};
+use crate::config_expansion::{
+    format_effective_config, get_config_hover_markdown, get_override_hover_markdown,
+    EffectiveConfigParams, SHOW_EFFECTIVE_CONFIG_COMMAND,
+};
+use crate::config_tree::{build_config_tree, ConfigTreeFile, ConfigTreeParams};
+use crate::definition::{
+    get_definitions, get_document_links, get_import_hover_markdown, resolve_document_link,
+};
+use crate::diagnostic::{
+    apply_severity_overrides, diagnostics_for_config_nesting, diagnostics_for_conflicting_flags,
+    diagnostics_for_dead_assignments, diagnostics_for_default_values, diagnostics_for_flag_paths,
+    diagnostics_for_import_issues, diagnostics_for_missing_starlark_flags,
+    diagnostics_for_platform_configs, diagnostics_for_unknown_configs,
+    diagnostics_for_unstable_flags, diagnostics_for_unused_configs, diagnostics_from_parser,
+    diagnostics_from_rcconfig, DiagnosticCheck, SeverityOverride,
+};
+use crate::file_utils::{get_workspace_path, is_bazelrc_file};
+use crate::flag_alias::{get_flag_alias_hover_markdown, get_flag_aliases};
+use crate::flag_paths::{get_flag_paths, get_path_status, PathStatus};
+use crate::flag_value_style::{
+    get_flag_value_style_edits, FlagValueStyle, NormalizeFlagValuesParams,
+    NORMALIZE_FLAG_VALUES_COMMAND, REWRITE_FLAG_VALUE_KIND,
+};
+use crate::folding::get_folding_ranges;
+use crate::formatting::{
+    get_completed_line, get_lines_in_range, get_text_edits_for_lines, FormatLineFlow, FormatStyle,
+};
+use crate::import_graph::{
+    find_import_issues, get_defined_configs, get_transitive_imports, load_imported_files,
+    load_imported_rc_files, load_related_files, load_related_rc_files, read_file_from_disk,
+    ImportIssue, RcFile,
+};
+use crate::index_cache::{
+    get_diagnostics_digest, get_index_cache_file, read_workspace_index, write_workspace_index,
+    DiagnosticsDigest, IndexedFile, WorkspaceIndex,
+};
+use crate::inlay_hints::{get_inlay_hints, InlayHintSettings};
+use crate::line_continuations::REWRITE_LINE_CONTINUATIONS_KIND;
 use crate::line_index::{IndexEntry, IndexEntryKind, IndexedLines};
-use crate::lsp_utils::{lsp_pos_to_offset, range_to_lsp};
-use crate::parser::{parse_from_str, Line, ParserResult};
+use crate::live_flags::{find_bazel_binary, get_cache_dir, load_live_bazel_flags};
+use crate::logging::LogLevel;
+use crate::lsp_utils::{
+    apply_content_change, lsp_pos_to_offset, negotiate_position_encoding, range_to_lsp,
+    PositionEncoding,
+};
+use crate::organize::ORGANIZE_LINES_KIND;
+use crate::parallel::map_in_parallel;
+use crate::parser::{parse_from_str, reparse_lines, Line, ParserResult};
+use crate::placeholders::get_placeholder_hover_markdown;
+use crate::platform_config::{
+    get_platform_config_hover_markdown, is_platform_specific_config_enabled,
+};
+use crate::progress::{get_percentage, WorkDoneReporter};
+use crate::rc_discovery::{find_workspace_rc_files, sort_by_read_order, WRAPPER_FILE_NAMES};
+use crate::references::{find_references, get_linked_editing_ranges, get_reference_target};
+use crate::rename::{get_label_rename_edits, is_label, prepare_label_rename};
+use crate::replace_flag::{
+    get_flag_replacement_edits, make_confirmed_workspace_edit, make_replacement_workspace_edit,
+    ReplaceFlagParams, REPLACE_FLAG_COMMAND,
+};
 use crate::semantic_token::{
-    convert_to_lsp_tokens, semantic_tokens_from_lines, RCSemanticToken, LEGEND_TYPE,
+    compute_token_edits, convert_to_lsp_tokens, semantic_tokens_from_lines, RCSemanticToken,
+    LEGEND_TYPE,
+};
+use crate::server_status::{
+    to_unix_millis, FlagSource, ServerStatus, ServerStatusParams, StatusNotification,
 };
+use crate::signature_help::get_signature_help;
+use crate::starlark_flags::{
+    get_main_repository_labels, get_starlark_flag_hover_markdown, query_existing_labels,
+};
+use crate::suppressions::{diagnostics_for_suppressions, filter_suppressed_diagnostics};
+use crate::symbols::{get_config_symbols, get_document_symbols};
+use crate::version_resolution::{WorkspaceFlags, VERSION_FILE_NAMES};
 use dashmap::DashMap;
 use ropey::Rope;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedReceiver;
 use tower_lsp::jsonrpc::{Error, Result};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer};
 
-struct TextDocumentItem {
-    uri: Url,
-    text: String,
-    version: i32,
-}
+struct TextDocumentItem {
+    uri: Url,
+    text: String,
+    version: i32,
+    // The lines of the text, if they were already re-parsed incrementally
+    lines: Option<Vec<Line>>,
+}
+
+#[derive(Debug)]
+pub struct AnalyzedDocument {
+    rope: Rope,
+    version: i32,
+    // The flags of the Bazel version used by the document's workspace
+    bazel_flags: Arc<BazelFlags>,
+    semantic_tokens: Vec<RCSemanticToken>,
+    indexed_lines: IndexedLines,
+    parser_errors: Vec<chumsky::prelude::Simple<char>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Settings {
+    // The Bazel version whose flags should be used. Auto-detected if unset or `auto`.
+    #[serde(default)]
+    pub bazel_version: Option<String>,
+    // The Bazel binary used to load the flags, to run `bazel query` and `bazel canonicalize-flags`
+    #[serde(default)]
+    pub bazel_path: Option<String>,
+    #[serde(default)]
+    pub format_lines: FormatLineFlow,
+    // Should flags be sorted alphabetically within each line when formatting?
+    #[serde(default)]
+    pub format_sort_flags: bool,
+    // Should imported files outside the workspace (e.g., `~/.bazelrc`) be analyzed?
+    // Disabled by default, since those files might contain personal information.
+    #[serde(default)]
+    pub include_files_outside_workspace: bool,
+    // Should typing `=` accept a flag completion?
+    #[serde(default = "default_true")]
+    pub completion_commit_with_equals: bool,
+    // Should `=` be appended when completing flags which require a value?
+    #[serde(default)]
+    pub completion_insert_equals: bool,
+    // Should hidden and internal flags be offered by auto-completion?
+    #[serde(default)]
+    pub completion_show_hidden_flags: bool,
+    // Which inlay hints should be shown?
+    #[serde(default = "default_true")]
+    pub inlay_hints_abbreviations: bool,
+    #[serde(default = "default_true")]
+    pub inlay_hints_implied_values: bool,
+    #[serde(default = "default_true")]
+    pub inlay_hints_import_paths: bool,
+    // Should Starlark flags (`--//tools:my_flag`) be validated using `bazel query`?
+    #[serde(default)]
+    pub validate_starlark_flags: bool,
+    // Should the flags be loaded from the workspace's Bazel binary instead of the packaged flags?
+    #[serde(default)]
+    pub load_flags_from_bazel: bool,
+    // Should the paths passed to flags like `--disk_cache` be checked on disk?
+    #[serde(default)]
+    pub check_flag_paths: bool,
+    // A JSON file with additional flags, relative to the workspace
+    #[serde(default)]
+    pub custom_flags_file: Option<String>,
+    // Should experimental and incompatible flags be diagnosed?
+    #[serde(default)]
+    pub diagnose_unstable_flags: bool,
+    // Experimental and incompatible flags which are used on purpose
+    #[serde(default)]
+    pub allowed_unstable_flags: Vec<String>,
+    // Overrides the severity of the diagnostics reported by individual checks
+    #[serde(default)]
+    pub diagnostic_severities: HashMap<DiagnosticCheck, SeverityOverride>,
+}
+
+impl Settings {
+    // The explicitly configured Bazel version, if any
+    pub fn pinned_bazel_version(&self) -> Option<&str> {
+        self.bazel_version
+            .as_deref()
+            .filter(|v| !v.is_empty() && *v != "auto")
+    }
+
+    // The configured custom flags file, resolved relative to the workspace
+    pub fn custom_flags_path(&self, workspace: &Path) -> Option<PathBuf> {
+        self.custom_flags_file
+            .as_deref()
+            .filter(|f| !f.is_empty())
+            .map(|f| workspace.join(f))
+    }
+
+    pub fn format_style(&self) -> FormatStyle {
+        FormatStyle {
+            line_flow: self.format_lines,
+            sort_flags: self.format_sort_flags,
+        }
+    }
+
+    pub fn inlay_hint_settings(&self) -> InlayHintSettings {
+        InlayHintSettings {
+            abbreviations: self.inlay_hints_abbreviations,
+            implied_values: self.inlay_hints_implied_values,
+            import_paths: self.inlay_hints_import_paths,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            bazel_version: None,
+            bazel_path: None,
+            format_lines: Default::default(),
+            format_sort_flags: false,
+            include_files_outside_workspace: false,
+            completion_commit_with_equals: true,
+            completion_insert_equals: false,
+            completion_show_hidden_flags: false,
+            inlay_hints_abbreviations: true,
+            inlay_hints_implied_values: true,
+            inlay_hints_import_paths: true,
+            validate_starlark_flags: false,
+            load_flags_from_bazel: false,
+            check_flag_paths: false,
+            custom_flags_file: None,
+            diagnose_unstable_flags: false,
+            allowed_unstable_flags: Vec::new(),
+            diagnostic_severities: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BackendState {
+    pub client: Client,
+    pub document_map: DashMap<String, Arc<AnalyzedDocument>>,
+    // The open `.bazeliskrc` documents, together with their version. They only support
+    // diagnostics, completion and hover.
+    pub bazeliskrc_documents: DashMap<String, (Rope, i32)>,
+    // The Bazel flags, resolved per workspace
+    pub workspace_flags: WorkspaceFlags,
+    pub settings: std::sync::RwLock<Arc<Settings>>,
+    // Settings overridden for individual workspace folders, requested via `workspace/configuration`
+    pub folder_settings: std::sync::RwLock<Vec<(PathBuf, Arc<Settings>)>>,
+    // The capabilities announced by the client during `initialize`
+    pub client_capabilities: std::sync::RwLock<ClientCapabilities>,
+    // An optional message which should be displayed to the user on startup
+    pub startup_warning: Option<String>,
+    // The semantic tokens most recently sent for each document, together with
+    // their result id. Used to compute `semanticTokens/full/delta` responses.
+    pub semantic_token_results: DashMap<String, (String, Vec<SemanticToken>)>,
+    pub next_result_id: AtomicU64,
+    // The workspace folders announced by the client during `initialize`
+    pub workspace_roots: std::sync::RwLock<Vec<PathBuf>>,
+    // The Bazel binary used for `bazel query` and for loading the flags, if configured
+    // via `--bazel-path`
+    pub bazel_path: Option<String>,
+    // Per workspace: Which Starlark flag labels exist, according to `bazel query`. `None`
+    // while the query is running.
+    pub starlark_flag_index: DashMap<PathBuf, HashMap<String, Option<bool>>>,
+    // The bazelrc files found in each workspace, invalidated via `didChangeWatchedFiles`
+    pub workspace_rc_files: DashMap<PathBuf, Arc<Vec<PathBuf>>>,
+    // The paths passed to flags, as found on disk, together with the time they were checked
+    pub flag_path_statuses: DashMap<PathBuf, (Instant, PathStatus)>,
+    // The latest diagnostics run for each document. Older runs are cancelled.
+    pub diagnostics_generations: DashMap<String, u64>,
+    // Bumped whenever any document or setting changes. Diagnostics also depend on other
+    // files, hence the pulled diagnostics are only reused while this stays unchanged.
+    pub diagnostics_revision: AtomicU64,
+    // Bumped whenever the diagnostics of all files might change without any bazelrc file
+    // changing, e.g. with the settings or the flags
+    pub settings_revision: AtomicU64,
+    // The diagnostics most recently pulled for each file: a fingerprint of the inputs they
+    // were computed from (`None` if restored from the index cache), their result id and
+    // a digest of the diagnostics
+    pub diagnostic_results: DashMap<String, (Option<u64>, String, u64)>,
+    // The directory the workspace indices are cached in, if any
+    pub index_cache_dir: Option<PathBuf>,
+    // The parsed bazelrc files of each workspace, loaded from the index cache on first use
+    pub workspace_indexes: DashMap<PathBuf, WorkspaceIndex>,
+    // Whether the server's log is forwarded to the client, as set by `initialize` and `$/setTrace`
+    pub trace: std::sync::RwLock<TraceValue>,
+    // The unit of the columns exchanged with the client, negotiated during `initialize`
+    pub position_encoding: std::sync::RwLock<PositionEncoding>,
+    // The log messages to forward as `$/logTrace` notifications. Taken by `initialized`.
+    pub log_traces: std::sync::Mutex<Option<UnboundedReceiver<LogTraceParams>>>,
+    // Raised to verbose while the client requests verbose traces. Only set together with
+    // `log_traces`.
+    pub log_level: Option<LogLevel>,
+    // The status most recently sent via `bazelrc/status`, per workspace
+    pub published_statuses: DashMap<Option<PathBuf>, ServerStatus>,
+    // Whether the process serves further clients, e.g. via `--listen tcp://...`
+    pub shared_process: bool,
+}
+
+// A cheap handle to the server state, which can be moved into background tasks
+#[derive(Clone, Debug)]
+pub struct Backend(Arc<BackendState>);
+
+impl Backend {
+    pub fn new(state: BackendState) -> Backend {
+        Backend(Arc::new(state))
+    }
+}
+
+impl std::ops::Deref for Backend {
+    type Target = BackendState;
+
+    fn deref(&self) -> &BackendState {
+        &self.0
+    }
+}
+
+// How long the status of a path on disk is cached
+const FLAG_PATH_STATUS_TTL: Duration = Duration::from_secs(10);
+
+// How often the progress of indexing files is reported to the client
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// The delays between the attempts to load the live flags, if they failed while the
+// packaged flags are unavailable
+const LIVE_FLAGS_RETRY_DELAYS: [Duration; 3] = [
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+];
+
+// How long to wait for further changes before computing the diagnostics while typing
+const DIAGNOSTICS_DEBOUNCE: Duration = Duration::from_millis(150);
+
+fn unchanged_diagnostic_report(result_id: String) -> DocumentDiagnosticReportResult {
+    DocumentDiagnosticReportResult::Report(DocumentDiagnosticReport::Unchanged(
+        RelatedUnchangedDocumentDiagnosticReport {
+            related_documents: None,
+            unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport { result_id },
+        },
+    ))
+}
+
+impl Backend {
+    // Determines which Starlark flags used within the lines exist, according to the cached
+    // results of `bazel query`. Labels not checked before are looked up in the background,
+    // and the documents of the workspace are re-analyzed once the query finished.
+    fn check_starlark_flags(&self, workspace: &Path, lines: &[Line]) -> HashMap<String, bool> {
+        let labels = get_main_repository_labels(lines);
+        let mut index = self
+            .starlark_flag_index
+            .entry(workspace.to_path_buf())
+            .or_default();
+        let unchecked = labels
+            .iter()
+            .filter(|l| !index.contains_key(*l))
+            .cloned()
+            .collect::<Vec<_>>();
+        // Don't start another query for the same labels while this one is running
+        for label in &unchecked {
+            index.insert(label.clone(), None);
+        }
+        let known = labels
+            .into_iter()
+            .filter_map(|l| Some((l.clone(), (*index.get(&l)?)?)))
+            .collect();
+        drop(index);
+        if !unchecked.is_empty() {
+            let backend = self.clone();
+            let workspace = workspace.to_path_buf();
+            tokio::spawn(async move {
+                backend.query_starlark_flags(workspace, unchecked).await;
+            });
+        }
+        known
+    }
+
+    async fn query_starlark_flags(&self, workspace: PathBuf, labels: Vec<String>) {
+        let bazel_command = self
+            .get_bazel_path(&workspace)
+            .unwrap_or("bazel".to_string());
+        let query_workspace = workspace.clone();
+        let query_labels = labels.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            query_existing_labels(&bazel_command, &query_workspace, &query_labels)
+        })
+        .await
+        .unwrap_or_else(|err| Err(err.to_string()));
+        let existing = match result {
+            Ok(existing) => Some(existing),
+            Err(msg) => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Validating Starlark flags failed: {msg}"),
+                    )
+                    .await;
+                None
+            }
+        };
+        {
+            // The index might have been cleared in the meantime, e.g. by a settings change
+            let mut index = self
+                .starlark_flag_index
+                .entry(workspace.clone())
+                .or_default();
+            for label in labels {
+                // Don't retry failed queries on every change. Assume the labels exist instead.
+                let exists = existing.as_ref().is_none_or(|e| e.contains(&label));
+                index.insert(label, Some(exists));
+            }
+        }
+        self.reanalyze_workspaces(&[workspace]).await;
+    }
+
+    // Checks the given paths on disk. Recently checked paths are served from the cache.
+    async fn check_flag_paths(&self, paths: Vec<PathBuf>) -> HashMap<PathBuf, PathStatus> {
+        let mut statuses = HashMap::new();
+        let mut unchecked = Vec::new();
+        for path in paths {
+            match self.flag_path_statuses.get(&path) {
+                Some(entry) if entry.0.elapsed() < FLAG_PATH_STATUS_TTL => {
+                    statuses.insert(path, entry.1);
+                }
+                _ => unchecked.push(path),
+            }
+        }
+        if !unchecked.is_empty() {
+            // Network file systems might be slow. Don't block the server.
+            let checked = tokio::task::spawn_blocking(move || {
+                unchecked
+                    .into_iter()
+                    .map(|path| {
+                        let status = get_path_status(&path);
+                        (path, status)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .await
+            .unwrap_or_default();
+            let now = Instant::now();
+            // Drop the expired entries, such that paths no longer used don't pile up
+            self.flag_path_statuses
+                .retain(|_, entry| entry.0.elapsed() < FLAG_PATH_STATUS_TTL);
+            for (path, status) in checked {
+                self.flag_path_statuses.insert(path.clone(), (now, status));
+                statuses.insert(path, status);
+            }
+        }
+        statuses
+    }
+
+    // Loads the flags from the Bazel binary used within the workspace, if enabled. Until they
+    // arrive, the packaged flags are used. Afterwards, the open documents are re-analyzed.
+    // Failures are only shown to the user if `show_failure` is set, and logged otherwise.
+    async fn load_live_flags(&self, workspace: &Path, show_failure: bool) {
+        let bazel_path = self.get_bazel_path(workspace);
+        let enabled =
+            bazel_path.is_some() || self.settings_for(Some(workspace)).load_flags_from_bazel;
+        if !enabled || !self.workspace_flags.start_live_loading(workspace) {
+            return;
+        }
+        let Some(bazel_command) = bazel_path.or_else(find_bazel_binary) else {
+            self.workspace_flags.set_live_flags_failed(workspace);
+            self.publish_status(Some(workspace)).await;
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    "Using the packaged flags because neither `bazelisk` nor `bazel` were found on the PATH",
+                )
+                .await;
+            return;
+        };
+        self.publish_status(Some(workspace)).await;
+        let load_command = bazel_command.clone();
+        let load_workspace = workspace.to_path_buf();
+        let result = tokio::task::spawn_blocking(move || {
+            load_live_bazel_flags(&load_command, &load_workspace, get_cache_dir().as_deref())
+        })
+        .await
+        .unwrap_or_else(|err| Err(err.to_string()));
+        match result {
+            Ok(flags) => {
+                tracing::info!(workspace = %workspace.display(), "Loaded the flags from `{bazel_command}`");
+                self.workspace_flags.set_live_flags(workspace, flags);
+                self.reanalyze_workspaces(&[workspace.to_path_buf()]).await;
+            }
+            Err(msg) => {
+                tracing::warn!(workspace = %workspace.display(), "Running `{bazel_command}` failed: {msg}");
+                self.workspace_flags.set_live_flags_failed(workspace);
+                self.publish_status(Some(workspace)).await;
+                let message = format!(
+                    "Using the packaged flags because running `{bazel_command}` failed:\n{msg}"
+                );
+                if show_failure {
+                    self.client
+                        .show_message(MessageType::WARNING, message)
+                        .await;
+                } else {
+                    self.client.log_message(MessageType::WARNING, message).await;
+                }
+            }
+        }
+    }
+
+    // Loads the live flags of the workspace in the background. Running Bazel can take a
+    // while, and the notification which triggered the loading shouldn't wait for it.
+    fn spawn_live_flags_loading(&self, workspace: PathBuf) {
+        let backend = self.clone();
+        tokio::spawn(async move {
+            backend.load_live_flags(&workspace, true).await;
+            // Without the packaged flags, the live flags are the only flags available
+            if backend.workspace_flags.packaged_flags_error().is_some() {
+                backend.retry_live_flags(&workspace).await;
+            }
+        });
+    }
+
+    // Retries loading the live flags of the workspace after it failed, with increasing
+    // delays. Only used while the packaged flags are unavailable, since the workspace is
+    // limited to the syntax-related features until its live flags are loaded.
+    async fn retry_live_flags(&self, workspace: &Path) {
+        for delay in LIVE_FLAGS_RETRY_DELAYS {
+            if !self.workspace_flags.live_flags_failed(workspace) {
+                return;
+            }
+            tokio::time::sleep(delay).await;
+            // Loading may have been restarted meanwhile, e.g. after a configuration change
+            if !self.workspace_flags.live_flags_failed(workspace) {
+                return;
+            }
+            self.workspace_flags.forget_live_flags(workspace);
+            self.load_live_flags(workspace, false).await;
+        }
+        if self.workspace_flags.live_flags_failed(workspace) {
+            tracing::error!(workspace = %workspace.display(), "Giving up loading the flags from Bazel");
+            self.client
+                .log_message(
+                    MessageType::ERROR,
+                    format!(
+                        "Giving up loading the flags of {} from Bazel. Only the syntax-related features are available.",
+                        workspace.display()
+                    ),
+                )
+                .await;
+        }
+    }
+
+    // The status of the given workspace, or of files outside of any workspace
+    fn get_server_status(&self, workspace: Option<&Path>) -> ServerStatus {
+        let flag_source = match workspace.map(|w| self.workspace_flags.has_live_flags(w)) {
+            Some(Some(true)) => FlagSource::Live,
+            Some(None) => FlagSource::Loading,
+            _ => FlagSource::Packaged,
+        };
+        let indexed_files = workspace
+            .and_then(|w| self.workspace_indexes.get(w))
+            .map_or(0, |index| index.file_count());
+        ServerStatus {
+            workspace: workspace.and_then(|w| Url::from_directory_path(w).ok()),
+            bazel_version: workspace
+                .and_then(|w| self.workspace_flags.get_version(w))
+                .or_else(|| self.workspace_flags.default_flags().bazel_version.clone()),
+            flag_source,
+            indexed_files,
+            last_flag_refresh: workspace
+                .and_then(|w| self.workspace_flags.get_refresh_time(w))
+                .and_then(to_unix_millis),
+        }
+    }
+
+    // Sends a `bazelrc/status` notification, if the status of the workspace changed
+    async fn publish_status(&self, workspace: Option<&Path>) {
+        let status = self.get_server_status(workspace);
+        let key = workspace.map(|w| w.to_path_buf());
+        if self.published_statuses.get(&key).as_deref() == Some(&status) {
+            return;
+        }
+        self.published_statuses.insert(key, status.clone());
+        self.client
+            .send_notification::<StatusNotification>(status)
+            .await;
+    }
+
+    // Custom `bazelrc/serverStatus` request: The status of the document's workspace
+    pub async fn server_status(&self, params: ServerStatusParams) -> Result<ServerStatus> {
+        let workspace = params
+            .text_document
+            .and_then(|doc| doc.uri.to_file_path().ok())
+            .and_then(|path| get_workspace_path(&path));
+        Ok(self.get_server_status(workspace.as_deref()))
+    }
+
+    // The workspaces containing at least one open document
+    fn get_open_workspaces(&self) -> HashSet<PathBuf> {
+        self.document_map
+            .iter()
+            .filter_map(|doc| get_workspace_path(&Url::parse(doc.key()).ok()?.to_file_path().ok()?))
+            .collect()
+    }
+
+    pub fn position_encoding(&self) -> PositionEncoding {
+        *self.position_encoding.read().unwrap()
+    }
+
+    // The settings for the given file, including the overrides of its workspace folder
+    pub fn settings_for(&self, file_path: Option<&Path>) -> Arc<Settings> {
+        let folder_settings = file_path.and_then(|path| {
+            self.folder_settings
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(folder, _)| path.starts_with(folder))
+                .max_by_key(|(folder, _)| folder.components().count())
+                .map(|(_, settings)| settings.clone())
+        });
+        folder_settings.unwrap_or_else(|| self.settings.read().unwrap().clone())
+    }
+
+    // The Bazel binary configured for the workspace, falling back to `--bazel-path`. Clients
+    // of a shared process can't choose the binary, since any local process might connect.
+    fn get_bazel_path(&self, workspace: &Path) -> Option<String> {
+        if self.shared_process {
+            return self.bazel_path.clone();
+        }
+        self.settings_for(Some(workspace))
+            .bazel_path
+            .clone()
+            .filter(|p| !p.is_empty())
+            .or_else(|| self.bazel_path.clone())
+    }
+
+    // The workspaces announced by the client and the workspaces of all open documents
+    fn get_known_workspaces(&self) -> HashSet<PathBuf> {
+        let mut workspaces = self.get_open_workspaces();
+        workspaces.extend(self.workspace_roots.read().unwrap().iter().cloned());
+        workspaces
+    }
+
+    // Re-analyzes the open documents depending on bazelrc files which changed on disk:
+    // Documents importing them and, since configs might be defined or used in any of the
+    // workspace's files, all documents within the same workspace.
+    async fn reanalyze_dependent_documents(&self, changed_files: &HashSet<PathBuf>) {
+        let changed_workspaces = changed_files
+            .iter()
+            .filter_map(|path| get_workspace_path(path))
+            .collect::<HashSet<_>>();
+        let documents = self
+            .document_map
+            .iter()
+            .filter_map(|doc| {
+                let uri = Url::parse(doc.key()).ok()?;
+                let file_path = uri.to_file_path().ok()?;
+                Some((uri, file_path, doc.indexed_lines.lines.clone()))
+            })
+            .collect::<Vec<_>>();
+        for (uri, file_path, lines) in documents {
+            let is_dependent = get_workspace_path(&file_path)
+                .is_some_and(|w| changed_workspaces.contains(&w))
+                || !self
+                    .get_transitive_imports(&file_path, &lines)
+                    .is_disjoint(changed_files);
+            if !is_dependent {
+                continue;
+            }
+            let Some((text, version)) = self
+                .document_map
+                .get(uri.as_str())
+                .map(|doc| (doc.rope.to_string(), doc.version))
+            else {
+                continue;
+            };
+            let document = TextDocumentItem {
+                uri,
+                text,
+                version,
+                lines: None,
+            };
+            self.on_change(document, Duration::ZERO).await;
+        }
+        // Also covers the workspace diagnostics of files not opened in the editor
+        self.refresh_diagnostics().await;
+    }
+
+    // Re-analyzes the open documents within the given workspaces, e.g. after their flags changed
+    async fn reanalyze_workspaces(&self, workspaces: &[PathBuf]) {
+        if workspaces.is_empty() {
+            return;
+        }
+        self.reanalyze_documents(|uri| {
+            uri.to_file_path()
+                .ok()
+                .and_then(|path| get_workspace_path(&path))
+                .is_some_and(|w| workspaces.contains(&w))
+        })
+        .await;
+    }
+
+    // Re-analyzes the open documents accepted by the filter
+    async fn reanalyze_documents(&self, filter: impl Fn(&Url) -> bool) {
+        let affected_documents = self
+            .document_map
+            .iter()
+            .filter_map(|doc| {
+                let uri = Url::parse(doc.key()).ok()?;
+                if !filter(&uri) {
+                    return None;
+                }
+                Some(TextDocumentItem {
+                    uri,
+                    text: doc.rope.to_string(),
+                    version: doc.version,
+                    lines: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        for document in affected_documents {
+            self.on_change(document, Duration::ZERO).await;
+        }
+        self.refresh_diagnostics().await;
+    }
+
+    // Requests the settings of each workspace folder via `workspace/configuration`,
+    // such that they can be overridden per folder
+    async fn update_folder_settings(&self) {
+        let supports_configuration = self
+            .client_capabilities
+            .read()
+            .unwrap()
+            .workspace
+            .as_ref()
+            .and_then(|w| w.configuration)
+            .unwrap_or(false);
+        let folders = self.workspace_roots.read().unwrap().clone();
+        if !supports_configuration || folders.is_empty() {
+            return;
+        }
+        let items = folders
+            .iter()
+            .map(|folder| ConfigurationItem {
+                scope_uri: Url::from_directory_path(folder).ok(),
+                section: Some("bazelrc".to_string()),
+            })
+            .collect::<Vec<_>>();
+        let values = match self.client.configuration(items).await {
+            Ok(values) => values,
+            Err(err) => {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Failed to request the workspace folder settings: {err}"),
+                    )
+                    .await;
+                return;
+            }
+        };
+        let mut folder_settings = Vec::<(PathBuf, Arc<Settings>)>::new();
+        for (folder, value) in folders.into_iter().zip(values) {
+            if value.is_null() {
+                continue;
+            }
+            match serde_json::from_value::<Settings>(value) {
+                Ok(settings) => folder_settings.push((folder, Arc::new(settings))),
+                Err(err) => {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("Invalid settings for {}: {err}", folder.display()),
+                        )
+                        .await;
+                }
+            }
+        }
+        *self.folder_settings.write().unwrap() = folder_settings;
+    }
+
+    // Applies the Bazel version, binary and custom flags configured for the workspace.
+    // Returns whether the custom flags changed.
+    async fn apply_workspace_settings(
+        &self,
+        workspace: &Path,
+        previous: Option<&Settings>,
+    ) -> bool {
+        let settings = self.settings_for(Some(workspace));
+        self.workspace_flags
+            .pin_version(workspace, settings.pinned_bazel_version());
+        if self.shared_process && settings.bazel_path.as_ref().is_some_and(|p| !p.is_empty()) {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    "Ignoring `bazelrc.bazelPath` because the server is shared with other clients. Pass `--bazel-path` to the server instead.",
+                )
+                .await;
+        }
+        // The live flags have to be reloaded using the new binary
+        if previous.is_some_and(|p| p.bazel_path != settings.bazel_path) {
+            self.workspace_flags.forget_live_flags(workspace);
+        }
+        let custom_flags = match settings.custom_flags_path(workspace) {
+            Some(path) => match load_custom_flags(&path) {
+                Ok(flags) => Some(flags),
+                Err(err) => {
+                    self.client
+                        .show_message(
+                            MessageType::WARNING,
+                            format!(
+                                "Failed to load the custom flags from {}: {err}",
+                                path.display()
+                            ),
+                        )
+                        .await;
+                    None
+                }
+            },
+            None => None,
+        };
+        self.workspace_flags
+            .set_custom_flags(workspace, custom_flags)
+    }
+
+    // Watches the configured custom flags files, to reload them on changes. Replaces the
+    // watchers registered for previous settings.
+    async fn watch_custom_flags_files(&self) {
+        if !self.supports_file_watching() {
+            return;
+        }
+        let mut files = self
+            .folder_settings
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, settings)| settings.custom_flags_file.clone())
+            .chain([self.settings.read().unwrap().custom_flags_file.clone()])
+            .flatten()
+            .filter(|f| !f.is_empty())
+            .collect::<Vec<_>>();
+        files.sort();
+        files.dedup();
+        let id = "custom-flags-files".to_string();
+        let _ = self
+            .client
+            .unregister_capability(vec![Unregistration {
+                id: id.clone(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+            }])
+            .await;
+        if files.is_empty() {
+            return;
+        }
+        let watchers = files
+            .iter()
+            .map(|file| FileSystemWatcher {
+                glob_pattern: GlobPattern::String(format!("**/{file}")),
+                kind: None,
+            })
+            .collect::<Vec<_>>();
+        let registration = Registration {
+            id,
+            method: "workspace/didChangeWatchedFiles".to_string(),
+            register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                watchers,
+            })
+            .ok(),
+        };
+        if let Err(err) = self.client.register_capability(vec![registration]).await {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Failed to watch the custom flags files: {err}"),
+                )
+                .await;
+        }
+    }
+
+    // Remembers the semantic tokens sent to the client and returns their result id
+    fn store_semantic_tokens(&self, uri: String, tokens: Vec<SemanticToken>) -> String {
+        let result_id = self
+            .next_result_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_string();
+        self.semantic_token_results
+            .insert(uri, (result_id.clone(), tokens));
+        result_id
+    }
+
+    // Remembers the diagnostics reported for a file, together with a fingerprint of the
+    // inputs they were computed from, and returns their result id. The result id is kept
+    // if the diagnostics didn't change, such that the client can skip updating them.
+    fn store_diagnostics(&self, uri: String, inputs: u64, diagnostics: &[Diagnostic]) -> String {
+        let digest = get_diagnostics_digest(diagnostics);
+        let unchanged_result_id = self
+            .diagnostic_results
+            .get(&uri)
+            .filter(|previous| previous.2 == digest)
+            .map(|previous| previous.1.clone());
+        let result_id = unchanged_result_id.unwrap_or_else(|| {
+            self.next_result_id
+                .fetch_add(1, Ordering::Relaxed)
+                .to_string()
+        });
+        self.diagnostic_results
+            .insert(uri, (Some(inputs), result_id.clone(), digest));
+        result_id
+    }
+
+    // Fingerprints the inputs of the diagnostics of a workspace's files: The files on disk,
+    // based on their modification times, the versions of the open documents among them, and
+    // the settings. Changes to other workspaces don't affect the fingerprint.
+    fn fingerprint_files(&self, paths: &[PathBuf]) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        self.settings_revision
+            .load(Ordering::Relaxed)
+            .hash(&mut hasher);
+        for path in paths {
+            path.hash(&mut hasher);
+            std::fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .hash(&mut hasher);
+            Url::from_file_path(path)
+                .ok()
+                .and_then(|uri| self.document_map.get(uri.as_str()))
+                .map(|doc| doc.version)
+                .hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    // The result id of the diagnostics reported for a file, if computed from the same inputs
+    fn get_cached_diagnostics_result_id(&self, uri: &str, inputs: u64) -> Option<String> {
+        self.diagnostic_results
+            .get(uri)
+            .filter(|previous| previous.0 == Some(inputs))
+            .map(|previous| previous.1.clone())
+    }
+
+    // Loads the index of the workspace from the cache, unless it was loaded before. The
+    // diagnostics digests of the cached index are restored, such that the result ids
+    // reported before a restart stay valid while the diagnostics don't change.
+    fn load_workspace_index(&self, workspace: &Path) {
+        if self.workspace_indexes.contains_key(workspace) {
+            return;
+        }
+        let index = self
+            .index_cache_dir
+            .as_deref()
+            .and_then(|dir| read_workspace_index(&get_index_cache_file(dir, workspace)))
+            .unwrap_or_default();
+        for (path, digest) in &index.diagnostics {
+            let Ok(uri) = Url::from_file_path(path) else {
+                continue;
+            };
+            // Result ids handed out later must not collide with the restored ones
+            if let Ok(id) = digest.result_id.parse::<u64>() {
+                self.next_result_id.fetch_max(id + 1, Ordering::Relaxed);
+            }
+            self.diagnostic_results
+                .entry(uri.to_string())
+                .or_insert_with(|| (None, digest.result_id.clone(), digest.digest));
+        }
+        self.workspace_indexes
+            .entry(workspace.to_path_buf())
+            .or_insert(index);
+    }
+
+    fn save_workspace_index(&self, workspace: &Path) {
+        let (Some(cache_dir), Some(index)) =
+            (&self.index_cache_dir, self.workspace_indexes.get(workspace))
+        else {
+            return;
+        };
+        // The cache is only an optimization. Failing to write it is not an error.
+        let cache_file = get_index_cache_file(cache_dir, workspace);
+        if let Err(err) = write_workspace_index(&cache_file, &index) {
+            tracing::warn!(
+                "Failed to write the index cache {}: {err}",
+                cache_file.display()
+            );
+        }
+    }
+
+    // Loads all files transitively imported by the given file. The contents of
+    // documents opened in the editor take precedence over the contents on disk.
+    pub fn load_imported_files(&self, file_path: &Path, lines: &[Line]) -> Vec<RcFile> {
+        let include_outside_workspace = self
+            .settings_for(Some(file_path))
+            .include_files_outside_workspace;
+        load_imported_files(
+            file_path,
+            lines,
+            &self.workspace_flags.get(Some(file_path)),
+            include_outside_workspace,
+            |path| self.read_file(path),
+        )
+    }
+
+    // Loads the imports of the given file, and the workspace `.bazelrc` with its imports
+    pub fn load_related_files(&self, file_path: &Path, lines: &[Line]) -> Vec<RcFile> {
+        let include_outside_workspace = self
+            .settings_for(Some(file_path))
+            .include_files_outside_workspace;
+        load_related_files(
+            file_path,
+            lines,
+            &self.workspace_flags.get(Some(file_path)),
+            include_outside_workspace,
+            |path| self.read_file(path),
+        )
+    }
+
+    // Finds import cycles and duplicate imports starting at the given file
+    pub fn find_import_issues(&self, file_path: &Path, lines: &[Line]) -> Vec<ImportIssue> {
+        let include_outside_workspace = self
+            .settings_for(Some(file_path))
+            .include_files_outside_workspace;
+        find_import_issues(
+            file_path,
+            lines,
+            &self.workspace_flags.get(Some(file_path)),
+            include_outside_workspace,
+            |path| self.read_file(path),
+        )
+    }
+
+    // All paths transitively imported by the given file, including missing files
+    pub fn get_transitive_imports(&self, file_path: &Path, lines: &[Line]) -> HashSet<PathBuf> {
+        let include_outside_workspace = self
+            .settings_for(Some(file_path))
+            .include_files_outside_workspace;
+        get_transitive_imports(
+            file_path,
+            lines,
+            &self.workspace_flags.get(Some(file_path)),
+            include_outside_workspace,
+            |path| self.read_file(path),
+        )
+    }
+
+    // The bazelrc files of the given workspace. Only cached if the client reports changed
+    // files, otherwise the workspace is searched again on every call.
+    fn get_workspace_rc_files(&self, workspace: &Path) -> Arc<Vec<PathBuf>> {
+        if let Some(paths) = self.workspace_rc_files.get(workspace) {
+            return paths.clone();
+        }
+        let paths = Arc::new(find_workspace_rc_files(workspace, false));
+        if self.supports_file_watching() {
+            self.workspace_rc_files
+                .insert(workspace.to_path_buf(), paths.clone());
+        }
+        paths
+    }
+
+    // Loads all other bazelrc files of the workspace. Files which didn't change on disk
+    // since they were indexed aren't parsed again.
+    pub fn load_workspace_files(&self, file_path: &Path) -> Vec<RcFile> {
+        let _span =
+            tracing::debug_span!("load_workspace_files", file = %file_path.display()).entered();
+        let Some(workspace) = get_workspace_path(file_path) else {
+            return Vec::new();
+        };
+        self.load_all_workspace_files(&workspace, Some(file_path))
+    }
+
+    // All bazelrc files of the workspace, except for `excluded_file`
+    fn load_all_workspace_files(
+        &self,
+        workspace: &Path,
+        excluded_file: Option<&Path>,
+    ) -> Vec<RcFile> {
+        self.load_workspace_index(workspace);
+        let bazel_flags = self.workspace_flags.get(Some(workspace));
+        let paths = self
+            .get_workspace_rc_files(workspace)
+            .iter()
+            .filter(|path| Some(path.as_path()) != excluded_file)
+            .cloned()
+            .collect::<Vec<_>>();
+        map_in_parallel(
+            &paths,
+            |path| {
+                if let Some(doc) = Url::from_file_path(path)
+                    .ok()
+                    .and_then(|uri| self.document_map.get(uri.as_str()))
+                {
+                    return Some(RcFile {
+                        path: path.clone(),
+                        rope: doc.rope.clone(),
+                        lines: doc.indexed_lines.lines.clone(),
+                        read_only: false,
+                    });
+                }
+                let indexed_file = self.workspace_indexes.get(workspace)?.index_file(path)?;
+                Some(indexed_file.to_rc_file(path.clone(), &bazel_flags))
+            },
+            &|| {},
+        )
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    // The files searched for references from the given file: the file itself, followed
+    // by all files it imports and all bazelrc files of its workspace
+    pub fn load_reference_scope(&self, current_file: RcFile) -> Vec<RcFile> {
+        let mut files = self.load_related_files(&current_file.path, &current_file.lines);
+        for file in self.load_workspace_files(&current_file.path) {
+            if !files.iter().any(|f| f.path == file.path) {
+                files.push(file);
+            }
+        }
+        files.insert(0, current_file);
+        files
+    }
+
+    // The given file together with its related files, in the order in which Bazel reads
+    // them. Workspace files are taken from the workspace index instead of reading them again.
+    pub fn load_files_in_read_order(&self, current_file: RcFile) -> Vec<RcFile> {
+        let include_outside_workspace = self
+            .settings_for(Some(&current_file.path))
+            .include_files_outside_workspace;
+        let bazel_flags = self.workspace_flags.get(Some(&current_file.path));
+        let workspace_files = self.load_workspace_files(&current_file.path);
+        let workspace_files_by_path = workspace_files
+            .iter()
+            .map(|f| (f.path.as_path(), f))
+            .collect::<HashMap<_, _>>();
+        let mut files = load_related_rc_files(
+            &current_file.path,
+            &current_file.lines,
+            include_outside_workspace,
+            |path| match workspace_files_by_path.get(path) {
+                Some(loaded) => Some((*loaded).clone()),
+                None => {
+                    let contents = self.read_file(path)?;
+                    Some(RcFile::from_str(
+                        path.to_path_buf(),
+                        &contents,
+                        &bazel_flags,
+                    ))
+                }
+            },
+        );
+        let file_path = current_file.path.clone();
+        files.push(current_file);
+        sort_by_read_order(&file_path, &mut files);
+        files
+    }
+
+    // The node of a call hierarchy item, together with the files searched for its calls
+    fn load_call_hierarchy_item(
+        &self,
+        item: &CallHierarchyItem,
+    ) -> Option<(Arc<BazelFlags>, Vec<RcFile>, CallHierarchyNode)> {
+        let node = serde_json::from_value::<CallHierarchyNode>(item.data.clone()?).ok()?;
+        let file_path = item.uri.to_file_path().ok()?;
+        let bazel_flags = self.workspace_flags.get(Some(&file_path));
+        let contents = self.read_file(&file_path)?;
+        let current_file = RcFile::from_str(file_path, &contents, &bazel_flags);
+        Some((bazel_flags, self.load_reference_scope(current_file), node))
+    }
+
+    // Loads the bazelrc files of all known workspaces, the documents opened in the
+    // editor and all files imported by any of them
+    pub fn load_all_files(&self) -> Vec<RcFile> {
+        self.load_all_files_reporting(&|| {})
+    }
+
+    // Like `load_all_files`, but the files are read and parsed in parallel. Files which
+    // didn't change on disk are taken from the workspace's index instead of being parsed
+    // again. `on_loaded` is called from the worker threads for each bazelrc file of the
+    // workspaces.
+    fn load_all_files_reporting(&self, on_loaded: &(dyn Fn() + Sync)) -> Vec<RcFile> {
+        let open_files = self
+            .document_map
+            .iter()
+            .filter_map(|doc| {
+                let path = Url::parse(doc.key()).ok()?.to_file_path().ok()?;
+                Some(RcFile {
+                    path,
+                    rope: doc.rope.clone(),
+                    lines: doc.indexed_lines.lines.clone(),
+                    read_only: false,
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut workspaces = self.workspace_roots.read().unwrap().clone();
+        workspaces.extend(
+            open_files
+                .iter()
+                .filter_map(|f| get_workspace_path(&f.path)),
+        );
+
+        let mut seen = HashSet::<PathBuf>::new();
+        let mut files = Vec::<RcFile>::new();
+        for file in open_files {
+            if seen.insert(file.path.clone()) {
+                files.push(file);
+            }
+        }
+        // Documents opened in the editor are also indexed, such that their entry stays
+        // up to date for the next start of the language server
+        let mut indexed_paths = HashSet::<PathBuf>::new();
+        let mut paths = Vec::<(PathBuf, PathBuf)>::new();
+        for workspace in workspaces.iter().collect::<HashSet<_>>() {
+            self.load_workspace_index(workspace);
+            for path in self.get_workspace_rc_files(workspace).iter() {
+                if indexed_paths.insert(path.clone()) {
+                    paths.push((workspace.clone(), path.clone()));
+                }
+            }
+        }
+        let indexed_files = map_in_parallel(
+            &paths,
+            |(workspace, path)| {
+                let indexed_file = self.workspace_indexes.get(workspace)?.index_file(path)?;
+                let bazel_flags = self.workspace_flags.get(Some(path));
+                let file = indexed_file.to_rc_file(path.clone(), &bazel_flags);
+                Some((indexed_file, file))
+            },
+            on_loaded,
+        );
+        let mut updated_indexes = HashMap::<PathBuf, HashMap<PathBuf, IndexedFile>>::new();
+        for ((workspace, path), indexed) in paths.into_iter().zip(indexed_files) {
+            let Some((indexed_file, file)) = indexed else {
+                continue;
+            };
+            if seen.insert(path.clone()) {
+                files.push(file);
+            }
+            updated_indexes
+                .entry(workspace)
+                .or_default()
+                .insert(path, indexed_file);
+        }
+        for (workspace, indexed_files) in updated_indexes {
+            let changed = self
+                .workspace_indexes
+                .get_mut(&workspace)
+                .is_some_and(|mut index| index.update_files(indexed_files));
+            if changed {
+                self.save_workspace_index(&workspace);
+            }
+        }
+
+        // Merge the imports in the order of the importing files, such that the result
+        // doesn't depend on the scheduling of the threads. Files loaded above aren't
+        // parsed again.
+        let files_by_path = files
+            .iter()
+            .map(|f| (f.path.as_path(), f))
+            .collect::<HashMap<_, _>>();
+        let imports = map_in_parallel(
+            &files,
+            |file| {
+                let include_outside_workspace = self
+                    .settings_for(Some(&file.path))
+                    .include_files_outside_workspace;
+                let bazel_flags = self.workspace_flags.get(Some(&file.path));
+                load_imported_rc_files(&file.path, &file.lines, include_outside_workspace, |path| {
+                    match files_by_path.get(path) {
+                        Some(loaded) => Some((*loaded).clone()),
+                        None => {
+                            let contents = self.read_file(path)?;
+                            Some(RcFile::from_str(
+                                path.to_path_buf(),
+                                &contents,
+                                &bazel_flags,
+                            ))
+                        }
+                    }
+                })
+            },
+            &|| {},
+        );
+        drop(files_by_path);
+        for import in imports.into_iter().flatten() {
+            if seen.insert(import.path.clone()) {
+                files.push(import);
+            }
+        }
+        files
+    }
+
+    // Loads all files and publishes the new number of indexed files of each workspace
+    async fn index_all_files(&self, work_done_token: Option<ProgressToken>) -> Vec<RcFile> {
+        let files = self.load_all_files_with_progress(work_done_token).await;
+        for workspace in self.get_known_workspaces() {
+            self.publish_status(Some(&workspace)).await;
+        }
+        files
+    }
+
+    // Loads all files on a blocking thread. If the client passed a work done token,
+    // the number of loaded files is reported via `$/progress` in the meantime.
+    async fn load_all_files_with_progress(
+        &self,
+        work_done_token: Option<ProgressToken>,
+    ) -> Vec<RcFile> {
+        let Some(token) = work_done_token else {
+            return self.load_all_files();
+        };
+        let progress = WorkDoneReporter::begin(&self.client, token, "Indexing bazelrc files").await;
+        let loaded = Arc::new(AtomicUsize::new(0));
+        let mut task = {
+            let backend = self.clone();
+            let loaded = loaded.clone();
+            tokio::task::spawn_blocking(move || {
+                backend.load_all_files_reporting(&|| {
+                    loaded.fetch_add(1, Ordering::Relaxed);
+                })
+            })
+        };
+        let files = loop {
+            tokio::select! {
+                result = &mut task => {
+                    break result.unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+                }
+                _ = tokio::time::sleep(PROGRESS_INTERVAL) => {
+                    let count = loaded.load(Ordering::Relaxed);
+                    progress.report(format!("{count} files"), None).await;
+                }
+            }
+        };
+        progress.end(format!("Indexed {} files", files.len())).await;
+        files
+    }
+
+    // Reads a file, preferring the contents of documents opened in the editor
+    pub fn read_file(&self, path: &Path) -> Option<String> {
+        let uri = Url::from_file_path(path).ok()?;
+        match self.document_map.get(&uri.to_string()) {
+            Some(doc) => Some(doc.rope.to_string()),
+            None => read_file_from_disk(path),
+        }
+    }
 
-#[derive(Debug)]
-pub struct AnalyzedDocument {
-    rope: Rope,
-    semantic_tokens: Vec<RCSemanticToken>,
-    indexed_lines: IndexedLines,
-    parser_errors: Vec<chumsky::prelude::Simple<char>>,
-}
+    // `bazelrc.replaceFlagEverywhere` command: Replaces a flag in all bazelrc files of the workspace
+    async fn replace_flag_everywhere(&self, params: ReplaceFlagParams) -> Result<()> {
+        let encoding = self.position_encoding();
+        let workspace = params
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|p| get_workspace_path(&p))
+            .ok_or(Error::invalid_params("Unable to determine the workspace!"))?;
+        let edits_by_file = self
+            .get_workspace_rc_files(&workspace)
+            .iter()
+            .cloned()
+            .filter_map(|path| {
+                let contents = self.read_file(&path)?;
+                let bazel_flags = self.workspace_flags.get(Some(&path));
+                let file = RcFile::from_str(path, &contents, &bazel_flags);
+                let edits = get_flag_replacement_edits(&file.rope, &file.lines, &params, encoding);
+                Some((Url::from_file_path(&file.path).ok()?, edits))
+            })
+            .collect::<Vec<_>>();
+        let edit = make_replacement_workspace_edit(&params, edits_by_file);
+        let response = self.client.apply_edit(edit).await?;
+        if !response.applied {
+            let reason = response.failure_reason.unwrap_or_default();
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("Failed to replace `{}`: {reason}", params.flag),
+                )
+                .await;
+        }
+        Ok(())
+    }
 
-#[derive(Deserialize, Serialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct Settings {
-    #[serde(default)]
-    pub format_lines: FormatLineFlow,
-}
+    // `bazelrc.normalizeFlagValues` command: Rewrites all flags of the workspace's bazelrc
+    // files into `--flag=value` or `--flag value` form
+    async fn normalize_flag_values_everywhere(
+        &self,
+        params: NormalizeFlagValuesParams,
+    ) -> Result<()> {
+        let encoding = self.position_encoding();
+        let workspace = params
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|p| get_workspace_path(&p))
+            .ok_or(Error::invalid_params("Unable to determine the workspace!"))?;
+        let edits_by_file = self
+            .get_workspace_rc_files(&workspace)
+            .iter()
+            .cloned()
+            .filter_map(|path| {
+                let contents = self.read_file(&path)?;
+                let bazel_flags = self.workspace_flags.get(Some(&path));
+                let file = RcFile::from_str(path, &contents, &bazel_flags);
+                let edits = get_flag_value_style_edits(
+                    &bazel_flags,
+                    &file.rope,
+                    &file.lines,
+                    params.style,
+                    encoding,
+                );
+                Some((Url::from_file_path(&file.path).ok()?, edits))
+            })
+            .collect::<Vec<_>>();
+        let label = match params.style {
+            FlagValueStyle::Equals => "Write all flags as `--flag=value`",
+            FlagValueStyle::Space => "Write all flags as `--flag value`",
+        };
+        let edit = make_confirmed_workspace_edit(label.to_string(), edits_by_file);
+        let response = self.client.apply_edit(edit).await?;
+        if !response.applied {
+            let reason = response.failure_reason.unwrap_or_default();
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("Failed to rewrite the flags: {reason}"),
+                )
+                .await;
+        }
+        Ok(())
+    }
 
-#[derive(Debug)]
-pub struct Backend {
-    pub client: Client,
-    pub document_map: DashMap<String, AnalyzedDocument>,
-    pub bazel_flags: BazelFlags,
-    pub settings: std::sync::RwLock<Settings>,
-    // An optional message which should be displayed to the user on startup
-    pub startup_warning: Option<String>,
-}
+    // `bazelrc.canonicalizeFlags` command: Rewrites the flags of the selected lines into
+    // their canonical form. Uses `bazel canonicalize-flags` if a Bazel binary is configured
+    // for the workspace, and the flag database otherwise.
+    async fn canonicalize_flags(&self, params: CanonicalizeFlagsParams) -> Result<()> {
+        let encoding = self.position_encoding();
+        let file_path = params
+            .uri
+            .to_file_path()
+            .map_err(|_| Error::invalid_params("Unsupported URI scheme!"))?;
+        let (rope, lines, bazel_flags) = {
+            let doc = self
+                .document_map
+                .get(&params.uri.to_string())
+                .ok_or(Error::invalid_params("Unknown document!"))?;
+            let range = lsp_pos_to_offset(&doc.rope, &params.range.start, encoding)
+                .zip(lsp_pos_to_offset(&doc.rope, &params.range.end, encoding))
+                .ok_or(Error::invalid_params("Range out of bounds"))?;
+            let lines = get_lines_in_range(&doc.indexed_lines.lines, &(range.0..range.1))
+                .iter()
+                .filter(|l| {
+                    l.command
+                        .as_ref()
+                        .is_some_and(|c| c.0 != "import" && c.0 != "try-import")
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            (doc.rope.clone(), lines, doc.bazel_flags.clone())
+        };
+        let workspace = get_workspace_path(&file_path);
+        let bazel_command = workspace.as_deref().and_then(|w| self.get_bazel_path(w));
 
-impl Backend {
-    async fn on_change(&self, params: TextDocumentItem) {
+        let mut canonical = lines
+            .iter()
+            .map(|line| canonicalize_line_flags(&bazel_flags, line))
+            .collect::<Vec<_>>();
+        if let (Some(bazel_command), Some(workspace)) = (bazel_command, workspace) {
+            // The lines Bazel can canonicalize, as pairs of command and flag arguments
+            let (line_nrs, bazel_lines): (Vec<_>, Vec<_>) = lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| supports_bazel_canonicalization(line))
+                .map(|(i, line)| {
+                    let command = line
+                        .command
+                        .as_ref()
+                        .map(|c| c.0.clone())
+                        .unwrap_or_default();
+                    let flags = line
+                        .flags
+                        .iter()
+                        .flat_map(get_flag_args)
+                        .collect::<Vec<_>>();
+                    (i, (command, flags))
+                })
+                .unzip();
+            if !bazel_lines.is_empty() {
+                let result = tokio::task::spawn_blocking(move || {
+                    canonicalize_lines_with_bazel(&bazel_command, &workspace, &bazel_lines)
+                })
+                .await
+                .unwrap_or_else(|err| Err(err.to_string()));
+                match result {
+                    Ok(results) => {
+                        for (i, flags) in line_nrs.into_iter().zip(results) {
+                            canonical[i] = flags;
+                        }
+                    }
+                    Err(msg) => {
+                        self.client
+                            .show_message(
+                                MessageType::WARNING,
+                                format!(
+                                    "Using the flag database to canonicalize the flags:\n{msg}"
+                                ),
+                            )
+                            .await;
+                    }
+                }
+            }
+        }
+        let edits = lines
+            .iter()
+            .zip(&canonical)
+            .filter_map(|(line, canonical)| get_canonicalize_edit(&rope, line, canonical, encoding))
+            .collect::<Vec<_>>();
+        if edits.is_empty() {
+            return Ok(());
+        }
+        let response = self
+            .client
+            .apply_edit(make_workspace_edit(&params.uri, edits))
+            .await?;
+        if !response.applied {
+            let reason = response.failure_reason.unwrap_or_default();
+            self.client
+                .show_message(
+                    MessageType::WARNING,
+                    format!("Failed to canonicalize the flags: {reason}"),
+                )
+                .await;
+        }
+        Ok(())
+    }
+
+    // `$/setTrace` notification: Enables or disables forwarding the log to the client
+    pub async fn set_trace(&self, params: SetTraceParams) {
+        self.update_trace(params.value);
+    }
+
+    fn update_trace(&self, trace: TraceValue) {
+        if let Some(log_level) = &self.log_level {
+            log_level.set_verbose(trace == TraceValue::Verbose);
+        }
+        *self.trace.write().unwrap() = trace;
+    }
+
+    // Custom `bazelrc/configTree` request: The flags of the given document and all its
+    // imports, grouped by file, command and config.
+    pub async fn config_tree(&self, params: ConfigTreeParams) -> Result<Vec<ConfigTreeFile>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document.uri;
+        let file_path = uri
+            .to_file_path()
+            .ok()
+            .ok_or(Error::invalid_params("Unsupported URI scheme!"))?;
+        let root = {
+            let doc = self
+                .document_map
+                .get(&uri.to_string())
+                .ok_or(Error::invalid_params("Unknown document!"))?;
+            RcFile {
+                path: file_path,
+                rope: doc.rope.clone(),
+                lines: doc.indexed_lines.lines.clone(),
+                read_only: false,
+            }
+        };
+        let imports = self.load_imported_files(&root.path, &root.lines);
+        let mut files = vec![root];
+        files.extend(imports);
+        Ok(build_config_tree(&files, encoding))
+    }
+
+    // Re-analyzes the document and schedules the computation of its diagnostics.
+    // The document is updated immediately, such that hover and completion requests
+    // never wait for the diagnostics.
+    async fn on_change(&self, params: TextDocumentItem, debounce: Duration) {
         let rope = ropey::Rope::from_str(&params.text);
         let src = rope.to_string();
 
         let file_path_buf = params.uri.to_file_path().ok();
         let file_path = file_path_buf.as_deref();
+        let bazel_flags = self.workspace_flags.get(file_path);
 
-        let ParserResult {
-            tokens: _,
-            mut lines,
-            errors,
-        } = parse_from_str(&src);
-        combine_key_value_flags(&mut lines, &self.bazel_flags);
-        let semantic_tokens = semantic_tokens_from_lines(&lines);
-        let indexed_lines = IndexedLines::from_lines(lines);
-
-        let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
-        diagnostics.extend(diagnostics_from_parser(&rope, &errors));
-        diagnostics.extend(diagnostics_from_rcconfig(
-            &rope,
-            &indexed_lines.lines,
-            &self.bazel_flags,
-            file_path,
-        ));
+        let (lines, errors) = match params.lines {
+            Some(lines) => (lines, Vec::new()),
+            None => {
+                let _span = tracing::debug_span!("parse", uri = %params.uri).entered();
+                let ParserResult {
+                    tokens: _,
+                    mut lines,
+                    errors,
+                } = parse_from_str(&src);
+                combine_key_value_flags(&mut lines, &bazel_flags);
+                (lines, errors)
+            }
+        };
+        let (semantic_tokens, indexed_lines) = {
+            let _span = tracing::debug_span!("index", uri = %params.uri).entered();
+            (
+                semantic_tokens_from_lines(&lines),
+                IndexedLines::from_lines(lines),
+            )
+        };
 
         self.document_map.insert(
             params.uri.to_string(),
-            AnalyzedDocument {
+            Arc::new(AnalyzedDocument {
                 rope,
+                version: params.version,
+                bazel_flags,
                 parser_errors: errors,
                 semantic_tokens,
                 indexed_lines,
-            },
+            }),
         );
 
+        self.schedule_diagnostics(&params.uri, debounce);
+
+        let workspace = file_path.and_then(get_workspace_path);
+        if let Some(message) = workspace
+            .as_deref()
+            .and_then(|workspace| self.workspace_flags.take_message(workspace))
+        {
+            self.client.show_message(MessageType::INFO, message).await;
+        }
+        self.publish_status(workspace.as_deref()).await;
+    }
+
+    // Diagnostics are computed in the background. Newer changes cancel older runs.
+    // Clients pulling the diagnostics request them on their own.
+    fn schedule_diagnostics(&self, uri: &Url, debounce: Duration) {
+        let generation = {
+            let mut generation = self
+                .diagnostics_generations
+                .entry(uri.to_string())
+                .or_insert(0);
+            *generation += 1;
+            *generation
+        };
+        self.diagnostics_revision.fetch_add(1, Ordering::Relaxed);
+        if !self.supports_pull_diagnostics() {
+            let backend = self.clone();
+            let uri = uri.clone();
+            tokio::spawn(
+                async move { backend.publish_diagnostics(uri, generation, debounce).await },
+            );
+        }
+    }
+
+    // Stores the contents of a `.bazeliskrc` document and re-computes its diagnostics
+    fn on_bazeliskrc_change(&self, uri: &Url, rope: Rope, version: i32, debounce: Duration) {
+        self.bazeliskrc_documents
+            .insert(uri.to_string(), (rope, version));
+        self.schedule_diagnostics(uri, debounce);
+    }
+
+    // Looks up an open bazelrc document. Requests which are not supported for
+    // `.bazeliskrc` documents return no result for them. The document is returned
+    // without holding on to the map's lock, so it can be used while loading other files.
+    fn get_document(&self, uri: &str) -> Result<Option<Arc<AnalyzedDocument>>> {
+        match self.document_map.get(uri) {
+            Some(doc) => Ok(Some(doc.clone())),
+            None if self.bazeliskrc_documents.contains_key(uri) => Ok(None),
+            None => Err(Error::invalid_params("Unknown document!")),
+        }
+    }
+
+    // Does the client report changes to files matching dynamically registered globs?
+    fn supports_file_watching(&self) -> bool {
+        self.client_capabilities
+            .read()
+            .unwrap()
+            .workspace
+            .as_ref()
+            .and_then(|w| w.did_change_watched_files.as_ref())
+            .and_then(|d| d.dynamic_registration)
+            .unwrap_or(false)
+    }
+
+    // Is the given diagnostics run still the latest one for the document?
+    fn is_current_diagnostics_run(&self, uri: &Url, generation: u64) -> bool {
+        self.diagnostics_generations
+            .get(uri.as_str())
+            .is_some_and(|g| *g == generation)
+    }
+
+    // Did the client announce support for `textDocument/diagnostic`?
+    fn supports_pull_diagnostics(&self) -> bool {
+        self.client_capabilities
+            .read()
+            .unwrap()
+            .text_document
+            .as_ref()
+            .is_some_and(|t| t.diagnostic.is_some())
+    }
+
+    // Asks clients pulling the diagnostics to pull them again, e.g. after the settings changed
+    async fn refresh_diagnostics(&self) {
+        self.diagnostics_revision.fetch_add(1, Ordering::Relaxed);
+        self.settings_revision.fetch_add(1, Ordering::Relaxed);
+        let supports_refresh = self
+            .client_capabilities
+            .read()
+            .unwrap()
+            .workspace
+            .as_ref()
+            .and_then(|w| w.diagnostic.as_ref())
+            .and_then(|d| d.refresh_support)
+            .unwrap_or(false);
+        if supports_refresh && self.supports_pull_diagnostics() {
+            let _ = self.client.workspace_diagnostic_refresh().await;
+        }
+    }
+
+    async fn publish_diagnostics(&self, uri: Url, generation: u64, debounce: Duration) {
+        // Wait for further changes while the user is typing
+        if !debounce.is_zero() {
+            tokio::time::sleep(debounce).await;
+        }
+        if !self.is_current_diagnostics_run(&uri, generation) {
+            return;
+        }
+        let Some((diagnostics, version)) = self.compute_diagnostics(&uri, Some(generation)).await
+        else {
+            return;
+        };
         self.client
-            .publish_diagnostics(params.uri.clone(), diagnostics, Some(params.version))
+            .publish_diagnostics(uri, diagnostics, Some(version))
             .await;
     }
+
+    // Computes the diagnostics for an open document, together with the document version
+    // they belong to. Returns `None` if the given diagnostics run got cancelled.
+    async fn compute_diagnostics(
+        &self,
+        uri: &Url,
+        generation: Option<u64>,
+    ) -> Option<(Vec<Diagnostic>, i32)> {
+        let encoding = self.position_encoding();
+        let is_cancelled = || generation.is_some_and(|g| !self.is_current_diagnostics_run(uri, g));
+        if let Some(doc) = self.bazeliskrc_documents.get(uri.as_str()) {
+            let (rope, version) = doc.value();
+            let parsed = parse_bazeliskrc(&rope.to_string());
+            let workspace = uri
+                .to_file_path()
+                .ok()
+                .and_then(|p| p.parent().map(Path::to_path_buf));
+            let diagnostics =
+                diagnostics_from_bazeliskrc(rope, &parsed, workspace.as_deref(), encoding);
+            return Some((diagnostics, *version));
+        }
+        let (rope, lines, errors, bazel_flags, version) =
+            self.document_map.get(uri.as_str()).map(|doc| {
+                (
+                    doc.rope.clone(),
+                    doc.indexed_lines.lines.clone(),
+                    doc.parser_errors.clone(),
+                    doc.bazel_flags.clone(),
+                    doc.version,
+                )
+            })?;
+        let file_path = uri.to_file_path().ok();
+        let diagnostics = self
+            .compute_file_diagnostics(
+                file_path.as_deref(),
+                rope,
+                lines,
+                &errors,
+                &bazel_flags,
+                None,
+                &is_cancelled,
+            )
+            .await?;
+        Some((diagnostics, version))
+    }
+
+    // Computes the diagnostics for the contents of a file, which is not necessarily
+    // opened in the editor. `workspace_files` are all bazelrc files of the file's workspace,
+    // if already loaded. Returns `None` once `is_cancelled` returns true.
+    #[allow(clippy::too_many_arguments)]
+    async fn compute_file_diagnostics(
+        &self,
+        file_path: Option<&Path>,
+        rope: Rope,
+        lines: Vec<Line>,
+        errors: &[chumsky::prelude::Simple<char>],
+        bazel_flags: &BazelFlags,
+        workspace_files: Option<&[RcFile]>,
+        is_cancelled: &(dyn Fn() -> bool + Send + Sync),
+    ) -> Option<Vec<Diagnostic>> {
+        let encoding = self.position_encoding();
+        let settings = self.settings_for(file_path);
+        let related_files = file_path
+            .map(|p| self.load_related_files(p, &lines))
+            .unwrap_or_default();
+        let flag_aliases = get_flag_aliases(
+            lines
+                .iter()
+                .chain(related_files.iter().flat_map(|f| &f.lines)),
+            bazel_flags,
+        );
+
+        let mut diagnostics: Vec<Diagnostic> = Vec::<Diagnostic>::new();
+        diagnostics.extend(diagnostics_from_parser(&rope, errors, encoding));
+        diagnostics.extend(diagnostics_from_rcconfig(
+            &rope,
+            &lines,
+            bazel_flags,
+            &flag_aliases,
+            file_path,
+            encoding,
+        ));
+        diagnostics.extend(diagnostics_for_default_values(
+            &rope,
+            &lines,
+            bazel_flags,
+            file_path,
+            &related_files,
+            encoding,
+        ));
+        diagnostics.extend(diagnostics_for_platform_configs(
+            &rope,
+            &lines,
+            bazel_flags,
+            is_platform_specific_config_enabled(
+                lines
+                    .iter()
+                    .chain(related_files.iter().flat_map(|f| &f.lines)),
+                bazel_flags,
+            ),
+            encoding,
+        ));
+        diagnostics.extend(diagnostics_for_config_nesting(
+            &rope,
+            &lines,
+            bazel_flags,
+            &related_files,
+            encoding,
+        ));
+        if settings.diagnose_unstable_flags {
+            diagnostics.extend(diagnostics_for_unstable_flags(
+                &rope,
+                &lines,
+                bazel_flags,
+                &settings.allowed_unstable_flags,
+                encoding,
+            ));
+        }
+        if let Some(file_path) = file_path {
+            let known_configs = get_defined_configs(
+                lines
+                    .iter()
+                    .chain(related_files.iter().flat_map(|f| &f.lines)),
+            );
+            let import_issues = self.find_import_issues(file_path, &lines);
+            diagnostics.extend(diagnostics_for_import_issues(
+                &rope,
+                &lines,
+                file_path,
+                &import_issues,
+                encoding,
+            ));
+            let workspace_files = match workspace_files {
+                Some(files) => files
+                    .iter()
+                    .filter(|f| f.path != file_path)
+                    .cloned()
+                    .collect(),
+                None => self.load_workspace_files(file_path),
+            };
+            diagnostics.extend(diagnostics_for_unknown_configs(
+                &rope,
+                &lines,
+                bazel_flags,
+                &known_configs,
+                &workspace_files,
+                encoding,
+            ));
+            if get_workspace_path(file_path).is_some() {
+                diagnostics.extend(diagnostics_for_unused_configs(
+                    &rope,
+                    &lines,
+                    bazel_flags,
+                    &workspace_files,
+                    encoding,
+                ));
+            }
+            let mut files = vec![RcFile {
+                path: file_path.to_path_buf(),
+                rope: rope.clone(),
+                lines: lines.clone(),
+                read_only: false,
+            }];
+            files.extend(related_files);
+            sort_by_read_order(file_path, &mut files);
+            diagnostics.extend(diagnostics_for_conflicting_flags(
+                file_path,
+                bazel_flags,
+                &files,
+                encoding,
+            ));
+            diagnostics.extend(diagnostics_for_dead_assignments(
+                file_path,
+                bazel_flags,
+                &files,
+                encoding,
+            ));
+            // The remaining checks are slow. Skip them if the document changed in between.
+            if is_cancelled() {
+                return None;
+            }
+            if settings.check_flag_paths {
+                let paths = get_flag_paths(&lines, bazel_flags, file_path)
+                    .map(|(_, path, _)| path)
+                    .collect::<Vec<_>>();
+                let path_statuses = self.check_flag_paths(paths).await;
+                diagnostics.extend(diagnostics_for_flag_paths(
+                    &rope,
+                    &lines,
+                    bazel_flags,
+                    file_path,
+                    &path_statuses,
+                    encoding,
+                ));
+            }
+            if let Some(workspace) =
+                get_workspace_path(file_path).filter(|_| settings.validate_starlark_flags)
+            {
+                let existing_labels = self.check_starlark_flags(&workspace, &lines);
+                diagnostics.extend(diagnostics_for_missing_starlark_flags(
+                    &rope,
+                    &lines,
+                    &existing_labels,
+                    encoding,
+                ));
+            }
+        }
+
+        if is_cancelled() {
+            return None;
+        }
+        let mut diagnostics = filter_suppressed_diagnostics(&rope, &lines, diagnostics, encoding);
+        diagnostics.extend(diagnostics_for_suppressions(&rope, &lines, encoding));
+        Some(apply_severity_overrides(
+            diagnostics,
+            &settings.diagnostic_severities,
+        ))
+    }
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> Result<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> Result<InitializeResult> {
+        // Positions use the client's preferred encoding, e.g. UTF-8 for clients which
+        // don't want to convert their offsets to UTF-16
+        let position_encoding = negotiate_position_encoding(
+            params
+                .capabilities
+                .general
+                .as_ref()
+                .and_then(|general| general.position_encodings.as_deref()),
+        );
+        *self.position_encoding.write().unwrap() = position_encoding;
+        *self.client_capabilities.write().unwrap() = params.capabilities;
+        if let Some(trace) = params.trace {
+            self.update_trace(trace);
+        }
+        #[allow(deprecated)]
+        let workspace_roots = match params.workspace_folders {
+            Some(folders) => folders
+                .iter()
+                .filter_map(|f| f.uri.to_file_path().ok())
+                .collect::<Vec<_>>(),
+            None => params
+                .root_uri
+                .and_then(|uri| uri.to_file_path().ok())
+                .into_iter()
+                .collect::<Vec<_>>(),
+        };
+        *self.workspace_roots.write().unwrap() = workspace_roots;
+        // Clients can pass the initial settings as initialization options, either
+        // directly or nested under `bazelrc`, as for `workspace/didChangeConfiguration`
+        if let Some(mut options) = params.initialization_options {
+            let options = options
+                .as_object_mut()
+                .and_then(|o| o.remove("bazelrc"))
+                .unwrap_or(options);
+            match serde_json::from_value::<Settings>(options) {
+                Ok(settings) => *self.settings.write().unwrap() = Arc::new(settings),
+                Err(err) => {
+                    self.client
+                        .log_message(
+                            MessageType::ERROR,
+                            format!("Invalid initialization options: {}", err),
+                        )
+                        .await;
+                }
+            }
+        }
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
                 name: "bazelrc Language Server".to_string(),
@@ -100,8 +2007,9 @@ impl LanguageServer for Backend {
             }),
             offset_encoding: None,
             capabilities: ServerCapabilities {
+                position_encoding: Some(position_encoding.to_lsp()),
                 text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                    TextDocumentSyncKind::INCREMENTAL,
                 )),
                 semantic_tokens_provider: Some(
                     SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
@@ -122,24 +2030,82 @@ impl LanguageServer for Backend {
                                     token_modifiers: vec![],
                                 },
                                 range: None,
-                                full: Some(SemanticTokensFullOptions::Bool(true)),
+                                full: Some(SemanticTokensFullOptions::Delta { delta: Some(true) }),
                             },
                             static_registration_options: StaticRegistrationOptions::default(),
                         },
                     ),
                 ),
                 completion_provider: Some(CompletionOptions {
-                    trigger_characters: Some(vec!["-".to_string()]),
+                    trigger_characters: Some(vec!["-".to_string(), ":".to_string()]),
                     ..Default::default()
                 }),
                 hover_provider: Some(HoverProviderCapability::Simple(true)),
+                // Diagnostics depend on imported files and configs defined in other files
+                diagnostic_provider: Some(DiagnosticServerCapabilities::Options(
+                    DiagnosticOptions {
+                        identifier: Some("bazelrc".to_string()),
+                        inter_file_dependencies: true,
+                        workspace_diagnostics: true,
+                        work_done_progress_options: Default::default(),
+                    },
+                )),
+                signature_help_provider: Some(SignatureHelpOptions {
+                    trigger_characters: Some(vec!["=".to_string(), " ".to_string()]),
+                    retrigger_characters: None,
+                    work_done_progress_options: Default::default(),
+                }),
                 document_formatting_provider: Some(OneOf::Left(true)),
                 document_range_formatting_provider: Some(OneOf::Left(true)),
+                document_on_type_formatting_provider: Some(DocumentOnTypeFormattingOptions {
+                    first_trigger_character: "\n".to_string(),
+                    more_trigger_character: None,
+                }),
                 document_link_provider: Some(DocumentLinkOptions {
-                    resolve_provider: None,
+                    resolve_provider: Some(true),
                     work_done_progress_options: Default::default(),
                 }),
                 definition_provider: Some(OneOf::Left(true)),
+                document_symbol_provider: Some(OneOf::Left(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
+                inlay_hint_provider: Some(OneOf::Left(true)),
+                folding_range_provider: Some(FoldingRangeProviderCapability::Simple(true)),
+                workspace_symbol_provider: Some(OneOf::Left(true)),
+                references_provider: Some(OneOf::Left(true)),
+                rename_provider: Some(OneOf::Right(RenameOptions {
+                    prepare_provider: Some(true),
+                    work_done_progress_options: Default::default(),
+                })),
+                call_hierarchy_provider: Some(CallHierarchyServerCapability::Simple(true)),
+                linked_editing_range_provider: Some(LinkedEditingRangeServerCapabilities::Simple(
+                    true,
+                )),
+                execute_command_provider: Some(ExecuteCommandOptions {
+                    commands: vec![
+                        REPLACE_FLAG_COMMAND.to_string(),
+                        NORMALIZE_FLAG_VALUES_COMMAND.to_string(),
+                        CANONICALIZE_FLAGS_COMMAND.to_string(),
+                        RUN_WITH_CONFIG_COMMAND.to_string(),
+                        SHOW_EFFECTIVE_CONFIG_COMMAND.to_string(),
+                    ],
+                    work_done_progress_options: Default::default(),
+                }),
+                code_action_provider: Some(CodeActionProviderCapability::Options(
+                    CodeActionOptions {
+                        code_action_kinds: Some(vec![
+                            CodeActionKind::QUICKFIX,
+                            CodeActionKind::REFACTOR_REWRITE,
+                            CodeActionKind::new(REWRITE_BOOLEAN_KIND),
+                            CodeActionKind::new(REWRITE_FLAG_VALUE_KIND),
+                            CodeActionKind::new(REWRITE_LINE_CONTINUATIONS_KIND),
+                            CodeActionKind::new(ORGANIZE_LINES_KIND),
+                        ]),
+                        resolve_provider: Some(true),
+                        work_done_progress_options: Default::default(),
+                    },
+                )),
                 ..ServerCapabilities::default()
             },
         })
@@ -150,11 +2116,133 @@ impl LanguageServer for Backend {
             .log_message(MessageType::INFO, "server initialized!")
             .await;
 
+        self.update_folder_settings().await;
+
+        if let Some(mut log_traces) = self.log_traces.lock().unwrap().take() {
+            let backend = self.clone();
+            tokio::spawn(async move {
+                while let Some(mut params) = log_traces.recv().await {
+                    let trace = *backend.trace.read().unwrap();
+                    if trace != TraceValue::Verbose {
+                        params.verbose = None;
+                    }
+                    if trace != TraceValue::Off {
+                        backend
+                            .client
+                            .send_notification::<notification::LogTrace>(params)
+                            .await;
+                    }
+                }
+            });
+        }
+
         if let Some(warning) = &self.startup_warning {
             self.client
                 .show_message(MessageType::WARNING, warning)
                 .await;
         }
+
+        // Watch the files determining the Bazel version, to re-resolve it on changes, and
+        // the bazelrc files, to re-analyze the documents importing them
+        if self.supports_file_watching() {
+            let watchers = VERSION_FILE_NAMES
+                .iter()
+                .chain(&["*.bazelrc", ".bazelrc"])
+                .chain(&WRAPPER_FILE_NAMES)
+                .map(|name| FileSystemWatcher {
+                    glob_pattern: GlobPattern::String(format!("**/{name}")),
+                    kind: None,
+                })
+                .collect::<Vec<_>>();
+            let registration = Registration {
+                id: "bazel-files".to_string(),
+                method: "workspace/didChangeWatchedFiles".to_string(),
+                register_options: serde_json::to_value(DidChangeWatchedFilesRegistrationOptions {
+                    watchers,
+                })
+                .ok(),
+            };
+            if let Err(err) = self.client.register_capability(vec![registration]).await {
+                self.client
+                    .log_message(
+                        MessageType::WARNING,
+                        format!("Failed to watch the Bazel files: {err}"),
+                    )
+                    .await;
+            }
+        }
+        self.watch_custom_flags_files().await;
+    }
+
+    async fn did_change_watched_files(&self, params: DidChangeWatchedFilesParams) {
+        let changed_paths = params
+            .changes
+            .iter()
+            .filter_map(|change| change.uri.to_file_path().ok())
+            .collect::<Vec<_>>();
+
+        // Bazelrc files might have been created or deleted, and wrapper scripts might
+        // pass other `--bazelrc` files to Bazel
+        for path in &changed_paths {
+            if is_bazelrc_file(path) || WRAPPER_FILE_NAMES.iter().any(|name| path.ends_with(name)) {
+                if let Some(workspace) = get_workspace_path(path) {
+                    self.workspace_rc_files.remove(&workspace);
+                }
+            }
+        }
+
+        // A bazelrc file changed on disk, e.g. an imported file edited in another editor.
+        // The contents of open documents are tracked via `didChange` instead.
+        let changed_bazelrc_files = changed_paths
+            .iter()
+            .filter(|path| is_bazelrc_file(path))
+            .filter(|path| {
+                Url::from_file_path(path)
+                    .is_ok_and(|uri| !self.document_map.contains_key(uri.as_str()))
+            })
+            .cloned()
+            .collect::<HashSet<_>>();
+        if !changed_bazelrc_files.is_empty() {
+            self.reanalyze_dependent_documents(&changed_bazelrc_files)
+                .await;
+        }
+
+        // A `.bazelversion` or `.bazeliskrc` changed. Re-analyze the open documents
+        // of workspaces which are now using a different Bazel version.
+        let workspaces = changed_paths
+            .iter()
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| VERSION_FILE_NAMES.contains(&name))
+            })
+            .filter_map(|path| get_workspace_path(path))
+            .collect::<HashSet<_>>();
+        let changed_workspaces = workspaces
+            .iter()
+            .filter(|workspace| self.workspace_flags.refresh(workspace))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.reanalyze_workspaces(&changed_workspaces).await;
+        // Bazelisk now might run a different Bazel binary. Reload the live flags.
+        for workspace in workspaces {
+            if self.workspace_flags.forget_live_flags(&workspace) {
+                self.spawn_live_flags_loading(workspace);
+            }
+        }
+
+        // A custom flags file changed. Reload it for the workspaces using it.
+        let mut reloaded_workspaces = Vec::new();
+        for workspace in self.get_known_workspaces() {
+            let settings = self.settings_for(Some(&workspace));
+            let is_changed = settings
+                .custom_flags_path(&workspace)
+                .is_some_and(|path| changed_paths.contains(&path));
+            if is_changed && self.apply_workspace_settings(&workspace, None).await {
+                reloaded_workspaces.push(workspace);
+            }
+        }
+        self.reanalyze_workspaces(&reloaded_workspaces).await;
     }
 
     async fn shutdown(&self) -> Result<()> {
@@ -170,7 +2258,42 @@ impl LanguageServer for Backend {
             return;
         };
         match serde_json::from_value::<Settings>(bazelrc_settings) {
-            Ok(new_settings) => *self.settings.write().unwrap() = new_settings,
+            Ok(new_settings) => {
+                let workspaces = self.get_known_workspaces();
+                let previous_settings = workspaces
+                    .iter()
+                    .map(|workspace| self.settings_for(Some(workspace)))
+                    .collect::<Vec<_>>();
+                *self.settings.write().unwrap() = Arc::new(new_settings);
+                self.update_folder_settings().await;
+                self.watch_custom_flags_files().await;
+                // Give build settings added since the last query another chance
+                self.starlark_flag_index.clear();
+                // The Bazel version or binary might have changed
+                for (workspace, previous) in workspaces.iter().zip(&previous_settings) {
+                    self.apply_workspace_settings(workspace, Some(previous))
+                        .await;
+                }
+                // Loading the flags from Bazel might have been enabled
+                for workspace in self.get_open_workspaces() {
+                    self.spawn_live_flags_loading(workspace);
+                }
+                // The diagnostics depend on the settings
+                self.reanalyze_documents(|_| true).await;
+                // The inlay hints depend on the settings
+                let supports_refresh = self
+                    .client_capabilities
+                    .read()
+                    .unwrap()
+                    .workspace
+                    .as_ref()
+                    .and_then(|w| w.inlay_hint.as_ref())
+                    .and_then(|i| i.refresh_support)
+                    .unwrap_or(false);
+                if supports_refresh {
+                    let _ = self.client.inlay_hint_refresh().await;
+                }
+            }
             Err(err) => {
                 self.client
                     .show_message(MessageType::ERROR, format!("Invalid settings: {}", err))
@@ -182,93 +2305,570 @@ impl LanguageServer for Backend {
         }
     }
 
-    async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: params.text_document.text,
-            version: params.text_document.version,
-        })
-        .await
+    async fn did_open(&self, params: DidOpenTextDocumentParams) {
+        let uri = &params.text_document.uri;
+        if uri
+            .to_file_path()
+            .is_ok_and(|path| is_bazeliskrc_file(&path))
+        {
+            let rope = Rope::from_str(&params.text_document.text);
+            self.on_bazeliskrc_change(uri, rope, params.text_document.version, Duration::ZERO);
+            return;
+        }
+        let workspace = params
+            .text_document
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|p| get_workspace_path(&p));
+        if let Some(workspace) = &workspace {
+            self.apply_workspace_settings(workspace, None).await;
+        }
+        self.on_change(
+            TextDocumentItem {
+                uri: params.text_document.uri,
+                text: params.text_document.text,
+                version: params.text_document.version,
+                lines: None,
+            },
+            Duration::ZERO,
+        )
+        .await;
+        // The document is first analyzed using the packaged flags
+        if let Some(workspace) = workspace {
+            self.spawn_live_flags_loading(workspace);
+        }
+    }
+
+    async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        let encoding = self.position_encoding();
+        let uri = params.text_document.uri;
+        if let Some(mut rope) = self
+            .bazeliskrc_documents
+            .get(uri.as_str())
+            .map(|d| d.0.clone())
+        {
+            for change in params.content_changes {
+                apply_content_change(&mut rope, &change, encoding);
+            }
+            self.on_bazeliskrc_change(
+                &uri,
+                rope,
+                params.text_document.version,
+                DIAGNOSTICS_DEBOUNCE,
+            );
+            return;
+        }
+        let (mut rope, bazel_flags, mut lines) = match self.document_map.get(&uri.to_string()) {
+            Some(doc) => {
+                // Lines with parser errors are re-parsed completely, to report all errors
+                let lines = doc
+                    .parser_errors
+                    .is_empty()
+                    .then(|| doc.indexed_lines.lines.clone());
+                (doc.rope.clone(), doc.bazel_flags.clone(), lines)
+            }
+            None => (Rope::new(), self.workspace_flags.default_flags(), None),
+        };
+        // Only re-parse the lines affected by the changes
+        for change in params.content_changes {
+            let Some(edit) = apply_content_change(&mut rope, &change, encoding) else {
+                lines = None;
+                continue;
+            };
+            if let Some(current_lines) = &mut lines {
+                let inserted_chars = change.text.chars().count();
+                match reparse_lines(current_lines, &rope, &edit, inserted_chars) {
+                    Some(reparsed) => {
+                        combine_key_value_flags(&mut current_lines[reparsed], &bazel_flags)
+                    }
+                    None => lines = None,
+                }
+            }
+        }
+        // The lines were combined using the previous flags, which might have changed since
+        let file_path = uri.to_file_path().ok();
+        if !Arc::ptr_eq(
+            &bazel_flags,
+            &self.workspace_flags.get(file_path.as_deref()),
+        ) {
+            lines = None;
+        }
+        self.on_change(
+            TextDocumentItem {
+                uri,
+                text: rope.to_string(),
+                version: params.text_document.version,
+                lines,
+            },
+            DIAGNOSTICS_DEBOUNCE,
+        )
+        .await
+    }
+
+    async fn did_close(&self, params: DidCloseTextDocumentParams) {
+        self.document_map
+            .remove(&params.text_document.uri.to_string());
+        self.bazeliskrc_documents
+            .remove(&params.text_document.uri.to_string());
+        // Cancel pending diagnostics runs
+        self.diagnostics_generations
+            .remove(&params.text_document.uri.to_string());
+        self.semantic_token_results
+            .remove(&params.text_document.uri.to_string());
+        self.diagnostic_results
+            .remove(&params.text_document.uri.to_string());
+        let workspace = params
+            .text_document
+            .uri
+            .to_file_path()
+            .ok()
+            .and_then(|path| get_workspace_path(&path));
+        self.publish_status(workspace.as_deref()).await;
+    }
+
+    async fn diagnostic(
+        &self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReportResult> {
+        let uri = params.text_document.uri;
+        let revision = self.diagnostics_revision.load(Ordering::Relaxed);
+        // Nothing changed since the diagnostics were last computed
+        let cached_result_id = self.get_cached_diagnostics_result_id(uri.as_str(), revision);
+        if let Some(result_id) =
+            cached_result_id.filter(|id| params.previous_result_id.as_ref() == Some(id))
+        {
+            return Ok(unchanged_diagnostic_report(result_id));
+        }
+        let (diagnostics, _) = self
+            .compute_diagnostics(&uri, None)
+            .await
+            .ok_or(Error::invalid_params("Unknown document!"))?;
+        let result_id = self.store_diagnostics(uri.to_string(), revision, &diagnostics);
+        if params.previous_result_id.as_ref() == Some(&result_id) {
+            return Ok(unchanged_diagnostic_report(result_id));
+        }
+        Ok(DocumentDiagnosticReportResult::Report(
+            DocumentDiagnosticReport::Full(RelatedFullDocumentDiagnosticReport {
+                related_documents: None,
+                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                    result_id: Some(result_id),
+                    items: diagnostics,
+                },
+            }),
+        ))
     }
 
-    async fn did_change(&self, mut params: DidChangeTextDocumentParams) {
-        self.on_change(TextDocumentItem {
-            uri: params.text_document.uri,
-            text: std::mem::take(&mut params.content_changes[0].text),
-            version: params.text_document.version,
-        })
-        .await
-    }
+    // Reports the diagnostics of the workspaces' bazelrc files which are not opened in
+    // the editor. Open documents are covered by `textDocument/diagnostic`.
+    async fn workspace_diagnostic(
+        &self,
+        params: WorkspaceDiagnosticParams,
+    ) -> Result<WorkspaceDiagnosticReportResult> {
+        let previous_result_ids = params
+            .previous_result_ids
+            .into_iter()
+            .map(|p| (p.uri, p.value))
+            .collect::<HashMap<_, _>>();
+        // The files not opened in the editor, with the inputs of their diagnostics and
+        // the result id of their cached diagnostics, if still valid
+        let mut pending = Vec::<(PathBuf, Url, u64, Option<String>)>::new();
+        let mut workspace_paths = Vec::<(PathBuf, Vec<PathBuf>)>::new();
+        // The files of the workspaces with outdated diagnostics, loaded once for all of them
+        let mut workspace_files = HashMap::<PathBuf, Vec<RcFile>>::new();
+        for workspace in self.get_known_workspaces() {
+            self.load_workspace_index(&workspace);
+            let include_outside_workspace = self
+                .settings_for(Some(&workspace))
+                .include_files_outside_workspace;
+            let paths = find_workspace_rc_files(&workspace, include_outside_workspace);
+            // Diagnostics also depend on the other files of the workspace. Only reuse them
+            // while none of the workspace's documents and files on disk changed.
+            let inputs = self.fingerprint_files(&paths);
+            let pending_before = pending.len();
+            for path in paths.iter().cloned() {
+                let Ok(uri) = Url::from_file_path(&path) else {
+                    continue;
+                };
+                if self.document_map.contains_key(uri.as_str()) {
+                    continue;
+                }
+                let previous_result_id = previous_result_ids.get(&uri);
+                let cached_result_id = self
+                    .get_cached_diagnostics_result_id(uri.as_str(), inputs)
+                    .filter(|id| previous_result_id == Some(id));
+                pending.push((path, uri, inputs, cached_result_id));
+            }
+            if pending[pending_before..].iter().any(|p| p.3.is_none()) {
+                let files = self.load_all_workspace_files(&workspace, None);
+                workspace_files.insert(workspace.clone(), files);
+            }
+            workspace_paths.push((workspace, paths));
+        }
 
-    async fn did_close(&self, params: DidCloseTextDocumentParams) {
-        self.document_map
-            .remove(&params.text_document.uri.to_string());
+        let progress = match params.work_done_progress_params.work_done_token {
+            Some(token) => {
+                Some(WorkDoneReporter::begin(&self.client, token, "Checking bazelrc files").await)
+            }
+            None => None,
+        };
+        // Read and parse the files whose diagnostics are recomputed in parallel
+        let parsed_files = map_in_parallel(
+            &pending,
+            |(path, _, _, cached_result_id)| {
+                if cached_result_id.is_some() {
+                    return None;
+                }
+                let contents = read_file_from_disk(path)?;
+                let bazel_flags = self.workspace_flags.get(Some(path));
+                let ParserResult {
+                    tokens: _,
+                    mut lines,
+                    errors,
+                } = parse_from_str(&contents);
+                combine_key_value_flags(&mut lines, &bazel_flags);
+                Some((Rope::from_str(&contents), lines, errors, bazel_flags))
+            },
+            &|| {},
+        );
+
+        let total = pending.len();
+        let mut items = Vec::<WorkspaceDocumentDiagnosticReport>::new();
+        for (i, ((path, uri, inputs, cached_result_id), parsed_file)) in
+            pending.into_iter().zip(parsed_files).enumerate()
+        {
+            if let Some(progress) = &progress {
+                if get_percentage(i, total) != get_percentage(i + 1, total) {
+                    progress
+                        .report(
+                            format!("{}/{total}", i + 1),
+                            Some(get_percentage(i + 1, total)),
+                        )
+                        .await;
+                }
+            }
+            let previous_result_id = previous_result_ids.get(&uri);
+            let result_id = match cached_result_id {
+                Some(result_id) => result_id,
+                None => {
+                    let Some((rope, lines, errors, bazel_flags)) = parsed_file else {
+                        continue;
+                    };
+                    let files = get_workspace_path(&path)
+                        .and_then(|workspace| workspace_files.get(&workspace));
+                    let Some(diagnostics) = self
+                        .compute_file_diagnostics(
+                            Some(&path),
+                            rope,
+                            lines,
+                            &errors,
+                            &bazel_flags,
+                            files.map(|f| f.as_slice()),
+                            &|| false,
+                        )
+                        .await
+                    else {
+                        continue;
+                    };
+                    let result_id = self.store_diagnostics(uri.to_string(), inputs, &diagnostics);
+                    if previous_result_id != Some(&result_id) {
+                        items.push(WorkspaceDocumentDiagnosticReport::Full(
+                            WorkspaceFullDocumentDiagnosticReport {
+                                uri,
+                                version: None,
+                                full_document_diagnostic_report: FullDocumentDiagnosticReport {
+                                    result_id: Some(result_id),
+                                    items: diagnostics,
+                                },
+                            },
+                        ));
+                        continue;
+                    }
+                    result_id
+                }
+            };
+            items.push(WorkspaceDocumentDiagnosticReport::Unchanged(
+                WorkspaceUnchangedDocumentDiagnosticReport {
+                    uri,
+                    version: None,
+                    unchanged_document_diagnostic_report: UnchangedDocumentDiagnosticReport {
+                        result_id,
+                    },
+                },
+            ));
+        }
+        if let Some(progress) = progress {
+            progress.end(format!("Checked {total} files")).await;
+        }
+        // Persist the digests, such that the result ids survive a restart
+        for (workspace, paths) in workspace_paths {
+            let digests = paths
+                .into_iter()
+                .filter_map(|path| {
+                    let uri = Url::from_file_path(&path).ok()?;
+                    let result = self.diagnostic_results.get(uri.as_str())?;
+                    let digest = DiagnosticsDigest {
+                        result_id: result.1.clone(),
+                        digest: result.2,
+                    };
+                    Some((path, digest))
+                })
+                .collect::<HashMap<_, _>>();
+            let changed = match self.workspace_indexes.get_mut(&workspace) {
+                Some(mut index) if index.diagnostics != digests => {
+                    index.diagnostics = digests;
+                    true
+                }
+                _ => false,
+            };
+            if changed {
+                self.save_workspace_index(&workspace);
+            }
+        }
+        Ok(WorkspaceDiagnosticReportResult::Report(
+            WorkspaceDiagnosticReport { items },
+        ))
     }
 
     async fn semantic_tokens_full(
         &self,
         params: SemanticTokensParams,
     ) -> Result<Option<SemanticTokensResult>> {
+        let encoding = self.position_encoding();
         let uri = params.text_document.uri.to_string();
-        let doc = self
-            .document_map
-            .get(&uri)
-            .ok_or(Error::invalid_params("Unknown document!"))?;
-        let lsp_tokens = convert_to_lsp_tokens(&doc.rope, &doc.semantic_tokens);
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        let lsp_tokens = convert_to_lsp_tokens(&doc.rope, &doc.semantic_tokens, encoding);
+        let result_id = self.store_semantic_tokens(uri, lsp_tokens.clone());
         Ok(Some(SemanticTokensResult::Tokens(SemanticTokens {
-            result_id: None,
+            result_id: Some(result_id),
             data: lsp_tokens,
         })))
     }
 
+    async fn semantic_tokens_full_delta(
+        &self,
+        params: SemanticTokensDeltaParams,
+    ) -> Result<Option<SemanticTokensFullDeltaResult>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document.uri.to_string();
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        let lsp_tokens = convert_to_lsp_tokens(&doc.rope, &doc.semantic_tokens, encoding);
+        // Fall back to sending all tokens if we don't know the previous result anymore
+        let edits = self
+            .semantic_token_results
+            .get(&uri)
+            .filter(|previous| previous.0 == params.previous_result_id)
+            .map(|previous| compute_token_edits(&previous.1, &lsp_tokens));
+        let result_id = self.store_semantic_tokens(uri, lsp_tokens.clone());
+        Ok(Some(match edits {
+            Some(edits) => SemanticTokensFullDeltaResult::TokensDelta(SemanticTokensDelta {
+                result_id: Some(result_id),
+                edits,
+            }),
+            None => SemanticTokensFullDeltaResult::Tokens(SemanticTokens {
+                result_id: Some(result_id),
+                data: lsp_tokens,
+            }),
+        }))
+    }
+
     async fn completion(&self, params: CompletionParams) -> Result<Option<CompletionResponse>> {
+        let encoding = self.position_encoding();
         let text_document_position = params.text_document_position;
         let uri = text_document_position.text_document.uri.to_string();
-        let doc = self
-            .document_map
-            .get(&uri)
-            .ok_or(Error::invalid_params("Unknown document!"))?;
-        let pos = lsp_pos_to_offset(&doc.rope, &text_document_position.position)
+        if let Some(bazeliskrc) = self.bazeliskrc_documents.get(&uri) {
+            let rope = &bazeliskrc.0;
+            let pos = lsp_pos_to_offset(rope, &text_document_position.position, encoding)
+                .ok_or(Error::invalid_params("Position out of range"))?;
+            let parsed = parse_bazeliskrc(&rope.to_string());
+            return Ok(Some(CompletionResponse::Array(
+                get_bazeliskrc_completion_items(rope, &parsed, pos, encoding),
+            )));
+        }
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        let pos = lsp_pos_to_offset(&doc.rope, &text_document_position.position, encoding)
             .ok_or(Error::invalid_params("Position out of range"))?;
 
+        let options = {
+            let file_path = text_document_position.text_document.uri.to_file_path().ok();
+            let settings = self.settings_for(file_path.as_deref());
+            CompletionSettings {
+                commit_with_equals: settings.completion_commit_with_equals,
+                insert_equals: settings.completion_insert_equals,
+                show_hidden_flags: settings.completion_show_hidden_flags,
+                snippets: self
+                    .client_capabilities
+                    .read()
+                    .unwrap()
+                    .text_document
+                    .as_ref()
+                    .and_then(|t| t.completion.as_ref())
+                    .and_then(|c| c.completion_item.as_ref())
+                    .and_then(|i| i.snippet_support)
+                    .unwrap_or(false),
+            }
+        };
+
+        // Offer the configs defined anywhere in the import graph
+        let lines = &doc.indexed_lines.lines;
+        let related_files = match text_document_position.text_document.uri.to_file_path() {
+            Ok(file_path) => self.load_related_files(&file_path, lines),
+            Err(_) => Vec::new(),
+        };
+        let known_configs = get_defined_configs(
+            lines
+                .iter()
+                .chain(related_files.iter().flat_map(|f| &f.lines)),
+        );
+        let flag_aliases = get_flag_aliases(
+            lines
+                .iter()
+                .chain(related_files.iter().flat_map(|f| &f.lines)),
+            &doc.bazel_flags,
+        );
+
         Ok(Some(CompletionResponse::Array(get_completion_items(
-            &self.bazel_flags,
+            &doc.bazel_flags,
             &doc.rope,
             &doc.indexed_lines,
             pos,
+            &options,
+            &known_configs,
+            &flag_aliases,
+            encoding,
         ))))
     }
 
+    async fn signature_help(&self, params: SignatureHelpParams) -> Result<Option<SignatureHelp>> {
+        let encoding = self.position_encoding();
+        let text_document_position = params.text_document_position_params;
+        let uri = text_document_position.text_document.uri.to_string();
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        let pos = lsp_pos_to_offset(&doc.rope, &text_document_position.position, encoding)
+            .ok_or(Error::invalid_params("Position out of range"))?;
+        Ok(get_signature_help(
+            &doc.bazel_flags,
+            &doc.rope,
+            &doc.indexed_lines.lines,
+            pos,
+        ))
+    }
+
     async fn goto_definition(
         &self,
         params: GotoDefinitionParams,
     ) -> Result<Option<GotoDefinitionResponse>> {
+        let encoding = self.position_encoding();
         let uri = params.text_document_position_params.text_document.uri;
         let file_path = uri
             .to_file_path()
             .ok()
             .ok_or(Error::invalid_params("Unsupported URI scheme!"))?;
-        let doc = self
-            .document_map
-            .get(&uri.to_string())
-            .ok_or(Error::invalid_params("Unknown document!"))?;
-        let pos = lsp_pos_to_offset(&doc.rope, &params.text_document_position_params.position)
-            .ok_or(Error::invalid_params("Position out of range"))?;
-        let IndexEntry { kind, line_nr, .. } =
-            doc.indexed_lines.find_symbol_at_position(pos).unwrap();
+        let Some(doc) = self.get_document(uri.as_str())? else {
+            return Ok(None);
+        };
+        let pos = lsp_pos_to_offset(
+            &doc.rope,
+            &params.text_document_position_params.position,
+            encoding,
+        )
+        .ok_or(Error::invalid_params("Position out of range"))?;
+        let Some(IndexEntry { kind, line_nr, .. }) = doc.indexed_lines.find_symbol_at_position(pos)
+        else {
+            return Ok(None);
+        };
         let definitions = get_definitions(&file_path, kind, &doc.indexed_lines.lines[*line_nr]);
         Ok(definitions)
     }
 
+    async fn references(&self, params: ReferenceParams) -> Result<Option<Vec<Location>>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document_position.text_document.uri;
+        let file_path = uri
+            .to_file_path()
+            .ok()
+            .ok_or(Error::invalid_params("Unsupported URI scheme!"))?;
+        let bazel_flags = self.workspace_flags.get(Some(&file_path));
+        let (target, current_file) = {
+            let Some(doc) = self.get_document(uri.as_str())? else {
+                return Ok(None);
+            };
+            let pos =
+                lsp_pos_to_offset(&doc.rope, &params.text_document_position.position, encoding)
+                    .ok_or(Error::invalid_params("Position out of range"))?;
+            let Some(IndexEntry { kind, line_nr, .. }) =
+                doc.indexed_lines.find_symbol_at_position(pos)
+            else {
+                return Ok(None);
+            };
+            let line = &doc.indexed_lines.lines[*line_nr];
+            let Some(target) = get_reference_target(&bazel_flags, line, kind) else {
+                return Ok(None);
+            };
+            let current_file = RcFile {
+                path: file_path.clone(),
+                rope: doc.rope.clone(),
+                lines: doc.indexed_lines.lines.clone(),
+                read_only: false,
+            };
+            (target, current_file)
+        };
+
+        let files = self.load_reference_scope(current_file);
+
+        let include_declaration = params.context.include_declaration;
+        let locations = files
+            .iter()
+            .flat_map(|file| {
+                let uri = Url::from_file_path(&file.path).ok();
+                find_references(
+                    &bazel_flags,
+                    &file.rope,
+                    &file.lines,
+                    &target,
+                    include_declaration,
+                )
+                .into_iter()
+                .filter_map(move |span| {
+                    Some(Location {
+                        uri: uri.clone()?,
+                        range: range_to_lsp(&file.rope, &span, encoding)?,
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Some(locations))
+    }
+
     async fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let encoding = self.position_encoding();
         // Find the right document and offset
         let text_document_position = params.text_document_position_params;
-        let uri = text_document_position.text_document.uri.to_string();
-        let doc = self
-            .document_map
-            .get(&uri)
-            .ok_or(Error::invalid_params("Unknown document!"))?;
-        let pos = lsp_pos_to_offset(&doc.rope, &text_document_position.position)
+        let uri = text_document_position.text_document.uri;
+        if let Some(bazeliskrc) = self.bazeliskrc_documents.get(uri.as_str()) {
+            let rope = &bazeliskrc.0;
+            let pos = lsp_pos_to_offset(rope, &text_document_position.position, encoding)
+                .ok_or(Error::invalid_params("Position out of range"))?;
+            let parsed = parse_bazeliskrc(&rope.to_string());
+            return Ok(
+                get_bazeliskrc_hover_markdown(&parsed, pos).map(|(content, span)| Hover {
+                    contents: HoverContents::Scalar(MarkedString::String(content)),
+                    range: range_to_lsp(rope, &span, encoding),
+                }),
+            );
+        }
+        let Some(doc) = self.get_document(uri.as_str())? else {
+            return Ok(None);
+        };
+        let pos = lsp_pos_to_offset(&doc.rope, &text_document_position.position, encoding)
             .ok_or(Error::invalid_params("Position out of range"))?;
 
         Ok(|| -> Option<Hover> {
@@ -284,26 +2884,158 @@ impl LanguageServer for Backend {
 
                     line.command
                         .as_ref()
-                        .and_then(|cmd| COMMAND_DOCS.get(cmd.0.as_str()))
+                        .and_then(|cmd| {
+                            get_command_documentation_markdown(&doc.bazel_flags, &cmd.0)
+                        })
                         .map(|docs| {
-                            let contents =
-                                HoverContents::Scalar(MarkedString::String(docs.to_string()));
+                            let contents = HoverContents::Scalar(MarkedString::String(docs));
                             Hover {
                                 contents,
-                                range: range_to_lsp(&doc.rope, span),
+                                range: range_to_lsp(&doc.rope, span, encoding),
                             }
                         })
                 }
-                IndexEntryKind::Config => None,
+                IndexEntryKind::Config => {
+                    // Explain the platform-specific configs, e.g. `build:linux`
+                    let line = &doc.indexed_lines.lines[*line_nr];
+                    let command = &line.command.as_ref()?.0;
+                    let config = &line.config.as_ref()?.0;
+                    let lines = &doc.indexed_lines.lines;
+                    let related_files = match uri.to_file_path() {
+                        Ok(file_path) => self.load_related_files(&file_path, lines),
+                        Err(_) => Vec::new(),
+                    };
+                    let enabled = is_platform_specific_config_enabled(
+                        lines
+                            .iter()
+                            .chain(related_files.iter().flat_map(|f| &f.lines)),
+                        &doc.bazel_flags,
+                    );
+                    let content = get_platform_config_hover_markdown(command, config, enabled)?;
+                    Some(Hover {
+                        contents: HoverContents::Scalar(MarkedString::String(content)),
+                        range: range_to_lsp(&doc.rope, span, encoding),
+                    })
+                }
                 IndexEntryKind::FlagValue(flag_nr) | IndexEntryKind::FlagName(flag_nr) => {
                     let line = &doc.indexed_lines.lines[*line_nr];
-                    let flag_name = &line.flags.get(*flag_nr)?.name.as_ref()?.0;
-                    let (_, flag_info) = self.bazel_flags.get_by_invocation(flag_name)?;
-                    let content = flag_info.get_documentation_markdown();
+                    let flag = line.flags.get(*flag_nr)?;
+                    let command = &line.command.as_ref()?.0;
+                    if command == "import" || command == "try-import" {
+                        // Show where the imported file is located
+                        let file_path = uri.to_file_path().ok()?;
+                        let raw_path = &flag.value.as_ref()?.0;
+                        let content = get_import_hover_markdown(&file_path, command, raw_path)?;
+                        return Some(Hover {
+                            contents: HoverContents::Scalar(MarkedString::String(content)),
+                            range: range_to_lsp(&doc.rope, span, encoding),
+                        });
+                    }
+                    let flag_name = &flag.name.as_ref()?.0;
+                    if let Some(content) = get_starlark_flag_hover_markdown(flag_name) {
+                        return Some(Hover {
+                            contents: HoverContents::Scalar(MarkedString::String(content)),
+                            range: range_to_lsp(&doc.rope, span, encoding),
+                        });
+                    }
+                    if doc.bazel_flags.get_by_invocation(flag_name).is_none() {
+                        // Maybe an alias defined via `--flag_alias` in the import graph
+                        let lines = &doc.indexed_lines.lines;
+                        let related_files = match uri.to_file_path() {
+                            Ok(file_path) => self.load_related_files(&file_path, lines),
+                            Err(_) => Vec::new(),
+                        };
+                        let flag_aliases = get_flag_aliases(
+                            lines
+                                .iter()
+                                .chain(related_files.iter().flat_map(|f| &f.lines)),
+                            &doc.bazel_flags,
+                        );
+                        let content = get_flag_alias_hover_markdown(&flag_aliases, flag_name)?;
+                        return Some(Hover {
+                            contents: HoverContents::Scalar(MarkedString::String(content)),
+                            range: range_to_lsp(&doc.rope, span, encoding),
+                        });
+                    }
+                    let Some((_, flag_info)) = doc.bazel_flags.get_by_invocation(flag_name) else {
+                        // Document flags from other Bazel versions, explaining why they are unknown
+                        let flag_info = doc.bazel_flags.get_from_other_versions(flag_name)?;
+                        // Link the documentation of the last version supporting the flag
+                        let last_version = flag_info.bazel_versions.last().map(String::as_str);
+                        let mut content = flag_info.get_documentation_markdown(last_version);
+                        if let Some(message) = doc.bazel_flags.get_unavailable_message(flag_name) {
+                            content = format!("**{message}**\n\n{content}");
+                        }
+                        return Some(Hover {
+                            contents: HoverContents::Scalar(MarkedString::String(content)),
+                            range: range_to_lsp(&doc.rope, span, encoding),
+                        });
+                    };
+                    // The current file and its related files, shared by the config expansion
+                    // and the override note
+                    let file_path = uri.to_file_path().ok();
+                    let files = file_path.as_ref().map(|file_path| {
+                        self.load_files_in_read_order(RcFile {
+                            path: file_path.clone(),
+                            rope: doc.rope.clone(),
+                            lines: doc.indexed_lines.lines.clone(),
+                            read_only: false,
+                        })
+                    });
+                    let content = match (kind, &flag.value) {
+                        // Show the flags a `--config` expands to
+                        (IndexEntryKind::FlagValue(_), Some(config))
+                            if flag_info.name == "config" =>
+                        {
+                            let (file_path, files) = file_path.as_ref().zip(files.as_ref())?;
+                            get_config_hover_markdown(
+                                files,
+                                &doc.bazel_flags,
+                                file_path,
+                                command,
+                                &config.0,
+                            )
+                        }
+                        // Show the value with `%workspace%` expanded
+                        (IndexEntryKind::FlagValue(_), Some(value)) => {
+                            let docs = flag_info.get_documentation_markdown(
+                                doc.bazel_flags.bazel_version.as_deref(),
+                            );
+                            match uri.to_file_path().ok().and_then(|file_path| {
+                                get_placeholder_hover_markdown(
+                                    &file_path,
+                                    &flag_info.name,
+                                    &value.0,
+                                )
+                            }) {
+                                Some(expansion) => format!("{expansion}\n\n---\n\n{docs}"),
+                                None => docs,
+                            }
+                        }
+                        _ => flag_info
+                            .get_documentation_markdown(doc.bazel_flags.bazel_version.as_deref()),
+                    };
+                    // Explain if the assignment is overridden later on
+                    let override_note =
+                        file_path
+                            .as_ref()
+                            .zip(files.as_ref())
+                            .and_then(|(file_path, files)| {
+                                get_override_hover_markdown(
+                                    files,
+                                    &doc.bazel_flags,
+                                    file_path,
+                                    span,
+                                )
+                            });
+                    let content = match override_note {
+                        Some(note) => format!("{note}\n\n---\n\n{content}"),
+                        None => content,
+                    };
                     let contents = HoverContents::Scalar(MarkedString::String(content));
                     Some(Hover {
                         contents,
-                        range: range_to_lsp(&doc.rope, span),
+                        range: range_to_lsp(&doc.rope, span, encoding),
                     })
                 }
             }
@@ -311,12 +3043,12 @@ impl LanguageServer for Backend {
     }
 
     async fn formatting(&self, params: DocumentFormattingParams) -> Result<Option<Vec<TextEdit>>> {
+        let encoding = self.position_encoding();
         // Find the right document
         let uri = params.text_document.uri.to_string();
-        let doc = self
-            .document_map
-            .get(&uri)
-            .ok_or(Error::invalid_params("Unknown document!"))?;
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
         let rope = &doc.rope;
 
         if !doc.parser_errors.is_empty() {
@@ -330,7 +3062,10 @@ impl LanguageServer for Backend {
         Ok(Some(get_text_edits_for_lines(
             lines,
             rope,
-            self.settings.read().unwrap().format_lines,
+            &doc.bazel_flags,
+            self.settings_for(params.text_document.uri.to_file_path().ok().as_deref())
+                .format_style(),
+            encoding,
         )))
     }
 
@@ -338,12 +3073,12 @@ impl LanguageServer for Backend {
         &self,
         params: DocumentRangeFormattingParams,
     ) -> Result<Option<Vec<TextEdit>>> {
+        let encoding = self.position_encoding();
         // Find the right document
         let uri = params.text_document.uri.to_string();
-        let doc = self
-            .document_map
-            .get(&uri)
-            .ok_or(Error::invalid_params("Unknown document!"))?;
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
         let rope = &doc.rope;
 
         if !doc.parser_errors.is_empty() {
@@ -354,64 +3089,469 @@ impl LanguageServer for Backend {
 
         // Format the line range
         let all_lines = &doc.indexed_lines.lines;
-        let start_offset = lsp_pos_to_offset(rope, &params.range.start)
+        let start_offset = lsp_pos_to_offset(rope, &params.range.start, encoding)
             .ok_or(Error::invalid_params("Position out of range!"))?;
-        let end_offset = lsp_pos_to_offset(rope, &params.range.end)
+        let end_offset = lsp_pos_to_offset(rope, &params.range.end, encoding)
             .ok_or(Error::invalid_params("Position out of range!"))?;
-        // XXX not correct, yet
-        let first_idx = all_lines.partition_point(|l: &Line| l.span.start < start_offset);
-        let last_idx = all_lines.partition_point(|l: &Line| l.span.end < end_offset) + 1;
+        Ok(Some(get_text_edits_for_lines(
+            get_lines_in_range(all_lines, &(start_offset..end_offset)),
+            rope,
+            &doc.bazel_flags,
+            self.settings_for(params.text_document.uri.to_file_path().ok().as_deref())
+                .format_style(),
+            encoding,
+        )))
+    }
+
+    async fn on_type_formatting(
+        &self,
+        params: DocumentOnTypeFormattingParams,
+    ) -> Result<Option<Vec<TextEdit>>> {
+        let encoding = self.position_encoding();
+        // Find the right document
+        let uri = params.text_document_position.text_document.uri.to_string();
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        let rope = &doc.rope;
+
+        // While typing, the document is frequently incomplete. Don't report an error.
+        if !doc.parser_errors.is_empty() {
+            return Ok(None);
+        }
 
+        // Format the line which was just completed by typing a newline
+        let pos = lsp_pos_to_offset(rope, &params.text_document_position.position, encoding)
+            .ok_or(Error::invalid_params("Position out of range!"))?;
+        let Some(line) = get_completed_line(&doc.indexed_lines.lines, pos) else {
+            return Ok(None);
+        };
         Ok(Some(get_text_edits_for_lines(
-            &all_lines[first_idx..last_idx],
+            std::slice::from_ref(line),
             rope,
-            self.settings.read().unwrap().format_lines,
+            &doc.bazel_flags,
+            self.settings_for(
+                params
+                    .text_document_position
+                    .text_document
+                    .uri
+                    .to_file_path()
+                    .ok()
+                    .as_deref(),
+            )
+            .format_style(),
+            encoding,
         )))
     }
 
     async fn document_link(&self, params: DocumentLinkParams) -> Result<Option<Vec<DocumentLink>>> {
+        let encoding = self.position_encoding();
         // Find the right document
         let uri = params.text_document.uri.to_string();
-        let doc = self
-            .document_map
-            .get(&uri)
-            .ok_or(Error::invalid_params("Unknown document!"))?;
-        let rope = &doc.rope;
-        let file_path = params
-            .text_document
-            .uri
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        Ok(Some(get_document_links(
+            &params.text_document.uri,
+            &doc.rope,
+            &doc.indexed_lines.lines,
+            encoding,
+        )))
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> Result<Option<DocumentSymbolResponse>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document.uri.to_string();
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        Ok(Some(DocumentSymbolResponse::Nested(get_document_symbols(
+            &doc.rope,
+            &doc.indexed_lines.lines,
+            encoding,
+        ))))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> Result<Option<Vec<CodeLens>>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document.uri;
+        let Some(doc) = self.get_document(uri.as_str())? else {
+            return Ok(None);
+        };
+        // Count the references across the document's workspace, including this document
+        let Ok(file_path) = uri.to_file_path() else {
+            return Ok(None);
+        };
+        let files = self.load_reference_scope(RcFile {
+            path: file_path,
+            rope: doc.rope.clone(),
+            lines: doc.indexed_lines.lines.clone(),
+            read_only: false,
+        });
+        let reference_counts = count_config_references(&files, &doc.bazel_flags);
+        Ok(Some(get_code_lenses(
+            &uri,
+            &doc.rope,
+            &doc.indexed_lines.lines,
+            &reference_counts,
+            encoding,
+        )))
+    }
+
+    async fn inlay_hint(&self, params: InlayHintParams) -> Result<Option<Vec<InlayHint>>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document.uri;
+        let Some(doc) = self.get_document(uri.as_str())? else {
+            return Ok(None);
+        };
+        let settings = self
+            .settings_for(uri.to_file_path().ok().as_deref())
+            .inlay_hint_settings();
+        Ok(Some(get_inlay_hints(
+            &doc.bazel_flags,
+            &doc.rope,
+            uri.to_file_path().ok().as_deref(),
+            &doc.indexed_lines.lines,
+            &params.range,
+            &settings,
+            encoding,
+        )))
+    }
+
+    async fn folding_range(&self, params: FoldingRangeParams) -> Result<Option<Vec<FoldingRange>>> {
+        let uri = params.text_document.uri.to_string();
+        let Some(doc) = self.get_document(&uri)? else {
+            return Ok(None);
+        };
+        Ok(Some(get_folding_ranges(
+            &doc.rope,
+            &doc.indexed_lines.lines,
+        )))
+    }
+
+    async fn linked_editing_range(
+        &self,
+        params: LinkedEditingRangeParams,
+    ) -> Result<Option<LinkedEditingRanges>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document_position_params.text_document.uri;
+        let Some(doc) = self.get_document(uri.as_str())? else {
+            return Ok(None);
+        };
+        let pos = lsp_pos_to_offset(
+            &doc.rope,
+            &params.text_document_position_params.position,
+            encoding,
+        )
+        .ok_or(Error::invalid_params("Position out of range"))?;
+        let Some(spans) =
+            get_linked_editing_ranges(&doc.bazel_flags, &doc.rope, &doc.indexed_lines, pos)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(LinkedEditingRanges {
+            ranges: spans
+                .iter()
+                .filter_map(|span| range_to_lsp(&doc.rope, span, encoding))
+                .collect::<Vec<_>>(),
+            // Config names end at whitespace, comments and quotes
+            word_pattern: Some("[^\\s#\"'=:]+".to_string()),
+        }))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>> {
+        let encoding = self.position_encoding();
+        let Some(doc) = self.get_document(params.text_document.uri.as_str())? else {
+            return Ok(None);
+        };
+        let pos = lsp_pos_to_offset(&doc.rope, &params.position, encoding)
+            .ok_or(Error::invalid_params("Position out of range"))?;
+        let Some((_, span)) = prepare_label_rename(&doc.rope, &doc.indexed_lines.lines, pos) else {
+            return Ok(None);
+        };
+        Ok(range_to_lsp(&doc.rope, &span, encoding).map(PrepareRenameResponse::Range))
+    }
+
+    async fn rename(&self, params: RenameParams) -> Result<Option<WorkspaceEdit>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document_position.text_document.uri;
+        let file_path = uri
             .to_file_path()
             .ok()
             .ok_or(Error::invalid_params("Unsupported URI scheme!"))?;
+        if !is_label(&params.new_name) {
+            return Err(Error::invalid_params(format!(
+                "{:?} is not a valid label",
+                params.new_name
+            )));
+        }
+        let (label, current_file) = {
+            let Some(doc) = self.get_document(uri.as_str())? else {
+                return Ok(None);
+            };
+            let pos =
+                lsp_pos_to_offset(&doc.rope, &params.text_document_position.position, encoding)
+                    .ok_or(Error::invalid_params("Position out of range"))?;
+            let Some((label, _)) = prepare_label_rename(&doc.rope, &doc.indexed_lines.lines, pos)
+            else {
+                return Ok(None);
+            };
+            let current_file = RcFile {
+                path: file_path,
+                rope: doc.rope.clone(),
+                lines: doc.indexed_lines.lines.clone(),
+                read_only: false,
+            };
+            (label, current_file)
+        };
+        let files = self.load_reference_scope(current_file);
+        Ok(Some(WorkspaceEdit {
+            changes: Some(get_label_rename_edits(
+                &files,
+                &label,
+                &params.new_name,
+                encoding,
+            )),
+            ..Default::default()
+        }))
+    }
 
-        // Link all `import` and `try-import` lines
-        let links = doc
-            .indexed_lines
-            .lines
-            .iter()
-            .filter_map(|line| {
-                let command = line.command.as_ref()?;
-                if command.0 != "import" && command.0 != "try-import" {
-                    return None;
-                }
-                if line.flags.len() != 1 {
-                    return None;
-                }
-                let flag = &line.flags[0];
-                if flag.name.is_some() {
-                    return None;
-                }
-                let value = flag.value.as_ref()?;
-                let path = resolve_bazelrc_path(&file_path, &value.0)?;
-                let url = Url::from_file_path(path).ok()?;
-                Some(DocumentLink {
-                    range: range_to_lsp(rope, &value.1)?,
-                    target: Some(url),
-                    tooltip: None,
-                    data: None,
-                })
-            })
-            .collect::<Vec<_>>();
-        Ok(Some(links))
+    async fn prepare_call_hierarchy(
+        &self,
+        params: CallHierarchyPrepareParams,
+    ) -> Result<Option<Vec<CallHierarchyItem>>> {
+        let encoding = self.position_encoding();
+        let uri = params.text_document_position_params.text_document.uri;
+        let file_path = uri
+            .to_file_path()
+            .ok()
+            .ok_or(Error::invalid_params("Unsupported URI scheme!"))?;
+        let (bazel_flags, index, pos, current_file) = {
+            let Some(doc) = self.get_document(uri.as_str())? else {
+                return Ok(None);
+            };
+            let pos = lsp_pos_to_offset(
+                &doc.rope,
+                &params.text_document_position_params.position,
+                encoding,
+            )
+            .ok_or(Error::invalid_params("Position out of range"))?;
+            let current_file = RcFile {
+                path: file_path,
+                rope: doc.rope.clone(),
+                lines: doc.indexed_lines.lines.clone(),
+                read_only: false,
+            };
+            (
+                doc.bazel_flags.clone(),
+                doc.indexed_lines.clone(),
+                pos,
+                current_file,
+            )
+        };
+        let files = self.load_reference_scope(current_file);
+        Ok(
+            prepare_call_hierarchy(&bazel_flags, &index, pos, &files, encoding)
+                .map(|item| vec![item]),
+        )
+    }
+
+    async fn incoming_calls(
+        &self,
+        params: CallHierarchyIncomingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyIncomingCall>>> {
+        let encoding = self.position_encoding();
+        let Some((bazel_flags, files, node)) = self.load_call_hierarchy_item(&params.item) else {
+            return Ok(None);
+        };
+        Ok(Some(get_incoming_calls(
+            &bazel_flags,
+            &files,
+            &node,
+            encoding,
+        )))
+    }
+
+    async fn outgoing_calls(
+        &self,
+        params: CallHierarchyOutgoingCallsParams,
+    ) -> Result<Option<Vec<CallHierarchyOutgoingCall>>> {
+        let encoding = self.position_encoding();
+        let Some((bazel_flags, files, node)) = self.load_call_hierarchy_item(&params.item) else {
+            return Ok(None);
+        };
+        Ok(Some(get_outgoing_calls(
+            &bazel_flags,
+            &files,
+            &node,
+            encoding,
+        )))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> Result<Option<Vec<SymbolInformation>>> {
+        let encoding = self.position_encoding();
+        let files = self
+            .index_all_files(params.work_done_progress_params.work_done_token)
+            .await;
+        Ok(Some(get_config_symbols(&files, &params.query, encoding)))
+    }
+
+    async fn document_link_resolve(&self, link: DocumentLink) -> Result<DocumentLink> {
+        Ok(resolve_document_link(link))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> Result<Option<CodeActionResponse>> {
+        let encoding = self.position_encoding();
+        // Find the right document
+        let uri = params.text_document.uri;
+        let Some(doc) = self.get_document(uri.as_str())? else {
+            return Ok(None);
+        };
+        let rope = &doc.rope;
+        let start_offset = lsp_pos_to_offset(rope, &params.range.start, encoding)
+            .ok_or(Error::invalid_params("Position out of range!"))?;
+        let end_offset = lsp_pos_to_offset(rope, &params.range.end, encoding)
+            .ok_or(Error::invalid_params("Position out of range!"))?;
+
+        // Defer computing the edits to `codeAction/resolve`, if the client supports it
+        let client_resolves_edits = self
+            .client_capabilities
+            .read()
+            .unwrap()
+            .text_document
+            .as_ref()
+            .and_then(|c| c.code_action.as_ref())
+            .and_then(|c| c.resolve_support.as_ref())
+            .is_some_and(|r| r.properties.iter().any(|p| p == "edit"));
+
+        Ok(Some(get_code_actions(
+            &doc.bazel_flags,
+            rope,
+            &doc.indexed_lines,
+            &uri,
+            &(start_offset..end_offset),
+            !client_resolves_edits,
+            encoding,
+        )))
+    }
+
+    async fn execute_command(
+        &self,
+        params: ExecuteCommandParams,
+    ) -> Result<Option<serde_json::Value>> {
+        match params.command.as_str() {
+            REPLACE_FLAG_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .ok_or(Error::invalid_params("Missing arguments!"))?;
+                let args = serde_json::from_value::<ReplaceFlagParams>(args)
+                    .map_err(|err| Error::invalid_params(err.to_string()))?;
+                self.replace_flag_everywhere(args).await?;
+                Ok(None)
+            }
+            NORMALIZE_FLAG_VALUES_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .ok_or(Error::invalid_params("Missing arguments!"))?;
+                let args = serde_json::from_value::<NormalizeFlagValuesParams>(args)
+                    .map_err(|err| Error::invalid_params(err.to_string()))?;
+                self.normalize_flag_values_everywhere(args).await?;
+                Ok(None)
+            }
+            CANONICALIZE_FLAGS_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .ok_or(Error::invalid_params("Missing arguments!"))?;
+                let args = serde_json::from_value::<CanonicalizeFlagsParams>(args)
+                    .map_err(|err| Error::invalid_params(err.to_string()))?;
+                self.canonicalize_flags(args).await?;
+                Ok(None)
+            }
+            SHOW_EFFECTIVE_CONFIG_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .ok_or(Error::invalid_params("Missing arguments!"))?;
+                let args = serde_json::from_value::<EffectiveConfigParams>(args)
+                    .map_err(|err| Error::invalid_params(err.to_string()))?;
+                let file_path = args
+                    .uri
+                    .to_file_path()
+                    .map_err(|_| Error::invalid_params("Unsupported URI scheme!"))?;
+                let contents = self
+                    .read_file(&file_path)
+                    .ok_or(Error::invalid_params("Unable to read the document!"))?;
+                let bazel_flags = self.workspace_flags.get(Some(&file_path));
+                let file = RcFile::from_str(file_path.clone(), &contents, &bazel_flags);
+                let mut files = self.load_related_files(&file_path, &file.lines);
+                files.insert(0, file);
+                sort_by_read_order(&file_path, &mut files);
+                // Returned as text, to be shown by the editor as a read-only document
+                let text = format_effective_config(
+                    &files,
+                    &bazel_flags,
+                    &file_path,
+                    &args.command,
+                    &args.configs,
+                );
+                Ok(Some(serde_json::Value::String(text)))
+            }
+            RUN_WITH_CONFIG_COMMAND => {
+                let args = params
+                    .arguments
+                    .into_iter()
+                    .next()
+                    .ok_or(Error::invalid_params("Missing arguments!"))?;
+                let args = serde_json::from_value::<RunWithConfigParams>(args)
+                    .map_err(|err| Error::invalid_params(err.to_string()))?;
+                let cwd = args
+                    .uri
+                    .to_file_path()
+                    .ok()
+                    .and_then(|p| get_workspace_path(&p))
+                    .map(|p| p.to_string_lossy().into_owned());
+                self.client
+                    .send_notification::<RunInTerminal>(RunInTerminalParams {
+                        cwd,
+                        command_line: get_run_command_line(&args),
+                    })
+                    .await;
+                Ok(None)
+            }
+            _ => Err(Error::invalid_params("Unknown command!")),
+        }
+    }
+
+    async fn code_action_resolve(&self, action: CodeAction) -> Result<CodeAction> {
+        let encoding = self.position_encoding();
+        let data =
+            get_code_action_data(&action).ok_or(Error::invalid_params("Missing action data!"))?;
+        let doc = self
+            .document_map
+            .get(&data.uri.to_string())
+            .ok_or(Error::invalid_params("Unknown document!"))?;
+        Ok(resolve_code_action(
+            &doc.bazel_flags,
+            &doc.rope,
+            &doc.indexed_lines,
+            action,
+            encoding,
+        ))
     }
 }
@@ -1,14 +1,113 @@
+// `analysis`, `flags` and `parser` form the stable library API. All other modules are
+// implementation details of the binary and hidden from the documentation.
+pub mod analysis;
+#[doc(hidden)]
 pub mod bazel_flags;
+#[doc(hidden)]
 pub mod bazel_flags_proto;
+#[doc(hidden)]
 pub mod bazel_version;
+#[doc(hidden)]
+pub mod bazeliskrc;
+#[doc(hidden)]
+pub mod call_hierarchy;
+#[doc(hidden)]
+pub mod canonicalize;
+#[doc(hidden)]
+pub mod code_action;
+#[doc(hidden)]
+pub mod code_lens;
+#[doc(hidden)]
 pub mod completion;
+#[doc(hidden)]
+pub mod config_expansion;
+#[doc(hidden)]
+pub mod config_tree;
+#[doc(hidden)]
 pub mod definition;
+#[doc(hidden)]
 pub mod diagnostic;
+#[doc(hidden)]
+pub mod env_flags;
+#[doc(hidden)]
 pub mod file_utils;
+#[doc(hidden)]
+pub mod flag_alias;
+#[doc(hidden)]
+pub mod flag_docs;
+#[doc(hidden)]
+pub mod flag_paths;
+#[doc(hidden)]
+pub mod flag_report;
+#[doc(hidden)]
+pub mod flag_value_style;
+#[doc(hidden)]
+pub mod flag_values;
+pub mod flags;
+#[doc(hidden)]
+pub mod folding;
+#[doc(hidden)]
 pub mod formatting;
+#[doc(hidden)]
+pub mod import_graph;
+#[doc(hidden)]
+pub mod index_cache;
+#[doc(hidden)]
+pub mod inlay_hints;
+#[doc(hidden)]
 pub mod language_server;
+#[doc(hidden)]
+pub mod line_continuations;
+#[doc(hidden)]
 pub mod line_index;
+#[doc(hidden)]
+pub mod lint_report;
+#[doc(hidden)]
+pub mod live_flags;
+#[doc(hidden)]
+pub mod logging;
+#[doc(hidden)]
 pub mod lsp_utils;
+#[doc(hidden)]
+pub mod organize;
+#[doc(hidden)]
+pub mod parallel;
+#[doc(hidden)]
+pub mod parse_dump;
 pub mod parser;
+#[doc(hidden)]
+pub mod placeholders;
+#[doc(hidden)]
+pub mod platform_config;
+#[doc(hidden)]
+pub mod progress;
+#[doc(hidden)]
+pub mod rc_discovery;
+#[doc(hidden)]
+pub mod references;
+#[doc(hidden)]
+pub mod rename;
+#[doc(hidden)]
+pub mod replace_flag;
+#[doc(hidden)]
 pub mod semantic_token;
+#[doc(hidden)]
+pub mod server_status;
+#[doc(hidden)]
+pub mod signature_help;
+#[doc(hidden)]
+pub mod starlark_flags;
+#[doc(hidden)]
+pub mod suppressions;
+#[doc(hidden)]
+pub mod symbols;
+#[cfg(test)]
+mod test_utils;
+#[doc(hidden)]
+pub mod text_diff;
+#[doc(hidden)]
 pub mod tokenizer;
+#[doc(hidden)]
+pub mod transport;
+#[doc(hidden)]
+pub mod version_resolution;
@@ -0,0 +1,319 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, CodeActionOrCommand, Diagnostic, DiagnosticSeverity, TextEdit, Url,
+};
+
+use crate::{
+    code_action::{get_flag_span, make_workspace_edit},
+    diagnostic::DiagnosticCheck,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::Line,
+    tokenizer::Span,
+};
+
+// The kind of the rewrites joining and splitting `\` line continuations
+pub const REWRITE_LINE_CONTINUATIONS_KIND: &str = "refactor.rewrite.lineContinuations";
+
+// The indentation of continuation lines, as used by the formatter
+const CONTINUATION: &str = " \\\n    ";
+
+// The spans of the escaped newlines within `span`, i.e. of `\` followed by a newline.
+// Other escaped characters (including `\\`) are skipped.
+fn find_escaped_newlines(rope: &Rope, span: &Span) -> Vec<Span> {
+    let chars = rope.slice(span.clone()).chars().collect::<Vec<_>>();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            i += 1;
+            continue;
+        }
+        let newline_len = match (chars.get(i + 1), chars.get(i + 2)) {
+            (Some('\n'), _) => 1,
+            (Some('\r'), Some('\n')) => 2,
+            _ => 0,
+        };
+        if newline_len > 0 {
+            result.push(span.start + i..span.start + i + 1 + newline_len);
+        }
+        i += 1 + newline_len.max(1);
+    }
+    result
+}
+
+// The spans of the command, config and flag tokens of a line, in order
+fn get_token_spans(line: &Line) -> Vec<Span> {
+    let mut spans = line
+        .command
+        .iter()
+        .chain(line.config.iter())
+        .chain(
+            line.flags
+                .iter()
+                .flat_map(|f| f.name.iter().chain(f.value.iter())),
+        )
+        .map(|t| t.1.clone())
+        .collect::<Vec<_>>();
+    spans.sort_by_key(|s| s.start);
+    spans
+}
+
+// The end of the line's contents, excluding the comment and the terminating newline
+fn get_content_end(rope: &Rope, line: &Line) -> usize {
+    if let Some(comment) = &line.comment {
+        return comment.1.start;
+    }
+    let mut end = line.span.end;
+    if end > line.span.start && rope.get_char(end - 1) == Some('\n') {
+        end -= 1;
+        if end > line.span.start && rope.get_char(end - 1) == Some('\r') {
+            end -= 1;
+        }
+    }
+    end
+}
+
+// The edits joining a line spread over multiple physical lines into a single line.
+// Escaped newlines within tokens are removed. Between tokens, the whitespace around
+// the escaped newlines is collapsed into a single space. Comments are kept as is.
+fn get_join_edits(rope: &Rope, line: &Line) -> Vec<(Span, &'static str)> {
+    let tokens = get_token_spans(line);
+    let mut edits = tokens
+        .iter()
+        .flat_map(|t| find_escaped_newlines(rope, t))
+        .map(|span| (span, ""))
+        .collect::<Vec<_>>();
+    let content_end = get_content_end(rope, line);
+    let mut prev_end = None;
+    for token in tokens.iter().map(Some).chain([None]) {
+        let gap = prev_end.unwrap_or(line.span.start)..token.map_or(content_end, |t| t.start);
+        if gap.start < gap.end && !find_escaped_newlines(rope, &gap).is_empty() {
+            let between_tokens = prev_end.is_some() && (token.is_some() || line.comment.is_some());
+            edits.push((gap, if between_tokens { " " } else { "" }));
+        }
+        if let Some(token) = token {
+            prev_end = Some(token.end);
+        }
+    }
+    edits
+}
+
+// The edits putting each flag on a separate line, as done by the `lineContinuations` formatting style
+fn get_split_edits(line: &Line) -> Option<Vec<(Span, &'static str)>> {
+    if line.flags.len() < 2 || line.comment.is_some() {
+        return None;
+    }
+    let mut prev_end = line.config.as_ref().or(line.command.as_ref())?.1.end;
+    let mut edits = Vec::new();
+    for (flag_nr, flag) in line.flags.iter().enumerate() {
+        let span = get_flag_span(flag)?;
+        // Values in separate tokens (e.g. `-c opt`) stay attached to their flag
+        if flag_nr == 0 || flag.name.is_some() {
+            edits.push((prev_end..span.start, CONTINUATION));
+        }
+        prev_end = span.end;
+    }
+    Some(edits)
+}
+
+// Offers to join a line with `\` line continuations into a single line, or to split a
+// line with multiple flags into one flag per line
+pub fn line_continuation_action(
+    rope: &Rope,
+    uri: &Url,
+    line: &Line,
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let join_edits = get_join_edits(rope, line);
+    let (title, edits) = if !join_edits.is_empty() {
+        ("Join the line continuations into a single line", join_edits)
+    } else {
+        ("Put each flag on a separate line", get_split_edits(line)?)
+    };
+    let edit = resolve_edits.then(|| {
+        let edits = edits
+            .iter()
+            .filter_map(|(span, new_text)| {
+                Some(TextEdit {
+                    range: range_to_lsp(rope, span, encoding)?,
+                    new_text: new_text.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        make_workspace_edit(uri, edits)
+    });
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: title.to_string(),
+        kind: Some(CodeActionKind::new(REWRITE_LINE_CONTINUATIONS_KIND)),
+        edit,
+        ..Default::default()
+    }))
+}
+
+// Diagnoses line continuations which most likely don't do what was intended:
+// * comments ending in `\` also comment out the following line
+// * a `\` followed by trailing whitespace escapes the whitespace instead of the newline
+pub fn diagnostics_for_line_continuations(
+    rope: &Rope,
+    line: &Line,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if let Some((_, span)) = &line.comment {
+        let comment = rope.slice(span.clone());
+        if let Some(newline) = comment.chars().position(|c| c == '\n') {
+            let continued = span.start + newline + 1..span.end;
+            let is_blank = rope
+                .slice(continued.clone())
+                .chars()
+                .all(|c| c.is_whitespace() || c == '\\');
+            if !is_blank {
+                diagnostics.push(Diagnostic {
+                    range: range_to_lsp(rope, &continued, encoding).unwrap(),
+                    message: "Commented out by the `\\` at the end of the preceding comment line"
+                        .to_string(),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: DiagnosticCheck::LineContinuation.code(),
+                    ..Default::default()
+                });
+            }
+        }
+    } else if let Some(last_token) = get_token_spans(line).last() {
+        // An escaped whitespace is part of the token
+        let content_end = get_content_end(rope, line);
+        let ends_with_escaped_whitespace = last_token.end >= 2
+            && matches!(rope.get_char(last_token.end - 1), Some(' ' | '\t'))
+            && rope.get_char(last_token.end - 2) == Some('\\');
+        let has_next_line = content_end < line.span.end;
+        if ends_with_escaped_whitespace && has_next_line {
+            diagnostics.push(Diagnostic {
+                range: range_to_lsp(rope, &(last_token.end - 2..content_end), encoding).unwrap(),
+                message: "The `\\` is followed by whitespace and hence does not continue the line"
+                    .to_string(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                code: DiagnosticCheck::LineContinuation.code(),
+                ..Default::default()
+            });
+        }
+    }
+
+    diagnostics
+}
+
+#[cfg(test)]
+fn apply_line_continuation_action(text: &str) -> Option<(String, String)> {
+    use crate::{
+        bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags},
+        code_action::apply_text_edits,
+        parser::parse_from_str,
+    };
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let rope = Rope::from_str(text);
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let uri = Url::parse("file:///ws/.bazelrc").unwrap();
+    match line_continuation_action(&rope, &uri, &lines[0], true, PositionEncoding::Utf16)? {
+        CodeActionOrCommand::CodeAction(action) => {
+            let edits = action.edit?.changes?.remove(&uri)?;
+            Some((
+                action.title,
+                apply_text_edits(text, &edits, PositionEncoding::Utf16),
+            ))
+        }
+        CodeActionOrCommand::Command(_) => None,
+    }
+}
+
+#[test]
+fn test_join_line_continuations() {
+    let join = |text: &str| apply_line_continuation_action(text).unwrap().1;
+    assert_eq!(
+        join("build \\\n    --jobs 4 \\\n    --keep_going\n"),
+        "build --jobs 4 --keep_going\n"
+    );
+    // Consecutive continuations, Windows newlines and trailing comments
+    assert_eq!(
+        join("build:ci \\\r\n\\\r\n  --keep_going \\\r\n  # note\r\n"),
+        "build:ci --keep_going # note\r\n"
+    );
+    // Trailing continuations are removed, together with the empty line they continue onto
+    assert_eq!(
+        join("build --keep_going \\\n\nbuild --jobs=4"),
+        "build --keep_going\nbuild --jobs=4"
+    );
+    // Within tokens, the escaped newline is removed without inserting whitespace
+    assert_eq!(
+        join("build --keep_\\\ngoing \"--copt=a \\\n b\""),
+        "build --keep_going \"--copt=a  b\""
+    );
+    // Escaped newlines within comments are left to the formatter
+    assert_eq!(
+        apply_line_continuation_action("build --jobs=4 # a \\\n b\n"),
+        None
+    );
+}
+
+#[test]
+fn test_split_into_line_continuations() {
+    assert_eq!(
+        apply_line_continuation_action("build:ci --jobs 4 -c opt --keep_going\n"),
+        Some((
+            "Put each flag on a separate line".to_string(),
+            "build:ci \\\n    --jobs 4 \\\n    -c opt \\\n    --keep_going\n".to_string()
+        ))
+    );
+    // Not offered for single flags and lines with comments
+    assert_eq!(apply_line_continuation_action("build --jobs=4\n"), None);
+    assert_eq!(
+        apply_line_continuation_action("build --jobs=4 --keep_going # fast\n"),
+        None
+    );
+}
+
+#[test]
+fn test_diagnose_line_continuations() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, diagnostic::diagnostics_from_string};
+    use tower_lsp::lsp_types::{Position, Range};
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let diagnose = |text: &str| {
+        diagnostics_from_string(text, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|d| (d.message, d.range))
+            .collect::<Vec<_>>()
+    };
+
+    // Diagnostics on continuation lines point at the physical line
+    assert_eq!(
+        diagnose("build --keep_going \\\n  --unknown_flag\n"),
+        vec![(
+            "Unknown flag \"--unknown_flag\"".to_string(),
+            Range::new(Position::new(1, 2), Position::new(1, 16))
+        )]
+    );
+
+    // A comment ending in `\` swallows the next line
+    assert_eq!(
+        diagnose("build --jobs=4 # fast \\\nbuild --keep_going\n"),
+        vec![(
+            "Commented out by the `\\` at the end of the preceding comment line".to_string(),
+            Range::new(Position::new(1, 0), Position::new(1, 18))
+        )]
+    );
+    // ... unless that line is empty anyway
+    assert_eq!(diagnose("# fast \\\n\nbuild --keep_going\n"), vec![]);
+
+    // A `\` followed by whitespace does not continue the line
+    let diagnostics = diagnose("build --keep_going \\ \n  --jobs=4\n");
+    assert_eq!(
+        diagnostics[0],
+        (
+            "The `\\` is followed by whitespace and hence does not continue the line".to_string(),
+            Range::new(Position::new(0, 19), Position::new(0, 21))
+        )
+    );
+    assert_eq!(diagnose("build --keep_going \\\n  --jobs=4\n"), vec![]);
+}
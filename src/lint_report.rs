@@ -0,0 +1,149 @@
+use serde_json::{json, Value};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity};
+
+// A diagnostic reported for a specific file
+#[derive(Debug)]
+pub struct LintFinding {
+    // The file path as given on the command line, or `<stdin>`
+    pub path: String,
+    pub diagnostic: Diagnostic,
+}
+
+// Diagnostics without an explicit severity are shown as errors by editors
+fn severity_name(diagnostic: &Diagnostic) -> &'static str {
+    match diagnostic.severity {
+        Some(DiagnosticSeverity::WARNING) => "warning",
+        Some(DiagnosticSeverity::INFORMATION) => "info",
+        Some(DiagnosticSeverity::HINT) => "hint",
+        _ => "error",
+    }
+}
+
+// Compiler-style output, e.g. `.bazelrc:3:7: error: Unknown flag "--foo"`.
+// Line and column numbers are 1-based.
+pub fn format_human(findings: &[LintFinding]) -> String {
+    let mut result = String::new();
+    for finding in findings {
+        let start = finding.diagnostic.range.start;
+        result += &format!(
+            "{}:{}:{}: {}: {}\n",
+            finding.path,
+            start.line + 1,
+            start.character + 1,
+            severity_name(&finding.diagnostic),
+            finding.diagnostic.message
+        );
+    }
+    result
+}
+
+pub fn format_json(findings: &[LintFinding]) -> Value {
+    findings
+        .iter()
+        .map(|finding| {
+            let range = finding.diagnostic.range;
+            json!({
+                "file": finding.path,
+                "line": range.start.line + 1,
+                "column": range.start.character + 1,
+                "endLine": range.end.line + 1,
+                "endColumn": range.end.character + 1,
+                "severity": severity_name(&finding.diagnostic),
+                "message": finding.diagnostic.message,
+            })
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+// Static Analysis Results Interchange Format, as consumed by code review bots
+// Ref: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+pub fn format_sarif(findings: &[LintFinding]) -> Value {
+    let results = findings
+        .iter()
+        .map(|finding| {
+            let range = finding.diagnostic.range;
+            let level = match finding.diagnostic.severity {
+                Some(DiagnosticSeverity::WARNING) => "warning",
+                Some(DiagnosticSeverity::INFORMATION) | Some(DiagnosticSeverity::HINT) => "note",
+                _ => "error",
+            };
+            json!({
+                "level": level,
+                "message": { "text": finding.diagnostic.message },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": finding.path },
+                        "region": {
+                            "startLine": range.start.line + 1,
+                            "startColumn": range.start.character + 1,
+                            "endLine": range.end.line + 1,
+                            "endColumn": range.end.character + 1,
+                        }
+                    }
+                }]
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "bazelrc-lsp",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": "https://github.com/salesforce-misc/bazelrc-lsp",
+                }
+            },
+            "results": results,
+        }]
+    })
+}
+
+#[test]
+fn test_lint_report() {
+    use tower_lsp::lsp_types::{Position, Range};
+
+    let findings = vec![
+        LintFinding {
+            path: ".bazelrc".to_string(),
+            diagnostic: Diagnostic::new_simple(
+                Range::new(Position::new(2, 6), Position::new(2, 11)),
+                "Unknown flag \"--foo\"".to_string(),
+            ),
+        },
+        LintFinding {
+            path: "ci.bazelrc".to_string(),
+            diagnostic: Diagnostic {
+                range: Range::new(Position::new(0, 0), Position::new(0, 5)),
+                severity: Some(DiagnosticSeverity::WARNING),
+                message: "The flag \"--batch\" is deprecated.".to_string(),
+                ..Default::default()
+            },
+        },
+    ];
+    assert_eq!(
+        format_human(&findings),
+        ".bazelrc:3:7: error: Unknown flag \"--foo\"\n\
+        ci.bazelrc:1:1: warning: The flag \"--batch\" is deprecated.\n"
+    );
+    assert_eq!(
+        format_json(&findings)[1],
+        json!({
+            "file": "ci.bazelrc",
+            "line": 1,
+            "column": 1,
+            "endLine": 1,
+            "endColumn": 6,
+            "severity": "warning",
+            "message": "The flag \"--batch\" is deprecated.",
+        })
+    );
+    let sarif = format_sarif(&findings);
+    assert_eq!(sarif["runs"][0]["results"][0]["level"], "error");
+    assert_eq!(
+        sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+        3
+    );
+}
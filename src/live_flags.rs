@@ -0,0 +1,229 @@
+use std::{
+    env,
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use prost::Message;
+
+use crate::{
+    bazel_flags::{
+        load_bazel_flag_infos_from_command, load_packaged_bazel_flag_collection,
+        merge_packaged_flag_metadata, BazelFlags,
+    },
+    bazel_flags_proto::{FlagCollection, FlagInfo},
+    bazel_version::{find_closest_version, AVAILABLE_BAZEL_VERSIONS},
+    file_utils::get_home_path,
+};
+
+// Finds Bazelisk or Bazel on the `PATH`. Bazelisk is preferred, since it respects
+// the `.bazelversion` of the workspace.
+pub fn find_bazel_binary() -> Option<String> {
+    let path = env::var_os("PATH")?;
+    ["bazelisk", "bazel"]
+        .iter()
+        .find_map(|name| {
+            env::split_paths(&path)
+                .map(|dir| dir.join(name).with_extension(env::consts::EXE_EXTENSION))
+                .find(|candidate| candidate.is_file())
+        })
+        .map(|p| p.to_string_lossy().into_owned())
+}
+
+// The directory used to cache the flags of Bazel versions between runs
+pub fn get_cache_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .or_else(|| env::var_os("LOCALAPPDATA"))
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| Some(get_home_path()?.join(".cache")))?;
+    Some(base.join("bazelrc-lsp"))
+}
+
+// Extracts the version from the output of `bazel --version`, e.g. `bazel 8.0.0`
+fn parse_bazel_version_output(output: &str) -> Option<&str> {
+    let version = output.trim().strip_prefix("bazel ")?.trim();
+    (!version.is_empty()).then_some(version)
+}
+
+// Development builds report `no_version`. Their flags change without a version bump,
+// hence they are never cached. The check also keeps the version safe to use in file names.
+fn is_cacheable_version(version: &str) -> bool {
+    version.starts_with(|c: char| c.is_ascii_digit())
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ['.', '-', '_'].contains(&c))
+}
+
+// Determines the version of the Bazel binary, as used within the given workspace
+#[tracing::instrument(level = "debug", skip(workspace))]
+pub fn query_bazel_version(bazel_command: &str, workspace: &Path) -> Result<String, String> {
+    let result = Command::new(bazel_command)
+        .arg("--version")
+        .current_dir(workspace)
+        .output()
+        .map_err(|err| err.to_string())?;
+    let stdout = String::from_utf8_lossy(&result.stdout);
+    match parse_bazel_version_output(&stdout) {
+        Some(version) if result.status.success() => Ok(version.to_string()),
+        _ => Err(format!(
+            "`{bazel_command} --version` failed:\n{stdout}{}",
+            String::from_utf8_lossy(&result.stderr)
+        )),
+    }
+}
+
+fn get_cache_file(cache_dir: &Path, bazel_version: &str) -> PathBuf {
+    cache_dir.join(format!("flags-{bazel_version}.binpb"))
+}
+
+fn read_cached_flags(cache_file: &Path) -> Option<Vec<FlagInfo>> {
+    let data = std::fs::read(cache_file).ok()?;
+    let collection = FlagCollection::decode(&mut Cursor::new(data)).ok()?;
+    Some(collection.flag_infos)
+}
+
+fn write_cached_flags(cache_file: &Path, flags: &[FlagInfo]) -> std::io::Result<()> {
+    if let Some(parent) = cache_file.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let collection = FlagCollection {
+        flag_infos: flags.to_vec(),
+        ..Default::default()
+    };
+    // Write to a temporary file first, such that concurrent readers never see partial files
+    let tmp_file = cache_file.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp_file, collection.encode_to_vec())?;
+    std::fs::rename(&tmp_file, cache_file)
+}
+
+// Builds the flags from the live flags, filling gaps from the closest packaged version
+fn from_live_flags(flags: Vec<FlagInfo>, bazel_version: &str) -> BazelFlags {
+    // Development builds are closest to the latest release
+    let version_hint = if is_cacheable_version(bazel_version) {
+        bazel_version
+    } else {
+        "latest"
+    };
+    let packaged_version =
+        find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), version_hint).0;
+    // The live flags are usable even without the packaged metadata
+    let packaged = load_packaged_bazel_flag_collection()
+        .map(|c| c.flag_infos)
+        .unwrap_or_default();
+    BazelFlags::from_flags(
+        merge_packaged_flag_metadata(flags, &packaged, &packaged_version),
+        None,
+    )
+}
+
+// Loads the flags by running `bazel help flags-as-proto` within the workspace.
+// The flags are cached on disk per Bazel version, such that later runs only
+// need to call the much cheaper `bazel --version`.
+pub fn load_live_bazel_flags(
+    bazel_command: &str,
+    workspace: &Path,
+    cache_dir: Option<&Path>,
+) -> Result<BazelFlags, String> {
+    let bazel_version = query_bazel_version(bazel_command, workspace)?;
+    let cache_file = cache_dir
+        .filter(|_| is_cacheable_version(&bazel_version))
+        .map(|dir| get_cache_file(dir, &bazel_version));
+    if let Some(flags) = cache_file.as_deref().and_then(read_cached_flags) {
+        return Ok(from_live_flags(flags, &bazel_version));
+    }
+    let flags = load_bazel_flag_infos_from_command(bazel_command, Some(workspace))?;
+    if let Some(cache_file) = &cache_file {
+        // The cache is only an optimization. Failing to write it is not an error.
+        let _ = write_cached_flags(cache_file, &flags);
+    }
+    Ok(from_live_flags(flags, &bazel_version))
+}
+
+#[test]
+fn test_parse_bazel_version() {
+    assert_eq!(parse_bazel_version_output("bazel 8.0.0\n"), Some("8.0.0"));
+    assert_eq!(
+        parse_bazel_version_output("bazel 9.0.0-pre.20250105.1"),
+        Some("9.0.0-pre.20250105.1")
+    );
+    assert_eq!(
+        parse_bazel_version_output("bazel no_version"),
+        Some("no_version")
+    );
+    assert_eq!(parse_bazel_version_output("bazelisk v1.25.0"), None);
+    assert_eq!(parse_bazel_version_output("bazel "), None);
+
+    assert!(is_cacheable_version("8.0.0"));
+    assert!(is_cacheable_version("7.5.0rc2"));
+    assert!(!is_cacheable_version("no_version"));
+    assert!(!is_cacheable_version("8.0.0/../../x"));
+}
+
+#[test]
+fn test_flag_cache() {
+    use crate::test_utils::TestDir;
+
+    let cache_dir = TestDir::new();
+    let cache_file = get_cache_file(&cache_dir, "8.0.0");
+    assert_eq!(read_cached_flags(&cache_file), None);
+
+    let flags = vec![FlagInfo {
+        name: "jobs".to_string(),
+        commands: vec!["build".to_string()],
+        ..Default::default()
+    }];
+    write_cached_flags(&cache_file, &flags).unwrap();
+    assert_eq!(read_cached_flags(&cache_file), Some(flags));
+}
+
+#[cfg(unix)]
+#[test]
+fn test_load_live_bazel_flags() {
+    use base64::prelude::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::test_utils::TestDir;
+
+    let root = TestDir::new();
+    let workspace = root.join("workspace");
+    let cache_dir = root.join("cache");
+    std::fs::create_dir_all(&workspace).unwrap();
+
+    // A fake Bazel binary, which only supports the flags-as-proto output of a single flag
+    let collection = FlagCollection {
+        flag_infos: vec![FlagInfo {
+            name: "my_flag".to_string(),
+            commands: vec!["build".to_string()],
+            ..Default::default()
+        }],
+        ..Default::default()
+    };
+    let proto = BASE64_STANDARD.encode(collection.encode_to_vec());
+    let bazel = root.join("bazel");
+    let write_bazel = |help_output: &str| {
+        std::fs::write(
+            &bazel,
+            format!(
+                "#!/bin/sh\n\
+                if [ \"$1\" = \"--version\" ]; then echo \"bazel 8.0.0\"; exit 0; fi\n\
+                {help_output}\n"
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&bazel, std::fs::Permissions::from_mode(0o755)).unwrap();
+    };
+    write_bazel(&format!("printf %s {proto}"));
+    let bazel_command = bazel.to_str().unwrap();
+
+    let flags = load_live_bazel_flags(bazel_command, &workspace, Some(&cache_dir)).unwrap();
+    assert!(flags.flags_by_name.contains_key("my_flag"));
+    assert!(get_cache_file(&cache_dir, "8.0.0").exists());
+
+    // Afterwards, the flags are served from the cache without invoking `bazel help`
+    write_bazel("exit 1");
+    let flags = load_live_bazel_flags(bazel_command, &workspace, Some(&cache_dir)).unwrap();
+    assert!(flags.flags_by_name.contains_key("my_flag"));
+    assert!(load_live_bazel_flags(bazel_command, &workspace, None).is_err());
+}
@@ -0,0 +1,239 @@
+use std::{
+    fmt::{Debug, Write as _},
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use tower_lsp::lsp_types::LogTraceParams;
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span, Event, Metadata, Subscriber,
+};
+use tracing_subscriber::{
+    filter::Targets,
+    layer::{Context, SubscriberExt},
+    registry::{LookupSpan, SpanRef},
+    reload, Layer, Registry,
+};
+
+// Formats the fields of events and spans as `message key=value ...`. Also stored in the
+// extensions of each span.
+#[derive(Default)]
+struct FieldFormatter(String);
+
+impl Visit for FieldFormatter {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.record_debug(field, &format_args!("{value}"));
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, "{}={value:?}", field.name());
+        }
+    }
+}
+
+// The time a span was created, stored in the span's extensions
+struct SpanStart(Instant);
+
+// Writes the events to the log file (or stderr) and forwards them to the client as
+// `$/logTrace` notifications. Spans log their duration when they are closed, e.g.
+// `parse{uri="file:///.bazelrc"}: took 1.2ms`.
+struct LogLayer {
+    file: Option<Mutex<File>>,
+    trace_sender: UnboundedSender<LogTraceParams>,
+    start: Instant,
+}
+
+impl LogLayer {
+    fn write_line(&self, metadata: &Metadata, message: String, verbose: String) {
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let line = format!("[{elapsed:10.3}s {:>5}] {message}", metadata.level());
+        match &self.file {
+            Some(file) => {
+                let _ = writeln!(file.lock().unwrap(), "{line}");
+            }
+            None => {
+                let _ = writeln!(io::stderr(), "{line}");
+            }
+        }
+        let _ = self.trace_sender.send(LogTraceParams {
+            message: line,
+            verbose: Some(verbose),
+        });
+    }
+}
+
+// The span's name followed by its fields, e.g. `parse{uri="file:///.bazelrc"}`
+fn format_span<S: for<'a> LookupSpan<'a>>(span: &SpanRef<S>) -> String {
+    match span.extensions().get::<FieldFormatter>() {
+        Some(fields) if !fields.0.is_empty() => format!("{}{{{}}}", span.name(), fields.0),
+        _ => span.name().to_string(),
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for LogLayer {
+    fn on_new_span(&self, attrs: &span::Attributes, id: &span::Id, ctx: Context<S>) {
+        let mut fields = FieldFormatter::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            extensions.insert(fields);
+            extensions.insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record, ctx: Context<S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<FieldFormatter>() {
+                values.record(fields);
+            }
+        }
+    }
+
+    // The message is prefixed by the names of the entered spans, e.g. `diagnostics > parse: `.
+    // Their fields are only part of the verbose trace.
+    fn on_event(&self, event: &Event, ctx: Context<S>) {
+        let mut fields = FieldFormatter::default();
+        event.record(&mut fields);
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| scope.from_root().collect::<Vec<_>>())
+            .unwrap_or_default();
+        let context = if spans.is_empty() {
+            String::new()
+        } else {
+            let names = spans.iter().map(|s| s.name()).collect::<Vec<_>>();
+            format!("{}: ", names.join(" > "))
+        };
+        let mut verbose = event.metadata().target().to_string();
+        for span in &spans {
+            verbose.push_str(" > ");
+            verbose.push_str(&format_span(span));
+        }
+        self.write_line(event.metadata(), format!("{context}{}", fields.0), verbose);
+    }
+
+    fn on_close(&self, id: span::Id, ctx: Context<S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(elapsed) = span.extensions().get::<SpanStart>().map(|s| s.0.elapsed()) else {
+            return;
+        };
+        self.write_line(
+            span.metadata(),
+            format!("{}: took {elapsed:.1?}", format_span(&span)),
+            span.metadata().target().to_string(),
+        );
+    }
+}
+
+// Events of this crate up to the given level. Dependencies (e.g. tower-lsp) only report
+// warnings and errors.
+fn get_log_targets(verbose: bool) -> Targets {
+    let level = if verbose {
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    };
+    Targets::new()
+        .with_default(LevelFilter::WARN)
+        .with_target("bazelrc_lsp", level)
+}
+
+// Changes the log level while running, e.g. when the client requests verbose traces
+#[derive(Clone, Debug)]
+pub struct LogLevel {
+    handle: reload::Handle<Targets, Registry>,
+    // Requested on the command line, hence always verbose
+    verbose: bool,
+}
+
+impl LogLevel {
+    // Includes debug events and timings while `verbose` is set. Never drops below the
+    // level chosen on startup.
+    pub fn set_verbose(&self, verbose: bool) {
+        let _ = self.handle.reload(get_log_targets(self.verbose || verbose));
+    }
+}
+
+// Installs the subscriber for the language server. Events are written to the given
+// file, or to stderr if none is given. `verbose` includes debug events and the timing
+// of parsing, indexing and Bazel invocations. The returned receiver yields the
+// `$/logTrace` notifications for the client.
+pub fn init_logging(
+    log_file: Option<&Path>,
+    verbose: bool,
+) -> io::Result<(UnboundedReceiver<LogTraceParams>, LogLevel)> {
+    let file = match log_file {
+        Some(path) => Some(Mutex::new(
+            File::options().create(true).append(true).open(path)?,
+        )),
+        None => None,
+    };
+    let (trace_sender, trace_receiver) = unbounded_channel();
+    let (filter, handle) = reload::Layer::new(get_log_targets(verbose));
+    let subscriber = Registry::default().with(filter).with(LogLayer {
+        file,
+        trace_sender,
+        start: Instant::now(),
+    });
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|err| io::Error::other(err.to_string()))?;
+    Ok((trace_receiver, LogLevel { handle, verbose }))
+}
+
+#[test]
+fn test_log_layer() {
+    let (trace_sender, mut trace_receiver) = unbounded_channel();
+    let (filter, handle) = reload::Layer::new(get_log_targets(false));
+    let log_level = LogLevel {
+        handle,
+        verbose: false,
+    };
+    let subscriber = Registry::default().with(filter).with(LogLayer {
+        file: None,
+        trace_sender,
+        start: Instant::now(),
+    });
+    tracing::subscriber::with_default(subscriber, || {
+        tracing::debug!("Not logged");
+        log_level.set_verbose(true);
+        let span = tracing::debug_span!("parse", uri = "file:///.bazelrc").entered();
+        tracing::info!(lines = 3, "Parsed {}", ".bazelrc");
+        tracing::trace!("Not logged");
+        drop(span);
+    });
+    let mut messages = Vec::new();
+    while let Ok(params) = trace_receiver.try_recv() {
+        // Strip the timestamp
+        let message = params.message.split_once("] ").unwrap().1.to_string();
+        messages.push((message, params.verbose.unwrap()));
+    }
+    assert_eq!(messages.len(), 2);
+    assert_eq!(
+        messages[0],
+        (
+            "parse: Parsed .bazelrc lines=3".to_string(),
+            "bazelrc_lsp::logging > parse{uri=\"file:///.bazelrc\"}".to_string()
+        )
+    );
+    assert!(messages[1]
+        .0
+        .starts_with("parse{uri=\"file:///.bazelrc\"}: took "));
+}
@@ -1,26 +1,197 @@
 use ropey::Rope;
-use tower_lsp::lsp_types::{Position, Range};
+use tower_lsp::lsp_types::{Position, PositionEncodingKind, Range, TextDocumentContentChangeEvent};
 
 use crate::tokenizer::Span;
 
-pub fn lsp_pos_to_offset(rope: &Rope, pos: &Position) -> Option<usize> {
-    let char = rope.try_line_to_char(pos.line as usize).ok()?;
-    Some(char + pos.character as usize)
+// The unit of `Position::character`, negotiated per connection during `initialize`.
+// Spans within the server always count chars. UTF-16 is the default of the LSP
+// specification, and the encoding of columns in SARIF reports.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    Utf8,
+    #[default]
+    Utf16,
+    Utf32,
 }
 
-pub fn offset_to_lsp_pos(rope: &Rope, pos: usize) -> Option<Position> {
-    let line = rope.try_byte_to_line(pos).ok()?;
-    let first = rope.try_line_to_char(line).ok()?;
-    let character = rope.try_byte_to_char(pos).ok()? - first;
+impl PositionEncoding {
+    pub fn to_lsp(self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    fn from_lsp(kind: &PositionEncodingKind) -> Option<PositionEncoding> {
+        [
+            PositionEncoding::Utf8,
+            PositionEncoding::Utf16,
+            PositionEncoding::Utf32,
+        ]
+        .into_iter()
+        .find(|encoding| encoding.to_lsp() == *kind)
+    }
+}
+
+// Picks the first encoding of the client's `general.positionEncodings`, which are
+// ordered by preference. Clients not announcing any encoding only support UTF-16.
+pub fn negotiate_position_encoding(
+    client_encodings: Option<&[PositionEncodingKind]>,
+) -> PositionEncoding {
+    client_encodings
+        .unwrap_or_default()
+        .iter()
+        .find_map(PositionEncoding::from_lsp)
+        .unwrap_or(PositionEncoding::Utf16)
+}
+
+// The column of the char offset within its line, in the given encoding
+fn char_to_column(
+    rope: &Rope,
+    line_start: usize,
+    offset: usize,
+    encoding: PositionEncoding,
+) -> Option<usize> {
+    Some(match encoding {
+        PositionEncoding::Utf8 => {
+            rope.try_char_to_byte(offset).ok()? - rope.char_to_byte(line_start)
+        }
+        PositionEncoding::Utf16 => {
+            rope.try_char_to_utf16_cu(offset).ok()? - rope.char_to_utf16_cu(line_start)
+        }
+        PositionEncoding::Utf32 => offset.checked_sub(line_start)?,
+    })
+}
+
+// The column of the char offset within the given line
+pub fn get_lsp_column(
+    rope: &Rope,
+    line: usize,
+    offset: usize,
+    encoding: PositionEncoding,
+) -> Option<u32> {
+    let line_start = rope.try_line_to_char(line).ok()?;
+    char_to_column(rope, line_start, offset, encoding)?
+        .try_into()
+        .ok()
+}
+
+pub fn lsp_pos_to_offset(rope: &Rope, pos: &Position, encoding: PositionEncoding) -> Option<usize> {
+    let line_start = rope.try_line_to_char(pos.line as usize).ok()?;
+    let character = pos.character as usize;
+    match encoding {
+        PositionEncoding::Utf8 => {
+            let byte = rope.char_to_byte(line_start) + character;
+            rope.try_byte_to_char(byte).ok()
+        }
+        PositionEncoding::Utf16 => {
+            let utf16_cu = rope.char_to_utf16_cu(line_start) + character;
+            rope.try_utf16_cu_to_char(utf16_cu).ok()
+        }
+        PositionEncoding::Utf32 => Some(line_start + character),
+    }
+}
+
+pub fn offset_to_lsp_pos(
+    rope: &Rope,
+    offset: usize,
+    encoding: PositionEncoding,
+) -> Option<Position> {
+    let line = rope.try_char_to_line(offset).ok()?;
+    let line_start = rope.try_line_to_char(line).ok()?;
+    let character = char_to_column(rope, line_start, offset, encoding)?;
     Some(Position {
         line: line.try_into().ok()?,
         character: character.try_into().ok()?,
     })
 }
 
-pub fn range_to_lsp(rope: &Rope, span: &Span) -> Option<Range> {
+pub fn range_to_lsp(rope: &Rope, span: &Span, encoding: PositionEncoding) -> Option<Range> {
     Some(Range {
-        start: offset_to_lsp_pos(rope, span.start)?,
-        end: offset_to_lsp_pos(rope, span.end)?,
+        start: offset_to_lsp_pos(rope, span.start, encoding)?,
+        end: offset_to_lsp_pos(rope, span.end, encoding)?,
     })
 }
+
+// Applies a change sent via `didChange` and returns the replaced span, or `None`
+// if the change replaced the complete document
+pub fn apply_content_change(
+    rope: &mut Rope,
+    change: &TextDocumentContentChangeEvent,
+    encoding: PositionEncoding,
+) -> Option<Span> {
+    let edit = change.range.and_then(|range| {
+        let start = lsp_pos_to_offset(rope, &range.start, encoding)?.min(rope.len_chars());
+        let end = lsp_pos_to_offset(rope, &range.end, encoding)?.min(rope.len_chars());
+        Some(start..end.max(start))
+    });
+    match &edit {
+        Some(edit) => {
+            rope.remove(edit.clone());
+            rope.insert(edit.start, &change.text);
+        }
+        None => *rope = Rope::from_str(&change.text),
+    }
+    edit
+}
+
+// Shrinks the span to exclude trailing whitespace, e.g. the line break of a line
+pub fn trim_span_end(rope: &Rope, span: &Span) -> Span {
+    let mut end = span.end.min(rope.len_chars());
+    while end > span.start && rope.char(end - 1).is_whitespace() {
+        end -= 1;
+    }
+    span.start..end
+}
+
+#[test]
+fn test_position_encodings() {
+    // "ä" is two bytes in UTF-8, "𝄞" is four bytes in UTF-8 and two code units in UTF-16
+    let rope = Rope::from_str("# ä𝄞\nbuild --jobs=4 # 𝄞ä\n");
+    let flag_offset = "# ä𝄞\nbuild ".chars().count();
+    let comment_end = "# ä𝄞\nbuild --jobs=4 # 𝄞ä".chars().count();
+    for (encoding, column, comment_end_column) in [
+        (PositionEncoding::Utf8, 6, 23),
+        (PositionEncoding::Utf16, 6, 20),
+        (PositionEncoding::Utf32, 6, 19),
+    ] {
+        let pos = offset_to_lsp_pos(&rope, flag_offset, encoding).unwrap();
+        assert_eq!(pos, Position::new(1, column));
+        assert_eq!(lsp_pos_to_offset(&rope, &pos, encoding), Some(flag_offset));
+        let pos = offset_to_lsp_pos(&rope, comment_end, encoding).unwrap();
+        assert_eq!(pos, Position::new(1, comment_end_column));
+        assert_eq!(lsp_pos_to_offset(&rope, &pos, encoding), Some(comment_end));
+        // The end of the first line differs between the encodings
+        let line_end = offset_to_lsp_pos(&rope, 4, encoding).unwrap();
+        assert_eq!(
+            line_end.character,
+            match encoding {
+                PositionEncoding::Utf8 => 8,
+                PositionEncoding::Utf16 => 5,
+                PositionEncoding::Utf32 => 4,
+            }
+        );
+    }
+}
+
+#[test]
+fn test_negotiate_position_encoding() {
+    assert_eq!(negotiate_position_encoding(None), PositionEncoding::Utf16);
+    assert_eq!(
+        negotiate_position_encoding(Some(&[
+            PositionEncodingKind::new("utf-7"),
+            PositionEncodingKind::UTF8,
+            PositionEncodingKind::UTF16,
+        ])),
+        PositionEncoding::Utf8
+    );
+    assert_eq!(
+        negotiate_position_encoding(Some(&[PositionEncodingKind::UTF32])),
+        PositionEncoding::Utf32
+    );
+    assert_eq!(
+        negotiate_position_encoding(Some(&[])),
+        PositionEncoding::Utf16
+    );
+}
@@ -1,19 +1,44 @@
+use std::cell::RefCell;
 use std::io::Read;
 use std::ops::Deref;
-use std::path::Path;
-use std::{env, fs, io, process};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{env, fs, io, process, thread};
 
 use bazelrc_lsp::bazel_flags::{
-    load_bazel_flags_from_command, load_packaged_bazel_flags, BazelFlags,
+    combine_key_value_flags, load_custom_flags, try_load_packaged_bazel_flags, BazelFlags,
 };
 use bazelrc_lsp::bazel_version::{
     determine_bazelisk_version, find_closest_version, AVAILABLE_BAZEL_VERSIONS,
 };
-use bazelrc_lsp::diagnostic::diagnostics_from_string;
-use bazelrc_lsp::formatting::{pretty_print, FormatLineFlow};
-use bazelrc_lsp::language_server::{Backend, Settings};
+use bazelrc_lsp::code_action::apply_text_edits;
+use bazelrc_lsp::diagnostic::{diagnostics_for_unstable_flags, diagnostics_from_string};
+use bazelrc_lsp::file_utils::get_workspace_path;
+use bazelrc_lsp::flag_docs::{export_flags, render_flags_markdown};
+use bazelrc_lsp::flag_report::{
+    find_exposed_flags, find_upgrade_issues, format_exposure_report, format_upgrade_report,
+};
+use bazelrc_lsp::flag_value_style::{get_flag_value_style_edits, FlagValueStyle};
+use bazelrc_lsp::formatting::{pretty_print, FormatLineFlow, FormatStyle};
+use bazelrc_lsp::import_graph::{load_imported_files, read_file_from_disk, RcFile};
+use bazelrc_lsp::language_server::{Backend, BackendState, Settings};
+use bazelrc_lsp::lint_report::{format_human, format_json, format_sarif, LintFinding};
+use bazelrc_lsp::live_flags::{get_cache_dir, load_live_bazel_flags};
+use bazelrc_lsp::logging::init_logging;
+use bazelrc_lsp::lsp_utils::PositionEncoding;
+use bazelrc_lsp::organize::organize;
+use bazelrc_lsp::parse_dump::format_parse_dump;
+use bazelrc_lsp::parser::parse_from_str;
+use bazelrc_lsp::rc_discovery::discover_rc_files;
+use bazelrc_lsp::suppressions::filter_suppressed_diagnostics;
+use bazelrc_lsp::text_diff::unified_diff;
+use bazelrc_lsp::transport::{serve, ListenAddress};
+use bazelrc_lsp::version_resolution::WorkspaceFlags;
 use clap::{CommandFactory, Parser, Subcommand};
-use tower_lsp::{LspService, Server};
+use ropey::Rope;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity};
+use tower_lsp::LspService;
 use walkdir::WalkDir;
 
 #[derive(Parser)]
@@ -21,14 +46,20 @@ use walkdir::WalkDir;
 #[command(about = "Code Intelligence for bazelrc config files")]
 struct Cli {
     /// The Bazel version
-    #[arg(long, value_name = "VERSION", group = "bazel-version")]
+    #[arg(long, value_name = "VERSION", group = "bazel-version", global = true)]
     bazel_version: Option<String>,
     /// Path to a Bazel version
-    #[arg(long, value_name = "PATH", group = "bazel-version")]
+    #[arg(long, value_name = "PATH", group = "bazel-version", global = true)]
     bazel_path: Option<String>,
+    /// A JSON file with additional flags, in the format printed by the `flags` command
+    #[arg(long, value_name = "FILE", global = true)]
+    custom_flags: Option<PathBuf>,
     /// Should lines be combined / split when formatting bazelrc files?
     #[arg(long, default_value = "keep")]
     format_lines: FormatLineFlowCli,
+    /// Should flags be sorted alphabetically within each line when formatting?
+    #[arg(long)]
+    format_sort_flags: bool,
 
     #[command(subcommand)]
     command: Option<Commands>,
@@ -63,7 +94,7 @@ impl clap::ValueEnum for FormatLineFlowCli {
 #[derive(Subcommand)]
 enum Commands {
     /// Spawns the language server
-    Lsp {},
+    Lsp(LspArgs),
     /// Format a bazelrc file
     ///
     /// If no arguments are specified, format the bazelrc contents
@@ -73,6 +104,39 @@ enum Commands {
     Format(FormatArgs),
     /// Check your bazelrc files for mistakes
     Lint(LintArgs),
+    /// Generate a markdown reference of all flags of the Bazel version
+    Docs(DocsArgs),
+    /// Export all flags of the Bazel version, for consumption by other tools
+    ///
+    /// Includes the supported commands, documentation, allowed values and the
+    /// Bazel versions in which each flag was added or removed.
+    Flags(FlagsArgs),
+    /// Report the experimental and incompatible flags used by your bazelrc files
+    ///
+    /// Lists the status of each flag in the pinned Bazel version and in the
+    /// latest packaged Bazel version, to assess the risk of a Bazel upgrade.
+    ExperimentalFlags(ExperimentalFlagsArgs),
+    /// Report the flags which need attention when upgrading Bazel
+    ///
+    /// Lists every flag used by your bazelrc files which is removed, renamed
+    /// or newly deprecated between the two Bazel versions.
+    CheckUpgrade(CheckUpgradeArgs),
+    /// Rewrite `--flag value` into `--flag=value`, or the reverse
+    ///
+    /// Only the separators are changed, everything else is left untouched.
+    /// If no files are given, the bazelrc contents are read from stdin.
+    FixFlagValues(FixFlagValuesArgs),
+    /// Group the lines of bazelrc files by command and config, and sort their flags
+    ///
+    /// `import` lines are moved to the top and `try-import` lines to the bottom.
+    /// Lines for the same command and config keep their relative order.
+    /// If no files are given, the bazelrc contents are read from stdin.
+    Organize(OrganizeArgs),
+    /// Print the tokens and the parsed lines of a bazelrc file as JSON
+    ///
+    /// Useful for debugging and for reporting parser bugs. If no file is
+    /// specified, the bazelrc contents are read from stdin.
+    Dump(DumpArgs),
     /// List supported Bazel versions
     #[clap(hide = true)]
     BazelVersions {},
@@ -87,36 +151,154 @@ async fn main() {
     }
     // For backwards compatibility: If no command is specified, assume we should
     // launch the language server.
-    cli.command = Some(cli.command.unwrap_or(Commands::Lsp {}));
+    cli.command = Some(cli.command.unwrap_or(Commands::Lsp(LspArgs::default())));
 
-    let (bazel_flags, version_message) = load_bazel_flags(&cli);
+    let (bazel_flags, version_message) = if matches!(cli.command, Some(Commands::Lsp(_)))
+        && cli.bazel_path.is_some()
+    {
+        // The language server loads the flags from Bazel in the background. Until they
+        // arrive, the packaged flags are used.
+        let bazel_version = find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), "latest").0;
+        load_packaged_flags(&bazel_version, None)
+    } else {
+        load_bazel_flags(&cli)
+    };
+    let custom_flags = match &cli.custom_flags {
+        Some(path) => load_custom_flags(path).unwrap_or_else(|err| {
+            eprintln!(
+                "Failed to load the custom flags from {}: {err}",
+                path.display()
+            );
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    // The language server merges the custom flags separately for each workspace
+    let bazel_flags = if custom_flags.is_empty() || matches!(cli.command, Some(Commands::Lsp(_))) {
+        bazel_flags
+    } else {
+        bazel_flags.with_custom_flags(&custom_flags)
+    };
+    // Unless configured explicitly, the Bazel version is resolved separately for each workspace
+    let auto_detect_version = cli.bazel_version.is_none() && cli.bazel_path.is_none();
 
     match cli.command.unwrap() {
-        Commands::Lsp {} => {
-            let stdin = tokio::io::stdin();
-            let stdout = tokio::io::stdout();
-
-            let (service, socket) = LspService::new(|client| Backend {
-                client,
-                document_map: Default::default(),
-                bazel_flags,
-                settings: Settings {
-                    format_lines: cli.format_lines.0,
+        Commands::Lsp(args) => {
+            let (log_traces, log_level) = match init_logging(args.log_file.as_deref(), args.verbose)
+            {
+                Ok(logging) => logging,
+                Err(err) => {
+                    eprintln!("Failed to set up logging: {err}");
+                    process::exit(1);
                 }
-                .into(),
-                startup_warning: version_message,
-            });
-            Server::new(stdin, stdout, socket).serve(service).await;
+            };
+            // Each client gets its own server state. The log is only forwarded to the first one.
+            let mut log_traces = Some(log_traces);
+            let mut log_level = Some(log_level);
+            let shared_process = args.listen != ListenAddress::Stdio;
+            let make_service = || {
+                LspService::build(|client| {
+                    Backend::new(BackendState {
+                        client,
+                        document_map: Default::default(),
+                        bazeliskrc_documents: Default::default(),
+                        workspace_flags: WorkspaceFlags::new(
+                            bazel_flags.clone(),
+                            auto_detect_version,
+                        )
+                        .with_default_custom_flags(custom_flags.clone()),
+                        settings: std::sync::RwLock::new(Arc::new(Settings {
+                            format_lines: cli.format_lines.0,
+                            format_sort_flags: cli.format_sort_flags,
+                            ..Default::default()
+                        })),
+                        folder_settings: Default::default(),
+                        client_capabilities: Default::default(),
+                        startup_warning: version_message.clone(),
+                        semantic_token_results: Default::default(),
+                        next_result_id: Default::default(),
+                        workspace_roots: Default::default(),
+                        bazel_path: cli.bazel_path.clone(),
+                        starlark_flag_index: Default::default(),
+                        workspace_rc_files: Default::default(),
+                        flag_path_statuses: Default::default(),
+                        diagnostics_generations: Default::default(),
+                        diagnostics_revision: Default::default(),
+                        settings_revision: Default::default(),
+                        diagnostic_results: Default::default(),
+                        index_cache_dir: get_cache_dir(),
+                        workspace_indexes: Default::default(),
+                        trace: Default::default(),
+                        position_encoding: Default::default(),
+                        log_traces: log_traces.take().into(),
+                        log_level: log_level.take(),
+                        published_statuses: Default::default(),
+                        shared_process,
+                    })
+                })
+                .custom_method("bazelrc/configTree", Backend::config_tree)
+                .custom_method("bazelrc/serverStatus", Backend::server_status)
+                .custom_method("$/setTrace", Backend::set_trace)
+                .finish()
+            };
+            if let Err(err) = serve(&args.listen, make_service).await {
+                eprintln!("Failed to listen on {}: {err}", args.listen);
+                process::exit(1);
+            }
         }
         Commands::Format(args) => {
             if let Some(msg) = &version_message {
                 eprintln!("{}", msg);
             }
-            handle_format_cmd(&args, &bazel_flags, cli.format_lines.0);
+            let style = FormatStyle {
+                line_flow: cli.format_lines.0,
+                sort_flags: cli.format_sort_flags,
+            };
+            handle_format_cmd(&args, &bazel_flags, style);
         }
         Commands::Lint(args) => {
+            // Most checks need the flags. Explain why they are missing.
+            if let Some(msg) = version_message
+                .as_ref()
+                .filter(|_| bazel_flags.is_syntax_only())
+            {
+                eprintln!("{}", msg);
+            }
             handle_lint_cmd(&args, &bazel_flags);
         }
+        Commands::Docs(args) => {
+            if let Some(msg) = &version_message {
+                eprintln!("{}", msg);
+            }
+            handle_docs_cmd(&args, &bazel_flags);
+        }
+        Commands::Flags(args) => {
+            if let Some(msg) = &version_message {
+                eprintln!("{}", msg);
+            }
+            handle_flags_cmd(&args, &bazel_flags);
+        }
+        Commands::ExperimentalFlags(args) => {
+            if let Some(msg) = &version_message {
+                eprintln!("{}", msg);
+            }
+            handle_experimental_flags_cmd(&args, &bazel_flags);
+        }
+        Commands::CheckUpgrade(args) => {
+            handle_check_upgrade_cmd(&args);
+        }
+        Commands::FixFlagValues(args) => {
+            if let Some(msg) = &version_message {
+                eprintln!("{}", msg);
+            }
+            handle_fix_flag_values_cmd(&args, &bazel_flags);
+        }
+        Commands::Organize(args) => {
+            handle_organize_cmd(&args, &bazel_flags);
+        }
+        Commands::Dump(args) => {
+            handle_dump_cmd(&args);
+        }
         Commands::BazelVersions {} => {
             println!(
                 "{}",
@@ -126,31 +308,49 @@ async fn main() {
     };
 }
 
+// Falls back to the syntax-only features if the packaged flags can't be decoded. The
+// returned message explains why.
+fn load_packaged_flags(
+    bazel_version: &str,
+    message: Option<String>,
+) -> (BazelFlags, Option<String>) {
+    match try_load_packaged_bazel_flags(bazel_version) {
+        Ok(flags) => (flags, message),
+        Err(err) => (
+            BazelFlags::syntax_only(bazel_version),
+            Some(format!(
+                "Only syntax checks are available because the packaged flags could not be loaded: {err}"
+            )),
+        ),
+    }
+}
+
 fn load_bazel_flags(cli: &Cli) -> (BazelFlags, Option<String>) {
     if let Some(bazel_command) = &cli.bazel_path {
-        match load_bazel_flags_from_command(bazel_command) {
+        let cwd = env::current_dir().unwrap();
+        match load_live_bazel_flags(bazel_command, &cwd, get_cache_dir().as_deref()) {
             Ok(flags) => (flags, None),
             Err(msg) => {
                 let bazel_version =
                     find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), "latest").0;
                 let message =
                     format!("Using flags from Bazel {bazel_version} because running `{bazel_command}` failed:\n{}\n", msg);
-                (load_packaged_bazel_flags(&bazel_version), Some(message))
+                load_packaged_flags(&bazel_version, Some(message))
             }
         }
     } else if let Some(cli_version) = &cli.bazel_version {
         let (bazel_version, msg) =
             find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), cli_version);
-        (load_packaged_bazel_flags(&bazel_version), msg)
+        load_packaged_flags(&bazel_version, msg)
     } else if let Some(auto_detected) = determine_bazelisk_version(&env::current_dir().unwrap()) {
         let (bazel_version, msg) =
             find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), &auto_detected);
-        (load_packaged_bazel_flags(&bazel_version), msg)
+        load_packaged_flags(&bazel_version, msg)
     } else {
         let bazel_version = find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), "latest").0;
         let message = format!(
             "Using flags from Bazel {bazel_version} because auto-detecting the Bazel version failed");
-        (load_packaged_bazel_flags(&bazel_version), Some(message))
+        load_packaged_flags(&bazel_version, Some(message))
     }
 }
 
@@ -216,28 +416,163 @@ struct LintArgs {
     /// Suppress output and only indicate errors through the exit code
     #[arg(long, group = "fmt-action")]
     quiet: bool,
+    /// Keep running and re-lint whenever one of the files or their imports change
+    #[arg(long, conflicts_with = "quiet")]
+    watch: bool,
+    /// Also watch imported files outside the workspace (e.g., `~/.bazelrc`)
+    #[arg(long, requires = "watch")]
+    include_files_outside_workspace: bool,
+    /// How to print the diagnostics
+    #[arg(long, value_enum, default_value = "human")]
+    output_format: LintOutputFormat,
+    /// The lowest severity of diagnostics which makes the command fail
+    #[arg(long, value_enum, default_value = "warning")]
+    fail_on: LintSeverity,
+    /// Also report experimental (`--experimental_*`) and incompatible (`--incompatible_*`) flags
+    #[arg(long)]
+    unstable_flags: bool,
+    /// An experimental or incompatible flag which is used on purpose. Can be repeated.
+    #[arg(long, value_name = "FLAG", requires = "unstable_flags")]
+    allow_unstable_flag: Vec<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LintOutputFormat {
+    /// `file:line:column: severity: message`
+    Human,
+    /// A JSON array with one object per diagnostic
+    Json,
+    /// SARIF 2.1.0, as consumed by code review bots
+    Sarif,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum LintSeverity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl LintSeverity {
+    // Diagnostics without an explicit severity are treated as errors
+    fn is_reached_by(self, diagnostic: &Diagnostic) -> bool {
+        let threshold = match self {
+            LintSeverity::Error => DiagnosticSeverity::ERROR,
+            LintSeverity::Warning => DiagnosticSeverity::WARNING,
+            LintSeverity::Info => DiagnosticSeverity::INFORMATION,
+            LintSeverity::Hint => DiagnosticSeverity::HINT,
+        };
+        diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR) <= threshold
+    }
 }
 
 fn handle_lint_cmd(args: &LintArgs, bazel_flags: &BazelFlags) {
-    let had_errors = for_each_input_file(&args.files, |input: String, path: Option<&Path>| {
-        let diagnostics = diagnostics_from_string(&input, bazel_flags, path);
-        if !args.quiet {
-            for d in &diagnostics {
-                // TODO: improve printing, either using ariadne or codespan-reporting
-                println!(
-                    "{}: {}",
-                    path.and_then(Path::to_str).unwrap_or("<stdin>"),
-                    d.message
+    if args.watch && args.files.is_empty() {
+        let mut cmd = Cli::command();
+        cmd.error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "If the `--watch` flag is specified, input file(s) must be specified as part of the command line invocation",
+        ).exit();
+    }
+
+    // SARIF reports count columns in UTF-16 code units, like LSP clients by default
+    let encoding = PositionEncoding::Utf16;
+    loop {
+        let watched_files = RefCell::new(Vec::<PathBuf>::new());
+        let findings = RefCell::new(Vec::<LintFinding>::new());
+        let had_errors = for_each_input_file(&args.files, |input: String, path: Option<&Path>| {
+            if let Some(p) = path {
+                let file = RcFile::from_str(p.to_path_buf(), &input, bazel_flags);
+                let imports = load_imported_files(
+                    p,
+                    &file.lines,
+                    bazel_flags,
+                    args.include_files_outside_workspace,
+                    read_file_from_disk,
                 );
+                let mut watched = watched_files.borrow_mut();
+                watched.push(file.path);
+                watched.extend(imports.into_iter().map(|f| f.path));
+            }
+            let mut diagnostics = diagnostics_from_string(&input, bazel_flags, path, encoding);
+            if args.unstable_flags {
+                let file = RcFile::from_str(PathBuf::new(), &input, bazel_flags);
+                diagnostics.extend(filter_suppressed_diagnostics(
+                    &file.rope,
+                    &file.lines,
+                    diagnostics_for_unstable_flags(
+                        &file.rope,
+                        &file.lines,
+                        bazel_flags,
+                        &args.allow_unstable_flag,
+                        encoding,
+                    ),
+                    encoding,
+                ));
+            }
+            let had_diagnostics = diagnostics.iter().any(|d| args.fail_on.is_reached_by(d));
+            let path_str = path.map(|p| p.to_string_lossy().into_owned());
+            findings
+                .borrow_mut()
+                .extend(diagnostics.into_iter().map(|diagnostic| LintFinding {
+                    path: path_str.clone().unwrap_or("<stdin>".to_string()),
+                    diagnostic,
+                }));
+            had_diagnostics
+        });
+        if !args.quiet {
+            let findings = findings.into_inner();
+            match args.output_format {
+                LintOutputFormat::Human => print!("{}", format_human(&findings)),
+                LintOutputFormat::Json => println!("{:#}", format_json(&findings)),
+                LintOutputFormat::Sarif => println!("{:#}", format_sarif(&findings)),
             }
         }
-        !diagnostics.is_empty()
-    });
-    if had_errors {
-        process::exit(1);
+        if !args.watch {
+            if had_errors {
+                process::exit(1);
+            }
+            return;
+        }
+        eprintln!("Watching for changes...");
+        wait_for_changes(&watched_files.into_inner());
+        eprintln!("\n--- Changes detected, linting again ---");
+    }
+}
+
+// Blocks until the modification time of one of the given files changes
+fn wait_for_changes(files: &[PathBuf]) {
+    let get_mtimes = || {
+        files
+            .iter()
+            .map(|f| fs::metadata(f).and_then(|m| m.modified()).ok())
+            .collect::<Vec<_>>()
+    };
+    let initial_mtimes = get_mtimes();
+    loop {
+        thread::sleep(Duration::from_millis(500));
+        if get_mtimes() != initial_mtimes {
+            return;
+        }
     }
 }
 
+#[derive(Parser, Default)]
+struct LspArgs {
+    /// Append the server's log to this file, instead of writing it to stderr
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+    /// Also log debug messages and the time spent on parsing, indexing and running Bazel
+    #[arg(long)]
+    verbose: bool,
+    /// Where to accept clients: `stdio`, `tcp://HOST:PORT` or `ws://HOST:PORT`. TCP and
+    /// WebSocket accept any number of local clients, e.g. for remote development
+    /// environments. Only loopback addresses are allowed.
+    #[arg(long, value_name = "ADDRESS", default_value = "stdio")]
+    listen: ListenAddress,
+}
+
 #[derive(Parser)]
 struct FormatArgs {
     /// File(s) to format
@@ -248,9 +583,12 @@ struct FormatArgs {
     /// Only check if the given file(s) are formatted correctly
     #[arg(long, group = "fmt-action")]
     check: bool,
+    /// Print the changes which would be applied as a unified diff
+    #[arg(long, group = "fmt-action")]
+    diff: bool,
 }
 
-fn handle_format_cmd(args: &FormatArgs, bazel_flags: &BazelFlags, line_flow: FormatLineFlow) {
+fn handle_format_cmd(args: &FormatArgs, bazel_flags: &BazelFlags, style: FormatStyle) {
     if args.inplace && args.files.is_empty() {
         let mut cmd = Cli::command();
         cmd.error(
@@ -260,13 +598,20 @@ fn handle_format_cmd(args: &FormatArgs, bazel_flags: &BazelFlags, line_flow: For
     }
 
     let had_errors = for_each_input_file(&args.files, |input: String, path: Option<&Path>| {
-        let result = pretty_print(&input, bazel_flags, line_flow);
+        let result = pretty_print(&input, bazel_flags, style);
         match result {
             Ok(formatted) => {
-                if args.check {
-                    let input_name = path
-                        .map(|p| p.to_string_lossy().into_owned())
-                        .unwrap_or("<stdin>".to_string());
+                let input_name = path
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or("<stdin>".to_string());
+                if args.diff {
+                    // Like `--check`, exit with an error if any file needs reformatting
+                    print!(
+                        "{}",
+                        unified_diff(&input, &formatted, &input_name, &input_name)
+                    );
+                    return formatted != input;
+                } else if args.check {
                     if formatted != input {
                         println!(
                             "{} is NOT correctly formatted and needs reformatting",
@@ -299,8 +644,306 @@ fn handle_format_cmd(args: &FormatArgs, bazel_flags: &BazelFlags, line_flow: For
     }
 }
 
+#[derive(Parser)]
+struct FixFlagValuesArgs {
+    /// File(s) to rewrite
+    files: Vec<String>,
+    /// How values should be attached to their flags
+    #[arg(long, default_value = "equals")]
+    style: FlagValueStyleCli,
+    /// Inplace edit <file>s
+    #[arg(short = 'i', long, group = "fix-action")]
+    inplace: bool,
+    /// Only check if the given file(s) already use the style
+    #[arg(long, group = "fix-action")]
+    check: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum FlagValueStyleCli {
+    /// `--flag=value`
+    Equals,
+    /// `--flag value`
+    Space,
+}
+
+fn handle_fix_flag_values_cmd(args: &FixFlagValuesArgs, bazel_flags: &BazelFlags) {
+    if args.inplace && args.files.is_empty() {
+        let mut cmd = Cli::command();
+        cmd.error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "If the `-i` flag is specified, input file(s) must be specified as part of the command line invocation",
+        ).exit();
+    }
+    let style = match args.style {
+        FlagValueStyleCli::Equals => FlagValueStyle::Equals,
+        FlagValueStyleCli::Space => FlagValueStyle::Space,
+    };
+
+    let had_errors = for_each_input_file(&args.files, |input: String, path: Option<&Path>| {
+        let input_name = path
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or("<stdin>".to_string());
+        let rope = Rope::from_str(&input);
+        let mut lines = parse_from_str(&input).lines;
+        combine_key_value_flags(&mut lines, bazel_flags);
+        let edits =
+            get_flag_value_style_edits(bazel_flags, &rope, &lines, style, PositionEncoding::Utf16);
+        let fixed = apply_text_edits(&input, &edits, PositionEncoding::Utf16);
+        if args.check {
+            if !edits.is_empty() {
+                println!("{} has {} flag(s) to rewrite", input_name, edits.len());
+                return true;
+            }
+        } else if args.inplace {
+            if !edits.is_empty() {
+                fs::write(path.unwrap(), fixed).expect("Failed to write file");
+            }
+        } else {
+            if let Some(p) = path {
+                println!("--- {} ---", p.to_string_lossy());
+            }
+            print!("{}", fixed);
+        }
+        false
+    });
+    if had_errors {
+        process::exit(1);
+    }
+}
+
+#[derive(Parser)]
+struct OrganizeArgs {
+    /// File(s) to organize
+    files: Vec<String>,
+    /// Inplace edit <file>s
+    #[arg(short = 'i', long, group = "organize-action")]
+    inplace: bool,
+    /// Only check if the given file(s) are already organized
+    #[arg(long, group = "organize-action")]
+    check: bool,
+}
+
+fn handle_organize_cmd(args: &OrganizeArgs, bazel_flags: &BazelFlags) {
+    if args.inplace && args.files.is_empty() {
+        let mut cmd = Cli::command();
+        cmd.error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "If the `-i` flag is specified, input file(s) must be specified as part of the command line invocation",
+        ).exit();
+    }
+
+    let had_errors = for_each_input_file(&args.files, |input: String, path: Option<&Path>| {
+        let organized = match organize(&input, bazel_flags) {
+            Ok(organized) => organized,
+            Err(errors) => {
+                for e in errors {
+                    eprintln!("{}", e);
+                }
+                return true;
+            }
+        };
+        let input_name = path
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or("<stdin>".to_string());
+        if args.check {
+            if organized != input {
+                println!("{} is NOT organized", input_name);
+                return true;
+            }
+        } else if args.inplace {
+            if organized != input {
+                fs::write(path.unwrap(), organized).expect("Failed to write file");
+            }
+        } else {
+            if let Some(p) = path {
+                println!("--- {} ---", p.to_string_lossy());
+            }
+            print!("{}", organized);
+        }
+        false
+    });
+    if had_errors {
+        process::exit(1);
+    }
+}
+
+#[derive(Parser)]
+struct DocsArgs {
+    /// Write the reference to the given file instead of stdout
+    #[arg(short = 'o', long, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// The title of the generated document
+    #[arg(long, default_value = "Bazel flag reference")]
+    title: String,
+}
+
+fn handle_docs_cmd(args: &DocsArgs, bazel_flags: &BazelFlags) {
+    let markdown = render_flags_markdown(bazel_flags, &args.title);
+    match &args.output {
+        Some(path) => fs::write(path, markdown).expect("Failed to write file"),
+        None => print!("{}", markdown),
+    }
+}
+
+#[derive(Parser)]
+struct FlagsArgs {
+    /// How to print the flags
+    #[arg(long, value_enum, default_value = "json")]
+    format: FlagsOutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum FlagsOutputFormat {
+    /// A JSON array with one object per flag
+    Json,
+    /// One JSON object per line
+    Ndjson,
+}
+
+fn handle_flags_cmd(args: &FlagsArgs, bazel_flags: &BazelFlags) {
+    let flags = export_flags(bazel_flags);
+    match args.format {
+        FlagsOutputFormat::Json => println!("{:#}", serde_json::Value::from(flags)),
+        FlagsOutputFormat::Ndjson => {
+            for flag in flags {
+                println!("{flag}");
+            }
+        }
+    }
+}
+
+#[derive(Parser)]
+struct ExperimentalFlagsArgs {
+    /// The bazelrc file(s) to analyze, including their imports. Defaults to the
+    /// bazelrc files Bazel reads within the current workspace.
+    files: Vec<PathBuf>,
+}
+
+// Loads the given bazelrc files (by default the ones Bazel reads within the workspace) and all
+// their imports
+fn load_report_files(paths: &[PathBuf], bazel_flags: &BazelFlags) -> Vec<RcFile> {
+    let roots = if paths.is_empty() {
+        let cwd = env::current_dir().unwrap();
+        match get_workspace_path(&cwd) {
+            Some(workspace) => discover_rc_files(&cwd)
+                .into_iter()
+                .map(|(_, path)| path)
+                .filter(|path| path.starts_with(&workspace) && path.is_file())
+                .collect(),
+            None => {
+                eprintln!("Unable to determine the workspace. Please specify the bazelrc file(s)");
+                process::exit(1);
+            }
+        }
+    } else {
+        paths.to_vec()
+    };
+
+    // Collect the files, together with all their imports
+    let mut files = Vec::<RcFile>::new();
+    for root in roots {
+        let Some(contents) = read_file_from_disk(&root) else {
+            eprintln!("Failed to read {}", root.display());
+            process::exit(1);
+        };
+        let root = RcFile::from_str(root, &contents, bazel_flags);
+        let imports = load_imported_files(
+            &root.path,
+            &root.lines,
+            bazel_flags,
+            false,
+            read_file_from_disk,
+        );
+        for file in std::iter::once(root).chain(imports) {
+            if !files.iter().any(|f| f.path == file.path) {
+                files.push(file);
+            }
+        }
+    }
+    files
+}
+
+fn handle_experimental_flags_cmd(args: &ExperimentalFlagsArgs, bazel_flags: &BazelFlags) {
+    let files = load_report_files(&args.files, bazel_flags);
+    let latest_version = find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), "latest").0;
+    let latest_flags = try_load_packaged_bazel_flags(&latest_version).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(1);
+    });
+    let exposed = find_exposed_flags(&files, bazel_flags, &latest_flags);
+    print!("{}", format_exposure_report(&exposed, &latest_version));
+}
+
+#[derive(Parser)]
+struct DumpArgs {
+    /// The bazelrc file to dump
+    file: Option<PathBuf>,
+}
+
+fn handle_dump_cmd(args: &DumpArgs) {
+    let contents = match &args.file {
+        Some(path) => fs::read_to_string(path).unwrap_or_else(|err| {
+            eprintln!("Failed to read {}: {}", path.display(), err);
+            process::exit(1);
+        }),
+        None => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .expect("Failed to read from stdin");
+            input
+        }
+    };
+    println!(
+        "{:#}",
+        format_parse_dump(&contents, PositionEncoding::Utf16)
+    );
+}
+
 #[test]
 fn verify_cli() {
     use clap::CommandFactory;
     Cli::command().debug_assert();
 }
+
+#[derive(Parser)]
+struct CheckUpgradeArgs {
+    /// The Bazel version currently in use
+    #[arg(long, value_name = "VERSION")]
+    from: String,
+    /// The Bazel version to upgrade to. Defaults to the latest packaged version.
+    #[arg(long, value_name = "VERSION", default_value = "latest")]
+    to: String,
+    /// The bazelrc file(s) to analyze, including their imports. Defaults to the
+    /// `.bazelrc` of the current workspace.
+    files: Vec<PathBuf>,
+}
+
+fn handle_check_upgrade_cmd(args: &CheckUpgradeArgs) {
+    let load_version = |requested: &str| {
+        let (version, msg) = find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), requested);
+        // Requesting the latest version is not worth a message
+        if let Some(msg) = msg.filter(|_| requested != "latest") {
+            eprintln!("{}", msg);
+        }
+        // The report compares the flags of both versions, which is pointless without them
+        let flags = try_load_packaged_bazel_flags(&version).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        });
+        (version, flags)
+    };
+    let (from_version, from_flags) = load_version(&args.from);
+    let (to_version, to_flags) = load_version(&args.to);
+
+    let files = load_report_files(&args.files, &from_flags);
+    let issues = find_upgrade_issues(&files, &from_flags, &to_flags);
+    print!(
+        "{}",
+        format_upgrade_report(&issues, &from_version, &to_version)
+    );
+    if !issues.is_empty() {
+        process::exit(1);
+    }
+}
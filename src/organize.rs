@@ -0,0 +1,272 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{CodeAction, CodeActionKind, CodeActionOrCommand, TextEdit, Url};
+
+use crate::{
+    bazel_flags::{combine_key_value_flags, BazelFlags},
+    code_action::make_workspace_edit,
+    formatting::{format_line_into, sort_line_flags},
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::{parse_from_str, Line, ParserResult},
+};
+
+// The kind of the action organizing a whole bazelrc file, analogous to `source.organizeImports`
+pub const ORGANIZE_LINES_KIND: &str = "source.organizeLines";
+
+// A line together with the comment lines directly above it
+struct Stanza<'a> {
+    comments: Vec<&'a Line>,
+    line: &'a Line,
+}
+
+fn is_empty_line(line: &Line) -> bool {
+    line.command.is_none()
+        && line.config.is_none()
+        && line.flags.is_empty()
+        && line.comment.is_none()
+}
+
+// Separates groups of lines by an empty line
+fn push_separator(lines: &mut Vec<Line>) {
+    if lines.last().is_some_and(|l| !is_empty_line(l)) {
+        lines.push(Line::default());
+    }
+}
+
+fn get_command(line: &Line) -> &str {
+    line.command.as_ref().map(|c| c.0.as_str()).unwrap_or("")
+}
+
+// The position of a command within an organized file: `startup` lines first, followed
+// by `common` and `always`, which apply to all commands. The remaining commands are
+// sorted alphabetically.
+fn command_rank(command: &str) -> (usize, &str) {
+    match command {
+        "startup" => (0, ""),
+        "common" => (1, ""),
+        "always" => (2, ""),
+        _ => (3, command),
+    }
+}
+
+fn is_import(stanza: &Stanza) -> bool {
+    matches!(get_command(stanza.line), "import" | "try-import")
+}
+
+// Appends the stanzas, with an empty line in front of each group of lines sharing the
+// same command and config
+fn push_stanzas(result: &mut Vec<Line>, stanzas: &[Stanza], bazel_flags: &BazelFlags) {
+    let mut prev_key = None;
+    for stanza in stanzas {
+        let key = (
+            get_command(stanza.line),
+            stanza.line.config.as_ref().map(|c| &c.0),
+        );
+        if prev_key.is_none_or(|k| k != key) {
+            push_separator(result);
+        }
+        prev_key = Some(key);
+        result.extend(stanza.comments.iter().map(|l| (*l).clone()));
+        let mut line = stanza.line.clone();
+        sort_line_flags(&mut line, bazel_flags);
+        result.push(line);
+    }
+}
+
+// Reorders the lines of a bazelrc file:
+// * a leading comment block, separated by an empty line, stays at the top
+// * `import` and `try-import` lines stay where they are. Bazel reads the imported file in
+//   place of the import, so moving lines across an import changes which values win.
+// * between the imports, lines are grouped by command, then config, with an empty line
+//   between groups. Lines of the same group keep their relative order, since later lines
+//   override earlier ones.
+// * the flags of each line are sorted alphabetically
+//
+// Comment lines move together with the line following them. Returns `None` if the
+// file does not contain any commands.
+pub fn organize_lines(lines: &[Line], bazel_flags: &BazelFlags) -> Option<Vec<Line>> {
+    let first_command = lines.iter().position(|l| l.command.is_some())?;
+    let header_len = lines[..first_command]
+        .iter()
+        .rposition(is_empty_line)
+        .map_or(0, |i| i + 1);
+
+    let mut stanzas = Vec::<Stanza>::new();
+    let mut comments = Vec::<&Line>::new();
+    for line in &lines[header_len..] {
+        if line.command.is_some() {
+            stanzas.push(Stanza {
+                comments: std::mem::take(&mut comments),
+                line,
+            });
+        } else if !is_empty_line(line) {
+            comments.push(line);
+        }
+    }
+    let footer = comments;
+
+    let mut result = lines[..header_len].to_vec();
+    for segment in stanzas.chunk_by_mut(|a, b| is_import(a) == is_import(b)) {
+        if !is_import(&segment[0]) {
+            // A stable sort, keeping the order of the lines within each group
+            segment.sort_by(|a, b| {
+                let config = |s: &Stanza| s.line.config.as_ref().map(|c| c.0.clone());
+                command_rank(get_command(a.line))
+                    .cmp(&command_rank(get_command(b.line)))
+                    .then_with(|| config(a).cmp(&config(b)))
+            });
+        }
+        push_stanzas(&mut result, segment, bazel_flags);
+    }
+    if !footer.is_empty() {
+        push_separator(&mut result);
+        result.extend(footer.into_iter().cloned());
+    }
+    Some(result)
+}
+
+// Renders the organized lines. Lines which were spread over multiple physical lines
+// keep their `\` line continuations.
+pub fn organize_text(rope: &Rope, lines: &[Line], bazel_flags: &BazelFlags) -> Option<String> {
+    let mut out = String::with_capacity(rope.len_chars());
+    for line in organize_lines(lines, bazel_flags)? {
+        let text = rope.get_slice(line.span.clone())?.to_string();
+        let is_continued = text.trim_end_matches(['\r', '\n']).contains('\n');
+        format_line_into(&mut out, &line, is_continued);
+    }
+    Some(out)
+}
+
+// Parses and organizes the given string, as done by `bazelrc-lsp organize`
+pub fn organize(str: &str, bazel_flags: &BazelFlags) -> Result<String, Vec<String>> {
+    let ParserResult {
+        tokens: _,
+        mut lines,
+        errors,
+    } = parse_from_str(str);
+    if !errors.is_empty() {
+        return Err(errors
+            .into_iter()
+            .map(|e| format!("{}", e))
+            .collect::<Vec<_>>());
+    }
+    combine_key_value_flags(&mut lines, bazel_flags);
+    let rope = Rope::from_str(str);
+    Ok(organize_text(&rope, &lines, bazel_flags).unwrap_or(str.to_string()))
+}
+
+// Offers to organize the whole file, independent of the selected range
+pub fn organize_lines_action(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    uri: &Url,
+    lines: &[Line],
+    resolve_edits: bool,
+    encoding: PositionEncoding,
+) -> Option<CodeActionOrCommand> {
+    let organized = organize_text(rope, lines, bazel_flags)?;
+    if *rope == organized {
+        return None;
+    }
+    let edit = resolve_edits.then(|| {
+        let edits = range_to_lsp(rope, &(0..rope.len_chars()), encoding).map(|range| TextEdit {
+            range,
+            new_text: organized,
+        });
+        make_workspace_edit(uri, edits.into_iter().collect::<Vec<_>>())
+    });
+    Some(CodeActionOrCommand::CodeAction(CodeAction {
+        title: "Organize lines by command and config".to_string(),
+        kind: Some(CodeActionKind::new(ORGANIZE_LINES_KIND)),
+        edit,
+        ..Default::default()
+    }))
+}
+
+#[test]
+fn test_organize() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let run = |str: &str| organize(str, &bazel_flags).unwrap();
+
+    assert_eq!(
+        run("# Shared settings\n\
+            \n\
+            try-import %workspace%/user.bazelrc\n\
+            test --test_output=errors\n\
+            # Faster builds\n\
+            build:ci --jobs=8 --keep_going\n\
+            build --jobs=4\n\
+            \n\
+            import %workspace%/common.bazelrc\n\
+            build:ci --jobs=16\n\
+            common --color=yes\n\
+            startup --batch\n\
+            build --copt=-g -c opt\n\
+            # The end\n"),
+        "# Shared settings\n\
+        \n\
+        try-import %workspace%/user.bazelrc\n\
+        \n\
+        build --jobs=4\n\
+        \n\
+        # Faster builds\n\
+        build:ci --jobs=8 --keep_going\n\
+        \n\
+        test --test_output=errors\n\
+        \n\
+        import %workspace%/common.bazelrc\n\
+        \n\
+        startup --batch\n\
+        \n\
+        common --color=yes\n\
+        \n\
+        build -c opt --copt=-g\n\
+        \n\
+        build:ci --jobs=16\n\
+        \n\
+        # The end\n"
+    );
+    // Already organized files are unchanged, line continuations are kept
+    let organized = "build --jobs=4\n\ntest:ci \\\n    --keep_going \\\n    --test_output=errors\n";
+    assert_eq!(run(organized), organized);
+    // Comments directly above the first line are not a header
+    assert_eq!(
+        run("# Tests\ntest --keep_going\nbuild --jobs=4\n"),
+        "build --jobs=4\n\n# Tests\ntest --keep_going\n"
+    );
+    assert_eq!(run("# Only comments\n"), "# Only comments\n");
+    assert!(organize("build \"--unclosed", &bazel_flags).is_err());
+}
+
+#[test]
+fn test_organize_lines_action() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, code_action::apply_text_edits};
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let uri = Url::parse("file:///ws/.bazelrc").unwrap();
+    let action = |text: &str| {
+        let rope = Rope::from_str(text);
+        let mut lines = parse_from_str(text).lines;
+        combine_key_value_flags(&mut lines, &bazel_flags);
+        match organize_lines_action(
+            &bazel_flags,
+            &rope,
+            &uri,
+            &lines,
+            true,
+            PositionEncoding::Utf16,
+        )? {
+            CodeActionOrCommand::CodeAction(action) => {
+                let edits = action.edit?.changes?.remove(&uri)?;
+                Some(apply_text_edits(text, &edits, PositionEncoding::Utf16))
+            }
+            CodeActionOrCommand::Command(_) => None,
+        }
+    };
+    assert_eq!(
+        action("test --jobs=4\nbuild --keep_going\n"),
+        Some("build --keep_going\n\ntest --jobs=4\n".to_string())
+    );
+    assert_eq!(action("build --keep_going\n"), None);
+}
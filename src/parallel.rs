@@ -0,0 +1,36 @@
+use rayon::prelude::*;
+
+// Maps the items on rayon's global thread pool, preserving their order. The pool is
+// shared by all requests, such that no threads are spawned per call. `on_done` is
+// called from the worker threads after each item, e.g. to report progress.
+pub fn map_in_parallel<T, R, F>(items: &[T], f: F, on_done: &(dyn Fn() + Sync)) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    // Items are handed out one by one, such that a few large files don't stall a thread
+    items
+        .par_iter()
+        .with_max_len(1)
+        .map(|item| {
+            let result = f(item);
+            on_done();
+            result
+        })
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn test_map_in_parallel() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let items = (0..1000).collect::<Vec<_>>();
+    let done = AtomicUsize::new(0);
+    let results = map_in_parallel(&items, |i| i * 2, &|| {
+        done.fetch_add(1, Ordering::Relaxed);
+    });
+    assert_eq!(results, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+    assert_eq!(done.load(Ordering::Relaxed), 1000);
+    assert!(map_in_parallel(&[] as &[u32], |i| *i, &|| {}).is_empty());
+}
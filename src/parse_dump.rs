@@ -0,0 +1,127 @@
+use ropey::Rope;
+use serde_json::{json, Value};
+
+use crate::{
+    diagnostic::diagnostics_from_parser,
+    lsp_utils::{offset_to_lsp_pos, PositionEncoding},
+    parser::{parse_from_str, Flag, Line, ParserResult},
+    tokenizer::{Span, Spanned, Token},
+};
+
+// Spans are character offsets. Line and column numbers of the start are 1-based.
+fn span_json(rope: &Rope, span: &Span, encoding: PositionEncoding) -> Value {
+    let start = offset_to_lsp_pos(rope, span.start, encoding).unwrap_or_default();
+    json!({
+        "start": span.start,
+        "end": span.end,
+        "line": start.line + 1,
+        "column": start.character + 1,
+    })
+}
+
+fn spanned_json(
+    rope: &Rope,
+    spanned: &Option<Spanned<String>>,
+    encoding: PositionEncoding,
+) -> Value {
+    match spanned {
+        Some((text, span)) => json!({
+            "text": text,
+            "span": span_json(rope, span, encoding),
+        }),
+        None => Value::Null,
+    }
+}
+
+fn token_json(rope: &Rope, (token, span): &Spanned<Token>, encoding: PositionEncoding) -> Value {
+    let (kind, text) = match token {
+        Token::Token(text) => ("token", Some(text)),
+        Token::Comment(text) => ("comment", Some(text)),
+        Token::Newline => ("newline", None),
+        Token::EscapedNewline => ("escapedNewline", None),
+    };
+    json!({
+        "kind": kind,
+        "text": text,
+        "span": span_json(rope, span, encoding),
+    })
+}
+
+fn flag_json(rope: &Rope, flag: &Flag, encoding: PositionEncoding) -> Value {
+    json!({
+        "name": spanned_json(rope, &flag.name, encoding),
+        "value": spanned_json(rope, &flag.value, encoding),
+    })
+}
+
+fn line_json(rope: &Rope, line: &Line, encoding: PositionEncoding) -> Value {
+    json!({
+        "command": spanned_json(rope, &line.command, encoding),
+        "config": spanned_json(rope, &line.config, encoding),
+        "flags": line.flags.iter().map(|f| flag_json(rope, f, encoding)).collect::<Vec<_>>(),
+        "comment": spanned_json(rope, &line.comment, encoding),
+        "span": span_json(rope, &line.span, encoding),
+    })
+}
+
+// The token stream and the parsed lines of a bazelrc file, as printed by `bazelrc-lsp dump`.
+// Flags are reported as written, i.e. `--flag value` is not yet combined into one flag.
+pub fn format_parse_dump(contents: &str, encoding: PositionEncoding) -> Value {
+    let rope = Rope::from_str(contents);
+    let ParserResult {
+        tokens,
+        lines,
+        errors,
+    } = parse_from_str(contents);
+    let errors = diagnostics_from_parser(&rope, &errors, encoding)
+        .map(|d| {
+            json!({
+                "message": d.message,
+                "line": d.range.start.line + 1,
+                "column": d.range.start.character + 1,
+            })
+        })
+        .collect::<Vec<_>>();
+    json!({
+        "tokens": tokens.iter().map(|t| token_json(&rope, t, encoding)).collect::<Vec<_>>(),
+        "lines": lines.iter().map(|l| line_json(&rope, l, encoding)).collect::<Vec<_>>(),
+        "errors": errors,
+    })
+}
+
+#[test]
+fn test_parse_dump() {
+    let dump = format_parse_dump("build:ci --jobs 4 # fast\n", PositionEncoding::Utf16);
+    assert_eq!(
+        dump["tokens"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["kind"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["token", "token", "token", "comment", "newline"]
+    );
+    assert_eq!(
+        dump["lines"][0],
+        json!({
+            "command": {"text": "build", "span": {"start": 0, "end": 5, "line": 1, "column": 1}},
+            "config": {"text": "ci", "span": {"start": 5, "end": 8, "line": 1, "column": 6}},
+            "flags": [
+                {
+                    "name": {"text": "--jobs", "span": {"start": 9, "end": 15, "line": 1, "column": 10}},
+                    "value": null,
+                },
+                {
+                    "name": null,
+                    "value": {"text": "4", "span": {"start": 16, "end": 17, "line": 1, "column": 17}},
+                },
+            ],
+            "comment": {"text": " fast", "span": {"start": 18, "end": 24, "line": 1, "column": 19}},
+            "span": {"start": 0, "end": 25, "line": 1, "column": 1},
+        })
+    );
+    assert_eq!(dump["errors"], json!([]));
+
+    let dump = format_parse_dump("build \"--unclosed", PositionEncoding::Utf16);
+    assert_eq!(dump["errors"][0]["line"], 1);
+}
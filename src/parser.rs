@@ -1,14 +1,17 @@
 use chumsky::{error::Simple, Parser};
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
-use crate::tokenizer::{tokenizer, Span, Spanned, Token};
+use crate::tokenizer::{tokenizer, Token};
+pub use crate::tokenizer::{Span, Spanned};
 
-#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Clone, Debug, PartialEq, Eq, Default, Deserialize, Serialize)]
 pub struct Flag {
     pub name: Option<Spanned<String>>,
     pub value: Option<Spanned<String>>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Clone, Debug, PartialEq, Eq, Default, Deserialize, Serialize)]
 pub struct Line {
     pub command: Option<Spanned<String>>,
     pub config: Option<Spanned<String>>,
@@ -134,6 +137,89 @@ pub fn parse_from_str(str: &str) -> ParserResult {
     }
 }
 
+fn shift_span(span: &mut Span, delta: isize) {
+    span.start = span.start.wrapping_add_signed(delta);
+    span.end = span.end.wrapping_add_signed(delta);
+}
+
+// Moves the line by `delta` characters
+fn shift_line(line: &mut Line, delta: isize) {
+    let spanned = line
+        .command
+        .iter_mut()
+        .chain(line.config.iter_mut())
+        .chain(line.comment.iter_mut())
+        .chain(
+            line.flags
+                .iter_mut()
+                .flat_map(|f| f.name.iter_mut().chain(f.value.iter_mut())),
+        );
+    for (_, span) in spanned {
+        shift_span(span, delta);
+    }
+    shift_span(&mut line.span, delta);
+}
+
+// Updates the lines after the characters `edit` of the previous text were replaced by
+// `inserted_chars` characters, resulting in `rope`. Only the affected lines are re-parsed.
+// Returns the indices of the re-parsed lines, or `None` if the edit introduced parser errors,
+// in which case the complete text should be re-parsed to report the errors.
+pub fn reparse_lines(
+    lines: &mut Vec<Line>,
+    rope: &Rope,
+    edit: &Span,
+    inserted_chars: usize,
+) -> Option<std::ops::Range<usize>> {
+    let new_len = rope.len_chars();
+    let delta = inserted_chars as isize - edit.len() as isize;
+    let old_len = new_len.wrapping_add_signed(-delta);
+
+    // The lines touching the edit. Lines always end after a (non-escaped) newline, such that
+    // re-parsing can start at the beginning of the first affected line.
+    let first = lines.partition_point(|l| l.span.end < edit.start);
+    let region_start = match lines.get(first) {
+        Some(line) => line.span.start,
+        None => lines.last().map(|l| l.span.end).unwrap_or(0),
+    };
+    let mut last = lines.partition_point(|l| l.span.start <= edit.end);
+    loop {
+        // Trailing whitespace after the last newline doesn't belong to any line
+        let region_end = if last >= lines.len() {
+            old_len
+        } else {
+            lines[last].span.start
+        };
+        let new_region_end = region_end.wrapping_add_signed(delta);
+        let region = rope.slice(region_start..new_region_end).to_string();
+        let ParserResult {
+            tokens,
+            lines: mut new_lines,
+            errors,
+        } = parse_from_str(&region);
+        if !errors.is_empty() {
+            return None;
+        }
+        // The edit might have escaped the final newline, continuing into the next line
+        let ends_with_newline = tokens
+            .last()
+            .is_some_and(|(t, span)| *t == Token::Newline && span.end == region.chars().count());
+        if new_region_end < new_len && !ends_with_newline {
+            last += 1;
+            continue;
+        }
+        for line in &mut new_lines {
+            shift_line(line, region_start as isize);
+        }
+        let last = last.min(lines.len());
+        for line in &mut lines[last..] {
+            shift_line(line, delta);
+        }
+        let reparsed = first..first + new_lines.len();
+        lines.splice(first..last, new_lines);
+        return Some(reparsed);
+    }
+}
+
 #[test]
 fn test_command_specifier() {
     // The first token is the command name
@@ -359,3 +445,40 @@ fn test_empty_lines() {
         )
     );
 }
+
+#[test]
+fn test_reparse_lines() {
+    // Applies the edit both incrementally and by re-parsing the complete text
+    let check_edit = |text: &str, edit: Span, inserted: &str| {
+        let mut lines = parse_from_str(text).lines;
+        let mut rope = Rope::from_str(text);
+        rope.remove(edit.clone());
+        rope.insert(edit.start, inserted);
+        let expected = parse_from_str(&rope.to_string());
+        let reparsed = reparse_lines(&mut lines, &rope, &edit, inserted.chars().count());
+        if expected.errors.is_empty() {
+            assert!(reparsed.is_some(), "{text:?} {edit:?} {inserted:?}");
+            assert_eq!(lines, expected.lines, "{text:?} {edit:?} {inserted:?}");
+        }
+    };
+    let text =
+        "build --jobs=4 # comment\n\ntest:ci -k \\\n  --test_output=errors\n# end \\\ncontinued\n";
+    // Exhaustively test small edits at every position
+    let len = text.chars().count();
+    for start in 0..=len {
+        for end in start..(start + 3).min(len + 1) {
+            for inserted in ["", "x", "\\", "\n", " \\\n", "#", "'", "\"a b\"", "=y"] {
+                check_edit(text, start..end, inserted);
+            }
+        }
+    }
+    // Edits on empty texts and texts ending in whitespace
+    check_edit("", 0..0, "build -k\n");
+    check_edit("build -k\n  ", 11..11, "x");
+    check_edit("build -k", 8..8, "\n");
+
+    // Only the affected lines are re-parsed
+    let mut lines = parse_from_str("a\nb\nc\n").lines;
+    let rope = Rope::from_str("a\nbb\nc\n");
+    assert_eq!(reparse_lines(&mut lines, &rope, &(3..3), 1), Some(1..2));
+}
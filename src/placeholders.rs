@@ -0,0 +1,178 @@
+use std::{ops::Range, path::Path};
+
+use crate::file_utils::get_workspace_path;
+
+// The flags for which Bazel expands `%workspace%` within their values. Besides those,
+// `%workspace%` is only expanded within `import` and `try-import` paths.
+pub const WORKSPACE_PLACEHOLDER_FLAGS: [&str; 4] = [
+    "credential_helper",
+    "override_module",
+    "override_repository",
+    "registry",
+];
+
+// Flags whose values are executed by a shell, which takes care of environment variables
+const SHELL_COMMAND_FLAGS: [&str; 2] = ["run_under", "workspace_status_command"];
+
+// Tokens expanded by the dynamic linker within rpaths, e.g. `-Wl,-rpath,$ORIGIN/lib`
+const RUNTIME_TOKENS: [&str; 4] = ["ORIGIN", "EXEC_ORIGIN", "LIB", "PLATFORM"];
+
+#[derive(Debug, PartialEq)]
+pub enum Placeholder {
+    // `%workspace%`
+    Workspace,
+    // `~` at the beginning of a value
+    Home,
+    // `$NAME` or `${NAME}`
+    EnvVar(String),
+    // Any other `%name%`
+    Unknown(String),
+}
+
+pub fn supports_workspace_placeholder(flag_name: &str) -> bool {
+    WORKSPACE_PLACEHOLDER_FLAGS.contains(&flag_name)
+}
+
+pub fn is_shell_command_flag(flag_name: &str) -> bool {
+    SHELL_COMMAND_FLAGS.contains(&flag_name)
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Finds the placeholders within a value, together with their character ranges.
+// Make variables (`$(NAME)`) and escaped dollar signs (`$$`) are no placeholders.
+pub fn find_placeholders(value: &str) -> Vec<(Placeholder, Range<usize>)> {
+    let chars = value.chars().collect::<Vec<_>>();
+    let identifier_end = |start: usize| {
+        start
+            + chars[start..]
+                .iter()
+                .take_while(|c| is_identifier(**c))
+                .count()
+    };
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // Only `~` and `~/...` refer to the home directory, not `~user/...`
+            '~' if (i == 0 || ['=', '"', '\''].contains(&chars[i - 1]))
+                && chars
+                    .get(i + 1)
+                    .is_none_or(|c| ['/', '"', '\''].contains(c)) =>
+            {
+                result.push((Placeholder::Home, i..i + 1));
+            }
+            '%' if chars.get(i + 1).is_some_and(|c| is_identifier_start(*c)) => {
+                let end = identifier_end(i + 1);
+                if chars.get(end) == Some(&'%') {
+                    let name = chars[i + 1..end].iter().collect::<String>();
+                    let placeholder = if name == "workspace" {
+                        Placeholder::Workspace
+                    } else {
+                        Placeholder::Unknown(name)
+                    };
+                    result.push((placeholder, i..end + 1));
+                    i = end;
+                }
+            }
+            '$' if chars.get(i + 1) == Some(&'$') => i += 1,
+            '$' if chars.get(i + 1).is_some_and(|c| is_identifier_start(*c)) => {
+                let end = identifier_end(i + 1);
+                let name = chars[i + 1..end].iter().collect::<String>();
+                if !RUNTIME_TOKENS.contains(&name.as_str()) {
+                    result.push((Placeholder::EnvVar(name), i..end));
+                }
+                i = end - 1;
+            }
+            '$' if chars.get(i + 1) == Some(&'{') => {
+                let end = identifier_end(i + 2);
+                if end > i + 2 && chars.get(end) == Some(&'}') {
+                    let name = chars[i + 2..end].iter().collect::<String>();
+                    if !RUNTIME_TOKENS.contains(&name.as_str()) {
+                        result.push((Placeholder::EnvVar(name), i..end + 1));
+                    }
+                    i = end;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    result
+}
+
+// Shows the value with `%workspace%` expanded, for flags supporting it
+pub fn get_placeholder_hover_markdown(
+    file_path: &Path,
+    flag_name: &str,
+    value: &str,
+) -> Option<String> {
+    if !value.contains("%workspace%") || !supports_workspace_placeholder(flag_name) {
+        return None;
+    }
+    let workspace = get_workspace_path(file_path)?;
+    let expanded = value.replace("%workspace%", workspace.to_str()?);
+    Some(format!("Expands to `{expanded}`"))
+}
+
+#[test]
+fn test_find_placeholders() {
+    use Placeholder::*;
+
+    assert_eq!(
+        find_placeholders("=%workspace%/tools/helper"),
+        vec![(Workspace, 1..12)]
+    );
+    assert_eq!(find_placeholders("~/cache"), vec![(Home, 0..1)]);
+    assert_eq!(find_placeholders("=\"~/cache\""), vec![(Home, 2..3)]);
+    assert_eq!(
+        find_placeholders("=$HOME/x:${USER}"),
+        vec![
+            (EnvVar("HOME".to_string()), 1..6),
+            (EnvVar("USER".to_string()), 9..16)
+        ]
+    );
+    assert_eq!(
+        find_placeholders("=%WORKSPACE%/x"),
+        vec![(Unknown("WORKSPACE".to_string()), 1..12)]
+    );
+    // No placeholders
+    assert_eq!(find_placeholders("=$(location //x)"), vec![]);
+    assert_eq!(find_placeholders("=$$HOME"), vec![]);
+    assert_eq!(find_placeholders("=a~b"), vec![]);
+    assert_eq!(find_placeholders("=~user/x"), vec![]);
+    assert_eq!(find_placeholders("=100%"), vec![]);
+    assert_eq!(find_placeholders("=${}"), vec![]);
+    assert_eq!(find_placeholders("=-Wl,-rpath,$ORIGIN/../lib"), vec![]);
+    assert_eq!(find_placeholders("=-Wl,-rpath,${ORIGIN}/lib"), vec![]);
+}
+
+#[test]
+fn test_placeholder_hover() {
+    use crate::test_utils::TestDir;
+
+    let root = TestDir::workspace();
+    let file_path = root.join(".bazelrc");
+
+    assert_eq!(
+        get_placeholder_hover_markdown(&file_path, "registry", "file://%workspace%/registry"),
+        Some(format!(
+            "Expands to `file://{}/registry`",
+            root.to_str().unwrap()
+        ))
+    );
+    assert_eq!(
+        get_placeholder_hover_markdown(&file_path, "disk_cache", "%workspace%/cache"),
+        None
+    );
+    assert_eq!(
+        get_placeholder_hover_markdown(&file_path, "registry", "https://bcr.bazel.build"),
+        None
+    );
+}
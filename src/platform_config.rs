@@ -0,0 +1,130 @@
+use crate::{bazel_flags::BazelFlags, parser::Line};
+
+// The configs applied automatically with `--enable_platform_specific_config`,
+// together with the name of the corresponding operating system.
+// See `getPlatformName` in Bazel's `BlazeOptionHandler.java`.
+pub const PLATFORM_CONFIGS: [(&str, &str); 5] = [
+    ("linux", "Linux"),
+    ("macos", "macOS"),
+    ("windows", "Windows"),
+    ("freebsd", "FreeBSD"),
+    ("openbsd", "OpenBSD"),
+];
+
+// Flag name prefixes which only make sense on a specific operating system
+const OS_SPECIFIC_FLAG_PREFIXES: [(&str, &str); 10] = [
+    ("apple_", "macos"),
+    ("ios_", "macos"),
+    ("macos_", "macos"),
+    ("tvos_", "macos"),
+    ("visionos_", "macos"),
+    ("watchos_", "macos"),
+    ("xcode_", "macos"),
+    ("windows_", "windows"),
+    ("experimental_windows_", "windows"),
+    ("incompatible_windows_", "windows"),
+];
+
+// The operating system name for a platform-specific config name, e.g. `Linux` for `linux`
+pub fn get_platform_name(config: &str) -> Option<&'static str> {
+    PLATFORM_CONFIGS
+        .iter()
+        .find(|(c, _)| *c == config)
+        .map(|(_, os)| *os)
+}
+
+// The platform config of the operating system the flag is specific to, if any
+pub fn get_flag_platform(flag_name: &str) -> Option<&'static str> {
+    OS_SPECIFIC_FLAG_PREFIXES
+        .iter()
+        .find(|(prefix, _)| flag_name.starts_with(prefix))
+        .map(|(_, config)| *config)
+}
+
+// Is `--enable_platform_specific_config` enabled by the given lines? The last assignment wins.
+pub fn is_platform_specific_config_enabled<'a, I>(lines: I, bazel_flags: &BazelFlags) -> bool
+where
+    I: IntoIterator<Item = &'a Line>,
+{
+    let mut enabled = false;
+    for line in lines {
+        // Only unconditional lines enable the mechanism for regular invocations
+        if line.config.is_some() {
+            continue;
+        }
+        for flag in &line.flags {
+            let Some(name) = &flag.name else {
+                continue;
+            };
+            let Some((_, info)) = bazel_flags.get_by_invocation(&name.0) else {
+                continue;
+            };
+            if info.name != "enable_platform_specific_config" {
+                continue;
+            }
+            enabled = !info.is_negated_invocation(&name.0)
+                && !flag
+                    .value
+                    .as_ref()
+                    .is_some_and(|v| ["false", "no", "0"].contains(&v.0.to_lowercase().as_str()));
+        }
+    }
+    enabled
+}
+
+pub fn get_platform_config_hover_markdown(
+    command: &str,
+    config: &str,
+    enabled: bool,
+) -> Option<String> {
+    let os = get_platform_name(config)?;
+    let mut result = format!(
+        "Platform-specific config for {os}.\n\n\
+        With `--enable_platform_specific_config`, Bazel automatically applies `{command}:{config}` \
+        lines when running on {os}, as if `--config={config}` was passed.\n\n"
+    );
+    if enabled {
+        result += "`--enable_platform_specific_config` is set in this file or its imports.";
+    } else {
+        result += &format!(
+            "`--enable_platform_specific_config` is not set in this file or its imports. \
+            The config is only applied via an explicit `--config={config}`."
+        );
+    }
+    Some(result)
+}
+
+#[test]
+fn test_platform_specific_config_enabled() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::parser::parse_from_str;
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let enabled = |text: &str| {
+        let lines = parse_from_str(text).lines;
+        is_platform_specific_config_enabled(&lines, &flags)
+    };
+    assert!(enabled("common --enable_platform_specific_config"));
+    assert!(enabled("build --enable_platform_specific_config=true"));
+    assert!(!enabled("build -k"));
+    assert!(!enabled(
+        "common --enable_platform_specific_config\nbuild --noenable_platform_specific_config"
+    ));
+    assert!(!enabled("common --enable_platform_specific_config=no"));
+    // Setting it within a config only enables it when using that config
+    assert!(!enabled("build:ci --enable_platform_specific_config"));
+}
+
+#[test]
+fn test_platform_config_hover() {
+    let docs = get_platform_config_hover_markdown("build", "macos", true).unwrap();
+    assert!(docs.starts_with("Platform-specific config for macOS."));
+    assert!(docs.contains("automatically applies `build:macos` lines"));
+    assert!(docs.ends_with("is set in this file or its imports."));
+    let docs = get_platform_config_hover_markdown("test", "linux", false).unwrap();
+    assert!(docs.ends_with("only applied via an explicit `--config=linux`."));
+    assert_eq!(
+        get_platform_config_hover_markdown("build", "ci", true),
+        None
+    );
+}
@@ -0,0 +1,70 @@
+use tower_lsp::{
+    lsp_types::{
+        notification::Progress, ProgressParams, ProgressParamsValue, ProgressToken,
+        WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressReport,
+    },
+    Client,
+};
+
+// Reports the progress of a long-running request via `$/progress`, using the work done
+// token the client passed with the request
+pub struct WorkDoneReporter {
+    client: Client,
+    token: ProgressToken,
+}
+
+impl WorkDoneReporter {
+    pub async fn begin(client: &Client, token: ProgressToken, title: &str) -> WorkDoneReporter {
+        let reporter = WorkDoneReporter {
+            client: client.clone(),
+            token,
+        };
+        reporter
+            .send(WorkDoneProgress::Begin(WorkDoneProgressBegin {
+                title: title.to_string(),
+                ..Default::default()
+            }))
+            .await;
+        reporter
+    }
+
+    pub async fn report(&self, message: String, percentage: Option<u32>) {
+        self.send(WorkDoneProgress::Report(WorkDoneProgressReport {
+            message: Some(message),
+            percentage,
+            ..Default::default()
+        }))
+        .await;
+    }
+
+    pub async fn end(self, message: String) {
+        self.send(WorkDoneProgress::End(WorkDoneProgressEnd {
+            message: Some(message),
+        }))
+        .await;
+    }
+
+    async fn send(&self, progress: WorkDoneProgress) {
+        self.client
+            .send_notification::<Progress>(ProgressParams {
+                token: self.token.clone(),
+                value: ProgressParamsValue::WorkDone(progress),
+            })
+            .await;
+    }
+}
+
+// The percentage of `done` out of `total`
+pub fn get_percentage(done: usize, total: usize) -> u32 {
+    if total == 0 {
+        return 100;
+    }
+    (done.min(total) * 100 / total) as u32
+}
+
+#[test]
+fn test_get_percentage() {
+    assert_eq!(get_percentage(0, 0), 100);
+    assert_eq!(get_percentage(1, 3), 33);
+    assert_eq!(get_percentage(5, 4), 100);
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+use crate::{
+    file_utils::{find_bazelrc_files, get_home_path, get_workspace_path},
+    import_graph::{get_imported_paths, get_imports, RcFile},
+};
+
+// The scripts which might pass startup options to Bazel. Bazelisk runs the `tools/bazel`
+// wrapper instead of Bazel itself, if present.
+pub const WRAPPER_FILE_NAMES: [&str; 1] = ["tools/bazel"];
+
+// The modification times of a workspace's wrapper scripts, `None` for missing scripts
+type WrapperTimes = Vec<Option<SystemTime>>;
+
+// The options parsed from the wrapper scripts of each workspace, together with the
+// modification times of the scripts they were parsed from
+static WORKSPACE_RC_OPTIONS: Lazy<DashMap<PathBuf, (WrapperTimes, RcOptions)>> =
+    Lazy::new(DashMap::new);
+
+// Where Bazel found a bazelrc file, in the order in which Bazel reads them.
+// See https://bazel.build/run/bazelrc#bazelrc-file-locations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RcSource {
+    // `/etc/bazel.bazelrc`, skipped with `--nosystem_rc`
+    System,
+    // `%workspace%/.bazelrc`, skipped with `--noworkspace_rc`
+    Workspace,
+    // `$HOME/.bazelrc`, skipped with `--nohome_rc`
+    Home,
+    // Passed via `--bazelrc`
+    Explicit,
+}
+
+// The startup options controlling which bazelrc files are read
+#[derive(Debug, Clone, PartialEq)]
+pub struct RcOptions {
+    pub system_rc: bool,
+    pub workspace_rc: bool,
+    pub home_rc: bool,
+    pub ignore_all_rc_files: bool,
+    pub bazelrc: Vec<String>,
+}
+
+impl Default for RcOptions {
+    fn default() -> Self {
+        RcOptions {
+            system_rc: true,
+            workspace_rc: true,
+            home_rc: true,
+            ignore_all_rc_files: false,
+            bazelrc: Vec::new(),
+        }
+    }
+}
+
+// Picks up the startup options affecting the bazelrc files from a wrapper script.
+// Values containing shell expansions cannot be resolved and are skipped.
+pub fn parse_rc_options(script: &str, options: &mut RcOptions) {
+    let mut words = script
+        .lines()
+        .filter(|l| !l.trim_start().starts_with('#'))
+        .flat_map(|l| l.split_whitespace())
+        .map(|w| w.trim_matches(|c| c == '"' || c == '\'' || c == '\\'));
+    while let Some(word) = words.next() {
+        match word {
+            "--system_rc" => options.system_rc = true,
+            "--nosystem_rc" => options.system_rc = false,
+            "--workspace_rc" => options.workspace_rc = true,
+            "--noworkspace_rc" => options.workspace_rc = false,
+            "--home_rc" => options.home_rc = true,
+            "--nohome_rc" => options.home_rc = false,
+            "--ignore_all_rc_files" => options.ignore_all_rc_files = true,
+            "--noignore_all_rc_files" => options.ignore_all_rc_files = false,
+            "--bazelrc" => options.bazelrc.extend(words.next().map(str::to_string)),
+            _ => {
+                if let Some(value) = word.strip_prefix("--bazelrc=") {
+                    options.bazelrc.push(value.to_string());
+                }
+            }
+        }
+    }
+    options
+        .bazelrc
+        .retain(|v| !v.is_empty() && !v.contains('$'));
+}
+
+fn get_system_rc_path() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("ProgramData").map(|p| PathBuf::from(p).join("bazel.bazelrc"))
+    } else {
+        Some(PathBuf::from("/etc/bazel.bazelrc"))
+    }
+}
+
+// Lists the bazelrc files Bazel tries to read, in the order in which they are read, but
+// without the files they import. Missing files are skipped by Bazel. `system_rc` and
+// `home` are passed in to keep this testable.
+pub fn list_rc_files(
+    workspace: &Path,
+    options: &RcOptions,
+    system_rc: Option<&Path>,
+    home: Option<&Path>,
+) -> Vec<(RcSource, PathBuf)> {
+    if options.ignore_all_rc_files {
+        return Vec::new();
+    }
+    let mut result = Vec::<(RcSource, PathBuf)>::new();
+    if let Some(path) = system_rc.filter(|_| options.system_rc) {
+        result.push((RcSource::System, path.to_path_buf()));
+    }
+    if options.workspace_rc {
+        result.push((RcSource::Workspace, workspace.join(".bazelrc")));
+    }
+    if let Some(home) = home.filter(|_| options.home_rc) {
+        result.push((RcSource::Home, home.join(".bazelrc")));
+    }
+    for value in &options.bazelrc {
+        // Bazel stops reading further `--bazelrc` files after `/dev/null`
+        if value == "/dev/null" {
+            break;
+        }
+        let path = PathBuf::from(value);
+        let path = if path.is_absolute() {
+            path
+        } else {
+            workspace.join(path)
+        };
+        result.push((RcSource::Explicit, path));
+    }
+    result
+}
+
+// Discovers the bazelrc files Bazel reads when invoked in the workspace of `file_path`,
+// in the order in which Bazel reads them
+pub fn discover_rc_files(file_path: &Path) -> Vec<(RcSource, PathBuf)> {
+    let Some(workspace) = get_workspace_path(file_path) else {
+        return Vec::new();
+    };
+    let options = get_workspace_rc_options(&workspace);
+    list_rc_files(
+        &workspace,
+        &options,
+        get_system_rc_path().as_deref(),
+        get_home_path().as_deref(),
+    )
+}
+
+// The startup options passed by the workspace's wrapper scripts. The scripts are only
+// parsed again once they are modified.
+fn get_workspace_rc_options(workspace: &Path) -> RcOptions {
+    let scripts = WRAPPER_FILE_NAMES.map(|name| workspace.join(name));
+    let modified = scripts
+        .iter()
+        .map(|script| fs::metadata(script).and_then(|m| m.modified()).ok())
+        .collect::<Vec<_>>();
+    if let Some(cached) = WORKSPACE_RC_OPTIONS.get(workspace) {
+        if cached.0 == modified {
+            return cached.1.clone();
+        }
+    }
+    let mut options = RcOptions::default();
+    for script in &scripts {
+        if let Ok(script) = fs::read_to_string(script) {
+            parse_rc_options(&script, &mut options);
+        }
+    }
+    WORKSPACE_RC_OPTIONS.insert(workspace.to_path_buf(), (modified, options.clone()));
+    options
+}
+
+// All bazelrc files relevant for a workspace: the files Bazel reads by itself, followed
+// by all other `*.bazelrc` files inside the workspace. Files Bazel reads from outside
+// the workspace are only listed if `include_outside_workspace` is set.
+pub fn find_workspace_rc_files(workspace: &Path, include_outside_workspace: bool) -> Vec<PathBuf> {
+    let mut result = discover_rc_files(&workspace.join(".bazelrc"))
+        .into_iter()
+        .map(|(_, path)| path)
+        .filter(|path| path.is_file())
+        .filter(|path| include_outside_workspace || path.starts_with(workspace))
+        .collect::<Vec<_>>();
+    for path in find_bazelrc_files(workspace) {
+        if !result.contains(&path) {
+            result.push(path);
+        }
+    }
+    result
+}
+
+// Orders the files the way Bazel reads them when invoked in the workspace of `file_path`.
+// Imported files follow the file importing them. Files Bazel does not read (e.g. a file
+// which is not imported anywhere) come first and keep their relative order. Bazel reads
+// an imported file in place of the `import` line, so which of two lines takes precedence
+// is decided by `get_line_order`, not by the order of the files.
+pub fn sort_by_read_order(file_path: &Path, files: &mut [RcFile]) {
+    let mut order = Vec::<PathBuf>::new();
+    let mut visited = HashSet::<PathBuf>::new();
+    let mut pending = discover_rc_files(file_path)
+        .into_iter()
+        .map(|(_, path)| path)
+        .rev()
+        .collect::<Vec<_>>();
+    while let Some(path) = pending.pop() {
+        if !visited.insert(path.clone()) {
+            continue;
+        }
+        if let Some(file) = files.iter().find(|f| f.path == path) {
+            pending.extend(
+                get_imported_paths(&file.path, &file.lines)
+                    .into_iter()
+                    .rev(),
+            );
+        }
+        order.push(path);
+    }
+    files.sort_by_key(|f| order.iter().position(|p| p == &f.path).map_or(0, |i| i + 1));
+}
+
+fn add_lines_in_read_order(
+    path: &Path,
+    files: &[RcFile],
+    visited: &mut HashSet<PathBuf>,
+    result: &mut HashMap<(PathBuf, usize), usize>,
+) {
+    if !visited.insert(path.to_path_buf()) {
+        return;
+    }
+    let Some(file) = files.iter().find(|f| f.path == path) else {
+        return;
+    };
+    let imports = get_imports(&file.path, &file.lines)
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    for line_nr in 0..file.lines.len() {
+        let position = result.len();
+        result.insert((file.path.clone(), line_nr), position);
+        if let Some(imported) = imports.get(&line_nr) {
+            add_lines_in_read_order(imported, files, visited, result);
+        }
+    }
+}
+
+// The position at which Bazel reads each line of the given files when invoked in the
+// workspace of `file_path`. Imported files are read in place of the `import` line.
+// Lines of files which Bazel does not read are missing.
+pub fn get_line_read_order(file_path: &Path, files: &[RcFile]) -> HashMap<(PathBuf, usize), usize> {
+    let mut result = HashMap::<(PathBuf, usize), usize>::new();
+    let mut visited = HashSet::<PathBuf>::new();
+    for (_, path) in discover_rc_files(file_path) {
+        add_lines_in_read_order(&path, files, &mut visited, &mut result);
+    }
+    result
+}
+
+// The position of each line when the given files are read in their order, with imported
+// files read in place of the `import` line, like Bazel does. Expects the files in the order
+// established by `sort_by_read_order`.
+pub fn get_line_order(files: &[RcFile]) -> HashMap<(PathBuf, usize), usize> {
+    let mut result = HashMap::<(PathBuf, usize), usize>::new();
+    let mut visited = HashSet::<PathBuf>::new();
+    for file in files {
+        add_lines_in_read_order(&file.path, files, &mut visited, &mut result);
+    }
+    result
+}
+
+#[test]
+fn test_parse_rc_options() {
+    let mut options = RcOptions::default();
+    parse_rc_options(
+        "#!/bin/bash\n\
+        # --nosystem_rc is not passed\n\
+        exec \"$BAZEL_REAL\" --nohome_rc --bazelrc=tools/ci.bazelrc \\\n  \
+          --bazelrc \"$HOME/.remote.bazelrc\" --bazelrc 'tools/user.bazelrc' \"$@\"",
+        &mut options,
+    );
+    assert_eq!(
+        options,
+        RcOptions {
+            home_rc: false,
+            bazelrc: vec![
+                "tools/ci.bazelrc".to_string(),
+                "tools/user.bazelrc".to_string()
+            ],
+            ..Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_workspace_rc_options() {
+    use crate::test_utils::TestDir;
+    use std::time::Duration;
+
+    let workspace = TestDir::workspace();
+    assert!(get_workspace_rc_options(&workspace).home_rc);
+
+    // The options are re-parsed once the wrapper script changes
+    let script = workspace.write("tools/bazel", "exec \"$BAZEL_REAL\" --nohome_rc \"$@\"");
+    assert!(!get_workspace_rc_options(&workspace).home_rc);
+    workspace.write("tools/bazel", "exec \"$BAZEL_REAL\" \"$@\"");
+    fs::File::options()
+        .write(true)
+        .open(&script)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    assert!(get_workspace_rc_options(&workspace).home_rc);
+}
+
+#[test]
+fn test_list_rc_files() {
+    use crate::test_utils::TestDir;
+
+    let root = TestDir::new();
+    for path in [
+        "ws/MODULE.bazel",
+        "ws/.bazelrc",
+        "ws/tools/ci.bazelrc",
+        "home/.bazelrc",
+        "bazel.bazelrc",
+    ] {
+        root.write(path, "");
+    }
+    let workspace = root.join("ws");
+    let home = root.join("home");
+    let system_rc = root.join("bazel.bazelrc");
+
+    let list = |options: &RcOptions| {
+        list_rc_files(&workspace, options, Some(&system_rc), Some(&home))
+            .into_iter()
+            .map(|(source, _)| source)
+            .collect::<Vec<_>>()
+    };
+    let mut options = RcOptions {
+        bazelrc: vec![
+            "tools/ci.bazelrc".to_string(),
+            "tools/missing.bazelrc".to_string(),
+        ],
+        ..Default::default()
+    };
+    use RcSource::*;
+    assert_eq!(
+        list(&options),
+        vec![System, Workspace, Home, Explicit, Explicit]
+    );
+    assert_eq!(
+        list_rc_files(&workspace, &options, Some(&system_rc), Some(&home))[3].1,
+        workspace.join("tools/ci.bazelrc")
+    );
+
+    options.bazelrc.insert(0, "/dev/null".to_string());
+    options.system_rc = false;
+    options.home_rc = false;
+    assert_eq!(list(&options), vec![Workspace]);
+
+    options.ignore_all_rc_files = true;
+    assert_eq!(list(&options), vec![]);
+}
+
+#[test]
+fn test_sort_by_read_order() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, test_utils::TestDir};
+
+    let workspace = TestDir::workspace();
+    workspace.write(".bazelrc", "");
+    workspace.write(
+        "tools/bazel",
+        "exec bazel --nohome_rc --nosystem_rc --bazelrc=tools/ci.bazelrc \"$@\"",
+    );
+    workspace.write("tools/ci.bazelrc", "");
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let file =
+        |name: &str, contents: &str| RcFile::from_str(workspace.join(name), contents, &bazel_flags);
+    let mut files = vec![
+        file("tools/ci.bazelrc", "build -k"),
+        file("tools/imported.bazelrc", "build -k"),
+        file("tools/unused.bazelrc", "build -k"),
+        file(".bazelrc", "import %workspace%/tools/imported.bazelrc"),
+    ];
+    sort_by_read_order(&workspace.join(".bazelrc"), &mut files);
+    assert_eq!(
+        files
+            .iter()
+            .map(|f| f.path.strip_prefix(&workspace).unwrap().to_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec![
+            "tools/unused.bazelrc",
+            ".bazelrc",
+            "tools/imported.bazelrc",
+            "tools/ci.bazelrc"
+        ]
+    );
+
+    assert_eq!(
+        find_workspace_rc_files(&workspace, false)
+            .iter()
+            .map(|p| p.strip_prefix(&workspace).unwrap().to_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec![".bazelrc", "tools/ci.bazelrc"]
+    );
+
+    // Imported files are read in place of the `import` line
+    let read_order = get_line_read_order(&workspace.join(".bazelrc"), &files);
+    let position = |name: &str, line_nr: usize| read_order.get(&(workspace.join(name), line_nr));
+    assert_eq!(position(".bazelrc", 0), Some(&0));
+    assert_eq!(position("tools/imported.bazelrc", 0), Some(&1));
+    assert_eq!(position("tools/ci.bazelrc", 0), Some(&2));
+    assert_eq!(position("tools/unused.bazelrc", 0), None);
+}
@@ -0,0 +1,219 @@
+use ropey::Rope;
+
+use crate::{
+    bazel_flags::BazelFlags,
+    code_action::get_flag_span,
+    line_index::{IndexEntryKind, IndexedLines},
+    parser::{Flag, Line},
+    starlark_flags::parse_starlark_flag,
+    tokenizer::Span,
+};
+
+// The symbol for which references are searched
+#[derive(Debug, PartialEq, Eq)]
+pub enum ReferenceTarget {
+    Config(String),
+    // Identified by the flag's canonical name, such that the negated form,
+    // abbreviations and old names are all found
+    Flag(String),
+}
+
+// The name identifying a flag, independent of how exactly it was spelled
+fn get_flag_key(bazel_flags: &BazelFlags, flag: &Flag) -> Option<String> {
+    let name = &flag.name.as_ref()?.0;
+    if let Some(starlark_flag) = parse_starlark_flag(name) {
+        // `--//pkg:flag` and `--no//pkg:flag` set the same build setting
+        return Some(starlark_flag.label());
+    }
+    match bazel_flags.get_by_invocation(name) {
+        Some((_, info)) => Some(info.name.clone()),
+        None => {
+            let name = name.strip_suffix('=').unwrap_or(name);
+            Some(name.trim_start_matches('-').to_string())
+        }
+    }
+}
+
+pub fn is_config_flag(bazel_flags: &BazelFlags, flag: &Flag) -> bool {
+    get_flag_key(bazel_flags, flag).is_some_and(|k| k == "config")
+}
+
+// Determines what to search for, given the symbol at the cursor
+pub fn get_reference_target(
+    bazel_flags: &BazelFlags,
+    line: &Line,
+    kind: &IndexEntryKind,
+) -> Option<ReferenceTarget> {
+    match kind {
+        IndexEntryKind::Command => None,
+        IndexEntryKind::Config => Some(ReferenceTarget::Config(line.config.as_ref()?.0.clone())),
+        IndexEntryKind::FlagName(flag_nr) => Some(ReferenceTarget::Flag(get_flag_key(
+            bazel_flags,
+            &line.flags[*flag_nr],
+        )?)),
+        IndexEntryKind::FlagValue(flag_nr) => {
+            let flag = &line.flags[*flag_nr];
+            if is_config_flag(bazel_flags, flag) {
+                Some(ReferenceTarget::Config(flag.value.as_ref()?.0.clone()))
+            } else {
+                Some(ReferenceTarget::Flag(get_flag_key(bazel_flags, flag)?))
+            }
+        }
+    }
+}
+
+// Config names and flag values are spanned together with their `:` / `=` separator
+pub fn skip_separator(rope: &Rope, span: &Span) -> Span {
+    match rope.get_char(span.start) {
+        Some(':') | Some('=') => span.start + 1..span.end,
+        _ => span.clone(),
+    }
+}
+
+// Finds all references within the given lines. For configs, the `command:config`
+// definitions are only included if `include_declaration` is set.
+pub fn find_references(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    lines: &[Line],
+    target: &ReferenceTarget,
+    include_declaration: bool,
+) -> Vec<Span> {
+    let mut result = Vec::<Span>::new();
+    for line in lines {
+        match target {
+            ReferenceTarget::Config(config) => {
+                if let Some((name, span)) = &line.config {
+                    if include_declaration && name == config {
+                        result.push(skip_separator(rope, span));
+                    }
+                }
+                for flag in &line.flags {
+                    if !is_config_flag(bazel_flags, flag) {
+                        continue;
+                    }
+                    if let Some((value, span)) = &flag.value {
+                        if value == config {
+                            result.push(skip_separator(rope, span));
+                        }
+                    }
+                }
+            }
+            ReferenceTarget::Flag(key) => {
+                for flag in &line.flags {
+                    if get_flag_key(bazel_flags, flag).as_ref() == Some(key) {
+                        result.extend(get_flag_span(flag));
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+// The ranges which are edited together with the config name at `pos`: its definitions and
+// `--config` references within the same file. Other files are left to a proper rename.
+pub fn get_linked_editing_ranges(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    index: &IndexedLines,
+    pos: usize,
+) -> Option<Vec<Span>> {
+    // The cursor might also be placed directly behind the name
+    let entry = index
+        .find_symbol_at_position(pos)
+        .or_else(|| index.find_symbol_at_position(pos.checked_sub(1)?))?;
+    let target = get_reference_target(bazel_flags, &index.lines[entry.line_nr], &entry.kind)?;
+    if !matches!(&target, ReferenceTarget::Config(config) if !config.is_empty()) {
+        return None;
+    }
+    let ranges = find_references(bazel_flags, rope, &index.lines, &target, true);
+    ranges
+        .iter()
+        .any(|r| r.start <= pos && pos <= r.end)
+        .then_some(ranges)
+}
+
+#[test]
+fn test_find_references() {
+    use crate::{
+        bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags},
+        line_index::IndexedLines,
+        parser::parse_from_str,
+    };
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let text = "build:ci --keep_going\n\
+        test --config=ci -k\n\
+        build --nokeep_going --config ci --//my:setting\n\
+        build --//my:setting=1\n\
+        build --no//my:setting";
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let index = IndexedLines::from_lines(lines);
+    let lookup = |pos: usize| {
+        let entry = index.find_symbol_at_position(pos).unwrap();
+        get_reference_target(&bazel_flags, &index.lines[entry.line_nr], &entry.kind).unwrap()
+    };
+    let texts = |target: &ReferenceTarget, include_declaration: bool| {
+        find_references(
+            &bazel_flags,
+            &Rope::from_str(text),
+            &index.lines,
+            target,
+            include_declaration,
+        )
+        .into_iter()
+        .map(|s| text[s].to_string())
+        .collect::<Vec<_>>()
+    };
+
+    // Configs, looked up from the definition and from a `--config` usage
+    let target = lookup(7);
+    assert_eq!(target, ReferenceTarget::Config("ci".to_string()));
+    assert_eq!(texts(&target, true), vec!["ci", "ci", "ci"]);
+    assert_eq!(lookup(37), target);
+    assert_eq!(texts(&target, false), vec!["ci", "ci"]);
+
+    // Flags, including the negated and abbreviated forms
+    let target = lookup(12);
+    assert_eq!(target, ReferenceTarget::Flag("keep_going".to_string()));
+    assert_eq!(
+        texts(&target, true),
+        vec!["--keep_going", "-k", "--nokeep_going"]
+    );
+
+    // Starlark settings, including their negated form
+    let target = lookup(80);
+    assert_eq!(target, ReferenceTarget::Flag("//my:setting".to_string()));
+    assert_eq!(
+        texts(&target, true),
+        vec!["--//my:setting", "--//my:setting=1", "--no//my:setting"]
+    );
+}
+
+#[test]
+fn test_linked_editing_ranges() {
+    use crate::{
+        bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags},
+        parser::parse_from_str,
+    };
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let text = "build:ci --keep_going\ntest:ci --config=ci\nbuild --config ci --config=other\n";
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let index = IndexedLines::from_lines(lines);
+    let rope = Rope::from_str(text);
+    let ranges = |pos: usize| get_linked_editing_ranges(&bazel_flags, &rope, &index, pos);
+
+    let expected = Some(vec![6..8, 27..29, 39..41, 57..59]);
+    // On the definition, directly behind it and on a `--config` reference
+    assert_eq!(ranges(6), expected);
+    assert_eq!(ranges(8), expected);
+    assert_eq!(ranges(58), expected);
+    // Not on flags and on the `:` / `=` separators
+    assert_eq!(ranges(12), None);
+    assert_eq!(ranges(5), None);
+    assert_eq!(ranges(38), None);
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{TextEdit, Url};
+
+use crate::{
+    import_graph::RcFile,
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::{Flag, Line},
+    references::skip_separator,
+    starlark_flags::parse_starlark_flag,
+    tokenizer::Span,
+};
+
+// Is the text a label, e.g. `//platforms:linux_x86` or `@repo//pkg:target`?
+pub fn is_label(text: &str) -> bool {
+    let Some((repository, path)) = text.split_once("//") else {
+        return false;
+    };
+    (repository.is_empty() || repository.starts_with('@'))
+        && !path.is_empty()
+        && !text
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ',' | '='))
+}
+
+// The labels within a flag, with their spans. Values are split at `,` and `=`, such that
+// labels within lists (`--platforms=//a,//b`) and key-value pairs (`--flag_alias=name=//a`)
+// are found. Starlark flags (`--//pkg:setting`) contain a label in their name. Quoted or
+// escaped tokens are skipped, since their text can't be edited in place.
+fn get_flag_labels(rope: &Rope, flag: &Flag) -> Vec<(String, Span)> {
+    let mut labels = Vec::new();
+    if let Some((name, span)) = &flag.name {
+        if let Some(starlark_flag) = parse_starlark_flag(name) {
+            let prefix_len = if starlark_flag.negated { 4 } else { 2 };
+            let label = name.strip_suffix('=').unwrap_or(name)[prefix_len..].to_string();
+            let start = span.start + prefix_len;
+            if rope
+                .get_slice(span.clone())
+                .is_some_and(|s| s == name.as_str())
+            {
+                labels.push((label.clone(), start..start + label.chars().count()));
+            }
+        }
+    }
+    if let Some((value, span)) = &flag.value {
+        let span = skip_separator(rope, span);
+        let is_verbatim = rope
+            .get_slice(span.clone())
+            .is_some_and(|s| s == value.as_str());
+        if is_verbatim {
+            let mut start = span.start;
+            for part in value.split([',', '=']) {
+                let len = part.chars().count();
+                if is_label(part) {
+                    labels.push((part.to_string(), start..start + len));
+                }
+                start += len + 1;
+            }
+        }
+    }
+    labels
+}
+
+// The label at the cursor, which can be renamed. The cursor might also be placed directly
+// behind the label.
+pub fn prepare_label_rename(rope: &Rope, lines: &[Line], pos: usize) -> Option<(String, Span)> {
+    lines
+        .iter()
+        .filter(|line| line.span.start <= pos && pos <= line.span.end)
+        .flat_map(|line| &line.flags)
+        .flat_map(|flag| get_flag_labels(rope, flag))
+        .find(|(_, span)| span.start <= pos && pos <= span.end)
+}
+
+// All occurrences of the label within the given lines
+pub fn find_label_occurrences(rope: &Rope, lines: &[Line], label: &str) -> Vec<Span> {
+    lines
+        .iter()
+        .flat_map(|line| &line.flags)
+        .flat_map(|flag| get_flag_labels(rope, flag))
+        .filter(|(l, _)| l == label)
+        .map(|(_, span)| span)
+        .collect::<Vec<_>>()
+}
+
+// The edits renaming the label within all given files. Read-only files (e.g. `~/.bazelrc`)
+// are left untouched.
+pub fn get_label_rename_edits(
+    files: &[RcFile],
+    label: &str,
+    new_label: &str,
+    encoding: PositionEncoding,
+) -> HashMap<Url, Vec<TextEdit>> {
+    files
+        .iter()
+        .filter(|file| !file.read_only)
+        .filter_map(|file| {
+            let edits = find_label_occurrences(&file.rope, &file.lines, label)
+                .iter()
+                .filter_map(|span| {
+                    Some(TextEdit {
+                        range: range_to_lsp(&file.rope, span, encoding)?,
+                        new_text: new_label.to_string(),
+                    })
+                })
+                .collect::<Vec<_>>();
+            if edits.is_empty() {
+                return None;
+            }
+            Some((Url::from_file_path(&file.path).ok()?, edits))
+        })
+        .collect::<HashMap<_, _>>()
+}
+
+#[test]
+fn test_is_label() {
+    assert!(is_label("//platforms:linux_x86"));
+    assert!(is_label("@rules_go//go/toolchain:linux_amd64"));
+    assert!(is_label("@@rules_go+//go:toolchain"));
+    assert!(is_label("//my/package"));
+    assert!(is_label("@//pkg:x"));
+    assert!(!is_label("//"));
+    assert!(!is_label("/abs/path"));
+    assert!(!is_label("linux_x86"));
+    assert!(!is_label("https://example.com"));
+}
+
+#[test]
+fn test_label_rename() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use std::path::PathBuf;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let text = "build --platforms=//p:linux,//p:mac --extra_toolchains //p:linux\n\
+        build --flag_alias=os=//p:linux \"--platforms=//p:linux\" --//p:linux --no//p:linux=\n";
+    let files = [
+        RcFile::from_str(PathBuf::from("/ws/.bazelrc"), text, &bazel_flags),
+        RcFile::from_str(
+            PathBuf::from("/ws/ci.bazelrc"),
+            "build:ci --platforms=//p:linux\n",
+            &bazel_flags,
+        ),
+        RcFile {
+            read_only: true,
+            ..RcFile::from_str(
+                PathBuf::from("/home/.bazelrc"),
+                "build --platforms=//p:linux\n",
+                &bazel_flags,
+            )
+        },
+    ];
+    let (rope, lines) = (&files[0].rope, &files[0].lines);
+
+    // On the label, or directly behind it
+    let expected = Some(("//p:linux".to_string(), 18..27));
+    assert_eq!(prepare_label_rename(rope, lines, 18), expected);
+    assert_eq!(prepare_label_rename(rope, lines, 27), expected);
+    assert_eq!(
+        prepare_label_rename(rope, lines, 30),
+        Some(("//p:mac".to_string(), 28..35))
+    );
+    // Regular flag names and values other than labels can't be renamed
+    assert_eq!(prepare_label_rename(rope, lines, 8), None);
+    assert_eq!(prepare_label_rename(rope, lines, 84), None);
+
+    // Quoted tokens are skipped, Starlark flag names are included
+    let occurrences = find_label_occurrences(rope, lines, "//p:linux")
+        .into_iter()
+        .map(|span| rope.slice(span).to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(occurrences, vec!["//p:linux"; 5]);
+
+    let edits = get_label_rename_edits(
+        &files,
+        "//p:linux",
+        "//p:linux_x86",
+        PositionEncoding::Utf16,
+    );
+    let mut counts = edits
+        .iter()
+        .map(|(uri, edits)| (uri.path(), edits.len()))
+        .collect::<Vec<_>>();
+    counts.sort();
+    assert_eq!(counts, vec![("/ws/.bazelrc", 5), ("/ws/ci.bazelrc", 1)]);
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+
+use ropey::Rope;
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{
+    AnnotatedTextEdit, ChangeAnnotation, DocumentChanges, OneOf,
+    OptionalVersionedTextDocumentIdentifier, TextDocumentEdit, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    code_action::{format_flag, get_flag_span},
+    lsp_utils::{range_to_lsp, PositionEncoding},
+    parser::{Flag, Line},
+};
+
+pub const REPLACE_FLAG_COMMAND: &str = "bazelrc.replaceFlagEverywhere";
+
+// Arguments of the `bazelrc.replaceFlagEverywhere` command
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaceFlagParams {
+    // Any document inside the workspace. Used to determine the workspace root.
+    pub uri: Url,
+    // The flag to replace, e.g. `--experimental_x`
+    pub flag: String,
+    pub new_name: Option<String>,
+    pub new_value: Option<String>,
+}
+
+fn strip_dashes(name: &str) -> &str {
+    name.strip_prefix("--").unwrap_or(name)
+}
+
+// Computes the replacement for a single flag, if the flag matches
+fn replace_flag(flag: &Flag, params: &ReplaceFlagParams) -> Option<Flag> {
+    let old_name = strip_dashes(&params.flag);
+    let new_name = params.new_name.as_deref().map(strip_dashes);
+    let name = strip_dashes(&flag.name.as_ref()?.0);
+    let name = name.strip_suffix('=').unwrap_or(name);
+    let mut result = flag.clone();
+    if name == old_name {
+        let new_name = new_name.unwrap_or(old_name);
+        result.name.as_mut()?.0 = format!("--{new_name}");
+        if let Some(new_value) = &params.new_value {
+            result.value = Some((new_value.clone(), 0..0));
+        }
+    } else if name.strip_prefix("no") == Some(old_name) {
+        // The negated form only needs to be renamed
+        result.name.as_mut()?.0 = format!("--no{}", new_name?);
+    } else {
+        return None;
+    }
+    Some(result)
+}
+
+// The edits replacing all occurrences of a flag within a single file
+pub fn get_flag_replacement_edits(
+    rope: &Rope,
+    lines: &[Line],
+    params: &ReplaceFlagParams,
+    encoding: PositionEncoding,
+) -> Vec<TextEdit> {
+    lines
+        .iter()
+        .flat_map(|line| line.flags.iter())
+        .filter_map(|flag| {
+            let replacement = replace_flag(flag, params)?;
+            let new_text = format_flag(&replacement);
+            if new_text == format_flag(flag) {
+                return None;
+            }
+            Some(TextEdit {
+                range: range_to_lsp(rope, &get_flag_span(flag)?, encoding)?,
+                new_text,
+            })
+        })
+        .collect::<Vec<_>>()
+}
+
+// Combines the edits for all files into a single workspace edit. The edits
+// are annotated as requiring confirmation, such that editors show a preview.
+pub fn make_confirmed_workspace_edit(
+    label: String,
+    edits_by_file: Vec<(Url, Vec<TextEdit>)>,
+) -> WorkspaceEdit {
+    let annotation_id = "confirmEdit".to_string();
+    let document_changes = edits_by_file
+        .into_iter()
+        .filter(|(_, edits)| !edits.is_empty())
+        .map(|(uri, edits)| TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier { uri, version: None },
+            edits: edits
+                .into_iter()
+                .map(|text_edit| {
+                    OneOf::Right(AnnotatedTextEdit {
+                        text_edit,
+                        annotation_id: annotation_id.clone(),
+                    })
+                })
+                .collect::<Vec<_>>(),
+        })
+        .collect::<Vec<_>>();
+    WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(document_changes)),
+        change_annotations: Some(HashMap::from([(
+            annotation_id,
+            ChangeAnnotation {
+                label,
+                needs_confirmation: Some(true),
+                description: None,
+            },
+        )])),
+        ..Default::default()
+    }
+}
+
+pub fn make_replacement_workspace_edit(
+    params: &ReplaceFlagParams,
+    edits_by_file: Vec<(Url, Vec<TextEdit>)>,
+) -> WorkspaceEdit {
+    let label = match (&params.new_name, &params.new_value) {
+        (Some(name), _) => format!("Replace `{}` by `{}`", params.flag, name),
+        (None, Some(value)) => format!("Set `{}` to `{}`", params.flag, value),
+        (None, None) => format!("Replace `{}`", params.flag),
+    };
+    make_confirmed_workspace_edit(label, edits_by_file)
+}
+
+#[test]
+fn test_flag_replacement_edits() {
+    use crate::{
+        bazel_flags::{combine_key_value_flags, load_packaged_bazel_flags},
+        code_action::apply_text_edits,
+        parser::parse_from_str,
+    };
+
+    let replace = |text: &str, new_name: Option<&str>, new_value: Option<&str>| {
+        let bazel_flags = load_packaged_bazel_flags("8.0.0");
+        let mut lines = parse_from_str(text).lines;
+        combine_key_value_flags(&mut lines, &bazel_flags);
+        let params = ReplaceFlagParams {
+            uri: Url::parse("file:///ws/.bazelrc").unwrap(),
+            flag: "--experimental_remote_cache_compression".to_string(),
+            new_name: new_name.map(str::to_string),
+            new_value: new_value.map(str::to_string),
+        };
+        let edits = get_flag_replacement_edits(
+            &Rope::from_str(text),
+            &lines,
+            &params,
+            PositionEncoding::Utf16,
+        );
+        apply_text_edits(text, &edits, PositionEncoding::Utf16)
+    };
+
+    // Renames both the positive and the negated form
+    assert_eq!(
+        replace(
+            "build --experimental_remote_cache_compression\n\
+            build:ci --noexperimental_remote_cache_compression -k\n",
+            Some("--remote_cache_compression"),
+            None
+        ),
+        "build --remote_cache_compression\n\
+        build:ci --noremote_cache_compression -k\n"
+    );
+    // Replaces the value
+    assert_eq!(
+        replace(
+            "build --experimental_remote_cache_compression=true",
+            None,
+            Some("false")
+        ),
+        "build --experimental_remote_cache_compression=false"
+    );
+    // Other flags are left alone
+    assert_eq!(
+        replace(
+            "build --experimental_remote_cache_compression_threshold=100",
+            Some("--remote_cache_compression"),
+            None
+        ),
+        "build --experimental_remote_cache_compression_threshold=100"
+    );
+}
@@ -1,7 +1,12 @@
 use ropey::Rope;
-use tower_lsp::lsp_types::{SemanticToken, SemanticTokenType};
+use tower_lsp::lsp_types::{SemanticToken, SemanticTokenType, SemanticTokensEdit};
 
-use crate::{parser::Line, tokenizer::Span};
+use crate::{
+    lsp_utils::{get_lsp_column, PositionEncoding},
+    parser::Line,
+    starlark_flags::is_starlark_flag,
+    tokenizer::Span,
+};
 
 pub const LEGEND_TYPE: &[SemanticTokenType] = &[
     SemanticTokenType::COMMENT,
@@ -9,6 +14,7 @@ pub const LEGEND_TYPE: &[SemanticTokenType] = &[
     SemanticTokenType::NAMESPACE, // For the `:opt` config name
     SemanticTokenType::VARIABLE, // For the flag names
     SemanticTokenType::STRING,  // For the flag values
+    SemanticTokenType::PROPERTY, // For Starlark flags, e.g. `--//my/package:setting`
 ];
 
 #[derive(Debug)]
@@ -47,7 +53,12 @@ pub fn semantic_tokens_from_lines(lines: &[Line]) -> Vec<RCSemanticToken> {
         // Highlight all the flags
         for flag in &line.flags {
             if let Some(name) = &flag.name {
-                tokens.push(create_semantic_token(&name.1, &SemanticTokenType::VARIABLE))
+                let token_type = if is_starlark_flag(&name.0) {
+                    &SemanticTokenType::PROPERTY
+                } else {
+                    &SemanticTokenType::VARIABLE
+                };
+                tokens.push(create_semantic_token(&name.1, token_type))
             }
             if let Some(value) = &flag.value {
                 tokens.push(create_semantic_token(&value.1, &SemanticTokenType::STRING))
@@ -67,7 +78,11 @@ pub fn semantic_tokens_from_lines(lines: &[Line]) -> Vec<RCSemanticToken> {
 }
 
 // Converts our internal semantic tokens to the LSP representation of tokens
-pub fn convert_to_lsp_tokens(rope: &Rope, semtoks: &[RCSemanticToken]) -> Vec<SemanticToken> {
+pub fn convert_to_lsp_tokens(
+    rope: &Rope,
+    semtoks: &[RCSemanticToken],
+    encoding: PositionEncoding,
+) -> Vec<SemanticToken> {
     let mut pre_line = 0;
     let mut pre_start = 0;
     let lsp_tokens = semtoks
@@ -77,17 +92,22 @@ pub fn convert_to_lsp_tokens(rope: &Rope, semtoks: &[RCSemanticToken]) -> Vec<Se
             let end_line = rope.try_char_to_line(token.end).ok()?;
             let tokens = (start_line..(end_line + 1))
                 .filter_map(|line| {
-                    // Figure out start and end offset within line
-                    let first = rope.try_line_to_char(line).ok()? as u32;
+                    // Figure out start and end offset within line, in the negotiated encoding
+                    let first = rope.try_line_to_char(line).ok()?;
                     let start: u32 = if line == start_line {
-                        token.start as u32 - first
+                        get_lsp_column(rope, line, token.start, encoding)?
                     } else {
                         0
                     };
                     let end: u32 = if line == end_line {
-                        token.end as u32 - first
+                        get_lsp_column(rope, line, token.end, encoding)?
                     } else {
-                        rope.get_line(line).unwrap().len_chars() as u32
+                        get_lsp_column(
+                            rope,
+                            line,
+                            first + rope.get_line(line)?.len_chars(),
+                            encoding,
+                        )?
                     };
                     let length = end - start;
                     // Compute deltas to previous token
@@ -116,3 +136,118 @@ pub fn convert_to_lsp_tokens(rope: &Rope, semtoks: &[RCSemanticToken]) -> Vec<Se
         .collect::<Vec<_>>();
     lsp_tokens
 }
+
+// Computes the edits transforming the previously sent tokens into the new tokens.
+// Since tokens are delta-encoded, typing usually only changes a few consecutive
+// tokens. Hence, we produce a single edit, replacing everything between the
+// common prefix and suffix.
+pub fn compute_token_edits(
+    old: &[SemanticToken],
+    new: &[SemanticToken],
+) -> Vec<SemanticTokensEdit> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let deleted = old.len() - prefix - suffix;
+    let inserted = &new[prefix..new.len() - suffix];
+    if deleted == 0 && inserted.is_empty() {
+        return Vec::new();
+    }
+    // Offsets are counted in integers, and each token consists of 5 integers
+    vec![SemanticTokensEdit {
+        start: (prefix * 5) as u32,
+        delete_count: (deleted * 5) as u32,
+        data: Some(inserted.to_vec()),
+    }]
+}
+
+#[test]
+fn test_starlark_flag_tokens() {
+    use crate::parser::parse_from_str;
+
+    let lines = parse_from_str("build --//my:flag=1 --keep_going").lines;
+    let types = semantic_tokens_from_lines(&lines)
+        .iter()
+        .map(|t| LEGEND_TYPE[t.token_type].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::STRING,
+            SemanticTokenType::VARIABLE,
+        ]
+    );
+}
+
+#[test]
+fn test_trailing_comment_tokens() {
+    use crate::parser::parse_from_str;
+
+    // Trailing comments are highlighted separately from the flags they follow
+    let lines = parse_from_str("build --jobs=auto # keep in sync with CI").lines;
+    let tokens = semantic_tokens_from_lines(&lines);
+    let types = tokens
+        .iter()
+        .map(|t| LEGEND_TYPE[t.token_type].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        types,
+        vec![
+            SemanticTokenType::KEYWORD,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::STRING,
+            SemanticTokenType::COMMENT,
+        ]
+    );
+    assert_eq!(tokens[3].start, 18);
+}
+
+#[test]
+fn test_compute_token_edits() {
+    use crate::parser::parse_from_str;
+
+    let lsp_tokens = |str: &str| {
+        let rope = Rope::from_str(str);
+        let lines = parse_from_str(str).lines;
+        convert_to_lsp_tokens(
+            &rope,
+            &semantic_tokens_from_lines(&lines),
+            PositionEncoding::Utf16,
+        )
+    };
+    let old = lsp_tokens("build --a\ntest --b\nrun --c\n");
+    assert_eq!(compute_token_edits(&old, &old), Vec::new());
+
+    // Changing a flag on the middle line only replaces that flag's token
+    let new = lsp_tokens("build --a\ntest --bb\nrun --c\n");
+    let edits = compute_token_edits(&old, &new);
+    assert_eq!(
+        edits,
+        vec![SemanticTokensEdit {
+            start: 15,
+            delete_count: 5,
+            data: Some(vec![new[3]]),
+        }]
+    );
+
+    // Inserting a line only inserts the new tokens
+    let new = lsp_tokens("build --a\ncommon --x\ntest --b\nrun --c\n");
+    let edits = compute_token_edits(&old, &new);
+    assert_eq!(edits.len(), 1);
+    assert_eq!(edits[0].start, 10);
+    assert_eq!(edits[0].delete_count, 0);
+    assert_eq!(edits[0].data.as_ref().unwrap().len(), 2);
+
+    // Applying the edit to the old tokens produces the new tokens
+    let mut applied = old.clone();
+    let start = edits[0].start as usize / 5;
+    let end = start + edits[0].delete_count as usize / 5;
+    applied.splice(start..end, edits[0].data.clone().unwrap());
+    assert_eq!(applied, new);
+}
@@ -0,0 +1,70 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tower_lsp::lsp_types::{notification::Notification, TextDocumentIdentifier, Url};
+
+// Where the flags of a workspace come from
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FlagSource {
+    // The flags packaged with the language server
+    Packaged,
+    // The packaged flags, while the flags are loaded from the Bazel binary in the background
+    Loading,
+    // The flags reported by the workspace's Bazel binary
+    Live,
+}
+
+// The state of the language server for one workspace. Consumed by editor extensions
+// to render a status bar item.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatus {
+    // The workspace root. `None` for files outside of any workspace.
+    pub workspace: Option<Url>,
+    // The pinned or auto-detected Bazel version, if known
+    pub bazel_version: Option<String>,
+    pub flag_source: FlagSource,
+    // The number of bazelrc files within the index of the workspace
+    pub indexed_files: usize,
+    // When the flags were last (re-)loaded, in milliseconds since the Unix epoch
+    pub last_flag_refresh: Option<u64>,
+}
+
+pub fn to_unix_millis(time: SystemTime) -> Option<u64> {
+    Some(time.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64)
+}
+
+// Custom `bazelrc/status` notification: Sent whenever the status of a workspace changes
+pub enum StatusNotification {}
+
+impl Notification for StatusNotification {
+    type Params = ServerStatus;
+    const METHOD: &'static str = "bazelrc/status";
+}
+
+// Parameters of the custom `bazelrc/serverStatus` request. Without a document, the
+// status for files outside of any workspace is returned.
+#[derive(Deserialize, Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerStatusParams {
+    #[serde(default)]
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+#[test]
+fn test_server_status_json() {
+    let status = ServerStatus {
+        workspace: Url::parse("file:///ws").ok(),
+        bazel_version: Some("8.0.0".to_string()),
+        flag_source: FlagSource::Loading,
+        indexed_files: 2,
+        last_flag_refresh: to_unix_millis(UNIX_EPOCH + std::time::Duration::from_secs(1)),
+    };
+    assert_eq!(
+        serde_json::to_string(&status).unwrap(),
+        r#"{"workspace":"file:///ws","bazelVersion":"8.0.0","flagSource":"loading","indexedFiles":2,"lastFlagRefresh":1000}"#
+    );
+    let params: ServerStatusParams = serde_json::from_str("{}").unwrap();
+    assert!(params.text_document.is_none());
+}
@@ -0,0 +1,133 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{
+    Documentation, MarkupContent, MarkupKind, ParameterInformation, ParameterLabel, SignatureHelp,
+    SignatureInformation,
+};
+
+use crate::{
+    bazel_flags::BazelFlags,
+    bazel_flags_proto::FlagInfo,
+    flag_values::{get_flag_value, get_value_kind},
+    parser::Line,
+};
+
+// The flag whose value is being typed at `pos`: Either the cursor is within the value
+// of a flag (`--jobs=4`, `-c opt`) or it follows a flag which still lacks its value
+// (`--jobs=`, `--jobs `).
+fn find_flag_expecting_value<'a>(
+    bazel_flags: &'a BazelFlags,
+    rope: &Rope,
+    line: &'a Line,
+    pos: usize,
+) -> Option<&'a FlagInfo> {
+    for flag_nr in 0..line.flags.len() {
+        if let Some((info, _, (_, value_span))) = get_flag_value(bazel_flags, line, flag_nr) {
+            if value_span.start <= pos && pos <= value_span.end {
+                return Some(info);
+            }
+            continue;
+        }
+        let flag = &line.flags[flag_nr];
+        let Some((name, name_span)) = &flag.name else {
+            continue;
+        };
+        let Some((_, info)) = bazel_flags.get_by_invocation(name) else {
+            continue;
+        };
+        let expects_value = name.ends_with('=') || info.requires_value();
+        if flag.value.is_some() || !expects_value || name_span.end > pos {
+            continue;
+        }
+        // Only whitespace may separate the flag from the cursor
+        let is_next = rope
+            .get_slice(name_span.end..pos)
+            .is_some_and(|s| s.chars().all(|c| c == ' ' || c == '\t'));
+        if is_next {
+            return Some(info);
+        }
+    }
+    None
+}
+
+// Shows which kind of value the flag at the cursor expects
+pub fn get_signature_help(
+    bazel_flags: &BazelFlags,
+    rope: &Rope,
+    lines: &[Line],
+    pos: usize,
+) -> Option<SignatureHelp> {
+    let line = lines
+        .iter()
+        .find(|l| l.span.start <= pos && pos <= l.span.end)?;
+    let info = find_flag_expecting_value(bazel_flags, rope, line, pos)?;
+    let kind = get_value_kind(info);
+    let placeholder = kind.placeholder();
+    let signature = SignatureInformation {
+        label: format!("--{}={}", info.name, placeholder),
+        documentation: Some(Documentation::MarkupContent(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!(
+                "{}\n\n{}",
+                kind.describe(),
+                info.get_documentation_markdown(bazel_flags.bazel_version.as_deref())
+            ),
+        })),
+        parameters: Some(vec![ParameterInformation {
+            label: ParameterLabel::Simple(placeholder.to_string()),
+            documentation: None,
+        }]),
+        active_parameter: Some(0),
+    };
+    Some(SignatureHelp {
+        signatures: vec![signature],
+        active_signature: Some(0),
+        active_parameter: Some(0),
+    })
+}
+
+#[cfg(test)]
+fn test_signature_label(text: &str, pos: usize) -> Option<String> {
+    use crate::bazel_flags::combine_key_value_flags;
+    use crate::parser::parse_from_str;
+    use crate::test_utils::load_flags_with_value_types;
+
+    let bazel_flags = load_flags_with_value_types("8.0.0");
+    let mut lines = parse_from_str(text).lines;
+    combine_key_value_flags(&mut lines, &bazel_flags);
+    let help = get_signature_help(&bazel_flags, &Rope::from_str(text), &lines, pos)?;
+    Some(help.signatures[0].label.clone())
+}
+
+#[test]
+fn test_signature_help() {
+    // Directly after the `=` and within the value
+    assert_eq!(
+        test_signature_label("build --jobs=", 13).as_deref(),
+        Some("--jobs=<integer>")
+    );
+    assert_eq!(
+        test_signature_label("build --disk_cache=/tmp", 20).as_deref(),
+        Some("--disk_cache=<path>")
+    );
+    // After a space following a flag which requires a value
+    assert_eq!(
+        test_signature_label("build --jobs ", 13).as_deref(),
+        Some("--jobs=<integer>")
+    );
+    assert_eq!(
+        test_signature_label("build --platforms //:x", 20).as_deref(),
+        Some("--platforms=<label>")
+    );
+    // Abbreviations take their value from the next token
+    assert_eq!(
+        test_signature_label("build -c op", 11).as_deref(),
+        Some("--compilation_mode=<choice>")
+    );
+    // Not within flag names or after flags without a value
+    assert_eq!(test_signature_label("build --jobs", 9), None);
+    assert_eq!(test_signature_label("build --keep_going ", 19), None);
+    assert_eq!(
+        test_signature_label("build --jobs=4 --keep_going", 27),
+        None
+    );
+}
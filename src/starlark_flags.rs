@@ -0,0 +1,213 @@
+use std::{collections::HashSet, path::Path, process::Command};
+
+use crate::parser::Line;
+
+// Prefixes of user-defined Starlark flags, i.e. build settings set via their label
+pub const STARLARK_FLAG_PREFIXES: [&str; 4] = ["--//", "--no//", "--@", "--no@"];
+
+// A Starlark flag, e.g. `--@rules_x//my/package:setting`
+#[derive(Debug, PartialEq)]
+pub struct StarlarkFlag<'a> {
+    // `--no//...` sets a boolean build setting to false
+    pub negated: bool,
+    // The repository including its `@`, or `None` for the main repository
+    pub repository: Option<&'a str>,
+    pub package: &'a str,
+    pub target: &'a str,
+}
+
+impl StarlarkFlag<'_> {
+    // The label of the build setting, without the `--` and negation
+    pub fn label(&self) -> String {
+        format!(
+            "{}//{}:{}",
+            self.repository.unwrap_or_default(),
+            self.package,
+            self.target
+        )
+    }
+}
+
+pub fn is_starlark_flag(invocation: &str) -> bool {
+    STARLARK_FLAG_PREFIXES
+        .iter()
+        .any(|prefix| invocation.starts_with(prefix))
+}
+
+// Splits a Starlark flag into its label components
+pub fn parse_starlark_flag(invocation: &str) -> Option<StarlarkFlag<'_>> {
+    if !is_starlark_flag(invocation) {
+        return None;
+    }
+    let invocation = invocation.strip_suffix('=').unwrap_or(invocation);
+    let label = invocation.strip_prefix("--")?;
+    let (negated, label) = match label.strip_prefix("no") {
+        Some(rest) => (true, rest),
+        None => (false, label),
+    };
+    let (repository, path) = label.split_once("//")?;
+    let repository = (!repository.is_empty()).then_some(repository);
+    // `//my/package` is short for `//my/package:package`
+    let (package, target) = match path.split_once(':') {
+        Some((package, target)) => (package, target),
+        None => (path, path.rsplit('/').next()?),
+    };
+    if target.is_empty() {
+        return None;
+    }
+    Some(StarlarkFlag {
+        negated,
+        repository,
+        package,
+        target,
+    })
+}
+
+pub fn get_starlark_flag_hover_markdown(invocation: &str) -> Option<String> {
+    let flag = parse_starlark_flag(invocation)?;
+    let mut result = format!("Starlark flag `{}`\n\n", flag.label());
+    result += &format!(
+        "* Repository: {}\n",
+        flag.repository
+            .map(|r| format!("`{r}`"))
+            .unwrap_or("main repository".to_string())
+    );
+    result += &format!("* Package: `//{}`\n", flag.package);
+    result += &format!("* Target: `{}`\n", flag.target);
+    if flag.negated {
+        result += "\nSets the boolean build setting to `false`.\n";
+    }
+    Some(result)
+}
+
+// The labels of all Starlark flags from the main repository used within the given lines.
+// Flags from other repositories are not validated, since querying them might require
+// fetching the repository.
+pub fn get_main_repository_labels(lines: &[Line]) -> Vec<String> {
+    let mut labels = lines
+        .iter()
+        .flat_map(|line| &line.flags)
+        .filter_map(|flag| parse_starlark_flag(&flag.name.as_ref()?.0))
+        .filter(|flag| flag.repository.is_none())
+        .map(|flag| flag.label())
+        .collect::<Vec<_>>();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+// Quotes the labels for a query expression, such that they can't inject further
+// operators. Labels can't contain quotes, hence those are dropped.
+fn quote_query_words(labels: &[String]) -> String {
+    labels
+        .iter()
+        .filter(|l| !l.contains(['"', '\'']))
+        .map(|l| format!("\"{l}\""))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Runs `bazel query` within the workspace and returns the subset of the labels which exist
+#[tracing::instrument(level = "debug", skip(workspace))]
+pub fn query_existing_labels(
+    bazel_command: &str,
+    workspace: &Path,
+    labels: &[String],
+) -> Result<HashSet<String>, String> {
+    let result = Command::new(bazel_command)
+        .current_dir(workspace)
+        .args(["query", "--keep_going", "--output=label"])
+        .arg(format!("set({})", quote_query_words(labels)))
+        .output()
+        .map_err(|err| err.to_string())?;
+    // With `--keep_going`, exit code 3 signals that some targets could not be found
+    if !matches!(result.status.code(), Some(0) | Some(3)) {
+        return Err(format!(
+            "`{bazel_command} query` failed:\n{}",
+            String::from_utf8_lossy(&result.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&result.stdout)
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect())
+}
+
+#[test]
+fn test_parse_starlark_flag() {
+    assert_eq!(
+        parse_starlark_flag("--//my/package:setting"),
+        Some(StarlarkFlag {
+            negated: false,
+            repository: None,
+            package: "my/package",
+            target: "setting",
+        })
+    );
+    assert_eq!(
+        parse_starlark_flag("--no@rules_x//y:z"),
+        Some(StarlarkFlag {
+            negated: true,
+            repository: Some("@rules_x"),
+            package: "y",
+            target: "z",
+        })
+    );
+    let flag = parse_starlark_flag("--@@rules_x~//y").unwrap();
+    assert_eq!(flag.label(), "@@rules_x~//y:y");
+    assert_eq!(parse_starlark_flag("--//:flag").unwrap().label(), "//:flag");
+
+    // Regular flags and malformed labels
+    assert_eq!(parse_starlark_flag("--keep_going"), None);
+    assert_eq!(parse_starlark_flag("--nokeep_going"), None);
+    assert_eq!(parse_starlark_flag("--//my/package:"), None);
+    assert!(is_starlark_flag("--@dependency:my/package:bool_flag"));
+    assert_eq!(
+        parse_starlark_flag("--@dependency:my/package:bool_flag"),
+        None
+    );
+}
+
+#[test]
+fn test_starlark_flag_hover() {
+    assert_eq!(
+        get_starlark_flag_hover_markdown("--no@rules_x//y:z").unwrap(),
+        "Starlark flag `@rules_x//y:z`\n\n\
+        * Repository: `@rules_x`\n\
+        * Package: `//y`\n\
+        * Target: `z`\n\n\
+        Sets the boolean build setting to `false`.\n"
+    );
+    assert!(get_starlark_flag_hover_markdown("--//a:b")
+        .unwrap()
+        .contains("* Repository: main repository\n"));
+    assert_eq!(get_starlark_flag_hover_markdown("--keep_going"), None);
+}
+
+#[test]
+fn test_main_repository_labels() {
+    use crate::parser::parse_from_str;
+
+    let lines = parse_from_str(
+        "build --//tools:my_flag=1 --no//tools:my_flag --@rules_x//y:z\n\
+        test --//tools --keep_going",
+    )
+    .lines;
+    assert_eq!(
+        get_main_repository_labels(&lines),
+        vec!["//tools:my_flag", "//tools:tools"]
+    );
+}
+
+#[test]
+fn test_quote_query_words() {
+    assert_eq!(
+        quote_query_words(&[
+            "//tools:a".to_string(),
+            "//tools:b + deps(//...)".to_string(),
+            "//tools:\"c".to_string(),
+        ]),
+        "\"//tools:a\" \"//tools:b + deps(//...)\""
+    );
+}
@@ -0,0 +1,213 @@
+use ropey::Rope;
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity};
+
+use crate::{
+    diagnostic::DiagnosticCheck,
+    lsp_utils::{lsp_pos_to_offset, range_to_lsp, PositionEncoding},
+    parser::Line,
+    tokenizer::Span,
+};
+
+const SUPPRESSION_PREFIX: &str = "bazelrc-lsp:";
+
+#[derive(Debug, PartialEq)]
+pub enum SuppressionScope {
+    // `disable=...`: the line carrying the comment, or the next line for stand-alone comments
+    Line,
+    // `disable-file=...`: the complete file
+    File,
+}
+
+// A suppression comment, e.g. `# bazelrc-lsp: disable=deprecated-flag,unknown-flag`.
+// The check names are kept as written; `all` suppresses all checks.
+#[derive(Debug, PartialEq)]
+pub struct Suppression {
+    pub scope: SuppressionScope,
+    pub checks: Vec<String>,
+}
+
+impl Suppression {
+    fn suppresses(&self, check: DiagnosticCheck) -> bool {
+        self.checks.iter().any(|c| c == "all" || c == check.name())
+    }
+}
+
+// Parses the text of a comment (without the leading `#`)
+pub fn parse_suppression(comment: &str) -> Option<Suppression> {
+    let directive = comment.trim().strip_prefix(SUPPRESSION_PREFIX)?.trim();
+    let (kind, checks) = directive.split_once('=')?;
+    let scope = match kind.trim() {
+        "disable" => SuppressionScope::Line,
+        "disable-file" => SuppressionScope::File,
+        _ => return None,
+    };
+    let checks = checks
+        .split(',')
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect();
+    Some(Suppression { scope, checks })
+}
+
+// The suppressions of a file, together with the lines they apply to
+#[derive(Debug, Default)]
+pub struct Suppressions {
+    file: Vec<Suppression>,
+    lines: Vec<(Span, Suppression)>,
+}
+
+impl Suppressions {
+    pub fn from_lines(lines: &[Line]) -> Suppressions {
+        let mut result = Suppressions::default();
+        let mut pending = Vec::<Suppression>::new();
+        for line in lines {
+            let has_content = line.command.is_some() || !line.flags.is_empty();
+            if let Some(suppression) = line.comment.as_ref().and_then(|c| parse_suppression(&c.0)) {
+                match suppression.scope {
+                    SuppressionScope::File => result.file.push(suppression),
+                    SuppressionScope::Line if has_content => {
+                        result.lines.push((line.span.clone(), suppression))
+                    }
+                    SuppressionScope::Line => pending.push(suppression),
+                }
+            }
+            if has_content {
+                for suppression in pending.drain(..) {
+                    result.lines.push((line.span.clone(), suppression));
+                }
+            }
+        }
+        result
+    }
+
+    pub fn is_suppressed(&self, offset: usize, check: DiagnosticCheck) -> bool {
+        self.file.iter().any(|s| s.suppresses(check))
+            || self
+                .lines
+                .iter()
+                .any(|(span, s)| span.contains(&offset) && s.suppresses(check))
+    }
+}
+
+// Removes the diagnostics silenced by suppression comments. Only diagnostics which
+// belong to one of the configurable checks can be suppressed.
+pub fn filter_suppressed_diagnostics(
+    rope: &Rope,
+    lines: &[Line],
+    mut diagnostics: Vec<Diagnostic>,
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let suppressions = Suppressions::from_lines(lines);
+    if suppressions.file.is_empty() && suppressions.lines.is_empty() {
+        return diagnostics;
+    }
+    diagnostics.retain(|d| {
+        let Some(check) = DiagnosticCheck::of(d) else {
+            return true;
+        };
+        let Some(offset) = lsp_pos_to_offset(rope, &d.range.start, encoding) else {
+            return true;
+        };
+        !suppressions.is_suppressed(offset, check)
+    });
+    diagnostics
+}
+
+// Warns about suppression comments referring to checks which do not exist
+pub fn diagnostics_for_suppressions(
+    rope: &Rope,
+    lines: &[Line],
+    encoding: PositionEncoding,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::<Diagnostic>::new();
+    for (comment, span) in lines.iter().filter_map(|l| l.comment.as_ref()) {
+        let Some(suppression) = parse_suppression(comment) else {
+            continue;
+        };
+        for check in &suppression.checks {
+            if check == "all" || DiagnosticCheck::from_name(check).is_some() {
+                continue;
+            }
+            diagnostics.push(Diagnostic {
+                range: range_to_lsp(rope, span, encoding).unwrap(),
+                severity: Some(DiagnosticSeverity::WARNING),
+                message: format!("Unknown check `{check}` in suppression comment"),
+                code: DiagnosticCheck::Suppression.code(),
+                ..Default::default()
+            });
+        }
+    }
+    diagnostics
+}
+
+#[test]
+fn test_parse_suppression() {
+    assert_eq!(
+        parse_suppression(" bazelrc-lsp: disable=deprecated-flag, unknown-flag"),
+        Some(Suppression {
+            scope: SuppressionScope::Line,
+            checks: vec!["deprecated-flag".to_string(), "unknown-flag".to_string()],
+        })
+    );
+    assert_eq!(
+        parse_suppression("bazelrc-lsp:disable-file=all"),
+        Some(Suppression {
+            scope: SuppressionScope::File,
+            checks: vec!["all".to_string()],
+        })
+    );
+    assert_eq!(parse_suppression(" bazelrc-lsp: enable=unknown-flag"), None);
+    assert_eq!(parse_suppression(" disable=unknown-flag"), None);
+    assert_eq!(parse_suppression(" just a comment"), None);
+}
+
+#[test]
+fn test_filter_suppressed_diagnostics() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use crate::diagnostic::diagnostics_from_string;
+
+    let bazel_flags = load_packaged_bazel_flags("8.0.0");
+    let diagnose = |str: &str| {
+        diagnostics_from_string(str, &bazel_flags, None, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|d| (d.range.start.line, d.message))
+            .collect::<Vec<_>>()
+    };
+
+    // Trailing comments apply to their own line
+    let diags = diagnose(
+        "build --legacy_whole_archive # bazelrc-lsp: disable=deprecated-flag\n\
+        test --legacy_whole_archive",
+    );
+    assert_eq!(diags.len(), 1);
+    assert_eq!(diags[0].0, 1);
+
+    // Stand-alone comments apply to the next line, including line continuations
+    let diags = diagnose(
+        "# bazelrc-lsp: disable=unknown-flag\n\
+        build --legacy_whole_archive \\\n  --unknown_flag\n\
+        build --unknown_flag",
+    );
+    assert_eq!(
+        diags.iter().map(|d| d.0).collect::<Vec<_>>(),
+        vec![1, 3],
+        "{diags:?}"
+    );
+
+    // File-wide suppressions
+    let diags = diagnose(
+        "build --legacy_whole_archive --unknown_flag\n\
+        # bazelrc-lsp: disable-file=all",
+    );
+    assert_eq!(diags, vec![]);
+
+    // Unknown checks are reported
+    let diags = diagnose("build --jobs=1 # bazelrc-lsp: disable=typo-flag");
+    assert_eq!(
+        diags,
+        vec![(
+            0,
+            "Unknown check `typo-flag` in suppression comment".to_string()
+        )]
+    );
+}
@@ -0,0 +1,328 @@
+use std::collections::HashSet;
+
+use ropey::Rope;
+use tower_lsp::lsp_types::{DocumentSymbol, Location, SymbolInformation, SymbolKind, Url};
+
+use crate::{
+    import_graph::RcFile,
+    lsp_utils::{range_to_lsp, trim_span_end, PositionEncoding},
+    parser::Line,
+    tokenizer::Span,
+};
+
+#[allow(deprecated, clippy::too_many_arguments)]
+fn make_symbol(
+    name: String,
+    detail: Option<String>,
+    kind: SymbolKind,
+    rope: &Rope,
+    span: &Span,
+    selection_span: &Span,
+    children: Option<Vec<DocumentSymbol>>,
+    encoding: PositionEncoding,
+) -> Option<DocumentSymbol> {
+    Some(DocumentSymbol {
+        name,
+        detail,
+        kind,
+        tags: None,
+        deprecated: None,
+        range: range_to_lsp(rope, span, encoding)?,
+        selection_range: range_to_lsp(rope, selection_span, encoding)?,
+        children,
+    })
+}
+
+// The document outline: one symbol per run of consecutive lines using the same command
+// (e.g. `build`), with one child per run of lines using the same config (e.g. `build:ci`).
+// Imports are listed as top-level leaf symbols. Comments and empty lines don't interrupt
+// a run. Thereby, the ranges of siblings never overlap and children nest inside their parent.
+pub fn get_document_symbols(
+    rope: &Rope,
+    lines: &[Line],
+    encoding: PositionEncoding,
+) -> Vec<DocumentSymbol> {
+    struct ConfigEntry<'a> {
+        name: &'a str,
+        span: Span,
+        selection_span: Span,
+    }
+    struct CommandEntry<'a> {
+        name: &'a str,
+        span: Span,
+        selection_span: Span,
+        configs: Vec<ConfigEntry<'a>>,
+    }
+    enum Entry<'a> {
+        Command(CommandEntry<'a>),
+        Import(&'a Line),
+    }
+
+    let mut entries = Vec::<Entry>::new();
+    for line in lines {
+        let Some(command) = &line.command else {
+            continue;
+        };
+        if command.0 == "import" || command.0 == "try-import" {
+            entries.push(Entry::Import(line));
+            continue;
+        }
+        let line_span = trim_span_end(rope, &line.span);
+        let continues_command =
+            matches!(entries.last(), Some(Entry::Command(c)) if c.name == command.0);
+        if !continues_command {
+            entries.push(Entry::Command(CommandEntry {
+                name: &command.0,
+                span: line_span.clone(),
+                selection_span: command.1.clone(),
+                configs: Vec::new(),
+            }));
+        }
+        let Some(Entry::Command(command_entry)) = entries.last_mut() else {
+            continue;
+        };
+        let previous_end = command_entry.span.end;
+        command_entry.span.end = line_span.end;
+        let Some(config) = &line.config else {
+            continue;
+        };
+        // The config's run continues if the previous line of the command used it, too
+        match command_entry.configs.last_mut() {
+            Some(c) if c.name == config.0 && c.span.end == previous_end => {
+                c.span.end = line_span.end
+            }
+            _ => command_entry.configs.push(ConfigEntry {
+                name: &config.0,
+                span: line_span,
+                selection_span: config.1.clone(),
+            }),
+        }
+    }
+
+    entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Entry::Command(command) => {
+                let children = command
+                    .configs
+                    .iter()
+                    .filter_map(|config| {
+                        make_symbol(
+                            format!("{}:{}", command.name, config.name),
+                            None,
+                            SymbolKind::NAMESPACE,
+                            rope,
+                            &config.span,
+                            &config.selection_span,
+                            None,
+                            encoding,
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                make_symbol(
+                    command.name.to_string(),
+                    None,
+                    SymbolKind::MODULE,
+                    rope,
+                    &command.span,
+                    &command.selection_span,
+                    Some(children),
+                    encoding,
+                )
+            }
+            Entry::Import(line) => {
+                let command = line.command.as_ref()?;
+                let path = line.flags.first()?.value.as_ref()?;
+                make_symbol(
+                    path.0.clone(),
+                    Some(command.0.clone()),
+                    SymbolKind::FILE,
+                    rope,
+                    &trim_span_end(rope, &line.span),
+                    &path.1,
+                    None,
+                    encoding,
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+// The config definitions (e.g. `build:remote`) within the given files which
+// match the query. Each command / config combination is reported once per
+// file, pointing to its first definition.
+#[allow(deprecated)]
+pub fn get_config_symbols(
+    files: &[RcFile],
+    query: &str,
+    encoding: PositionEncoding,
+) -> Vec<SymbolInformation> {
+    let query = query.to_lowercase();
+    let mut result = Vec::<SymbolInformation>::new();
+    for file in files {
+        let Ok(uri) = Url::from_file_path(&file.path) else {
+            continue;
+        };
+        let mut seen = HashSet::<String>::new();
+        for line in &file.lines {
+            let (Some(command), Some(config)) = (&line.command, &line.config) else {
+                continue;
+            };
+            let name = format!("{}:{}", command.0, config.0);
+            if !name.to_lowercase().contains(&query) || !seen.insert(name.clone()) {
+                continue;
+            }
+            let Some(range) = range_to_lsp(&file.rope, &config.1, encoding) else {
+                continue;
+            };
+            result.push(SymbolInformation {
+                name,
+                kind: SymbolKind::NAMESPACE,
+                tags: None,
+                deprecated: None,
+                location: Location::new(uri.clone(), range),
+                container_name: file
+                    .path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned()),
+            });
+        }
+    }
+    result
+}
+
+#[test]
+fn test_document_symbols() {
+    use crate::parser::parse_from_str;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    let symbols = |text: &str| {
+        get_document_symbols(
+            &Rope::from_str(text),
+            &parse_from_str(text).lines,
+            PositionEncoding::Utf16,
+        )
+    };
+    let outline = |symbols: &[DocumentSymbol]| {
+        symbols
+            .iter()
+            .map(|s| {
+                let children = s
+                    .children
+                    .iter()
+                    .flatten()
+                    .map(|c| c.name.clone())
+                    .collect::<Vec<_>>();
+                (s.name.clone(), s.kind, children)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Consecutive lines are grouped, comments don't interrupt them
+    let grouped = symbols(
+        "build --jobs=4\nbuild:ci --keep_going\n# comment\nbuild:ci -c opt\nbuild --nokeep_going\n",
+    );
+    assert_eq!(
+        outline(&grouped),
+        vec![(
+            "build".to_string(),
+            SymbolKind::MODULE,
+            vec!["build:ci".to_string()]
+        )]
+    );
+    // The ranges span from the first to the last line using the command / config
+    assert_eq!(
+        grouped[0].range,
+        Range::new(Position::new(0, 0), Position::new(4, 20))
+    );
+    assert_eq!(
+        grouped[0].selection_range,
+        Range::new(Position::new(0, 0), Position::new(0, 5))
+    );
+    let config = &grouped[0].children.as_ref().unwrap()[0];
+    assert_eq!(
+        config.range,
+        Range::new(Position::new(1, 0), Position::new(3, 15))
+    );
+    assert_eq!(
+        config.selection_range,
+        Range::new(Position::new(1, 5), Position::new(1, 8))
+    );
+
+    // Interleaved commands and imports start new symbols, such that ranges never overlap
+    let interleaved = symbols("build --jobs=4\nimport %workspace%/ci.bazelrc\nbuild:ci --keep_going\ntest --test_output=all\nbuild:ci -c opt\n");
+    assert_eq!(
+        outline(&interleaved),
+        vec![
+            ("build".to_string(), SymbolKind::MODULE, vec![]),
+            (
+                "%workspace%/ci.bazelrc".to_string(),
+                SymbolKind::FILE,
+                vec![]
+            ),
+            (
+                "build".to_string(),
+                SymbolKind::MODULE,
+                vec!["build:ci".to_string()]
+            ),
+            ("test".to_string(), SymbolKind::MODULE, vec![]),
+            (
+                "build".to_string(),
+                SymbolKind::MODULE,
+                vec!["build:ci".to_string()]
+            ),
+        ]
+    );
+    assert_eq!(interleaved[1].detail.as_deref(), Some("import"));
+    // Interleaved configs, too
+    assert_eq!(
+        outline(&symbols(
+            "build:ci -k\nbuild:opt -c opt\nbuild:ci --jobs=4\n"
+        ))[0]
+            .2,
+        vec!["build:ci", "build:opt", "build:ci"]
+    );
+}
+
+#[test]
+fn test_config_symbols() {
+    use crate::bazel_flags::load_packaged_bazel_flags;
+    use std::path::PathBuf;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    let flags = load_packaged_bazel_flags("8.0.0");
+    let files = vec![
+        RcFile::from_str(
+            PathBuf::from("/ws/.bazelrc"),
+            "build:remote --jobs=4\nbuild:ci -k\ntest:remote -k\nbuild:remote -c opt\n",
+            &flags,
+        ),
+        RcFile::from_str(
+            PathBuf::from("/ws/ci.bazelrc"),
+            "build:Remote_CI -k\n",
+            &flags,
+        ),
+    ];
+    let names = |query: &str| {
+        get_config_symbols(&files, query, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|s| (s.name, s.location.uri.path().to_string()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names("remote"),
+        vec![
+            ("build:remote".to_string(), "/ws/.bazelrc".to_string()),
+            ("test:remote".to_string(), "/ws/.bazelrc".to_string()),
+            ("build:Remote_CI".to_string(), "/ws/ci.bazelrc".to_string()),
+        ]
+    );
+    assert_eq!(names("").len(), 4);
+    assert_eq!(names("test:").len(), 1);
+    let symbol = &get_config_symbols(&files, "build:remote", PositionEncoding::Utf16)[0];
+    assert_eq!(
+        symbol.location.range,
+        Range::new(Position::new(0, 5), Position::new(0, 12))
+    );
+    assert_eq!(symbol.container_name.as_deref(), Some(".bazelrc"));
+}
@@ -0,0 +1,56 @@
+use std::{
+    fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+};
+
+use tempfile::TempDir;
+
+use crate::bazel_flags::{load_packaged_bazel_flags, BazelFlags};
+
+// A temporary directory for a single test, removed when dropped
+pub struct TestDir(TempDir);
+
+impl TestDir {
+    pub fn new() -> TestDir {
+        TestDir(tempfile::tempdir().unwrap())
+    }
+
+    // A directory containing a `MODULE.bazel`, i.e. the root of a Bazel workspace
+    pub fn workspace() -> TestDir {
+        let dir = TestDir::new();
+        dir.write("MODULE.bazel", "");
+        dir
+    }
+
+    // Writes the file, creating its parent directories as needed
+    pub fn write(&self, path: impl AsRef<Path>, contents: &str) -> PathBuf {
+        let path = self.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        self.0.path()
+    }
+}
+
+// The packaged flags, with the value type `bazel help` reports for `--compilation_mode`.
+// Value types are only packaged if Bazelisk was available at build time.
+pub fn load_flags_with_value_types(bazel_version: &str) -> BazelFlags {
+    let flags = load_packaged_bazel_flags(bazel_version);
+    let mut compilation_mode = flags.get_by_invocation("-c").unwrap().1.clone();
+    compilation_mode.value_description = Some("fastbuild, dbg or opt".to_string());
+    flags.with_custom_flags(&[compilation_mode])
+}
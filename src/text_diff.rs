@@ -0,0 +1,168 @@
+// A minimal line-based diff, used to show the changes the formatter would apply
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum DiffOp<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+// Diffs two lists of lines based on their longest common subsequence
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffOp<'a>> {
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::<DiffOp>::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push(DiffOp::Equal(old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(DiffOp::Delete(old[i]));
+            i += 1;
+        } else {
+            ops.push(DiffOp::Insert(new[j]));
+            j += 1;
+        }
+    }
+    ops
+}
+
+// Renders a line of a hunk. Lines are compared including their line ending, such that
+// changes to only the final newline or only `\r\n` line endings are shown, too.
+fn push_diff_line(out: &mut String, prefix: char, line: &str) {
+    out.push(prefix);
+    out.push_str(line.strip_suffix('\n').unwrap_or(line));
+    out.push('\n');
+    if !line.ends_with('\n') {
+        out.push_str("\\ No newline at end of file\n");
+    }
+}
+
+// Renders the differences in the unified diff format, with 3 lines of context.
+// Returns an empty string if both texts are equal.
+pub fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    const CONTEXT: usize = 3;
+    if old == new {
+        return String::new();
+    }
+    let old_lines = old.split_inclusive('\n').collect::<Vec<_>>();
+    let new_lines = new.split_inclusive('\n').collect::<Vec<_>>();
+    let ops = diff_lines(&old_lines, &new_lines);
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, DiffOp::Equal(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    // Group the changes into hunks, merging hunks whose context would overlap
+    let mut hunks = Vec::<(usize, usize)>::new();
+    for i in changes {
+        let start = i.saturating_sub(CONTEXT);
+        let end = (i + CONTEXT + 1).min(ops.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    // The 1-based line numbers at the start of each op
+    let mut old_nr = 1;
+    let mut new_nr = 1;
+    let mut line_nrs = Vec::<(usize, usize)>::with_capacity(ops.len());
+    for op in &ops {
+        line_nrs.push((old_nr, new_nr));
+        match op {
+            DiffOp::Equal(_) => {
+                old_nr += 1;
+                new_nr += 1;
+            }
+            DiffOp::Delete(_) => old_nr += 1,
+            DiffOp::Insert(_) => new_nr += 1,
+        }
+    }
+    for (start, end) in hunks {
+        let hunk = &ops[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, DiffOp::Insert(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|op| !matches!(op, DiffOp::Delete(_)))
+            .count();
+        let (old_start, new_start) = line_nrs[start];
+        // By convention, empty ranges start at the line before
+        let old_start = if old_len == 0 {
+            old_start - 1
+        } else {
+            old_start
+        };
+        let new_start = if new_len == 0 {
+            new_start - 1
+        } else {
+            new_start
+        };
+        out += &format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start, old_len, new_start, new_len
+        );
+        for op in hunk {
+            match op {
+                DiffOp::Equal(l) => push_diff_line(&mut out, ' ', l),
+                DiffOp::Delete(l) => push_diff_line(&mut out, '-', l),
+                DiffOp::Insert(l) => push_diff_line(&mut out, '+', l),
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn test_unified_diff() {
+    assert_eq!(unified_diff("a\nb\n", "a\nb\n", "old", "new"), "");
+    assert_eq!(
+        unified_diff(
+            "build  --a\n1\n2\n3\n4\n5\n6\n7\n8\nbuild --b\n",
+            "build --a\n1\n2\n3\n4\n5\n6\n7\n8\nbuild --b\ntest --c\n",
+            "a/.bazelrc",
+            "b/.bazelrc"
+        ),
+        "--- a/.bazelrc\n\
+        +++ b/.bazelrc\n\
+        @@ -1,4 +1,4 @@\n\
+        -build  --a\n\
+        +build --a\n \
+        1\n \
+        2\n \
+        3\n\
+        @@ -8,3 +8,4 @@\n \
+        7\n \
+        8\n \
+        build --b\n\
+        +test --c\n"
+    );
+    // Changes to only the final newline or the line endings
+    assert_eq!(
+        unified_diff("build --a", "build --a\n", "old", "new"),
+        "--- old\n+++ new\n@@ -1,1 +1,1 @@\n-build --a\n\\ No newline at end of file\n+build --a\n"
+    );
+    assert_eq!(
+        unified_diff("build --a\r\n", "build --a\n", "old", "new"),
+        "--- old\n+++ new\n@@ -1,1 +1,1 @@\n-build --a\r\n+build --a\n"
+    );
+}
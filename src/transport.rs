@@ -0,0 +1,326 @@
+use std::{fmt, io, str::FromStr};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+    io::{
+        duplex, split, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader, DuplexStream, ReadHalf, WriteHalf,
+    },
+    net::{lookup_host, TcpListener},
+};
+use tokio_tungstenite::{
+    accept_hdr_async_with_config,
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::{header::ORIGIN, StatusCode},
+        protocol::WebSocketConfig,
+        Message,
+    },
+};
+use tower_lsp::{ClientSocket, LanguageServer, LspService, Server};
+
+// Where the language server accepts its clients
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ListenAddress {
+    // A single client, talking via stdin and stdout
+    #[default]
+    Stdio,
+    // `tcp://127.0.0.1:9257`: Any number of clients, each via its own TCP connection
+    Tcp(String),
+    // `ws://127.0.0.1:9257`: Any number of clients, each via its own WebSocket. Every
+    // text or binary message carries one JSON-RPC message, without `Content-Length` header.
+    WebSocket(String),
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ListenAddress, String> {
+        if s == "stdio" {
+            return Ok(ListenAddress::Stdio);
+        }
+        let (scheme, address) = s.split_once("://").ok_or_else(|| {
+            format!("Expected `stdio`, `tcp://HOST:PORT` or `ws://HOST:PORT`, found {s:?}")
+        })?;
+        // The path of WebSocket URLs is not used to route requests
+        let address = address.split('/').next().unwrap_or_default();
+        if address
+            .rsplit_once(':')
+            .is_none_or(|(host, port)| host.is_empty() || port.parse::<u16>().is_err())
+        {
+            return Err(format!("Expected `HOST:PORT`, found {address:?}"));
+        }
+        match scheme {
+            "tcp" => Ok(ListenAddress::Tcp(address.to_string())),
+            "ws" => Ok(ListenAddress::WebSocket(address.to_string())),
+            _ => Err(format!(
+                "Unsupported transport {scheme:?}, expected `tcp` or `ws`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ListenAddress::Stdio => write!(f, "stdio"),
+            ListenAddress::Tcp(address) => write!(f, "tcp://{address}"),
+            ListenAddress::WebSocket(address) => write!(f, "ws://{address}"),
+        }
+    }
+}
+
+// Clients can make the server read files and run Bazel, hence only clients on the same
+// host are accepted. Remote clients can connect via SSH or container port forwarding.
+async fn check_loopback(address: &str) -> io::Result<()> {
+    for resolved in lookup_host(address).await? {
+        if !resolved.ip().is_loopback() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "{resolved} is reachable from other hosts. Listen on a loopback address like 127.0.0.1 and forward the port instead."
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Serves the language server on the given address. `make_service` is called for each client.
+pub async fn serve<S, F>(address: &ListenAddress, mut make_service: F) -> io::Result<()>
+where
+    S: LanguageServer,
+    F: FnMut() -> (LspService<S>, ClientSocket),
+{
+    let (tcp_address, websocket) = match address {
+        ListenAddress::Stdio => {
+            let (service, socket) = make_service();
+            Server::new(tokio::io::stdin(), tokio::io::stdout(), socket)
+                .serve(service)
+                .await;
+            return Ok(());
+        }
+        ListenAddress::Tcp(address) => (address, false),
+        ListenAddress::WebSocket(address) => (address, true),
+    };
+    check_loopback(tcp_address).await?;
+    let listener = TcpListener::bind(tcp_address).await?;
+    tracing::info!("Listening on {address}");
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        tracing::info!("Accepted a connection from {peer}");
+        let (service, socket) = make_service();
+        tokio::spawn(async move {
+            let result = if websocket {
+                match accept_websocket(stream).await {
+                    Ok((read, write)) => {
+                        Server::new(read, write, socket).serve(service).await;
+                        Ok(())
+                    }
+                    Err(err) => Err(err),
+                }
+            } else {
+                let (read, write) = stream.into_split();
+                Server::new(read, write, socket).serve(service).await;
+                Ok(())
+            };
+            match result {
+                Ok(()) => tracing::info!("Connection from {peer} closed"),
+                Err(err) => tracing::warn!("Connection from {peer} failed: {err}"),
+            }
+        });
+    }
+}
+
+// Messages beyond this size are rejected, instead of buffering them
+const MAX_MESSAGE_SIZE: usize = 64 << 20;
+
+// Reads the next LSP message in `Content-Length` framing. `None` at the end of the stream.
+async fn read_lsp_message<R: AsyncRead + Unpin>(
+    reader: &mut BufReader<R>,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let content_length = content_length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length"))?;
+    let mut content = vec![0u8; content_length];
+    reader.read_exact(&mut content).await?;
+    Ok(Some(content))
+}
+
+async fn write_lsp_message<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    content: &[u8],
+) -> io::Result<()> {
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", content.len()).as_bytes())
+        .await?;
+    writer.write_all(content).await
+}
+
+// Browsers send an `Origin` with every WebSocket request, unlike editors. Rejecting them
+// keeps web pages from connecting to the language server via `ws://127.0.0.1`. The
+// signature is given by tungstenite.
+#[allow(clippy::result_large_err)]
+fn reject_browser_origins(
+    request: &Request,
+    response: Response,
+) -> Result<Response, ErrorResponse> {
+    if let Some(origin) = request.headers().get(ORIGIN) {
+        tracing::warn!("Rejected a WebSocket connection from origin {origin:?}");
+        let mut error = ErrorResponse::new(Some(
+            "Connections from browsers are not accepted".to_string(),
+        ));
+        *error.status_mut() = StatusCode::FORBIDDEN;
+        return Err(error);
+    }
+    Ok(response)
+}
+
+// Completes the WebSocket handshake and translates between WebSocket messages and the
+// `Content-Length` framing of the language server. Returns the streams to serve on.
+async fn accept_websocket<S>(
+    stream: S,
+) -> io::Result<(ReadHalf<DuplexStream>, WriteHalf<DuplexStream>)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE_SIZE))
+        .max_frame_size(Some(MAX_MESSAGE_SIZE));
+    let websocket = accept_hdr_async_with_config(stream, reject_browser_origins, Some(config))
+        .await
+        .map_err(io::Error::other)?;
+    let (mut ws_write, mut ws_read) = websocket.split();
+
+    let (server_stream, bridge_stream) = duplex(1 << 16);
+    let (bridge_read, mut bridge_write) = split(bridge_stream);
+    // Messages to the client, one JSON-RPC message per text message
+    tokio::spawn(async move {
+        let mut bridge_read = BufReader::new(bridge_read);
+        while let Ok(Some(message)) = read_lsp_message(&mut bridge_read).await {
+            let message = String::from_utf8_lossy(&message).into_owned();
+            if ws_write.send(Message::text(message)).await.is_err() {
+                break;
+            }
+        }
+        let _ = ws_write.close().await;
+    });
+    // Messages from the client. Pings and closes are answered by tungstenite.
+    tokio::spawn(async move {
+        while let Some(message) = ws_read.next().await {
+            let content = match message {
+                Ok(Message::Text(text)) => text.as_bytes().to_vec(),
+                Ok(Message::Binary(data)) => data.to_vec(),
+                Ok(Message::Close(_)) => break,
+                Ok(_) => continue,
+                Err(err) => {
+                    tracing::debug!("WebSocket closed: {err}");
+                    break;
+                }
+            };
+            if write_lsp_message(&mut bridge_write, &content)
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+        let _ = bridge_write.shutdown().await;
+    });
+    Ok(split(server_stream))
+}
+
+#[test]
+fn test_parse_listen_address() {
+    assert_eq!("stdio".parse(), Ok(ListenAddress::Stdio));
+    assert_eq!(
+        "tcp://127.0.0.1:9257".parse(),
+        Ok(ListenAddress::Tcp("127.0.0.1:9257".to_string()))
+    );
+    assert_eq!(
+        "ws://localhost:9257/bazelrc".parse(),
+        Ok(ListenAddress::WebSocket("localhost:9257".to_string()))
+    );
+    assert_eq!(
+        "tcp://[::1]:9257"
+            .parse::<ListenAddress>()
+            .unwrap()
+            .to_string(),
+        "tcp://[::1]:9257"
+    );
+    assert!("tcp://127.0.0.1".parse::<ListenAddress>().is_err());
+    assert!("http://127.0.0.1:80".parse::<ListenAddress>().is_err());
+    assert!("127.0.0.1:9257".parse::<ListenAddress>().is_err());
+}
+
+#[tokio::test]
+async fn test_check_loopback() {
+    assert!(check_loopback("127.0.0.1:9257").await.is_ok());
+    assert!(check_loopback("[::1]:9257").await.is_ok());
+    assert_eq!(
+        check_loopback("0.0.0.0:9257").await.unwrap_err().kind(),
+        io::ErrorKind::PermissionDenied
+    );
+}
+
+#[tokio::test]
+async fn test_websocket_bridge() {
+    let (client_stream, server_stream) = duplex(1 << 16);
+    let server = tokio::spawn(accept_websocket(server_stream));
+    let (mut client, _) = tokio_tungstenite::client_async("ws://127.0.0.1/", client_stream)
+        .await
+        .unwrap();
+    let (read, mut write) = server.await.unwrap().unwrap();
+    let mut read = BufReader::new(read);
+
+    // Client messages are forwarded in `Content-Length` framing
+    let payload = "{\"jsonrpc\":\"2.0\"}";
+    client.send(Message::text(payload)).await.unwrap();
+    assert_eq!(
+        read_lsp_message(&mut read).await.unwrap(),
+        Some(payload.as_bytes().to_vec())
+    );
+    // Messages of the server are sent as one text message each
+    write_lsp_message(&mut write, payload.as_bytes())
+        .await
+        .unwrap();
+    assert_eq!(
+        client.next().await.unwrap().unwrap(),
+        Message::text(payload)
+    );
+    // Closing the WebSocket ends the stream of the server
+    client.close(None).await.unwrap();
+    assert_eq!(read_lsp_message(&mut read).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_websocket_rejects_browsers() {
+    use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Error};
+
+    let (client_stream, server_stream) = duplex(1 << 16);
+    let server = tokio::spawn(accept_websocket(server_stream));
+    let mut request = "ws://127.0.0.1/".into_client_request().unwrap();
+    request
+        .headers_mut()
+        .insert(ORIGIN, "https://example.com".parse().unwrap());
+    let err = tokio_tungstenite::client_async(request, client_stream)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Http(response) if response.status() == StatusCode::FORBIDDEN));
+    assert!(server.await.unwrap().is_err());
+}
@@ -0,0 +1,482 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use dashmap::{DashMap, DashSet};
+
+use crate::{
+    bazel_flags::{try_load_packaged_bazel_flags, BazelFlags},
+    bazel_flags_proto::FlagInfo,
+    bazel_version::{determine_bazelisk_version, find_closest_version, AVAILABLE_BAZEL_VERSIONS},
+    file_utils::get_workspace_path,
+};
+
+// The files influencing the Bazel version of a workspace, besides `USE_BAZEL_VERSION`
+pub const VERSION_FILE_NAMES: [&str; 2] = [".bazelversion", ".bazeliskrc"];
+
+// Determines the packaged Bazel version to use for the given workspace, based on
+// `USE_BAZEL_VERSION`, the `.bazeliskrc` and the `.bazelversion`.
+// Also returns a message if the version could not be matched exactly.
+pub fn resolve_bazel_version(workspace: &Path) -> (String, Option<String>) {
+    match determine_bazelisk_version(workspace) {
+        Some(version) => find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), &version),
+        None => {
+            let bazel_version =
+                find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), "latest").0;
+            let message = format!(
+                "Using flags from Bazel {bazel_version} because auto-detecting the Bazel version failed"
+            );
+            (bazel_version, Some(message))
+        }
+    }
+}
+
+// The Bazel flags to use for each workspace. With auto-detection, the version is
+// resolved separately for each workspace. Otherwise, the default flags are used everywhere.
+#[derive(Debug)]
+pub struct WorkspaceFlags {
+    // Used for files outside of any workspace and if auto-detection is disabled
+    default_flags: Arc<BazelFlags>,
+    // The default flags merged with the default custom flags
+    merged_default_flags: Arc<BazelFlags>,
+    // Why the packaged flags could not be loaded. The syntax-only default flags are used
+    // everywhere, except for workspaces with live flags.
+    packaged_flags_error: RwLock<Option<String>>,
+    auto_detect: bool,
+    // The resolved Bazel version for each workspace root
+    versions: DashMap<PathBuf, String>,
+    // Versions configured explicitly for a workspace. They take precedence over auto-detection.
+    pinned_versions: DashMap<PathBuf, String>,
+    // Messages explaining the resolved versions, which were not yet shown to the user
+    messages: DashMap<PathBuf, String>,
+    // The flags for each Bazel version, shared between workspaces using the same version
+    flags_by_version: DashMap<String, Arc<BazelFlags>>,
+    // The flags loaded from the Bazel binary of a workspace. They take precedence over the
+    // packaged flags. `None` while loading is still in progress.
+    live_flags: DashMap<PathBuf, Option<Arc<BazelFlags>>>,
+    // Workspaces whose live flags failed to load. They keep using the packaged flags.
+    failed_live_flags: DashSet<PathBuf>,
+    // Additional flags, e.g. of company-internal wrapper scripts, configured per workspace.
+    // `default_custom_flags` apply to workspaces without any configured custom flags.
+    custom_flags: DashMap<PathBuf, Arc<Vec<FlagInfo>>>,
+    default_custom_flags: Arc<Vec<FlagInfo>>,
+    // The flags of a workspace merged with its custom flags, together with the flags
+    // they were merged into. Outdated as soon as the workspace uses different flags.
+    merged_flags: DashMap<PathBuf, (Arc<BazelFlags>, Arc<BazelFlags>)>,
+    // When the flags of each workspace were last (re-)loaded
+    refresh_times: DashMap<PathBuf, SystemTime>,
+}
+
+impl WorkspaceFlags {
+    pub fn new(default_flags: BazelFlags, auto_detect: bool) -> WorkspaceFlags {
+        let packaged_flags_error = default_flags
+            .is_syntax_only()
+            .then(|| "The packaged flags could not be loaded".to_string());
+        let default_flags = Arc::new(default_flags);
+        WorkspaceFlags {
+            merged_default_flags: default_flags.clone(),
+            default_flags,
+            packaged_flags_error: packaged_flags_error.into(),
+            auto_detect,
+            versions: Default::default(),
+            pinned_versions: Default::default(),
+            messages: Default::default(),
+            flags_by_version: Default::default(),
+            live_flags: Default::default(),
+            failed_live_flags: Default::default(),
+            custom_flags: Default::default(),
+            default_custom_flags: Default::default(),
+            merged_flags: Default::default(),
+            refresh_times: Default::default(),
+        }
+    }
+
+    // Adds custom flags to all workspaces without custom flags of their own, and to
+    // files outside of any workspace
+    pub fn with_default_custom_flags(mut self, custom_flags: Vec<FlagInfo>) -> WorkspaceFlags {
+        if !custom_flags.is_empty() {
+            self.merged_default_flags =
+                Arc::new(self.default_flags.with_custom_flags(&custom_flags));
+        }
+        self.default_custom_flags = Arc::new(custom_flags);
+        self
+    }
+
+    // Sets the custom flags of the workspace, or goes back to the default custom flags.
+    // Returns true if the custom flags changed.
+    pub fn set_custom_flags(&self, workspace: &Path, custom_flags: Option<Vec<FlagInfo>>) -> bool {
+        let changed = match custom_flags {
+            Some(flags) => self
+                .custom_flags
+                .insert(workspace.to_path_buf(), Arc::new(flags.clone()))
+                .is_none_or(|previous| *previous != flags),
+            None => self.custom_flags.remove(workspace).is_some(),
+        };
+        if changed {
+            self.merged_flags.remove(workspace);
+        }
+        changed
+    }
+
+    pub fn default_flags(&self) -> Arc<BazelFlags> {
+        self.merged_default_flags.clone()
+    }
+
+    pub fn packaged_flags_error(&self) -> Option<String> {
+        self.packaged_flags_error.read().unwrap().clone()
+    }
+
+    // The Bazel version of the given workspace, if pinned or auto-detected
+    pub fn get_version(&self, workspace: &Path) -> Option<String> {
+        if let Some(version) = self.pinned_versions.get(workspace) {
+            return Some(version.clone());
+        }
+        if !self.auto_detect {
+            return None;
+        }
+        let version = self
+            .versions
+            .entry(workspace.to_path_buf())
+            .or_insert_with(|| self.resolve(workspace));
+        Some(version.clone())
+    }
+
+    fn resolve(&self, workspace: &Path) -> String {
+        let (version, message) = resolve_bazel_version(workspace);
+        self.refresh_times
+            .insert(workspace.to_path_buf(), SystemTime::now());
+        match message {
+            Some(message) => self.messages.insert(workspace.to_path_buf(), message),
+            None => self.messages.remove(workspace).map(|(_, m)| m),
+        };
+        version
+    }
+
+    // Explains which flags are used for the workspace, if its Bazel version could not be
+    // matched exactly. Only returned once per resolution.
+    pub fn take_message(&self, workspace: &Path) -> Option<String> {
+        self.messages.remove(workspace).map(|(_, message)| message)
+    }
+
+    // The flags for the file at the given path
+    pub fn get(&self, file_path: Option<&Path>) -> Arc<BazelFlags> {
+        let workspace = file_path.and_then(get_workspace_path);
+        let flags = self.get_without_custom_flags(workspace.as_deref());
+        let Some(workspace) = workspace else {
+            return self.default_flags();
+        };
+        let custom_flags = match self.custom_flags.get(&workspace) {
+            Some(custom_flags) => custom_flags.clone(),
+            None => self.default_custom_flags.clone(),
+        };
+        if custom_flags.is_empty() {
+            return flags;
+        }
+        if Arc::ptr_eq(&flags, &self.default_flags)
+            && Arc::ptr_eq(&custom_flags, &self.default_custom_flags)
+        {
+            return self.default_flags();
+        }
+        if let Some(entry) = self.merged_flags.get(&workspace) {
+            if Arc::ptr_eq(&entry.0, &flags) {
+                return entry.1.clone();
+            }
+        }
+        let merged = Arc::new(flags.with_custom_flags(&custom_flags));
+        self.merged_flags.insert(workspace, (flags, merged.clone()));
+        merged
+    }
+
+    fn get_without_custom_flags(&self, workspace: Option<&Path>) -> Arc<BazelFlags> {
+        if let Some(flags) = workspace.and_then(|w| self.live_flags.get(w)?.clone()) {
+            return flags;
+        }
+        let default_flags = self.default_flags.clone();
+        let Some(version) = workspace.and_then(|workspace| self.get_version(workspace)) else {
+            return default_flags;
+        };
+        if let Some(flags) = self.flags_by_version.get(&version) {
+            return flags.clone();
+        }
+        // Don't retry decoding the packaged flags for every lookup
+        if self.packaged_flags_error().is_some() {
+            return default_flags;
+        }
+        match try_load_packaged_bazel_flags(&version) {
+            Ok(flags) => self
+                .flags_by_version
+                .entry(version)
+                .or_insert(Arc::new(flags))
+                .clone(),
+            Err(err) => {
+                tracing::error!("{err}");
+                *self.packaged_flags_error.write().unwrap() = Some(err);
+                default_flags
+            }
+        }
+    }
+
+    // Re-resolves the version of the workspace, e.g. after its `.bazelversion` changed.
+    // Returns true if the version changed.
+    pub fn refresh(&self, workspace: &Path) -> bool {
+        if !self.auto_detect {
+            return false;
+        }
+        let version = self.resolve(workspace);
+        let previous = self
+            .versions
+            .insert(workspace.to_path_buf(), version.clone());
+        previous.is_some_and(|p| p != version)
+    }
+
+    // Pins the Bazel version of the workspace, e.g. as configured in the settings, or goes
+    // back to the auto-detected version. Returns true if the flags of the workspace changed.
+    pub fn pin_version(&self, workspace: &Path, version: Option<&str>) -> bool {
+        // Don't auto-detect the version just to replace it
+        let previous = match self.pinned_versions.get(workspace) {
+            Some(pinned) => Some(pinned.clone()),
+            None => self
+                .versions
+                .get(workspace)
+                .filter(|_| self.auto_detect)
+                .map(|v| v.clone()),
+        };
+        match version {
+            Some(version) => {
+                let (version, message) =
+                    find_closest_version(AVAILABLE_BAZEL_VERSIONS.as_slice(), version);
+                let previous_pin = self
+                    .pinned_versions
+                    .insert(workspace.to_path_buf(), version.clone());
+                // Only explain the version once, not every time it is applied
+                if let Some(message) = message.filter(|_| previous_pin != Some(version)) {
+                    self.messages.insert(workspace.to_path_buf(), message);
+                }
+            }
+            None => {
+                self.pinned_versions.remove(workspace);
+            }
+        }
+        let changed = previous != self.get_version(workspace);
+        if changed {
+            self.refresh_times
+                .insert(workspace.to_path_buf(), SystemTime::now());
+        }
+        changed
+    }
+
+    // Marks the live flags of the workspace as being loaded.
+    // Returns false if they were already requested before.
+    pub fn start_live_loading(&self, workspace: &Path) -> bool {
+        let mut requested = false;
+        self.live_flags
+            .entry(workspace.to_path_buf())
+            .or_insert_with(|| {
+                requested = true;
+                None
+            });
+        requested
+    }
+
+    pub fn set_live_flags(&self, workspace: &Path, flags: BazelFlags) {
+        self.live_flags
+            .insert(workspace.to_path_buf(), Some(Arc::new(flags)));
+        self.refresh_times
+            .insert(workspace.to_path_buf(), SystemTime::now());
+    }
+
+    // Marks the live flags of the workspace as failed. They are not requested again.
+    pub fn set_live_flags_failed(&self, workspace: &Path) {
+        self.failed_live_flags.insert(workspace.to_path_buf());
+    }
+
+    pub fn live_flags_failed(&self, workspace: &Path) -> bool {
+        self.failed_live_flags.contains(workspace)
+    }
+
+    // Are the flags of the workspace loaded from its Bazel binary? `None` while they are
+    // still being loaded, `Some(false)` if the packaged flags are used.
+    pub fn has_live_flags(&self, workspace: &Path) -> Option<bool> {
+        if self.failed_live_flags.contains(workspace) {
+            return Some(false);
+        }
+        match self.live_flags.get(workspace) {
+            Some(flags) => flags.as_ref().map(|_| true),
+            None => Some(false),
+        }
+    }
+
+    // When the flags of the workspace were last (re-)loaded, if ever
+    pub fn get_refresh_time(&self, workspace: &Path) -> Option<SystemTime> {
+        self.refresh_times.get(workspace).map(|t| *t)
+    }
+
+    // Drops the live flags of the workspace, e.g. because its Bazel version changed.
+    // Returns true if they were loaded before.
+    pub fn forget_live_flags(&self, workspace: &Path) -> bool {
+        self.failed_live_flags.remove(workspace);
+        self.live_flags
+            .remove(workspace)
+            .is_some_and(|(_, flags)| flags.is_some())
+    }
+}
+
+#[test]
+fn test_workspace_flags() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, test_utils::TestDir};
+
+    let (root_a, root_b) = (TestDir::workspace(), TestDir::workspace());
+    root_a.write(".bazelversion", "7.0.0\n");
+    root_b.write(".bazelversion", "8.0.0\n");
+    let (ws_a, ws_b) = (root_a.to_path_buf(), root_b.to_path_buf());
+
+    // `USE_BAZEL_VERSION` would take precedence over the `.bazelversion`
+    if std::env::var("USE_BAZEL_VERSION").is_ok() {
+        return;
+    }
+    let flags = WorkspaceFlags::new(load_packaged_bazel_flags("6.5.0"), true);
+    assert_eq!(flags.get_version(&ws_a), Some("7.0.0".to_string()));
+    assert_eq!(flags.get_version(&ws_b), Some("8.0.0".to_string()));
+    let flags_a = flags.get(Some(&ws_a.join(".bazelrc")));
+    let flags_b = flags.get(Some(&ws_b.join("tools/ci.bazelrc")));
+    assert!(!Arc::ptr_eq(&flags_a, &flags_b));
+    // Files within the same workspace share the flags
+    assert!(Arc::ptr_eq(
+        &flags_a,
+        &flags.get(Some(&ws_a.join("x.bazelrc")))
+    ));
+
+    // Changes to the `.bazelversion` are picked up after a refresh
+    std::fs::write(ws_a.join(".bazelversion"), "8.0.0\n").unwrap();
+    assert_eq!(flags.get_version(&ws_a), Some("7.0.0".to_string()));
+    assert!(flags.refresh(&ws_a));
+    assert!(!flags.refresh(&ws_a));
+    assert!(Arc::ptr_eq(
+        &flags.get(Some(&ws_a.join(".bazelrc"))),
+        &flags_b
+    ));
+
+    // Unknown versions are mapped to the closest packaged version, explained by a message
+    std::fs::write(ws_b.join(".bazelversion"), "8.0.99\n").unwrap();
+    flags.refresh(&ws_b);
+    assert_eq!(
+        flags.take_message(&ws_b).unwrap(),
+        format!(
+            "Using flags from Bazel {} because flags for version 8.0.99 are not available",
+            flags.get_version(&ws_b).unwrap()
+        )
+    );
+    assert_eq!(flags.take_message(&ws_b), None);
+    assert_eq!(flags.take_message(&ws_a), None);
+
+    // Live flags loaded from the Bazel binary take precedence
+    assert_eq!(flags.has_live_flags(&ws_a), Some(false));
+    assert!(flags.start_live_loading(&ws_a));
+    assert!(!flags.start_live_loading(&ws_a));
+    assert_eq!(flags.has_live_flags(&ws_a), None);
+    assert!(flags.start_live_loading(&ws_b));
+    flags.set_live_flags_failed(&ws_b);
+    assert_eq!(flags.has_live_flags(&ws_b), Some(false));
+    assert!(!flags.start_live_loading(&ws_b));
+    assert!(!flags.forget_live_flags(&ws_b));
+    assert!(flags.start_live_loading(&ws_b));
+    let resolved_at = flags.get_refresh_time(&ws_a).unwrap();
+    flags.set_live_flags(&ws_a, BazelFlags::from_flags(vec![], None));
+    assert_eq!(flags.has_live_flags(&ws_a), Some(true));
+    assert!(flags.get_refresh_time(&ws_a).unwrap() >= resolved_at);
+    let live_a = flags.get(Some(&ws_a.join(".bazelrc")));
+    assert!(live_a.flags.is_empty());
+    assert!(Arc::ptr_eq(
+        &live_a,
+        &flags.get(Some(&ws_a.join("x.bazelrc")))
+    ));
+    assert!(flags.forget_live_flags(&ws_a));
+    assert!(Arc::ptr_eq(
+        &flags.get(Some(&ws_a.join(".bazelrc"))),
+        &flags_b
+    ));
+
+    // Without auto-detection, the default flags are used everywhere
+    let flags = WorkspaceFlags::new(load_packaged_bazel_flags("6.5.0"), false);
+    assert_eq!(flags.get_version(&ws_a), None);
+    assert_eq!(flags.get_refresh_time(&ws_a), None);
+    assert!(Arc::ptr_eq(
+        &flags.get(Some(&ws_b.join(".bazelrc"))),
+        &flags.default_flags()
+    ));
+
+    // Unless a version is pinned for the workspace
+    assert!(flags.pin_version(&ws_a, Some("7.0.0")));
+    assert!(!flags.pin_version(&ws_a, Some("7.0.0")));
+    assert_eq!(flags.get_version(&ws_a), Some("7.0.0".to_string()));
+    assert_eq!(flags.get_version(&ws_b), None);
+    assert!(flags.pin_version(&ws_a, None));
+    assert!(Arc::ptr_eq(
+        &flags.get(Some(&ws_a.join(".bazelrc"))),
+        &flags.default_flags()
+    ));
+
+    // Custom flags are merged into the flags of the workspace
+    let custom_flag = FlagInfo {
+        name: "remote_profile".to_string(),
+        ..Default::default()
+    };
+    assert!(flags.set_custom_flags(&ws_a, Some(vec![custom_flag.clone()])));
+    assert!(!flags.set_custom_flags(&ws_a, Some(vec![custom_flag.clone()])));
+    let custom_a = flags.get(Some(&ws_a.join(".bazelrc")));
+    assert!(custom_a.get_by_invocation("--remote_profile").is_some());
+    assert!(Arc::ptr_eq(
+        &custom_a,
+        &flags.get(Some(&ws_a.join("x.bazelrc")))
+    ));
+    assert!(flags.set_custom_flags(&ws_a, None));
+    assert!(Arc::ptr_eq(
+        &flags.get(Some(&ws_a.join(".bazelrc"))),
+        &flags.default_flags()
+    ));
+
+    // Default custom flags apply everywhere, unless overridden for a workspace
+    let flags = WorkspaceFlags::new(load_packaged_bazel_flags("6.5.0"), false)
+        .with_default_custom_flags(vec![custom_flag]);
+    assert!(flags
+        .get(Some(&ws_b.join(".bazelrc")))
+        .get_by_invocation("--remote_profile")
+        .is_some());
+    assert!(Arc::ptr_eq(
+        &flags.get(Some(&ws_b.join(".bazelrc"))),
+        &flags.default_flags()
+    ));
+    flags.set_custom_flags(&ws_b, Some(vec![]));
+    assert!(flags
+        .get(Some(&ws_b.join(".bazelrc")))
+        .get_by_invocation("--remote_profile")
+        .is_none());
+}
+
+#[test]
+fn test_syntax_only_flags() {
+    use crate::{bazel_flags::load_packaged_bazel_flags, test_utils::TestDir};
+
+    let ws = TestDir::workspace();
+    ws.write(".bazelversion", "8.0.0\n");
+
+    // Started without flags, e.g. because decoding the packaged flags failed
+    let flags = WorkspaceFlags::new(BazelFlags::syntax_only("8.0.0"), true);
+    assert!(flags.packaged_flags_error().is_some());
+    assert!(flags.get(Some(&ws.join(".bazelrc"))).is_syntax_only());
+    assert!(flags.default_flags().is_syntax_only());
+
+    // Only the workspaces whose live flags could be loaded support all features
+    assert!(flags.start_live_loading(&ws));
+    flags.set_live_flags_failed(&ws);
+    assert!(flags.live_flags_failed(&ws));
+    assert!(flags.get(Some(&ws.join(".bazelrc"))).is_syntax_only());
+    flags.forget_live_flags(&ws);
+    assert!(!flags.live_flags_failed(&ws));
+    assert!(flags.start_live_loading(&ws));
+    flags.set_live_flags(&ws, load_packaged_bazel_flags("8.0.0"));
+    assert!(!flags.get(Some(&ws.join(".bazelrc"))).is_syntax_only());
+    assert!(flags.default_flags().is_syntax_only());
+}
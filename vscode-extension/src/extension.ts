@@ -1,10 +1,14 @@
 import {
+  commands,
+  StatusBarAlignment,
+  window,
   workspace,
   type ExtensionContext
 } from 'vscode';
 
 import {
   type Executable,
+  ExecuteCommandRequest,
   LanguageClient,
   type LanguageClientOptions,
   type ServerOptions
@@ -13,14 +17,9 @@ import {
 async function startLsp (context: ExtensionContext) {
   const command = process.env.SERVER_PATH ?? context.asAbsolutePath('bazelrc-lsp');
 
-  const config = workspace.getConfiguration('bazelrc');
-  const bazelVersion = config.get<string>('bazelVersion') ?? 'auto';
-  const bazelVersionArgs =
-    bazelVersion !== 'auto' ? ['--bazel-version', bazelVersion] : [];
-
   const run: Executable = {
     command,
-    args: bazelVersionArgs.concat(['lsp']),
+    args: ['lsp'],
     options: {
       env: {
         ...process.env,
@@ -39,28 +38,122 @@ async function startLsp (context: ExtensionContext) {
   };
   // Options to control the language client
   const clientOptions: LanguageClientOptions = {
-    // Register the server for bazelrc documents
-    documentSelector: [{ language: 'bazelrc' }],
+    // Register the server for bazelrc documents and the `.bazeliskrc`
+    documentSelector: [{ language: 'bazelrc' }, { scheme: 'file', pattern: '**/.bazeliskrc' }],
     synchronize: {
       configurationSection: 'bazelrc'
-    }
+    },
+    // Most settings are applied by the server without a restart
+    initializationOptions: workspace.getConfiguration('bazelrc')
   };
 
   // Create the language client and start the client.
   const client = new LanguageClient('bazelrc-lsp', 'Bazelrc Language Server', serverOptions, clientOptions);
   await client.start();
+  // Triggered by the "Run `bazel build --config=...`" code lenses
+  client.onNotification('bazelrc/runInTerminal', (params: { cwd?: string, commandLine: string }) => {
+    const terminal = window.createTerminal({ name: 'Bazel', cwd: params.cwd });
+    terminal.show();
+    terminal.sendText(params.commandLine);
+  });
   return client;
 }
 
 let client: LanguageClient | null = null;
 
+// Shows the flags in effect for a Bazel invocation, e.g. `test --config=ci`,
+// based on the bazelrc file opened in the active editor
+async function openEffectiveConfig () {
+  const uri = window.activeTextEditor?.document.uri;
+  if (client === null || uri === undefined) {
+    return;
+  }
+  const invocation = await window.showInputBox({
+    prompt: 'Bazel command and configs',
+    placeHolder: 'test --config=ci'
+  });
+  if (invocation === undefined) {
+    return;
+  }
+  const [command, ...args] = invocation.trim().split(/\s+/);
+  const configs = args.map((a) => a.replace(/^--config=/, ''));
+  const content = await client.sendRequest(ExecuteCommandRequest.type, {
+    command: 'bazelrc.showEffectiveConfig',
+    arguments: [{ uri: uri.toString(), command: command ?? 'build', configs }]
+  });
+  const document = await workspace.openTextDocument({ language: 'bazelrc', content: content as string });
+  await window.showTextDocument(document, { preview: true });
+}
+
+// Rewrites the flags of the selected lines into their canonical form, using
+// `bazel canonicalize-flags` if `bazelrc.bazelPath` is configured
+async function canonicalizeSelectedFlags () {
+  const editor = window.activeTextEditor;
+  if (client === null || editor === undefined) {
+    return;
+  }
+  await client.sendRequest(ExecuteCommandRequest.type, {
+    command: 'bazelrc.canonicalizeFlags',
+    arguments: [{
+      uri: editor.document.uri.toString(),
+      range: client.code2ProtocolConverter.asRange(editor.selection)
+    }]
+  });
+}
+
+// Sent by the server via `bazelrc/status` and returned by `bazelrc/serverStatus`
+interface ServerStatus {
+  workspace?: string
+  bazelVersion?: string
+  flagSource: 'packaged' | 'loading' | 'live'
+  indexedFiles: number
+  lastFlagRefresh?: number
+}
+
+// Shows the Bazel version and the origin of the flags used for the active bazelrc file
+function createStatusBarItem (context: ExtensionContext, client: LanguageClient) {
+  const item = window.createStatusBarItem(StatusBarAlignment.Right);
+  item.name = 'Bazelrc';
+  const update = async () => {
+    const document = window.activeTextEditor?.document;
+    if (document?.languageId !== 'bazelrc') {
+      item.hide();
+      return;
+    }
+    const status: ServerStatus = await client.sendRequest('bazelrc/serverStatus', {
+      textDocument: { uri: document.uri.toString() }
+    });
+    const version = status.bazelVersion ?? 'unknown';
+    item.text = status.flagSource === 'loading' ? `$(sync~spin) Bazel ${version}` : `Bazel ${version}`;
+    const source = {
+      packaged: 'packaged with the extension',
+      loading: 'packaged with the extension, while loading them from Bazel',
+      live: 'loaded from Bazel'
+    }[status.flagSource];
+    const refreshed = status.lastFlagRefresh === undefined
+      ? ''
+      : `, last refreshed ${new Date(status.lastFlagRefresh).toLocaleTimeString()}`;
+    item.tooltip = `Bazel ${version}: flags ${source}${refreshed}. ${status.indexedFiles} bazelrc file(s) analyzed.`;
+    item.show();
+  };
+  context.subscriptions.push(item);
+  context.subscriptions.push(window.onDidChangeActiveTextEditor(() => { void update(); }));
+  client.onNotification('bazelrc/status', () => { void update(); });
+  void update();
+}
+
 export async function activate (context: ExtensionContext) {
   client = await startLsp(context);
+  createStatusBarItem(context, client);
+
+  context.subscriptions.push(commands.registerCommand('bazelrc.openEffectiveConfig', openEffectiveConfig));
+  context.subscriptions.push(commands.registerCommand('bazelrc.canonicalizeSelectedFlags', canonicalizeSelectedFlags));
 
+  // Start over with the flags of the new Bazel version, instead of mixing in state
+  // derived from the previous version's flags
   context.subscriptions.push(workspace.onDidChangeConfiguration(async (e) => {
     if (e.affectsConfiguration('bazelrc.bazelVersion')) {
-      await client?.stop();
-      client = await startLsp(context);
+      await client?.restart();
     }
   }));
 }